@@ -0,0 +1,1045 @@
+//! Responses of the EFA endpoints turned into model types. No HTTP stack,
+//! clock or other I/O, so a display or daemon bringing its own networking
+//! can parse with the exact same code as the app.
+
+use std::cmp::Reverse;
+use std::fmt;
+use std::time::Duration;
+
+use chrono::{DateTime, Datelike, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta, TimeZone};
+use encoding_rs::{Encoding, UTF_8};
+use html_escape::decode_html_entities;
+use quick_xml::encoding::detect_encoding;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Errors returned by the EFA client.
+#[derive(Clone, Debug, PartialEq)]
+pub enum EfaError {
+    /// The request could not be sent or no response arrived (offline, DNS, TLS, ...).
+    Network(String),
+    /// The server answered with a non-success HTTP status.
+    Http { status: u16 },
+    /// The response arrived but could not be parsed.
+    Parse(String),
+    /// The server answered with an empty body.
+    EmptyResponse,
+    /// The server answered with an error message instead of results,
+    /// e.g. for an unknown stop id.
+    ServerMessage(String),
+    /// The server asked us to slow down (HTTP 429). Requests to the same
+    /// endpoint are held back for `retry_after`.
+    RateLimited { retry_after: Duration },
+    /// A newer request took this one's place, see `RequestSlot`.
+    Cancelled,
+}
+
+impl fmt::Display for EfaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EfaError::Network(msg) => write!(f, "network error: {msg}"),
+            EfaError::Http { status } => write!(f, "HTTP status {status}"),
+            EfaError::Parse(msg) => write!(f, "parse error: {msg}"),
+            EfaError::EmptyResponse => write!(f, "empty response"),
+            EfaError::ServerMessage(msg) => write!(f, "server message: {msg}"),
+            EfaError::RateLimited { retry_after } => write!(f, "rate limited for {} s", retry_after.as_secs()),
+            EfaError::Cancelled => write!(f, "cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for EfaError {}
+
+/// WGS84 coordinate in decimal degrees.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Coord {
+    pub lat: f64,
+    pub lon: f64,
+}
+
+impl Coord {
+    /// Great-circle distance in meters.
+    pub fn distance_m(&self, other: &Coord) -> f64 {
+        const EARTH_RADIUS_M: f64 = 6_371_000.0;
+        let (lat1, lat2) = (self.lat.to_radians(), other.lat.to_radians());
+        let dlat = lat2 - lat1;
+        let dlon = (other.lon - self.lon).to_radians();
+        let a = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+        2.0 * EARTH_RADIUS_M * a.sqrt().asin()
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct StopSuggestion {
+    pub id: String,
+    pub name: String,
+    pub place: Option<String>,
+    /// How well a search hit matches the query, up to 1000. Search data
+    /// only, so not stored with favorites.
+    #[serde(default, skip_serializing)]
+    pub quality: Option<u32>,
+    /// EFA's pick among the hits of a search.
+    #[serde(default, skip_serializing)]
+    pub best: bool,
+}
+
+/// Kind of vehicle, from the EFA `motType` code.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum TransportMode {
+    Train,
+    SBahn,
+    Subway,
+    /// Stadtbahn, e.g. the Karlsruhe tram-trains running into the city.
+    LightRail,
+    Tram,
+    Bus,
+    CableCar,
+    Ferry,
+    /// Call-a-bus / AST services that only run when booked.
+    OnDemand,
+    RegionalTrain,
+    LongDistanceTrain,
+    /// Schienenersatzverkehr: buses replacing a rail line.
+    ReplacementBus,
+    #[default]
+    Other,
+}
+
+impl TransportMode {
+    pub fn from_mot_type(code: &str) -> Self {
+        match code.trim() {
+            "0" => TransportMode::Train,
+            "1" => TransportMode::SBahn,
+            "2" => TransportMode::Subway,
+            "3" => TransportMode::LightRail,
+            "4" => TransportMode::Tram,
+            // City, regional, express and community buses.
+            "5" | "6" | "7" | "19" => TransportMode::Bus,
+            "8" => TransportMode::CableCar,
+            "9" => TransportMode::Ferry,
+            "10" => TransportMode::OnDemand,
+            "13" => TransportMode::RegionalTrain,
+            "14" | "15" | "16" => TransportMode::LongDistanceTrain,
+            "17" => TransportMode::ReplacementBus,
+            _ => TransportMode::Other,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Departure {
+    pub line: String,
+    pub direction: Option<String>,
+    /// Expected departure: the realtime time if known, otherwise the planned one.
+    pub time: NaiveDateTime,
+    pub planned_time: NaiveDateTime,
+    pub realtime_time: Option<NaiveDateTime>,
+    pub mode: TransportMode,
+    /// Track or bay, e.g. "Gleis 1", if the stop has several.
+    pub platform: Option<String>,
+    /// Delay in minutes (negative when early); `None` without realtime data.
+    pub delay_minutes: Option<i32>,
+    pub cancelled: bool,
+    /// Company running the service, e.g. "AVG" or "VBK", if EFA names one.
+    #[serde(default)]
+    pub operator: Option<String>,
+    /// Stops served before this one, in order. Only filled by `departures_with_route`.
+    #[serde(default)]
+    pub previous_stops: Vec<CallingPoint>,
+    /// Stops still to come after this one, in order. Only filled by `departures_with_route`.
+    #[serde(default)]
+    pub onward_stops: Vec<CallingPoint>,
+}
+
+impl Departure {
+    /// Whether this is a bus replacing a rail line (Schienenersatzverkehr).
+    pub fn is_replacement(&self) -> bool {
+        is_replacement(self.mode, &self.line)
+    }
+
+    /// The next `max` onward stops as "Marktplatz → Durlacher Tor", or
+    /// `None` without stop data.
+    pub fn continues_via(&self, max: usize) -> Option<String> {
+        let names: Vec<&str> = self.onward_stops.iter().take(max).map(|s| s.name.as_str()).collect();
+        (!names.is_empty()).then(|| names.join(" → "))
+    }
+}
+
+/// Another stop on a departure's route.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CallingPoint {
+    pub id: String,
+    /// Stop name without the town, e.g. "Marktplatz (Pyramide U)".
+    pub name: String,
+}
+
+/// Replacement buses usually come as motType 17, but some operators run them
+/// as regular buses with lines like "SEV S1".
+pub fn is_replacement(mode: TransportMode, line: &str) -> bool {
+    mode == TransportMode::ReplacementBus || line.split_whitespace().any(|w| w.eq_ignore_ascii_case("SEV"))
+}
+
+// Central European Time and its summer time, in seconds east of UTC.
+const CET: i32 = 3600;
+const CEST: i32 = 2 * 3600;
+
+/// Offset of Europe/Berlin at the UTC time `utc`: summer time runs from
+/// 01:00 UTC on the last Sunday of March to the last Sunday of October.
+pub fn berlin_offset(utc: NaiveDateTime) -> FixedOffset {
+    // Both months have 31 days.
+    let switch = |month| {
+        let last = NaiveDate::from_ymd_opt(utc.year(), month, 31)?;
+        let sunday = last - TimeDelta::days(last.weekday().num_days_from_sunday().into());
+        sunday.and_hms_opt(1, 0, 0)
+    };
+    let summer = matches!((switch(3), switch(10)), (Some(start), Some(end)) if (start..end).contains(&utc));
+    FixedOffset::east_opt(if summer { CEST } else { CET }).expect("offsets are within a day")
+}
+
+/// A time as EFA sends it, Berlin wall-clock time, with its offset. In the
+/// hour repeated in autumn the earlier (summer) time is taken; times
+/// skipped in spring are moved past the gap.
+pub fn berlin(local: NaiveDateTime) -> DateTime<FixedOffset> {
+    let utc = [CEST, CET]
+        .into_iter()
+        .map(|offset| local - TimeDelta::seconds(offset.into()))
+        .find(|utc| berlin_offset(*utc).local_minus_utc() == (local - *utc).num_seconds() as i32)
+        .unwrap_or(local - TimeDelta::seconds(CET.into()));
+    berlin_offset(utc).from_utc_datetime(&utc)
+}
+
+/// "HH:MM" of a timestamp, the way times are shown throughout the app.
+pub fn hhmm(t: &NaiveDateTime) -> String {
+    t.format("%H:%M").to_string()
+}
+
+// Longer lists are no help; the user has to type more instead.
+const MAX_STOP_HITS: usize = 40;
+
+/// Stopfinder result that knows whether more stops match than were returned.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StopHits {
+    pub stops: Vec<StopSuggestion>,
+    /// EFA cut the list at the `anyMaxSizeHitList` asked for.
+    pub truncated: bool,
+    /// Number of hits that were asked for.
+    pub max: usize,
+}
+
+impl StopHits {
+    /// Limit to ask again with for the next chunk of hits. `None` if nothing
+    /// was cut off or the list is already as long as is useful; then only a
+    /// more specific query helps.
+    pub fn more(&self) -> Option<usize> {
+        (self.truncated && self.max < MAX_STOP_HITS).then(|| (self.max * 2).min(MAX_STOP_HITS))
+    }
+}
+
+/// Kind of place the stopfinder looks for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum LocationType {
+    Stop,
+    /// Streets and house addresses.
+    Address,
+    /// Points of interest, e.g. "ZKM (Zentrum für Kunst und Medien)".
+    Poi,
+}
+
+impl LocationType {
+    /// Bits of EFA's `anyObjFilter_sf` selecting this type.
+    pub fn filter_bits(self) -> u32 {
+        match self {
+            LocationType::Stop => 2,
+            LocationType::Address => 4 | 8,
+            LocationType::Poi => 32,
+        }
+    }
+
+    fn from_any_type(any_type: &str) -> Option<Self> {
+        match any_type {
+            "stop" => Some(LocationType::Stop),
+            "street" | "address" | "singlehouse" => Some(LocationType::Address),
+            "poi" => Some(LocationType::Poi),
+            _ => None,
+        }
+    }
+}
+
+/// One stopfinder hit.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Location {
+    Stop(StopSuggestion),
+    Address { name: String, place: Option<String>, coord: Option<Coord> },
+    Poi { name: String, place: Option<String>, coord: Option<Coord> },
+}
+
+impl Location {
+    pub fn location_type(&self) -> LocationType {
+        match self {
+            Location::Stop(_) => LocationType::Stop,
+            Location::Address { .. } => LocationType::Address,
+            Location::Poi { .. } => LocationType::Poi,
+        }
+    }
+
+    /// Name of the stop, street or place as EFA gives it; the town, if
+    /// known, is kept separately.
+    pub fn name(&self) -> &str {
+        match self {
+            Location::Stop(stop) => &stop.name,
+            Location::Address { name, .. } | Location::Poi { name, .. } => name,
+        }
+    }
+
+    pub fn into_stop(self) -> Option<StopSuggestion> {
+        match self {
+            Location::Stop(stop) => Some(stop),
+            _ => None,
+        }
+    }
+}
+
+pub fn parse_location(point: &Value) -> Option<Location> {
+    let typ = point.get("type")?.as_str()?;
+    let typ = if typ == "any" {
+        point.get("anyType")?.as_str()?
+    } else {
+        typ
+    };
+    let name = decode_text(point.get("name")?.as_str()?);
+    let reference = point.get("ref")?;
+    let place = reference
+        .get("place")
+        .and_then(|p| p.as_str())
+        .map(decode_text)
+        .filter(|p| !p.is_empty());
+    let coord = reference.get("coords").and_then(|c| c.as_str()).and_then(parse_coords);
+    Some(match LocationType::from_any_type(typ)? {
+        LocationType::Stop => {
+            let id = reference.get("id")?.as_str()?.to_string();
+            let quality = point.get("quality").and_then(|q| q.as_str()).and_then(|q| q.parse().ok());
+            let best = point.get("best").and_then(|b| b.as_str()) == Some("1");
+            Location::Stop(StopSuggestion { id, name, place, quality, best })
+        }
+        LocationType::Address => Location::Address { name, place, coord },
+        LocationType::Poi => Location::Poi { name, place, coord },
+    })
+}
+
+pub fn parse_stop_point(point: &Value) -> Option<StopSuggestion> {
+    parse_location(point)?.into_stop()
+}
+
+/// Error text EFA put into a JSON response's message list, if any.
+pub fn server_message(json: &Value) -> Option<String> {
+    let messages = json.get("stopFinder").and_then(|sf| sf.get("message")).or_else(|| json.get("message"))?;
+    messages
+        .as_array()?
+        .iter()
+        .find(|m| m.get("name").and_then(|n| n.as_str()) == Some("error"))
+        .and_then(|m| m.get("value")?.as_str())
+        .filter(|v| !v.is_empty())
+        .map(decode_text)
+}
+
+/// Hits of `types`, and how many hits there were before the others were
+/// dropped: EFA caps that number, not ours.
+pub fn parse_locations_json(body: &str, types: &[LocationType]) -> Result<(Vec<Location>, usize), EfaError> {
+    let json: Value = serde_json::from_str(body).map_err(|e| EfaError::Parse(e.to_string()))?;
+    let points = json
+        .get("stopFinder")
+        .and_then(|sf| sf.get("points"))
+        .or_else(|| json.get("stopFinder"));
+    let points: Vec<&Value> = match points {
+        Some(Value::Object(map)) => map.get("point").into_iter().collect(),
+        Some(Value::Array(arr)) => arr.iter().collect(),
+        _ => Vec::new(),
+    };
+    let hits = points.len();
+    let locations: Vec<Location> = points
+        .into_iter()
+        .filter_map(parse_location)
+        .filter(|l| types.contains(&l.location_type()))
+        .collect();
+
+    match server_message(&json) {
+        Some(msg) if locations.is_empty() => Err(EfaError::ServerMessage(msg)),
+        _ => Ok((locations, hits)),
+    }
+}
+
+pub fn parse_stopfinder_json(body: &str, max: usize) -> Result<StopHits, EfaError> {
+    let (locations, hits) = parse_locations_json(body, &[LocationType::Stop])?;
+    let mut stops: Vec<StopSuggestion> = locations.into_iter().filter_map(Location::into_stop).collect();
+    // Best match first, then by quality; equal ones keep EFA's order.
+    stops.sort_by_key(|s| (Reverse(s.best), Reverse(s.quality)));
+    Ok(StopHits { stops, truncated: max > 0 && hits >= max, max })
+}
+
+/// A stop found around a coordinate.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NearbyStop {
+    pub stop: StopSuggestion,
+    /// Straight-line distance in meters as reported by EFA.
+    pub distance_m: u32,
+    pub coord: Option<Coord>,
+}
+
+fn parse_assigned(stop: &Value) -> Option<NearbyStop> {
+    let id = stop.get("stopID")?.as_str()?.to_string();
+    let name = decode_text(stop.get("name")?.as_str()?);
+    let place = stop.get("place").and_then(|p| p.as_str()).map(decode_text).filter(|p| !p.is_empty());
+    let distance_m = stop.get("distance")?.as_str()?.parse().ok()?;
+    let coord = match (stop.get("x").and_then(|x| x.as_str()), stop.get("y").and_then(|y| y.as_str())) {
+        (Some(x), Some(y)) => parse_coords(&format!("{x},{y}")),
+        _ => None,
+    };
+    Some(NearbyStop { stop: StopSuggestion { id, name, place, ..Default::default() }, distance_m, coord })
+}
+
+pub fn parse_assigned_stop(body: &str) -> Result<Option<NearbyStop>, EfaError> {
+    let json: Value = serde_json::from_str(body).map_err(|e| EfaError::Parse(e.to_string()))?;
+    let assigned = json.get("stopFinder").and_then(|sf| sf.get("itdOdvAssignedStops"));
+    // A single assigned stop comes as an object rather than a list.
+    let stops: Vec<NearbyStop> = match assigned {
+        Some(Value::Array(stops)) => stops.iter().filter_map(parse_assigned).collect(),
+        Some(stop @ Value::Object(_)) => parse_assigned(stop).into_iter().collect(),
+        _ => match server_message(&json) {
+            Some(msg) => return Err(EfaError::ServerMessage(msg)),
+            None => Vec::new(),
+        },
+    };
+    Ok(stops.into_iter().min_by_key(|s| s.distance_m))
+}
+
+/// Coordinate from EFA's "lon,lat" notation (with `WGS84[DD.ddddd]` output).
+pub fn parse_coords(coords: &str) -> Option<Coord> {
+    let (lon, lat) = coords.split_once(',')?;
+    Some(Coord { lat: lat.trim().parse().ok()?, lon: lon.trim().parse().ok()? })
+}
+
+fn parse_coord_pin(pin: &Value) -> Option<NearbyStop> {
+    if pin.get("type").and_then(|t| t.as_str()).is_some_and(|t| t != "STOP") {
+        return None;
+    }
+    let id = pin.get("id")?.as_str()?.to_string();
+    let name = decode_text(pin.get("desc")?.as_str()?);
+    let place = pin
+        .get("locality")
+        .and_then(|p| p.as_str())
+        .map(decode_text)
+        .filter(|p| !p.is_empty());
+    // EFA sends the distance as a string, but be lenient about numbers.
+    let distance_m = match pin.get("distance")? {
+        Value::String(d) => d.parse().ok()?,
+        Value::Number(d) => d.as_f64()? as u32,
+        _ => return None,
+    };
+    let coord = pin.get("coords").and_then(|c| c.as_str()).and_then(parse_coords);
+    Some(NearbyStop { stop: StopSuggestion { id, name, place, ..Default::default() }, distance_m, coord })
+}
+
+pub fn parse_coord_json(body: &str) -> Result<Vec<NearbyStop>, EfaError> {
+    let json: Value = serde_json::from_str(body).map_err(|e| EfaError::Parse(e.to_string()))?;
+    let mut stops: Vec<NearbyStop> = match json.get("pins") {
+        Some(Value::Array(pins)) => pins.iter().filter_map(parse_coord_pin).collect(),
+        // No stops in range: EFA omits "pins" or sends null.
+        _ => match server_message(&json) {
+            Some(msg) => return Err(EfaError::ServerMessage(msg)),
+            None => Vec::new(),
+        },
+    };
+    stops.sort_by_key(|s| s.distance_m);
+    Ok(stops)
+}
+
+/// Departure (or arrival) board from an XSLT_DM_REQUEST response. Rows
+/// that had to be skipped and XML errors are reported to `warn`. Element
+/// names are matched without namespace prefix, e.g. `efa:itdDeparture`.
+/// `since` is the local time the board was asked for; it dates times that
+/// come without an `itdDate`.
+pub fn parse_departures_xml(xml: &str, since: NaiveDateTime, mut warn: impl FnMut(String)) -> Result<Vec<Departure>, EfaError> {
+    let mut reader = Reader::from_str(xml.trim_start_matches('\u{feff}'));
+
+    let mut buf = Vec::new();
+    let mut in_departure = false;
+    let mut in_datetime = false;
+    let mut in_rt_datetime = false;
+
+    let mut current_line: Option<String> = None;
+    let mut current_direction: Option<String> = None;
+    let mut current_time: Option<NaiveDateTime> = None;
+    let mut planned_time: Option<NaiveDateTime> = None;
+    let mut realtime_time: Option<NaiveDateTime> = None;
+    // Date of the itdDateTime block being read, and of the request itself
+    // as a fallback for planned times.
+    let mut block_date: Option<NaiveDate> = None;
+    let mut document_date: Option<NaiveDate> = None;
+    let mut mode = TransportMode::Other;
+    let mut platform: Option<String> = None;
+    let mut reported_delay: Option<i32> = None;
+    let mut cancelled = false;
+    let mut operator: Option<String> = None;
+    // Inside itdOperator, and in its name element.
+    let mut in_operator = false;
+    let mut in_operator_name = false;
+    let mut previous_stops: Vec<CallingPoint> = Vec::new();
+    let mut onward_stops: Vec<CallingPoint> = Vec::new();
+    // Inside itdPrevStopSeq (Some(false)) or itdOnwardStopSeq (Some(true)).
+    let mut stop_seq: Option<bool> = None;
+    let mut departures = Vec::new();
+    // Error text of an itdMessage, reported if the board turns out empty.
+    let mut in_error_message = false;
+    let mut server_message: Option<String> = None;
+    // Whether there was an itdDepartureList or itdArrivalList at all.
+    let mut has_list = false;
+
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(e)) => match e.local_name().as_ref() {
+                b"itdDepartureList" | b"itdArrivalList" => has_list = true,
+                // Arrival boards list the same rows as itdArrival.
+                b"itdDeparture" | b"itdArrival" => {
+                    in_departure = true;
+                    current_line = None;
+                    current_direction = None;
+                    current_time = None;
+                    planned_time = None;
+                    realtime_time = None;
+                    mode = TransportMode::Other;
+                    platform = parse_platform_attrs(&e);
+                    reported_delay = None;
+                    cancelled = false;
+                    operator = None;
+                    previous_stops.clear();
+                    onward_stops.clear();
+                    parse_trip_status_attrs(&e, &mut reported_delay, &mut cancelled);
+                }
+                b"itdOperator" if in_departure => in_operator = true,
+                b"name" if in_operator => in_operator_name = true,
+                b"itdPrevStopSeq" if in_departure => stop_seq = Some(false),
+                b"itdOnwardStopSeq" if in_departure => stop_seq = Some(true),
+                b"itdPoint" if stop_seq.is_some() => {
+                    let stops = if stop_seq == Some(true) { &mut onward_stops } else { &mut previous_stops };
+                    stops.extend(parse_calling_point(&e));
+                }
+                // Times of calling points are not the departure's own.
+                b"itdDateTime" if in_departure && stop_seq.is_none() && current_time.is_none() => {
+                    in_datetime = true;
+                    block_date = None;
+                }
+                b"itdRTDateTime" if in_departure && stop_seq.is_none() && realtime_time.is_none() => {
+                    in_rt_datetime = true;
+                    block_date = None;
+                }
+                b"itdDate" if in_departure && (in_datetime || in_rt_datetime) => {
+                    block_date = parse_date_attrs(&e);
+                }
+                b"itdDate" if !in_departure && document_date.is_none() => {
+                    document_date = parse_date_attrs(&e);
+                }
+                b"itdTime" if in_departure && in_datetime => {
+                    if let Some(t) = parse_datetime_attrs(&e, block_date.or(document_date), since) {
+                        planned_time = Some(t);
+                        if realtime_time.is_none() {
+                            current_time = Some(t);
+                        }
+                    }
+                }
+                b"itdTime" if in_departure && in_rt_datetime => {
+                    // An undated realtime is on the day of the planned time,
+                    // or the next: 00:03 for 23:58.
+                    if let Some(t) = parse_datetime_attrs(&e, block_date, planned_time.unwrap_or(since)) {
+                        realtime_time = Some(t);
+                        current_time = Some(t);
+                    }
+                }
+                b"itdServingLine" if in_departure => {
+                    parse_serving_line_attrs(&e, &mut current_line, &mut current_direction, &mut mode);
+                    parse_trip_status_attrs(&e, &mut reported_delay, &mut cancelled);
+                }
+                b"itdMessage" if is_error_message(&e) => {
+                    in_error_message = true;
+                    server_message = server_message.or_else(|| message_code(&e));
+                }
+                _ => {}
+            },
+            Ok(Event::Empty(e)) => match e.local_name().as_ref() {
+                b"itdDepartureList" | b"itdArrivalList" => has_list = true,
+                b"itdPoint" if stop_seq.is_some() => {
+                    let stops = if stop_seq == Some(true) { &mut onward_stops } else { &mut previous_stops };
+                    stops.extend(parse_calling_point(&e));
+                }
+                b"itdDate" if in_departure && (in_datetime || in_rt_datetime) => {
+                    block_date = parse_date_attrs(&e);
+                }
+                b"itdDate" if !in_departure && document_date.is_none() => {
+                    document_date = parse_date_attrs(&e);
+                }
+                b"itdTime" if in_departure && in_datetime => {
+                    if let Some(t) = parse_datetime_attrs(&e, block_date.or(document_date), since) {
+                        planned_time = Some(t);
+                        if realtime_time.is_none() {
+                            current_time = Some(t);
+                        }
+                    }
+                }
+                b"itdTime" if in_departure && in_rt_datetime => {
+                    // An undated realtime is on the day of the planned time,
+                    // or the next: 00:03 for 23:58.
+                    if let Some(t) = parse_datetime_attrs(&e, block_date, planned_time.unwrap_or(since)) {
+                        realtime_time = Some(t);
+                        current_time = Some(t);
+                    }
+                }
+                b"itdServingLine" if in_departure => {
+                    parse_serving_line_attrs(&e, &mut current_line, &mut current_direction, &mut mode);
+                    parse_trip_status_attrs(&e, &mut reported_delay, &mut cancelled);
+                }
+                b"itdMessage" if is_error_message(&e) => {
+                    server_message = server_message.or_else(|| message_code(&e));
+                }
+                _ => {}
+            },
+            Ok(Event::Text(t)) if in_error_message => {
+                let text = decode_text(String::from_utf8_lossy(&t).trim());
+                if !text.is_empty() {
+                    server_message = Some(text);
+                }
+            }
+            Ok(Event::Text(t)) if in_operator_name => {
+                let name = decode_text(String::from_utf8_lossy(&t).trim());
+                if !name.is_empty() {
+                    operator = Some(name);
+                }
+            }
+            Ok(Event::End(e)) => match e.local_name().as_ref() {
+                b"itdMessage" => {
+                    in_error_message = false;
+                }
+                b"itdOperator" => {
+                    in_operator = false;
+                }
+                b"name" => {
+                    in_operator_name = false;
+                }
+                b"itdDateTime" => {
+                    in_datetime = false;
+                }
+                b"itdRTDateTime" => {
+                    in_rt_datetime = false;
+                }
+                b"itdPrevStopSeq" | b"itdOnwardStopSeq" => {
+                    stop_seq = None;
+                }
+                b"itdDeparture" | b"itdArrival" => {
+                    if let (Some(line), Some(time), Some(planned)) = (
+                        current_line.take(),
+                        current_time.take(),
+                        planned_time.take(),
+                    ) {
+                        let realtime_time = realtime_time.take();
+                        let delay_minutes = reported_delay
+                            .or_else(|| realtime_time.map(|rt| (rt - planned).num_minutes() as i32));
+                        departures.push(Departure {
+                            line,
+                            direction: current_direction.take(),
+                            time,
+                            planned_time: planned,
+                            realtime_time,
+                            mode,
+                            platform: platform.take(),
+                            delay_minutes,
+                            cancelled,
+                            operator: operator.take(),
+                            previous_stops: std::mem::take(&mut previous_stops),
+                            onward_stops: std::mem::take(&mut onward_stops),
+                        });
+                    } else {
+                        warn("departure without line or time skipped".to_string());
+                    }
+                    in_departure = false;
+                }
+                _ => {}
+            },
+            Ok(Event::Eof) => break,
+            Err(e) => {
+                warn(format!("departure monitor XML: {e}"));
+                return Err(EfaError::Parse(e.to_string()));
+            }
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    match server_message {
+        Some(msg) if departures.is_empty() => Err(EfaError::ServerMessage(msg)),
+        // An empty board is fine, a response that isn't one should be noticed.
+        None if !has_list => {
+            warn("departure monitor XML without departure list".to_string());
+            Ok(departures)
+        }
+        _ => Ok(departures),
+    }
+}
+
+fn is_error_message(e: &quick_xml::events::BytesStart<'_>) -> bool {
+    e.attributes().flatten().any(|a| a.key.local_name().as_ref() == b"type" && a.value.as_ref() == b"error")
+}
+
+// "code -4050" as a fallback for messages without text.
+fn message_code(e: &quick_xml::events::BytesStart<'_>) -> Option<String> {
+    e.attributes()
+        .flatten()
+        .find(|a| a.key.local_name().as_ref() == b"code")
+        .map(|a| format!("code {}", String::from_utf8_lossy(&a.value)))
+}
+
+/// Date of an `itdDate` element.
+fn parse_date_attrs(e: &quick_xml::events::BytesStart<'_>) -> Option<NaiveDate> {
+    let (mut year, mut month, mut day) = (None, None, None);
+    for attr in e.attributes().flatten() {
+        let value = String::from_utf8_lossy(&attr.value);
+        match attr.key.local_name().as_ref() {
+            b"year" => year = value.parse().ok(),
+            b"month" => month = value.parse().ok(),
+            b"day" => day = value.parse().ok(),
+            _ => {}
+        }
+    }
+    NaiveDate::from_ymd_opt(year?, month?, day?)
+}
+
+/// Time of an `itdTime` element on `date`. Without a date it is the first
+/// such time no more than 12 hours before `near`.
+fn parse_datetime_attrs(e: &quick_xml::events::BytesStart<'_>, date: Option<NaiveDate>, near: NaiveDateTime) -> Option<NaiveDateTime> {
+    let (mut hour, mut minute) = (None, None);
+    for attr in e.attributes().flatten() {
+        let value = String::from_utf8_lossy(&attr.value);
+        match attr.key.local_name().as_ref() {
+            b"hour" => hour = value.parse().ok(),
+            b"minute" => minute = value.parse().ok(),
+            _ => {}
+        }
+    }
+    let time = NaiveTime::from_hms_opt(hour?, minute?, 0)?;
+    Some(match date {
+        Some(date) => date.and_time(time),
+        None => {
+            let at = near.date().and_time(time);
+            if at < near - TimeDelta::hours(12) { at + TimeDelta::days(1) } else { at }
+        }
+    })
+}
+
+pub fn parse_time_from_attrs(e: &quick_xml::events::BytesStart<'_>) -> Option<String> {
+    let mut hour = None;
+    let mut minute = None;
+    for attr in e.attributes().flatten() {
+        match attr.key.local_name().as_ref() {
+            b"hour" => hour = Some(String::from_utf8_lossy(&attr.value).to_string()),
+            b"minute" => minute = Some(String::from_utf8_lossy(&attr.value).to_string()),
+            _ => {}
+        }
+    }
+    if let (Some(h), Some(m)) = (hour, minute)
+        && let (Ok(hh), Ok(mm)) = (h.parse::<u8>(), m.parse::<u8>())
+    {
+        return Some(format!("{hh:02}:{mm:02}"));
+    }
+    None
+}
+
+fn parse_serving_line_attrs(
+    e: &quick_xml::events::BytesStart<'_>,
+    current_line: &mut Option<String>,
+    current_direction: &mut Option<String>,
+    mode: &mut TransportMode,
+) {
+    let mut symbol = None;
+    let mut number = None;
+    let mut direction = None;
+    for attr in e.attributes().flatten() {
+        match attr.key.local_name().as_ref() {
+            b"symbol" => symbol = Some(String::from_utf8_lossy(&attr.value).to_string()),
+            b"number" => number = Some(String::from_utf8_lossy(&attr.value).to_string()),
+            b"direction" => direction = Some(decode_text(&String::from_utf8_lossy(&attr.value))),
+            b"motType" => *mode = TransportMode::from_mot_type(&String::from_utf8_lossy(&attr.value)),
+            _ => {}
+        }
+    }
+    *current_line = symbol.or(number);
+    *current_direction = direction;
+}
+
+/// Platform of an `itdDeparture`: the display name ("Gleis 1") if present,
+/// otherwise the bare platform number.
+fn parse_platform_attrs(e: &quick_xml::events::BytesStart<'_>) -> Option<String> {
+    let mut name = None;
+    let mut number = None;
+    for attr in e.attributes().flatten() {
+        let value = decode_text(&String::from_utf8_lossy(&attr.value));
+        match attr.key.local_name().as_ref() {
+            b"platformName" if !value.is_empty() => name = Some(value),
+            b"platform" if !value.is_empty() => number = Some(value),
+            _ => {}
+        }
+    }
+    name.or(number)
+}
+
+// A stop of itdPrevStopSeq/itdOnwardStopSeq. The name without the town is
+// enough, the whole route runs through the same few places.
+fn parse_calling_point(e: &quick_xml::events::BytesStart<'_>) -> Option<CallingPoint> {
+    let mut id = None;
+    let mut name = None;
+    let mut short_name = None;
+    for attr in e.attributes().flatten() {
+        let value = decode_text(&String::from_utf8_lossy(&attr.value));
+        match attr.key.local_name().as_ref() {
+            b"stopID" if !value.is_empty() => id = Some(value),
+            b"name" if !value.is_empty() => name = Some(value),
+            b"nameWO" if !value.is_empty() => short_name = Some(value),
+            _ => {}
+        }
+    }
+    Some(CallingPoint { id: id?, name: short_name.or(name)? })
+}
+
+// EFA marks cancelled trips with this delay value.
+const CANCELLED_DELAY: &str = "-9999";
+
+/// Realtime status found on `itdDeparture` / `itdServingLine`: the reported
+/// delay (`delay="2"`) and cancellations (`delay="-9999"` or a
+/// `realtimeTripStatus` of `TRIP_CANCELLED`).
+fn parse_trip_status_attrs(e: &quick_xml::events::BytesStart<'_>, delay: &mut Option<i32>, cancelled: &mut bool) {
+    for attr in e.attributes().flatten() {
+        let value = String::from_utf8_lossy(&attr.value);
+        match attr.key.local_name().as_ref() {
+            b"delay" if value == CANCELLED_DELAY => *cancelled = true,
+            b"delay" => {
+                if let Ok(d) = value.parse() {
+                    *delay = Some(d);
+                }
+            }
+            b"realtimeTripStatus" if value.contains("CANCELLED") => *cancelled = true,
+            _ => {}
+        }
+    }
+}
+
+/// Departure (or arrival) board from an XSLT_DM_REQUEST with
+/// `outputFormat=JSON`, the same rows as `parse_departures_xml` gives.
+/// Rows that had to be skipped are reported to `warn`.
+pub fn parse_departures_json(body: &str, mut warn: impl FnMut(String)) -> Result<Vec<Departure>, EfaError> {
+    let json: Value = serde_json::from_str(body).map_err(|e| {
+        warn(format!("departure monitor JSON: {e}"));
+        EfaError::Parse(e.to_string())
+    })?;
+    // A board with a single row comes as `{"departure": {...}}`.
+    let rows = match json.get("departureList").or_else(|| json.get("arrivalList")) {
+        Some(Value::Object(map)) => json_list(map.get("departure").or_else(|| map.get("arrival"))),
+        rows => json_list(rows),
+    };
+    let mut departures = Vec::with_capacity(rows.len());
+    for row in rows {
+        match parse_departure_json(row) {
+            Some(dep) => departures.push(dep),
+            None => warn("departure without line or time skipped".to_string()),
+        }
+    }
+
+    match server_message(&json).or_else(|| server_message(json.get("dm")?)) {
+        Some(msg) if departures.is_empty() => Err(EfaError::ServerMessage(msg)),
+        _ => Ok(departures),
+    }
+}
+
+fn parse_departure_json(row: &Value) -> Option<Departure> {
+    let serving_line = row.get("servingLine")?;
+    let line = json_text(serving_line, "symbol").or_else(|| json_text(serving_line, "number"))?;
+    let planned_time = parse_json_datetime(row.get("dateTime")?)?;
+    let realtime_time = row.get("realDateTime").and_then(parse_json_datetime);
+    // As in the XML, the serving line's status wins over the row's.
+    let mut reported_delay = None;
+    let mut cancelled = false;
+    for status in [row, serving_line] {
+        match json_text(status, "delay").as_deref() {
+            Some(CANCELLED_DELAY) => cancelled = true,
+            Some(d) => reported_delay = d.parse().ok().or(reported_delay),
+            None => {}
+        }
+        if json_text(status, "realtimeTripStatus").is_some_and(|s| s.contains("CANCELLED")) {
+            cancelled = true;
+        }
+    }
+    let delay_minutes = reported_delay.or_else(|| realtime_time.map(|rt| (rt - planned_time).num_minutes() as i32));
+    let operator = row.get("operator").or_else(|| serving_line.get("operator"));
+    Some(Departure {
+        line,
+        direction: json_text(serving_line, "direction"),
+        time: realtime_time.unwrap_or(planned_time),
+        planned_time,
+        realtime_time,
+        mode: json_text(serving_line, "motType").map_or(TransportMode::Other, |m| TransportMode::from_mot_type(&m)),
+        platform: json_text(row, "platformName").or_else(|| json_text(row, "platform")),
+        delay_minutes,
+        cancelled,
+        operator: operator.and_then(|o| json_text(o, "name")),
+        previous_stops: json_list(row.get("prevStopSeq")).into_iter().filter_map(parse_calling_point_json).collect(),
+        onward_stops: json_list(row.get("onwardStopSeq")).into_iter().filter_map(parse_calling_point_json).collect(),
+    })
+}
+
+fn parse_calling_point_json(point: &Value) -> Option<CallingPoint> {
+    let id = json_text(point, "stopID").or_else(|| json_text(point.get("ref")?, "id"))?;
+    let name = json_text(point, "nameWO").or_else(|| json_text(point, "name"))?;
+    Some(CallingPoint { id, name })
+}
+
+/// `dateTime`/`realDateTime` of a JSON row: year, month, day, hour and
+/// minute as separate fields.
+fn parse_json_datetime(dt: &Value) -> Option<NaiveDateTime> {
+    let part = |key| json_text(dt, key)?.parse::<u32>().ok();
+    NaiveDate::from_ymd_opt(part("year")? as i32, part("month")?, part("day")?)?.and_hms_opt(part("hour")?, part("minute")?, 0)
+}
+
+// A non-empty text field. EFA quotes numbers, but be lenient about them.
+fn json_text(v: &Value, key: &str) -> Option<String> {
+    match v.get(key)? {
+        Value::String(s) if !s.is_empty() => Some(decode_text(s)),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+// EFA sends a single element as an object rather than a list.
+fn json_list(v: Option<&Value>) -> Vec<&Value> {
+    match v {
+        Some(Value::Array(items)) => items.iter().collect(),
+        Some(item @ Value::Object(_)) => vec![item],
+        _ => Vec::new(),
+    }
+}
+
+/// A response body as text. Its encoding is taken from a byte order mark,
+/// the charset of `content_type` or an XML declaration, in that order, and
+/// is UTF-8 otherwise; some EFA deployments answer in ISO-8859-1. The byte
+/// order mark is dropped.
+pub fn decode_body(bytes: &[u8], content_type: Option<&str>) -> String {
+    let (encoding, bom) = match detect_encoding(bytes) {
+        Some((encoding, bom)) if bom > 0 || encoding != UTF_8 => (encoding, bom),
+        _ => (content_type.and_then(charset).or_else(|| declared_encoding(bytes)).unwrap_or(UTF_8), 0),
+    };
+    encoding.decode_without_bom_handling(&bytes[bom..]).0.into_owned()
+}
+
+// Charset parameter of a Content-Type header, e.g. "text/xml; charset=ISO-8859-1".
+fn charset(content_type: &str) -> Option<&'static Encoding> {
+    content_type.split(';').skip(1).find_map(|param| {
+        let (name, value) = param.split_once('=')?;
+        if !name.trim().eq_ignore_ascii_case("charset") {
+            return None;
+        }
+        Encoding::for_label(value.trim().trim_matches('"').as_bytes())
+    })
+}
+
+// Encoding named by the `<?xml ... encoding="..."?>` declaration, if any.
+fn declared_encoding(bytes: &[u8]) -> Option<&'static Encoding> {
+    match Reader::from_reader(bytes).read_event() {
+        Ok(Event::Decl(decl)) => decl.encoder(),
+        _ => None,
+    }
+}
+
+pub fn decode_text(input: &str) -> String {
+    decode_html_entities(input).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{berlin, decode_body, parse_departures_json, parse_departures_xml, EfaError};
+    use chrono::{NaiveDate, NaiveDateTime};
+
+    // When the boards in testdata/ were asked for.
+    fn asked_at() -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 1, 15).unwrap().and_hms_opt(8, 1, 0).unwrap()
+    }
+
+    #[test]
+    fn parse_problems_go_to_the_caller() {
+        let mut warnings = Vec::new();
+        let board = parse_departures_xml(include_str!("../../testdata/departures.xml"), asked_at(), |w| warnings.push(w));
+        assert!(!board.expect("valid board").is_empty());
+        assert!(warnings.is_empty());
+
+        assert!(parse_departures_xml("<itdRequest></itdDepartureList>", asked_at(), |w| warnings.push(w)).is_err());
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with("departure monitor XML"));
+    }
+
+    #[test]
+    fn json_boards_match_the_xml_ones() {
+        let mut warnings = Vec::new();
+        let json = parse_departures_json(include_str!("../../testdata/departures.json"), |w| warnings.push(w)).expect("valid board");
+        let xml = parse_departures_xml(include_str!("../../testdata/departures.xml"), asked_at(), |w| warnings.push(w)).expect("valid board");
+        assert_eq!(json, xml);
+        assert!(warnings.is_empty());
+
+        let single = r#"{"departureList":{"departure":{"dateTime":{"year":"2024","month":"1","day":"15","hour":"8","minute":"5"},
+            "servingLine":{"symbol":"S2","motType":"1","delay":"-9999"},"onwardStopSeq":{"ref":{"id":"7000238"},"name":"Spöck"}}}}"#;
+        let board = parse_departures_json(single, |w| warnings.push(w)).expect("valid board");
+        assert!(board[0].cancelled);
+        assert_eq!(board[0].onward_stops[0].name, "Spöck");
+
+        let unknown = r#"{"dm":{"message":[{"name":"error","value":"stop invalid"}]},"departureList":null}"#;
+        assert_eq!(parse_departures_json(unknown, |w| warnings.push(w)), Err(EfaError::ServerMessage("stop invalid".into())));
+    }
+
+    #[test]
+    fn namespaced_and_latin1_boards_are_read() {
+        let fixture = include_str!("../../testdata/departures.xml");
+        let expected = parse_departures_xml(fixture, asked_at(), |w| panic!("{w}")).unwrap();
+
+        let namespaced = fixture
+            .replace("<itd", "<efa:itd")
+            .replace("</itd", "</efa:itd")
+            .replace("<efa:itdRequest ", "<efa:itdRequest xmlns:efa=\"http://www.mentz.net/efa\" ");
+        assert_eq!(parse_departures_xml(&namespaced, asked_at(), |w| panic!("{w}")).unwrap(), expected);
+
+        let latin1: Vec<u8> = fixture
+            .replacen("UTF-8", "ISO-8859-1", 1)
+            .chars()
+            .map(|c| u8::try_from(u32::from(c)).expect("Latin-1 text"))
+            .collect();
+        let body = decode_body(&latin1, None);
+        assert!(body.contains("Spöck"));
+        assert_eq!(parse_departures_xml(&body, asked_at(), |w| panic!("{w}")).unwrap(), expected);
+        let undeclared = &latin1[latin1.iter().position(|&b| b == b'\n').unwrap()..];
+        assert!(decode_body(undeclared, Some("text/xml; charset=iso-8859-1")).contains("Wörth"));
+
+        let bom = [b"\xEF\xBB\xBF".as_slice(), fixture.as_bytes()].concat();
+        assert_eq!(decode_body(&bom, Some("text/xml; charset=ISO-8859-1")), fixture);
+        assert_eq!(parse_departures_xml(&format!("\u{feff}{fixture}"), asked_at(), |w| panic!("{w}")).unwrap(), expected);
+
+        let mut warnings = Vec::new();
+        assert_eq!(parse_departures_xml("<html><body>Wartung</body></html>", asked_at(), |w| warnings.push(w)), Ok(Vec::new()));
+        assert_eq!(warnings.len(), 1);
+    }
+
+    #[test]
+    fn efa_times_are_berlin_time() {
+        let at = |m, d, h, min| NaiveDate::from_ymd_opt(2024, m, d).unwrap().and_hms_opt(h, min, 0).unwrap();
+        assert_eq!(berlin(at(1, 15, 8, 5)).to_rfc3339(), "2024-01-15T08:05:00+01:00");
+        assert_eq!(berlin(at(7, 1, 8, 5)).to_rfc3339(), "2024-07-01T08:05:00+02:00");
+        // Summer time starts on 31 March 2024 at 02:00, which becomes 03:00.
+        assert_eq!(berlin(at(3, 31, 1, 59)).to_rfc3339(), "2024-03-31T01:59:00+01:00");
+        assert_eq!(berlin(at(3, 31, 2, 30)).to_rfc3339(), "2024-03-31T03:30:00+02:00");
+        // 27 October 2024 has 02:30 twice; the first one is meant.
+        assert_eq!(berlin(at(10, 27, 2, 30)).to_rfc3339(), "2024-10-27T02:30:00+02:00");
+        assert_eq!(berlin(at(10, 27, 3, 0)).to_rfc3339(), "2024-10-27T03:00:00+01:00");
+    }
+}
@@ -4,12 +4,31 @@ fn greet(name: &str) -> String {
     format!("Hello, {}! You've been greeted from Rust!", name)
 }
 
+/// Diagnostic bundle for bug reports: app and platform info around what the
+/// frontend recorded (recent request metadata, parse warnings, panics).
+#[tauri::command]
+fn diagnostic_bundle(app: tauri::AppHandle, frontend: serde_json::Value) -> Result<String, String> {
+    let info = app.package_info();
+    let bundle = serde_json::json!({
+        "app": { "name": info.name, "version": info.version.to_string() },
+        "tauri": tauri::VERSION,
+        "platform": {
+            "os": std::env::consts::OS,
+            "family": std::env::consts::FAMILY,
+            "arch": std::env::consts::ARCH,
+        },
+        "frontend": frontend,
+    });
+    serde_json::to_string_pretty(&bundle).map_err(|e| e.to_string())
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_geolocation::init())
+        .plugin(tauri_plugin_notification::init())
         .plugin(tauri_plugin_opener::init())
-        .invoke_handler(tauri::generate_handler![greet])
+        .invoke_handler(tauri::generate_handler![greet, diagnostic_bundle])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
@@ -0,0 +1,87 @@
+use std::time::Duration;
+
+use chrono::NaiveDateTime;
+use leptos::prelude::{set_interval_with_handle, IntervalHandle};
+use leptos::task::spawn_local;
+use leptos::web_sys;
+use wasm_bindgen::JsValue;
+
+use crate::efa::{departures, Departure};
+use crate::messages::Locale;
+use crate::phrases;
+use crate::tz;
+
+/// Announcement for the next `count` departures after `now`.
+pub fn announcement(deps: &[Departure], now: NaiveDateTime, count: usize, locale: Locale) -> String {
+    deps.iter()
+        .filter(|d| !d.cancelled)
+        .filter_map(|d| {
+            let ahead = u32::try_from((d.time - now).num_minutes()).ok()?;
+            Some(phrases::departure(d, ahead, locale))
+        })
+        .take(count)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Speak `text` through the Web Speech API.
+pub fn speak(text: &str, locale: Locale) -> Result<(), JsValue> {
+    let window = web_sys::window().ok_or_else(|| JsValue::from_str("no window"))?;
+    let synth = window.speech_synthesis()?;
+    let utterance = web_sys::SpeechSynthesisUtterance::new_with_text(text)?;
+    utterance.set_lang(match locale {
+        Locale::En => "en-GB",
+        Locale::De => "de-DE",
+    });
+    synth.speak(&utterance);
+    Ok(())
+}
+
+/// Announce the next departures of `station_id` every `every` until the
+/// returned handle is cleared.
+pub fn start(station_id: String, every: Duration, locale: Locale) -> Result<IntervalHandle, JsValue> {
+    let tick = move || {
+        let station_id = station_id.clone();
+        spawn_local(async move {
+            let Ok(deps) = departures(&station_id, 5, None).await else { return };
+            let text = announcement(&deps, tz::now(), 3, locale);
+            if !text.is_empty() {
+                let _ = speak(&text, locale);
+            }
+        });
+    };
+    tick();
+    set_interval_with_handle(tick, every)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::announcement;
+    use crate::efa::Departure;
+    use crate::fixtures::{at, dep};
+    use crate::messages::Locale;
+    use crate::phrases::departure;
+
+    #[test]
+    fn sentences_use_singular_and_now() {
+        let s2 = dep("S2", "Spöck", "08:05");
+        assert_eq!(departure(&s2, 0, Locale::En), "S2 to Spöck departs now.");
+        assert_eq!(departure(&s2, 1, Locale::En), "S2 to Spöck departs in 1 minute.");
+        assert_eq!(departure(&s2, 4, Locale::De), "S2 nach Spöck fährt in 4 Minuten.");
+        assert_eq!(departure(&Departure { direction: None, ..dep("5", "", "08:05") }, 1, Locale::De), "5 fährt in 1 Minute.");
+    }
+
+    #[test]
+    fn announcement_skips_departed_and_limits_count() {
+        let deps = [
+            dep("1", "Durlach", "07:59"),
+            dep("S2", "Spöck", "08:01"),
+            dep("2", "Wolfartsweier", "08:03"),
+            dep("4", "Waldstadt", "08:09"),
+        ];
+        assert_eq!(
+            announcement(&deps, at("08:00"), 2, Locale::En),
+            "S2 to Spöck departs in 1 minute. 2 to Wolfartsweier departs in 3 minutes."
+        );
+    }
+}
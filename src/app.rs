@@ -1,22 +1,40 @@
 use leptos::task::spawn_local;
 use leptos::{ev::{SubmitEvent, MouseEvent}, prelude::*};
-use serde::{Deserialize, Serialize};
-use wasm_bindgen::prelude::*;
-use js_sys::JSON;
 use leptos::web_sys::console;
-use crate::efa::{stopfinder, StopSuggestion};
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::Duration;
+use crate::announce;
+use crate::area::AreaPanel;
+use crate::board::Board;
+use crate::chime;
+use crate::crash::{DebugPanel, ReportPanel};
+use crate::dashboards::ImportPanel;
+use crate::diagnostics::now_ms;
+use crate::disruptions;
+use crate::efa::{self, stopfinder_by_coord, stops_near, BoardFormat, Coord, EfaError, NearbyStop, RequestSlot, StopSuggestion};
+use crate::messages::{error_message, geo_error_message, Locale};
+use crate::geo::{self, Geofence, GeofenceTracker, Trigger};
+use crate::gtfs;
+use crate::gtfs_rt;
+use crate::hafas::FallbackSettings;
+use crate::onboarding::{suggest_favorites, WizardEvent, WizardStep};
+use crate::permissions::{self, Feature};
+use crate::planner::TripPlanner;
+use crate::priority::{prioritized, Priority};
+use crate::privacy::PrivacyPanel;
+use crate::provider::{stopfinder_hits, Backend};
+#[cfg(feature = "selftest")]
+use crate::selftest::SelfTestPanel;
+use crate::store::Store;
+use crate::summary::{summarize, StopSummary};
+use crate::tz::{self, TimeDisplay};
+use crate::undo::Command;
 
-#[wasm_bindgen]
-extern "C" {
-    // Use `catch` so JS exceptions (e.g. plugin errors) are returned as Err(JsValue)
-    #[wasm_bindgen(js_namespace = ["window", "__TAURI__", "core"], catch)]
-    async fn invoke(cmd: &str, args: JsValue) -> Result<JsValue, JsValue>;
-}
-
-#[derive(Serialize, Deserialize)]
-struct GreetArgs<'a> {
-    name: &'a str,
-}
+// Departures per favorite behind its status on the overview.
+const SUMMARY_DEPARTURES: usize = 5;
+// How often the GTFS-Realtime feed is fetched again.
+const REALTIME_EVERY: Duration = Duration::from_secs(60);
 
 #[component]
 pub fn App() -> impl IntoView {
@@ -26,16 +44,124 @@ pub fn App() -> impl IntoView {
     let (pos_msg, set_pos_msg) = signal(String::new());
     // Signal to hold station search results as structured entries
     let (stations, set_stations) = signal(Vec::<Station>::new());
+    // Limit for the next chunk of results when EFA cut the list short
+    let (more_stations, set_more_stations) = signal(None::<usize>);
     // Selected station
     let (selected, set_selected) = signal(None::<Station>);
+    // Stops around the current position
+    let (nearby, set_nearby) = signal(Vec::<NearbyStop>::new());
+    // Last known position, the center of the area board
+    let (position, set_position) = signal(None::<Coord>);
+    // Spoken departure announcements for the selected station (kiosk use)
+    let (announcing, set_announcing) = signal(None::<IntervalHandle>);
+    let (announce_every, set_announce_every) = signal(2u64);
+    // Chime for watched departures of the selected station
+    let (chiming, set_chiming) = signal(None::<IntervalHandle>);
+    // Position checks while geofences switch the board
+    let (fencing, set_fencing) = signal(None::<IntervalHandle>);
+    let (fence_label, set_fence_label) = signal(String::new());
+    // Persisted state: settings, the first-launch wizard, favorites, pins
+    let store = Store::provide();
+    let wizard = store.onboarding;
+    Effect::new(move |_| efa::set_api_budget(store.settings.with(|s| s.api_budget_per_hour)));
+    Effect::new(move |_| {
+        let code = store.settings.with(|s| s.language.code());
+        efa::configure(|kvv| kvv.with_language(code));
+    });
+    Effect::new(move |_| {
+        let retry = store.settings.with(|s| s.retry_policy());
+        efa::configure(|kvv| kvv.with_retry(retry));
+    });
+    Effect::new(move |_| {
+        let format = store.settings.with(|s| s.board_format);
+        efa::configure(|kvv| kvv.with_board_format(format));
+    });
+    Effect::new(move |_| tz::set_display(store.settings.with(|s| s.time_display)));
+    // The timetable is large: fetch it only when the setting itself changes
+    Effect::new(move |was: Option<bool>| {
+        let enabled = store.settings.with(|s| s.offline_timetable);
+        if was == Some(enabled) {
+            return enabled;
+        }
+        if !enabled {
+            efa::configure(|kvv| kvv.with_schedule(None));
+            return enabled;
+        }
+        spawn_local(async move {
+            match gtfs::download(gtfs::KVV_FEED_URL).await {
+                // Turned off again while downloading
+                Ok(_) if !store.settings.with_untracked(|s| s.offline_timetable) => {}
+                Ok(feed) => efa::configure(|kvv| kvv.with_schedule(Some(Rc::new(feed)))),
+                Err(e) => console::log_1(&format!("offline timetable failed: {e}").into()),
+            }
+        });
+        enabled
+    });
+    // Delays for the offline timetable, refreshed while it is on
+    let (realtime_polling, set_realtime_polling) = signal(None::<IntervalHandle>);
+    Effect::new(move |_| {
+        if let Some(handle) = realtime_polling.get_untracked() {
+            handle.clear();
+        }
+        let url = store.settings.with(|s| s.offline_timetable.then(|| s.realtime_feed_url.clone())).filter(|u| !u.is_empty());
+        let Some(url) = url else {
+            efa::configure(|kvv| kvv.with_realtime(None));
+            set_realtime_polling.set(None);
+            return;
+        };
+        let tick = move || {
+            let url = url.clone();
+            spawn_local(async move {
+                match gtfs_rt::fetch(&url).await {
+                    Ok(updates) => efa::configure(|kvv| kvv.with_realtime(Some(Rc::new(updates)).filter(|u| !u.is_empty()))),
+                    Err(e) => console::log_1(&format!("realtime feed failed: {e}").into()),
+                }
+            });
+        };
+        tick();
+        set_realtime_polling.set(set_interval_with_handle(tick, REALTIME_EVERY).map_err(|e| console::log_1(&e)).ok());
+    });
+    // An announcement makes one request per tick
+    let announce_too_often = move || {
+        efa::api_usage().would_exceed(Duration::from_secs(announce_every.get().max(1) * 60), 1)
+    };
+    // Every stop the user opens counts towards its popularity ranking
+    Effect::new(move |_| {
+        if let Some(st) = selected.get() {
+            store.popularity.update(|p| p.record(&st.id, now_ms()));
+        }
+    });
+
+    // Every favorite's status at a glance, fetched ahead of its board
+    let (summaries, set_summaries) = signal(HashMap::<String, StopSummary>::new());
+    Effect::new(move |_| {
+        let stops: Vec<String> = store.favorites.with(|f| f.stops.iter().map(|s| s.id.clone()).collect());
+        if stops.is_empty() {
+            return;
+        }
+        spawn_local(async move {
+            let (boards, notices) = prioritized(Priority::Prefetch, async {
+                futures::join!(efa::departures_batch(&stops, SUMMARY_DEPARTURES), disruptions::disruptions())
+            })
+            .await;
+            let (now, notices) = (tz::now(), notices.unwrap_or_default());
+            let summaries = stops.into_iter().zip(boards).filter_map(|(id, board)| {
+                let board = board.ok()?;
+                let disrupted = board.iter().any(|d| notices.iter().any(|n| n.affects(&d.line)));
+                Some((id, summarize(&board, now, disrupted)))
+            });
+            set_summaries.set(summaries.collect());
+        });
+    });
 
     let update_name = move |ev| {
         let v = event_target_value(&ev);
         set_name.set(v);
     };
 
-    let greet = move |ev: SubmitEvent| {
-        ev.prevent_default();
+    // Searching again cancels the search still running
+    let search_slot = StoredValue::new_local(RequestSlot::default());
+    let search = move |max: usize| {
         spawn_local(async move {
             let q = name.get_untracked();
             if q.is_empty() {
@@ -43,227 +169,343 @@ pub fn App() -> impl IntoView {
                 return;
             }
             set_greet_msg.set("Searching stations...".to_string());
-            match stopfinder(&q, 10).await {
-                Ok(list) => {
-                    if list.is_empty() {
+            set_more_stations.set(None);
+            let (backend, language) = store.settings.with_untracked(|s| (s.backend.clone(), s.language));
+            match search_slot.get_value().run(stopfinder_hits(&backend, language, &q, max)).await {
+                Ok(hits) => {
+                    if hits.stops.is_empty() {
                         set_greet_msg.set("No stations found.".to_string());
                         set_stations.set(Vec::new());
                     } else {
-                        set_greet_msg.set(format!("Found {} stations", list.len()));
-                        let formatted: Vec<Station> = list
+                        set_greet_msg.set(if hits.truncated {
+                            format!("Showing the first {} stations. More match: keep typing to narrow it down.", hits.stops.len())
+                        } else {
+                            format!("Found {} stations", hits.stops.len())
+                        });
+                        set_more_stations.set(hits.more());
+                        let mut formatted: Vec<Station> = hits.stops
                             .into_iter()
-                            .map(|s: StopSuggestion| Station { id: s.id, name: s.name, place: s.place })
+                            .map(Station::from)
                             .collect();
+                        store.popularity.with_untracked(|p| p.rank(&mut formatted, |s| &s.id, now_ms()));
                         set_stations.set(formatted);
                     }
                 }
+                Err(EfaError::Cancelled) => {}
                 Err(e) => {
-                    set_greet_msg.set(format!("Search failed: {}", e));
+                    console::log_1(&format!("stopfinder failed: {e}").into());
+                    set_greet_msg.set(error_message(&e, store.settings.get_untracked().language, None));
                     set_stations.set(Vec::new());
                 }
             }
         });
     };
 
-    let get_position = move |_: MouseEvent| {
-        spawn_local(async move {
-            // inner logic reused from below
-            let is_granted = |val: &serde_json::Value| -> bool {
-                if let Some(loc) = val.get("location").and_then(|v| v.as_str()) {
-                    return !matches!(loc, "prompt" | "prompt-with-rationale")
-                }
-                false
-            };
+    let greet = move |ev: SubmitEvent| {
+        ev.prevent_default();
+        search(10);
+    };
 
-            set_pos_msg.set("Checking permissions...".to_string());
-
-            // 1) Check permissions
-            let check_cmd = "plugin:geolocation|check_permissions";
-            let mut granted = false;
-            match invoke(check_cmd, JsValue::NULL).await {
-                Ok(jsv) => {
-                    if let Ok(val) = serde_wasm_bindgen::from_value::<serde_json::Value>(jsv) {
-                        // If the plugin explicitly asks for a rationale, show it to the user.
-                        if let Some(loc) = val.get("location").and_then(|v| v.as_str()) {
-                            if loc == "prompt-with-rationale" {
-                                set_pos_msg.set("Location permission requires a rationale: please allow location access when prompted.".to_string());
-                            }
-                        }
-                        granted = is_granted(&val);
-                    }
+    let toggle_announcements = move |_: MouseEvent| {
+        if let Some(handle) = announcing.get_untracked() {
+            handle.clear();
+            set_announcing.set(None);
+            return;
+        }
+        let Some(st) = selected.get_untracked() else { return };
+        let every = Duration::from_secs(announce_every.get_untracked().max(1) * 60);
+        match announce::start(st.id, every, store.settings.get_untracked().language) {
+            Ok(handle) => set_announcing.set(Some(handle)),
+            Err(e) => console::log_1(&e),
+        }
+    };
+
+    // (Re)start the chime check whenever the station or its watches change
+    Effect::new(move |_| {
+        if let Some(handle) = chiming.get_untracked() {
+            handle.clear();
+        }
+        let watched = selected.get().filter(|st| store.chimes.with(|c| c.watches_stop(&st.id)));
+        let handle = watched.and_then(|st| chime::start(st.id, store.chimes).map_err(|e| console::log_1(&e)).ok());
+        set_chiming.set(handle);
+    });
+    // Watch the position while geofences are on and switch to the board of
+    // each fence the user walks into
+    let tracker = StoredValue::new_local(GeofenceTracker::default());
+    Effect::new(move |_| {
+        if let Some(handle) = fencing.get_untracked() {
+            handle.clear();
+        }
+        if !store.geofences.with(|g| g.enabled && !g.fences.is_empty()) {
+            set_fencing.set(None);
+            return;
+        }
+        let tick = move || {
+            spawn_local(async move {
+                let Ok(pos) = geo::current_position().await else { return };
+                let mut t = tracker.get_value();
+                let entered = store.geofences.with_untracked(|g| t.update(&g.fences, pos).map(|f| f.stop.clone()));
+                tracker.set_value(t);
+                if let Some(stop) = entered {
+                    set_selected.set(Some(stop.into()));
                 }
-                Err(e) => {
-                    console::log_1(&e);
-                    if let Ok(sj) = JSON::stringify(&e) {
-                        console::log_1(&sj.into());
-                    }
-                    let s = js_value_to_string(&e);
-                    set_pos_msg.set(format!("check_permissions error: {}", s));
-                    return;
+            });
+        };
+        tick();
+        set_fencing.set(set_interval_with_handle(tick, Duration::from_secs(60)).map_err(|e| console::log_1(&e)).ok());
+    });
+    let add_geofence = move |_: MouseEvent| {
+        let Some(st) = selected.get_untracked() else { return };
+        let label = fence_label.get_untracked();
+        let label = if label.trim().is_empty() { st.name.clone() } else { label.trim().to_string() };
+        spawn_local(async move {
+            match geo::current_position().await {
+                Ok(pos) => {
+                    store.geofences.update(|g| g.put(Geofence::new(label, st.into(), pos)));
+                    set_fence_label.set(String::new());
                 }
+                Err(e) => set_pos_msg.set(geo_error_message(&e, store.settings.get_untracked().language)),
             }
+        });
+    };
 
-            // 2) If not granted, request permissions
-            if !granted {
-                set_pos_msg.set("Requesting permissions...".to_string());
-                match invoke("plugin:geolocation|request_permissions", JsValue::NULL).await {
-                    Ok(jsv) => {
-                        if let Ok(val) = serde_wasm_bindgen::from_value::<serde_json::Value>(jsv) {
-                            granted = is_granted(&val);
-                        } else {
-                            set_pos_msg.set("Permission request response could not be parsed; aborting.".to_string());
-                            return;
-                        }
-                    }
-                    Err(e) => {
-                        console::log_1(&e);
-                        if let Ok(sj) = JSON::stringify(&e) {
-                            console::log_1(&sj.into());
-                        }
-                        let s = js_value_to_string(&e);
-                        set_pos_msg.set(format!("request_permissions error: {}", s));
-                        return;
-                    }
+    let board_chime = move || {
+        let st = selected.get()?;
+        store.chimes.with(|c| c.get(&st.id, None).map(|w| w.minutes))
+    };
+    let set_board_chime = move |minutes: Option<u32>| {
+        if let Some(st) = selected.get_untracked() {
+            store.chimes.update(|c| c.set(&st.id, None, minutes));
+        }
+    };
+
+    // Resolve a position into the nearest stops
+    let load_nearby = move |lat: f64, lon: f64| {
+        spawn_local(async move {
+            match stops_near(lat, lon, 600, 5).await {
+                Ok(list) => set_nearby.set(list),
+                Err(e) => {
+                    console::log_1(&format!("stops_near failed: {e}").into());
+                    set_pos_msg.set(error_message(&e, store.settings.get_untracked().language, None));
                 }
             }
+        });
+    };
 
-            if !granted {
-                set_pos_msg.set("Permissions not granted.".to_string());
-                return;
-            }
+    // Permission states, for hints next to features that can't be used yet
+    let (grants, set_grants) = signal(None::<permissions::Grants>);
+    let refresh_grants = move || spawn_local(async move { set_grants.set(Some(permissions::grants().await)) });
+    let nearby_hint = move || {
+        grants.get().and_then(|g| permissions::affordance(permissions::access(Feature::Nearby, &g), store.settings.with(|s| s.language)))
+    };
 
-            // 3) Now request the current position
-            set_pos_msg.set("Getting current position...".to_string());
-            match invoke("plugin:geolocation|get_current_position", JsValue::NULL).await {
-                Ok(jsv) => {
-                    match serde_wasm_bindgen::from_value::<serde_json::Value>(jsv) {
-                        Ok(val) => {
-                            if let Some(coords) = val.get("coords") {
-                                if let (Some(lon), Some(lat)) = (coords.get("longitude").and_then(|v| v.as_f64()), coords.get("latitude").and_then(|v| v.as_f64())) {
-                                    set_pos_msg.set(format!("Current position: longitude {}, latitude {}", lon, lat));
-                                    return;
-                                }
-                            }
-                            set_pos_msg.set(format!("Invalid value received: {val}"));
-                        }
-                        Err(e) => {
-                            set_pos_msg.set(format!("Error parsing position: {}", e));
-                        }
-                    }
+    // Look up nearby stops if the location policy allows it for `trigger`
+    let locate = move |trigger: Trigger| {
+        spawn_local(async move {
+            match geo::locate(trigger, store.settings.get_untracked().auto_nearby_on_launch).await {
+                Ok(Some(pos)) => {
+                    set_pos_msg.set(format!("Current position: longitude {}, latitude {}", pos.lon, pos.lat));
+                    set_position.set(Some(pos));
+                    load_nearby(pos.lat, pos.lon);
                 }
+                Ok(None) => {}
                 Err(e) => {
-                    console::log_1(&e);
-                    if let Ok(sj) = JSON::stringify(&e) {
-                        console::log_1(&sj.into());
-                    }
-                    let s = js_value_to_string(&e);
-                    set_pos_msg.set(format!("get_current_position error: {}", s));
+                    console::log_1(&format!("locate failed: {e:?}").into());
+                    set_pos_msg.set(geo_error_message(&e, store.settings.get_untracked().language));
                 }
             }
+            refresh_grants();
         });
     };
 
-    // Load position once on app startup (component mount)
-    {
-        let set_pos_msg_start = set_pos_msg.clone();
+    // Open the board of the stop EFA assigns to the current position
+    let depart_here = move || {
         spawn_local(async move {
-            // replicate the same flow as above but using the cloned setter
-            let is_granted = |val: &serde_json::Value| -> bool {
-                if let Some(loc) = val.get("location").and_then(|v| v.as_str()) {
-                    return !matches!(loc, "prompt" | "prompt-with-rationale")
-                }
-                false
-            };
-
-            set_pos_msg_start.set("Checking permissions...".to_string());
-
-            // 1) Check permissions
-            let check_cmd = "plugin:geolocation|check_permissions";
-            let mut granted = false;
-            match invoke(check_cmd, JsValue::NULL).await {
-                Ok(jsv) => {
-                    if let Ok(val) = serde_wasm_bindgen::from_value::<serde_json::Value>(jsv) {
-                        if let Some(loc) = val.get("location").and_then(|v| v.as_str()) {
-                            if loc == "prompt-with-rationale" {
-                                set_pos_msg_start.set("Location permission requires a rationale: please allow location access when prompted.".to_string());
-                            }
-                        }
-                        granted = is_granted(&val);
-                    }
-                }
-                Err(e) => {
-                    console::log_1(&e);
-                    if let Ok(sj) = JSON::stringify(&e) {
-                        console::log_1(&sj.into());
+            match geo::locate(Trigger::Feature, store.settings.get_untracked().auto_nearby_on_launch).await {
+                Ok(Some(pos)) => {
+                    set_position.set(Some(pos));
+                    match stopfinder_by_coord(pos.lat, pos.lon).await {
+                        Ok(Some(nearest)) => set_selected.set(Some(Station::from(nearest.stop))),
+                        Ok(None) => set_pos_msg.set("No stop found at your position.".to_string()),
+                        Err(e) => set_pos_msg.set(error_message(&e, store.settings.get_untracked().language, None)),
                     }
-                    let s = js_value_to_string(&e);
-                    set_pos_msg_start.set(format!("check_permissions error: {}", s));
-                    return;
                 }
+                Ok(None) => {}
+                Err(e) => set_pos_msg.set(geo_error_message(&e, store.settings.get_untracked().language)),
             }
+            refresh_grants();
+        });
+    };
 
-            // 2) If not granted, request permissions
-            if !granted {
-                set_pos_msg_start.set("Requesting permissions...".to_string());
-                match invoke("plugin:geolocation|request_permissions", JsValue::NULL).await {
-                    Ok(jsv) => {
-                        if let Ok(val) = serde_wasm_bindgen::from_value::<serde_json::Value>(jsv) {
-                            granted = is_granted(&val);
-                        } else {
-                            set_pos_msg_start.set("Permission request response could not be parsed; aborting.".to_string());
-                            return;
-                        }
-                    }
-                    Err(e) => {
-                        console::log_1(&e);
-                        if let Ok(sj) = JSON::stringify(&e) {
-                            console::log_1(&sj.into());
-                        }
-                        let s = js_value_to_string(&e);
-                        set_pos_msg_start.set(format!("request_permissions error: {}", s));
-                        return;
-                    }
+    let toggle_auto_nearby = move |ev| {
+        store.settings.update(|s| s.auto_nearby_on_launch = event_target_checked(&ev));
+    };
+
+    // Snackbar after destructive actions, offering to undo (or redo after an undo)
+    let (snackbar, set_snackbar) = signal(None::<(String, bool)>);
+    let (snackbar_seq, set_snackbar_seq) = signal(0u32);
+    let show_snackbar = move |text: String, redo: bool| {
+        let seq = snackbar_seq.get_untracked() + 1;
+        set_snackbar_seq.set(seq);
+        set_snackbar.set(Some((text, redo)));
+        set_timeout(
+            move || {
+                // Only hide if no newer message replaced this one
+                if snackbar_seq.get_untracked() == seq {
+                    set_snackbar.set(None);
                 }
+            },
+            Duration::from_secs(6),
+        );
+    };
+
+    let remove_favorite = move |stop_id: &str| {
+        if let Some(command) = store.favorites.with_untracked(|f| Command::remove_favorite(f, stop_id)) {
+            show_snackbar(command.label(), false);
+            store.execute(command);
+        }
+    };
+
+    let toggle_favorite = move |_: MouseEvent| {
+        let Some(st) = selected.get_untracked() else { return };
+        if store.favorites.with_untracked(|f| f.contains(&st.id)) {
+            remove_favorite(&st.id);
+        } else {
+            store.favorites.update(|f| f.add(st.into()));
+        }
+    };
+
+    let undo_or_redo = move |_: MouseEvent| {
+        let redo = snackbar.get_untracked().is_some_and(|(_, redo)| redo) && store.undo.with_untracked(|u| u.can_redo());
+        if redo {
+            if let Some(command) = store.redo() {
+                show_snackbar(command.label(), false);
             }
+        } else if let Some(command) = store.undo() {
+            show_snackbar(command.undone_label(), true);
+        }
+    };
 
-            if !granted {
-                set_pos_msg_start.set("Permissions not granted.".to_string());
-                return;
+    let advance = move |event: WizardEvent| {
+        if let WizardEvent::HomeStopChosen(stop) = &event {
+            store.favorites.update(|f| f.add(stop.clone()));
+            set_selected.set(Some(stop.clone().into()));
+        }
+        wizard.update(|w| w.advance(event));
+    };
+
+    // First-launch favorite suggestions, kept in memory until the user confirms
+    let (suggested, set_suggested) = signal(Vec::<StopSuggestion>::new());
+    Effect::new(move |_| {
+        let Some(pos) = position.get() else { return };
+        if wizard.with_untracked(|w| w.is_done()) {
+            return;
+        }
+        spawn_local(async move {
+            match suggest_favorites(pos.lat, pos.lon).await {
+                Ok(stops) => set_suggested.set(stops),
+                Err(e) => console::log_1(&format!("suggest_favorites failed: {e}").into()),
             }
+        });
+    });
+    let keep_suggested = move |_: MouseEvent| {
+        let stops = suggested.get_untracked();
+        store.favorites.update(|f| stops.into_iter().for_each(|stop| f.add(stop)));
+        set_suggested.set(Vec::new());
+    };
 
-            // 3) Now request the current position
-            set_pos_msg_start.set("Getting current position...".to_string());
-            match invoke("plugin:geolocation|get_current_position", JsValue::NULL).await {
-                Ok(jsv) => {
-                    match serde_wasm_bindgen::from_value::<serde_json::Value>(jsv) {
-                        Ok(val) => {
-                            if let Some(coords) = val.get("coords") {
-                                if let (Some(lon), Some(lat)) = (coords.get("longitude").and_then(|v| v.as_f64()), coords.get("latitude").and_then(|v| v.as_f64())) {
-                                    set_pos_msg_start.set(format!("Current position: longitude {}, latitude {}", lon, lat));
-                                    return;
-                                }
-                            }
-                            set_pos_msg_start.set(format!("Invalid value received: {val}"));
-                        }
-                        Err(e) => {
-                            set_pos_msg_start.set(format!("Error parsing position: {}", e));
-                        }
-                    }
-                }
-                Err(e) => {
-                    console::log_1(&e);
-                    if let Ok(sj) = JSON::stringify(&e) {
-                        console::log_1(&sj.into());
-                    }
-                    let s = js_value_to_string(&e);
-                    set_pos_msg_start.set(format!("get_current_position error: {}", s));
-                }
+    let allow_location = move |_: MouseEvent| {
+        spawn_local(async move {
+            let granted = permissions::ensure(Feature::Nearby).await;
+            advance(WizardEvent::Answered(granted));
+            if granted {
+                // Nearby stops are offered on the home stop step
+                locate(Trigger::Feature);
+            } else {
+                refresh_grants();
             }
         });
+    };
+
+    let allow_notifications = move |_: MouseEvent| {
+        spawn_local(async move {
+            advance(WizardEvent::Answered(permissions::ensure(Feature::DelayAlerts).await));
+        });
+    };
+
+    // Startup never prompts; the policy only locates if the user opted in
+    locate(Trigger::Launch);
+    if let Some(home) = wizard.get_untracked().home_stop {
+        set_selected.set(Some(home.into()));
     }
 
     view! {
         <main class="container">
+            <Show when=move || !wizard.get().is_done()>
+                <div class="wizard">
+                    { move || match wizard.get().step {
+                        WizardStep::Welcome => view! {
+                            <h2>"Welcome to KVV"</h2>
+                            <p>"Live departures for Karlsruhe, right where you are. Two quick questions before we start."</p>
+                            <button on:click=move |_| advance(WizardEvent::Next)>"Get started"</button>
+                        }.into_any(),
+                        WizardStep::Location => view! {
+                            <h2>"Stops near you"</h2>
+                            <p>"Your location is only used to look up the stops around you. It is not stored."</p>
+                            <div class="row">
+                                <button on:click=move |_| advance(WizardEvent::Back)>"Back"</button>
+                                <button on:click=move |_| advance(WizardEvent::Next)>"Not now"</button>
+                                <button on:click=allow_location>"Allow location"</button>
+                            </div>
+                        }.into_any(),
+                        WizardStep::Notifications => view! {
+                            <h2>"Delay alerts"</h2>
+                            <p>"Get notified when your connections are delayed or cancelled. You can turn this off any time."</p>
+                            <div class="row">
+                                <button on:click=move |_| advance(WizardEvent::Back)>"Back"</button>
+                                <button on:click=move |_| advance(WizardEvent::Next)>"Not now"</button>
+                                <button on:click=allow_notifications>"Allow notifications"</button>
+                            </div>
+                        }.into_any(),
+                        WizardStep::HomeStop => view! {
+                            <h2>"Your home stop"</h2>
+                            <p>"Its departures are shown whenever you open the app."</p>
+                            <Show when=move || !suggested.get().is_empty()>
+                                <div class="suggested">
+                                    <p>
+                                        "Suggested favorites: "
+                                        { move || suggested.get().into_iter().map(|s| s.name).collect::<Vec<_>>().join(", ") }
+                                    </p>
+                                    <div class="row">
+                                        <button on:click=move |_| set_suggested.set(Vec::new())>"No thanks"</button>
+                                        <button on:click=keep_suggested>"Add to favorites"</button>
+                                    </div>
+                                </div>
+                            </Show>
+                            <form class="row" on:submit=greet>
+                                <input placeholder="Station name..." on:input=update_name />
+                                <button type="submit">"Search"</button>
+                            </form>
+                            <ul>
+                                { move || nearby.get().into_iter().map(|n| n.stop)
+                                    .chain(stations.get().into_iter().map(StopSuggestion::from))
+                                    .map(|stop| {
+                                        let display = match &stop.place {
+                                            Some(p) if !p.is_empty() => format!("{} ({})", stop.name, p),
+                                            _ => stop.name.clone(),
+                                        };
+                                        view! { <li on:click=move |_: MouseEvent| advance(WizardEvent::HomeStopChosen(stop.clone()))>{ display }</li> }
+                                    }).collect::<Vec<_>>() }
+                            </ul>
+                            <div class="row">
+                                <button on:click=move |_| advance(WizardEvent::Back)>"Back"</button>
+                                <button on:click=move |_| advance(WizardEvent::Next)>"Skip"</button>
+                            </div>
+                        }.into_any(),
+                        WizardStep::Done => ().into_any(),
+                    } }
+                </div>
+            </Show>
 
             // search bar pinned at top; logos removed
             <div class="search-bar">
@@ -278,9 +520,11 @@ pub fn App() -> impl IntoView {
             </div>
 
             <p>{ move || greet_msg.get() }</p>
+            { move || more_stations.get().map(|max| view! {
+                <button on:click=move |_: MouseEvent| search(max)>"Show more"</button>
+            }) }
             <ul>
                 { move || {
-                    let set_selected = set_selected.clone();
                     stations.get().iter().map(|s| {
                         let s = s.clone();
                         let display = if let Some(p) = &s.place {
@@ -288,37 +532,461 @@ pub fn App() -> impl IntoView {
                         } else {
                             format!("{} — {}", s.name, s.id)
                         };
-                        view! { <li on:click=move |_: MouseEvent| { set_selected.set(Some(s.clone())); }>{ display }</li> }
+                        view! { <li on:click=move |_: MouseEvent| {
+                            if let Some(handle) = announcing.get_untracked() {
+                                handle.clear();
+                                set_announcing.set(None);
+                            }
+                            set_selected.set(Some(s.clone()));
+                        }>{ display }</li> }
                     }).collect::<Vec<_>>()
                 } }
             </ul>
             <p>
                 { move || selected.get().map(|st| format!("Selected: {} ({})", st.name, st.id)).unwrap_or_default() }
             </p>
+            <Show when=move || selected.get().is_some()>
+                <button on:click=toggle_favorite>
+                    { move || {
+                        let is_favorite = selected.get().is_some_and(|st| store.favorites.with(|f| f.contains(&st.id)));
+                        if is_favorite { "★ Favorite" } else { "☆ Add to favorites" }
+                    } }
+                </button>
+                <div class="row announce">
+                    <label>
+                        "Announce every "
+                        <input
+                            type="number"
+                            min="1"
+                            prop:value=move || announce_every.get().to_string()
+                            on:input=move |ev| {
+                                if let Ok(v) = event_target_value(&ev).parse::<u64>() {
+                                    set_announce_every.set(v);
+                                }
+                            }
+                        />
+                        " min"
+                    </label>
+                    <button on:click=toggle_announcements>
+                        { move || if announcing.get().is_some() { "Stop announcements" } else { "Start announcements" } }
+                    </button>
+                </div>
+                <Show when=announce_too_often>
+                    <p class="warning">"Announcing this often exceeds the hourly request budget. The timetable service may block this device."</p>
+                </Show>
+                <div class="row chime">
+                    <label>
+                        <input
+                            type="checkbox"
+                            prop:checked=move || board_chime().is_some()
+                            on:change=move |ev| set_board_chime(event_target_checked(&ev).then(|| board_chime().unwrap_or(2)))
+                        />
+                        " Chime "
+                    </label>
+                    <input
+                        type="number"
+                        min="0"
+                        prop:value=move || board_chime().unwrap_or(2).to_string()
+                        on:input=move |ev| {
+                            let enabled = board_chime().is_some();
+                            if let (true, Ok(v)) = (enabled, event_target_value(&ev).parse::<u32>()) {
+                                set_board_chime(Some(v));
+                            }
+                        }
+                    />
+                    " min before every departure"
+                </div>
+            </Show>
+            { move || selected.get().map(|st| view! { <Board stop=st.into()/> }) }
+            <div class="row geofences">
+                <label>
+                    <input
+                        type="checkbox"
+                        prop:checked=move || store.geofences.get().enabled
+                        on:change=move |ev| store.geofences.update(|g| g.enabled = event_target_checked(&ev))
+                    />
+                    " Switch boards by location"
+                </label>
+                <Show when=move || selected.get().is_some()>
+                    <input
+                        placeholder="Home, Work, …"
+                        prop:value=move || fence_label.get()
+                        on:input=move |ev| set_fence_label.set(event_target_value(&ev))
+                    />
+                    <button on:click=add_geofence>"Show this board here"</button>
+                </Show>
+            </div>
+            <Show when=move || store.geofences.with(|g| !g.fences.is_empty())>
+                <ul class="geofences">
+                    { move || store.geofences.get().fences.into_iter().map(|fence| {
+                        let display = format!("{}: {}", fence.label, fence.stop.name);
+                        view! {
+                            <li>
+                                { display }
+                                <button class="remove" aria-label="Remove geofence" on:click=move |_: MouseEvent| store.geofences.update(|g| g.remove(&fence.label))>"✕"</button>
+                            </li>
+                        }
+                    }).collect::<Vec<_>>() }
+                </ul>
+            </Show>
+            <div class="row nearby">
+                <button on:click=move |_| locate(Trigger::Feature)>"Stops near me"</button>
+                <button on:click=move |_| depart_here()>"Departures from here"</button>
+                <label>
+                    <input
+                        type="checkbox"
+                        prop:checked=move || store.settings.get().auto_nearby_on_launch
+                        on:change=toggle_auto_nearby
+                    />
+                    " Show on launch"
+                </label>
+            </div>
+            { move || nearby_hint().map(|hint| view! { <p class="hint">{ hint }</p> }) }
+            <div class="row filter">
+                <label>
+                    <input
+                        type="checkbox"
+                        prop:checked=move || store.operator_filter.get().hide_replacement
+                        on:change=move |ev| store.operator_filter.update(|f| f.hide_replacement = event_target_checked(&ev))
+                    />
+                    " Hide rail replacement buses"
+                </label>
+            </div>
+            <div class="row language">
+                <label>
+                    "Timetable language "
+                    <select on:change=move |ev| {
+                        let language = if event_target_value(&ev) == "de" { Locale::De } else { Locale::En };
+                        store.settings.update(|s| s.language = language);
+                    }>
+                        <option value="en" selected=move || store.settings.get().language == Locale::En>"English"</option>
+                        <option value="de" selected=move || store.settings.get().language == Locale::De>"Deutsch"</option>
+                    </select>
+                </label>
+            </div>
+            <div class="row time-zone">
+                <label>
+                    <input
+                        type="checkbox"
+                        prop:checked=move || store.settings.get().time_display == TimeDisplay::Device
+                        on:change=move |ev| {
+                            let display = if event_target_checked(&ev) { TimeDisplay::Device } else { TimeDisplay::Berlin };
+                            store.settings.update(|s| s.time_display = display);
+                        }
+                    />
+                    " Show times in this device's time zone"
+                </label>
+            </div>
+            <div class="row accessibility">
+                <label>
+                    <input
+                        type="checkbox"
+                        prop:checked=move || store.settings.get().delay_shapes
+                        on:change=move |ev| store.settings.update(|s| s.delay_shapes = event_target_checked(&ev))
+                    />
+                    " Mark delays with shapes, not only colors"
+                </label>
+            </div>
+            <div class="row punctuality">
+                <label>
+                    <input
+                        type="checkbox"
+                        prop:checked=move || store.settings.get().punctuality_hints
+                        on:change=move |ev| store.settings.update(|s| s.punctuality_hints = event_target_checked(&ev))
+                    />
+                    " Remember delays on this device to point out lines that are usually late"
+                </label>
+            </div>
+            <Show when=move || tz::device_differs() && store.settings.get().time_display == TimeDisplay::Berlin>
+                <p class="hint">"Times are local time in Karlsruhe, which differs from this device's clock."</p>
+            </Show>
+            <div class="row privacy">
+                <label>
+                    <input
+                        type="checkbox"
+                        prop:checked=move || store.settings.get().nominatim_fallback
+                        on:change=move |ev| store.settings.update(|s| s.nominatim_fallback = event_target_checked(&ev))
+                    />
+                    " Look up unknown addresses with OpenStreetMap (sends them to nominatim.openstreetmap.org)"
+                </label>
+            </div>
+            <div class="row format">
+                <label>
+                    <input
+                        type="checkbox"
+                        prop:checked=move || store.settings.get().board_format == BoardFormat::Json
+                        on:change=move |ev| store.settings.update(|s| {
+                            s.board_format = if event_target_checked(&ev) { BoardFormat::Json } else { BoardFormat::Xml };
+                        })
+                    />
+                    " Fetch boards as JSON (smaller responses)"
+                </label>
+            </div>
+            <div class="row network">
+                <label>
+                    "Wait for KVV's server "
+                    <input type="number" min="1" prop:value=move || store.settings.get().request_timeout_secs.to_string()
+                        on:input=move |ev| if let Ok(v) = event_target_value(&ev).parse() { store.settings.update(|s| s.request_timeout_secs = v) } />
+                    " s, try "
+                    <input type="number" min="1" prop:value=move || store.settings.get().request_attempts.to_string()
+                        on:input=move |ev| if let Ok(v) = event_target_value(&ev).parse() { store.settings.update(|s| s.request_attempts = v) } />
+                    " times"
+                </label>
+            </div>
+            <div class="row offline">
+                <label>
+                    <input
+                        type="checkbox"
+                        prop:checked=move || store.settings.get().offline_timetable
+                        on:change=move |ev| store.settings.update(|s| s.offline_timetable = event_target_checked(&ev))
+                    />
+                    " Show the timetable when the KVV server can't be reached"
+                </label>
+            </div>
+            <Show when=move || store.settings.get().offline_timetable>
+                <p class="hint">"Downloads KVV's timetable once and again when it changes. Without a connection, boards show planned times only."</p>
+                <label>
+                    "Delays from a GTFS-Realtime feed "
+                    <input placeholder="TripUpdates URL"
+                        prop:value=move || store.settings.get().realtime_feed_url
+                        on:change=move |ev| store.settings.update(|s| s.realtime_feed_url = event_target_value(&ev).trim().to_string()) />
+                </label>
+            </Show>
+            <div class="row backend">
+                <label>
+                    "Departures from "
+                    <select on:change=move |ev| {
+                        let backend = match event_target_value(&ev).as_str() {
+                            "trias" => Backend::Trias { url: String::new(), requestor_ref: String::new() },
+                            "simulation" => Backend::Simulation,
+                            _ => Backend::Efa,
+                        };
+                        store.settings.update(|s| s.backend = backend);
+                    }>
+                        <option value="efa" selected=move || store.settings.get().backend == Backend::Efa>"KVV"</option>
+                        <option value="trias" selected=move || matches!(store.settings.get().backend, Backend::Trias { .. })>"A TRIAS interface"</option>
+                        <option value="simulation" selected=move || store.settings.get().backend == Backend::Simulation>"Simulation (made-up data)"</option>
+                    </select>
+                </label>
+            </div>
+            <Show when=move || matches!(store.settings.get().backend, Backend::Trias { .. })>
+                <div class="row backend">
+                    <input placeholder="TRIAS endpoint URL"
+                        prop:value=move || match store.settings.get().backend { Backend::Trias { url, .. } => url, _ => String::new() }
+                        on:change=move |ev| store.settings.update(|s| if let Backend::Trias { url, .. } = &mut s.backend { *url = event_target_value(&ev).trim().to_string() }) />
+                    <input placeholder="Access key (RequestorRef)"
+                        prop:value=move || match store.settings.get().backend { Backend::Trias { requestor_ref, .. } => requestor_ref, _ => String::new() }
+                        on:change=move |ev| store.settings.update(|s| if let Backend::Trias { requestor_ref, .. } = &mut s.backend { *requestor_ref = event_target_value(&ev).trim().to_string() }) />
+                </div>
+                <p class="hint">"Stop ids differ between backends: favorites saved with another one won't show boards."</p>
+            </Show>
+            <Show when=move || store.settings.get().backend == Backend::Efa>
+                <div class="row fallback"><FallbackSettings/></div>
+            </Show>
             <pre>{ move || pos_msg.get() }</pre>
+            <Show when=move || store.favorites.with(|f| !f.stops.is_empty())>
+                <h3>"Favorites"</h3>
+                <ul>
+                    { move || {
+                        let mut stops = store.favorites.get().stops;
+                        store.popularity.with(|p| p.rank(&mut stops, |s| &s.id, now_ms()));
+                        stops
+                    }.into_iter().map(|stop| {
+                        let display = stop.name.clone();
+                        let id = stop.id.clone();
+                        let summary = summaries.with(|s| s.get(&id).cloned());
+                        view! {
+                            <li>
+                                { summary.as_ref().map(|s| view! { <span class=format!("light {}", s.light.class()) aria-hidden="true">"●"</span> }) }
+                                <span on:click=move |_: MouseEvent| { set_selected.set(Some(stop.clone().into())); }>{ display }</span>
+                                { summary.map(|s| view! { <span class="hint">{ format!(" {}", s.label()) }</span> }) }
+                                <button class="remove" aria-label="Remove favorite" on:click=move |_: MouseEvent| remove_favorite(&id)>"✕"</button>
+                            </li>
+                        }
+                    }).collect::<Vec<_>>() }
+                </ul>
+            </Show>
+            <Show when=move || !nearby.get().is_empty()>
+                <h3>"Nearby stops"</h3>
+                <ul>
+                    { move || nearby.get().into_iter().map(|n| {
+                        let station = Station::from(n.stop.clone());
+                        let display = match &n.stop.place {
+                            Some(p) => format!("{} ({}) — {} m", n.stop.name, p, n.distance_m),
+                            None => format!("{} — {} m", n.stop.name, n.distance_m),
+                        };
+                        view! { <li on:click=move |_: MouseEvent| { set_selected.set(Some(station.clone())); }>{ display }</li> }
+                    }).collect::<Vec<_>>() }
+                </ul>
+            </Show>
+            <LazyDetails summary="Plan a trip"><TripPlanner/></LazyDetails>
+            <LazyDetails summary="Leaving around here"><AreaPanel center=position/></LazyDetails>
+            <LazyDetails summary="Import stops for a dashboard"><ImportPanel/></LazyDetails>
+            <LazyDetails summary="Privacy"><PrivacyPanel/></LazyDetails>
+            <LazyDetails summary="Report a problem"><ReportPanel/></LazyDetails>
+            <Show when=debug_screen>
+                <LazyDetails summary="Debug"><DebugPanel/></LazyDetails>
+            </Show>
+            { self_test() }
+            <Show when=move || snackbar.get().is_some()>
+                <div class="snackbar" role="status">
+                    <span>{ move || snackbar.get().map(|(text, _)| text).unwrap_or_default() }</span>
+                    <button on:click=undo_or_redo>
+                        { move || if snackbar.get().is_some_and(|(_, redo)| redo) && store.undo.with(|u| u.can_redo()) { "Redo" } else { "Undo" } }
+                    </button>
+                </div>
+            </Show>
         </main>
     }
 }
 
-// Convert a JsValue (string/number/object) into a readable String
-fn js_value_to_string(v: &JsValue) -> String {
-    if v.is_string() {
-        return v.as_string().unwrap_or_default();
-    }
-    if v.as_f64().is_some() {
-        if let Some(n) = v.as_f64() {
-            return n.to_string();
-        }
-    }
-    match JSON::stringify(v) {
-        Ok(s) => s.as_string().unwrap_or_else(|| format!("{:?}", v)),
-        Err(_) => v.as_string().unwrap_or_else(|| format!("{:?}", v)),
+/// `<details>` whose content is only built the first time it is opened, so
+/// rarely used panels cost nothing while the board loads.
+#[component]
+fn LazyDetails(summary: &'static str, children: ChildrenFn) -> impl IntoView {
+    let (opened, set_opened) = signal(false);
+    view! {
+        <details class="problem" on:toggle=move |_| set_opened.set(true)>
+            <summary>{ summary }</summary>
+            { move || opened.get().then(|| children()) }
+        </details>
     }
 }
 
+// The debug screen stays hidden unless the address ends in "#debug".
+fn debug_screen() -> bool {
+    let hash = leptos::web_sys::window().and_then(|w| w.location().hash().ok());
+    hash.as_deref() == Some("#debug")
+}
+
+// Left out of slim web builds, see the `selftest` feature.
+#[cfg(feature = "selftest")]
+fn self_test() -> impl IntoView {
+    view! { <LazyDetails summary="Self-test"><SelfTestPanel/></LazyDetails> }
+}
+
+#[cfg(not(feature = "selftest"))]
+fn self_test() -> impl IntoView {}
+
 #[derive(Clone)]
 struct Station {
     id: String,
     name: String,
     place: Option<String>,
 }
+
+impl From<StopSuggestion> for Station {
+    fn from(s: StopSuggestion) -> Self {
+        Station { id: s.id, name: s.name, place: s.place }
+    }
+}
+
+impl From<Station> for StopSuggestion {
+    fn from(s: Station) -> Self {
+        StopSuggestion { id: s.id, name: s.name, place: s.place, ..Default::default() }
+    }
+}
+
+/// The app in a real browser, with EFA answered from the test fixtures:
+/// `wasm-pack test --headless --firefox`.
+#[cfg(all(test, target_arch = "wasm32"))]
+mod browser_tests {
+    use super::App;
+    use crate::efa::{self, EfaClient, EfaError, Transport, TransportFuture};
+    use leptos::mount::mount_to;
+    use leptos::web_sys::{self, Event, EventInit, HtmlElement, HtmlInputElement};
+    use std::time::Duration;
+    use wasm_bindgen::JsCast;
+    use wasm_bindgen_test::{wasm_bindgen_test, wasm_bindgen_test_configure};
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    /// Mock EFA: answers each endpoint with its fixture.
+    struct Fixtures;
+
+    impl Transport for Fixtures {
+        fn get<'a>(&'a self, url: &'a str, _timeout: Duration) -> TransportFuture<'a> {
+            let body = if url.contains("XML_STOPFINDER_REQUEST") {
+                Ok(include_str!("../testdata/stopfinder.json").to_string())
+            } else if url.contains("XML_COORD_REQUEST") {
+                Ok(include_str!("../testdata/coord.json").to_string())
+            } else if url.contains("XSLT_DM_REQUEST") {
+                Ok(include_str!("../testdata/departures.xml").to_string())
+            } else {
+                Err(EfaError::Http { status: 404 })
+            };
+            Box::pin(async move { body })
+        }
+    }
+
+    /// No network at all.
+    struct Offline;
+
+    impl Transport for Offline {
+        fn get<'a>(&'a self, _url: &'a str, _timeout: Duration) -> TransportFuture<'a> {
+            Box::pin(async { Err(EfaError::Network("Failed to fetch".to_string())) })
+        }
+    }
+
+    // Lets spawned requests finish and the view catch up.
+    async fn settle() {
+        efa::sleep(Duration::from_millis(50)).await;
+    }
+
+    fn text(root: &HtmlElement) -> String {
+        root.text_content().unwrap_or_default()
+    }
+
+    fn container() -> HtmlElement {
+        let document = web_sys::window().unwrap().document().unwrap();
+        let root: HtmlElement = document.create_element("div").unwrap().unchecked_into();
+        document.body().unwrap().append_child(&root).unwrap();
+        root
+    }
+
+    async fn search(root: &HtmlElement, query: &str) {
+        let input: HtmlInputElement = root.query_selector("#greet-input").unwrap().unwrap().unchecked_into();
+        input.set_value(query);
+        // Leptos listens on the document, so events have to bubble.
+        let bubbling = EventInit::new();
+        bubbling.set_bubbles(true);
+        input.dispatch_event(&Event::new_with_event_init_dict("input", &bubbling).unwrap()).unwrap();
+        input.form().unwrap().request_submit().unwrap();
+        settle().await;
+    }
+
+    #[wasm_bindgen_test]
+    async fn search_then_select_a_stop() {
+        efa::configure(|_| EfaClient::new("http://fixtures.test/", Fixtures));
+        let root = container();
+        let _app = mount_to(root.clone(), App);
+        search(&root, "Karlsruhe, ZKM").await;
+        assert!(text(&root).contains("Found 2 stations"), "{}", text(&root));
+
+        let first: HtmlElement = root.query_selector(".search-bar ~ ul li").unwrap().unwrap().unchecked_into();
+        assert_eq!(first.text_content().as_deref(), Some("Karlsruhe, ZKM (Karlsruhe) — 7001004"));
+        first.click();
+        settle().await;
+        assert!(text(&root).contains("Selected: Karlsruhe, ZKM (7001004)"), "{}", text(&root));
+        assert!(text(&root).contains("Add to favorites"));
+
+        // The board's rows come in animated and settle once that is over.
+        let rows = root.query_selector_all(".board tbody tr").unwrap();
+        assert!(rows.length() >= 3, "{}", text(&root));
+        assert!(text(&root).contains("Wolfartsweier"), "{}", text(&root));
+        efa::sleep(Duration::from_secs(2)).await;
+        assert!(root.query_selector(".board tbody tr.row-enter").unwrap().is_none());
+        assert_eq!(root.query_selector_all(".board tbody tr").unwrap().length(), rows.length());
+    }
+
+    #[wasm_bindgen_test]
+    async fn failed_searches_say_so() {
+        efa::configure(|_| EfaClient::new("http://offline.test/", Offline));
+        let root = container();
+        let _app = mount_to(root.clone(), App);
+        search(&root, "Karlsruhe, ZKM").await;
+        // Network errors are retried with backoff before they are shown.
+        efa::sleep(Duration::from_secs(3)).await;
+        assert!(text(&root).contains("You appear to be offline. Check your connection and try again."), "{}", text(&root));
+    }
+}
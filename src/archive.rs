@@ -0,0 +1,255 @@
+use std::fmt;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use arrow_array::{ArrayRef, BooleanArray, Int32Array, RecordBatch, StringArray, TimestampSecondArray};
+use arrow_schema::{ArrowError, DataType, Field, Schema, TimeUnit};
+use chrono::NaiveDateTime;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::errors::ParquetError;
+use parquet::file::properties::WriterProperties;
+
+use crate::efa::{self, Departure, EfaClient, TransportMode};
+use crate::tz;
+
+// Stops are polled one after another; a round must fit into the request budget.
+const MIN_EVERY: Duration = Duration::from_secs(30);
+const BOARD_SIZE: usize = 20;
+
+#[derive(Debug)]
+pub enum ArchiveError {
+    Io(std::io::Error),
+    Parquet(String),
+}
+
+impl fmt::Display for ArchiveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArchiveError::Io(e) => write!(f, "could not write the archive: {e}"),
+            ArchiveError::Parquet(msg) => write!(f, "could not encode the archive: {msg}"),
+        }
+    }
+}
+
+impl From<std::io::Error> for ArchiveError {
+    fn from(e: std::io::Error) -> Self {
+        ArchiveError::Io(e)
+    }
+}
+
+impl From<ArrowError> for ArchiveError {
+    fn from(e: ArrowError) -> Self {
+        ArchiveError::Parquet(e.to_string())
+    }
+}
+
+impl From<ParquetError> for ArchiveError {
+    fn from(e: ParquetError) -> Self {
+        ArchiveError::Parquet(e.to_string())
+    }
+}
+
+/// What the archive daemon polls, from the command line.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Config {
+    pub dir: PathBuf,
+    pub stops: Vec<String>,
+    pub every: Duration,
+}
+
+impl Config {
+    /// `--archive <dir> --stops <id,...> [--every <seconds>]`; `None` unless
+    /// `--archive` is given, i.e. the app was not started as archiver.
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Option<Result<Config, String>> {
+        let mut dir = None;
+        let mut stops = Vec::new();
+        let mut every = Duration::from_secs(120);
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("{arg} needs a value"));
+            match arg.as_str() {
+                "--archive" => dir = Some(match value() {
+                    Ok(v) => PathBuf::from(v),
+                    Err(e) => return Some(Err(e)),
+                }),
+                "--stops" => match value() {
+                    Ok(v) => stops.extend(v.split(',').map(str::trim).filter(|s| !s.is_empty()).map(str::to_string)),
+                    Err(e) => return Some(Err(e)),
+                },
+                "--every" => match value().and_then(|v| v.parse().map_err(|_| format!("--every {v}: not a number of seconds"))) {
+                    Ok(secs) => every = Duration::from_secs(secs),
+                    Err(e) => return Some(Err(e)),
+                },
+                _ => {}
+            }
+        }
+        let dir = dir?;
+        if stops.is_empty() {
+            return Some(Err("--stops needs at least one stop id".to_string()));
+        }
+        Some(Ok(Config { dir, stops, every: every.max(MIN_EVERY) }))
+    }
+}
+
+/// Columns of the archive, as documented in the README.
+fn schema() -> Arc<Schema> {
+    let time = DataType::Timestamp(TimeUnit::Second, None);
+    Arc::new(Schema::new(vec![
+        Field::new("fetched_at", time.clone(), false),
+        Field::new("stop_id", DataType::Utf8, false),
+        Field::new("line", DataType::Utf8, false),
+        Field::new("direction", DataType::Utf8, true),
+        Field::new("mode", DataType::Utf8, false),
+        Field::new("planned_time", time.clone(), false),
+        Field::new("realtime_time", time, true),
+        Field::new("delay_minutes", DataType::Int32, true),
+        Field::new("platform", DataType::Utf8, true),
+        Field::new("cancelled", DataType::Boolean, false),
+        Field::new("operator", DataType::Utf8, true),
+    ]))
+}
+
+/// The `mode` column. Spelled out rather than derived, so renaming a
+/// variant doesn't change what older snapshots are compared against.
+fn mode_name(mode: TransportMode) -> &'static str {
+    match mode {
+        TransportMode::Train => "Train",
+        TransportMode::SBahn => "SBahn",
+        TransportMode::Subway => "Subway",
+        TransportMode::LightRail => "LightRail",
+        TransportMode::Tram => "Tram",
+        TransportMode::Bus => "Bus",
+        TransportMode::CableCar => "CableCar",
+        TransportMode::Ferry => "Ferry",
+        TransportMode::OnDemand => "OnDemand",
+        TransportMode::RegionalTrain => "RegionalTrain",
+        TransportMode::LongDistanceTrain => "LongDistanceTrain",
+        TransportMode::ReplacementBus => "ReplacementBus",
+        TransportMode::Other => "Other",
+    }
+}
+
+fn seconds(time: &NaiveDateTime) -> i64 {
+    time.and_utc().timestamp()
+}
+
+/// One round of boards as a record batch, in the column order of `schema`.
+fn batch(fetched_at: NaiveDateTime, boards: &[(String, Vec<Departure>)]) -> Result<RecordBatch, ArchiveError> {
+    let rows: Vec<(&str, &Departure)> = boards.iter().flat_map(|(stop, deps)| deps.iter().map(move |d| (stop.as_str(), d))).collect();
+    let text = |f: fn(&Departure) -> Option<&str>| -> ArrayRef { Arc::new(rows.iter().map(|(_, d)| f(d)).collect::<StringArray>()) };
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(TimestampSecondArray::from(vec![seconds(&fetched_at); rows.len()])),
+        Arc::new(rows.iter().map(|(stop, _)| Some(*stop)).collect::<StringArray>()),
+        text(|d| Some(d.line.as_str())),
+        text(|d| d.direction.as_deref()),
+        text(|d| Some(mode_name(d.mode))),
+        Arc::new(TimestampSecondArray::from(rows.iter().map(|(_, d)| seconds(&d.planned_time)).collect::<Vec<_>>())),
+        Arc::new(TimestampSecondArray::from(rows.iter().map(|(_, d)| d.realtime_time.as_ref().map(seconds)).collect::<Vec<_>>())),
+        Arc::new(Int32Array::from(rows.iter().map(|(_, d)| d.delay_minutes).collect::<Vec<_>>())),
+        text(|d| d.platform.as_deref()),
+        Arc::new(BooleanArray::from(rows.iter().map(|(_, d)| d.cancelled).collect::<Vec<_>>())),
+        text(|d| d.operator.as_deref()),
+    ];
+    Ok(RecordBatch::try_new(schema(), columns)?)
+}
+
+/// File of the round fetched at `fetched_at`, partitioned by day:
+/// `<dir>/date=YYYY-MM-DD/snapshot-HHMMSS.parquet`.
+pub fn snapshot_path(dir: &Path, fetched_at: NaiveDateTime) -> PathBuf {
+    dir.join(fetched_at.format("date=%Y-%m-%d").to_string())
+        .join(fetched_at.format("snapshot-%H%M%S.parquet").to_string())
+}
+
+/// Write one round of boards, `(stop id, departures)`, to its snapshot
+/// file. Rounds without any departure write nothing.
+pub fn write_snapshot(dir: &Path, fetched_at: NaiveDateTime, boards: &[(String, Vec<Departure>)]) -> Result<Option<PathBuf>, ArchiveError> {
+    if boards.iter().all(|(_, deps)| deps.is_empty()) {
+        return Ok(None);
+    }
+    let path = snapshot_path(dir, fetched_at);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let props = WriterProperties::builder().set_compression(Compression::SNAPPY).build();
+    let mut writer = ArrowWriter::try_new(File::create(&path)?, schema(), Some(props))?;
+    writer.write(&batch(fetched_at, boards)?)?;
+    writer.close()?;
+    Ok(Some(path))
+}
+
+/// Poll the configured stops forever, archiving every round. Boards that
+/// fail to load are left out of their round and reported on stderr.
+pub fn run(config: Config) {
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().expect("tokio runtime");
+    runtime.block_on(async move {
+        let client = EfaClient::kvv();
+        loop {
+            let fetched_at = tz::now();
+            let mut boards = Vec::with_capacity(config.stops.len());
+            for stop in &config.stops {
+                match client.departures(stop, BOARD_SIZE, None).await {
+                    Ok(deps) => boards.push((stop.clone(), deps)),
+                    Err(e) => eprintln!("{stop}: {e}"),
+                }
+            }
+            match write_snapshot(&config.dir, fetched_at, &boards) {
+                Ok(Some(path)) => println!("{}", path.display()),
+                Ok(None) => {}
+                Err(e) => eprintln!("{e}"),
+            }
+            efa::sleep(config.every).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{write_snapshot, Config};
+    use crate::efa::{Departure, TransportMode};
+    use chrono::NaiveDate;
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use parquet::record::RowAccessor;
+    use std::fs::File;
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    #[test]
+    fn arguments_start_the_archiver() {
+        let args = |s: &str| s.split(' ').map(str::to_string).collect::<Vec<_>>();
+        assert_eq!(Config::from_args(args("--release")), None);
+        let config = Config::from_args(args("--archive /data/kvv --stops 7001004,7000090 --every 5")).unwrap().unwrap();
+        assert_eq!(config.dir, PathBuf::from("/data/kvv"));
+        assert_eq!(config.stops, ["7001004", "7000090"]);
+        assert_eq!(config.every, Duration::from_secs(30));
+        assert!(Config::from_args(args("--archive /data/kvv")).unwrap().is_err());
+    }
+
+    #[test]
+    fn snapshots_are_written_per_day() {
+        let at = |minute| NaiveDate::from_ymd_opt(2024, 1, 15).unwrap().and_hms_opt(8, minute, 0).unwrap();
+        let dep = Departure {
+            line: "S2".to_string(),
+            mode: TransportMode::SBahn,
+            time: at(7),
+            planned_time: at(5),
+            realtime_time: Some(at(7)),
+            delay_minutes: Some(2),
+            ..Default::default()
+        };
+        let dir = std::env::temp_dir().join(format!("kvv-archive-{}", std::process::id()));
+        let boards = vec![("7001004".to_string(), vec![dep.clone(), dep]), ("7000090".to_string(), Vec::new())];
+        let path = write_snapshot(&dir, at(0), &boards).expect("snapshot written").expect("rows to write");
+        assert!(path.ends_with("date=2024-01-15/snapshot-080000.parquet"));
+
+        let reader = SerializedFileReader::new(File::open(&path).unwrap()).unwrap();
+        assert_eq!(reader.metadata().file_metadata().num_rows(), 2);
+        assert_eq!(reader.metadata().file_metadata().schema_descr().num_columns(), 11);
+        let first = reader.get_row_iter(None).unwrap().next().unwrap().unwrap();
+        assert_eq!(first.get_string(4).unwrap(), "SBahn");
+        assert_eq!(write_snapshot(&dir, at(1), &[("7000090".to_string(), Vec::new())]).unwrap(), None);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,177 @@
+use chrono::NaiveDateTime;
+use leptos::prelude::*;
+use leptos::task::spawn_local;
+
+use crate::efa::{Coord, Departure, EfaClient, EfaError};
+use crate::messages::error_message;
+use crate::store::use_store;
+use crate::tz;
+
+// Stops around the center whose boards are merged, nearest first.
+const AREA_STOPS: usize = 8;
+// Departures asked for per stop; enough to fill a quarter of an hour.
+const AREA_BOARD_SIZE: usize = 10;
+
+/// A departure on an area board, tagged with the stop it leaves from.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AreaDeparture {
+    pub stop_name: String,
+    pub departure: Departure,
+}
+
+/// Merge the boards of several nearby stops into one list.
+///
+/// `boards` should be ordered by distance from the area center: when the same
+/// vehicle (line, direction and planned time) shows up at several stops, only
+/// the nearest occurrence is kept. Only departures within `window_minutes`
+/// after `now` are returned, sorted by time.
+pub fn merge_boards(boards: Vec<(String, Vec<Departure>)>, now: NaiveDateTime, window_minutes: i64) -> Vec<AreaDeparture> {
+    let mut merged: Vec<(i64, AreaDeparture)> = Vec::new();
+    for (stop_name, departures) in boards {
+        for departure in departures {
+            let ahead = (departure.time - now).num_minutes();
+            if !(0..=window_minutes).contains(&ahead) {
+                continue;
+            }
+            let duplicate = merged.iter().any(|(_, seen)| {
+                seen.departure.line == departure.line
+                    && seen.departure.direction == departure.direction
+                    && seen.departure.planned_time == departure.planned_time
+            });
+            if !duplicate {
+                merged.push((ahead, AreaDeparture { stop_name: stop_name.clone(), departure }));
+            }
+        }
+    }
+    merged.sort_by_key(|(ahead, _)| *ahead);
+    merged.into_iter().map(|(_, d)| d).collect()
+}
+
+/// `EfaClient::departures_around` against the KVV, from now on.
+pub async fn departures_around(center: Coord, radius_m: u32, window_minutes: i64) -> Result<Vec<AreaDeparture>, EfaError> {
+    EfaClient::kvv().departures_around(center, radius_m, window_minutes, tz::now()).await
+}
+
+impl EfaClient {
+    /// Everything leaving the stops within `radius_m` of `center` in the
+    /// `window_minutes` after `now`, e.g. around Europaplatz in the next 15
+    /// minutes; see `merge_boards`. Stops whose board fails are left out.
+    pub async fn departures_around(
+        &self,
+        center: Coord,
+        radius_m: u32,
+        window_minutes: i64,
+        now: NaiveDateTime,
+    ) -> Result<Vec<AreaDeparture>, EfaError> {
+        let stops = self.stops_near(center.lat, center.lon, radius_m, AREA_STOPS).await?;
+        let ids: Vec<String> = stops.iter().map(|s| s.stop.id.clone()).collect();
+        let boards = self.departures_batch(&ids, AREA_BOARD_SIZE).await;
+        let boards = stops.into_iter().zip(boards).filter_map(|(s, board)| Some((s.stop.name, board.ok()?))).collect();
+        Ok(merge_boards(boards, now, window_minutes))
+    }
+}
+
+/// "Leaving around here": the merged board of the stops around `center`.
+#[component]
+pub fn AreaPanel(center: ReadSignal<Option<Coord>>) -> impl IntoView {
+    let store = use_store();
+    let (radius, set_radius) = signal(300u32);
+    let (window, set_window) = signal(15i64);
+    let (rows, set_rows) = signal(Vec::<AreaDeparture>::new());
+    let (message, set_message) = signal(String::new());
+    let load = move || {
+        let Some(center) = center.get_untracked() else {
+            set_message.set("Use \"Stops near me\" first.".to_string());
+            return;
+        };
+        set_message.set("Loading…".to_string());
+        spawn_local(async move {
+            match departures_around(center, radius.get_untracked(), window.get_untracked()).await {
+                Ok(found) => {
+                    set_message.set(if found.is_empty() { "Nothing leaves around here in that time.".to_string() } else { String::new() });
+                    set_rows.set(found);
+                }
+                Err(e) => set_message.set(error_message(&e, store.settings.with_untracked(|s| s.language), None)),
+            }
+        });
+    };
+    view! {
+        <div class="area">
+            <div class="row">
+                <label>
+                    "Within "
+                    <input type="number" min="50" step="50" prop:value=move || radius.get().to_string()
+                        on:input=move |ev| if let Ok(v) = event_target_value(&ev).parse() { set_radius.set(v) } />
+                    " m, next "
+                    <input type="number" min="1" prop:value=move || window.get().to_string()
+                        on:input=move |ev| if let Ok(v) = event_target_value(&ev).parse() { set_window.set(v) } />
+                    " min"
+                </label>
+                <button on:click=move |_| load()>"Show"</button>
+            </div>
+            <p>{ move || message.get() }</p>
+            <ul>
+                { move || rows.get().into_iter().map(|row| {
+                    let dep = row.departure;
+                    let direction = dep.direction.map(|d| format!(" → {d}")).unwrap_or_default();
+                    view! { <li>{ format!("{} {}{direction} ({})", tz::show(&dep.time), dep.line, row.stop_name) }</li> }
+                }).collect::<Vec<_>>() }
+            </ul>
+        </div>
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::merge_boards;
+    use crate::efa::{Coord, EfaClient, HttpTransport};
+    use crate::fixtures::{at, dep};
+    use chrono::{NaiveDate, TimeDelta};
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[test]
+    fn merges_dedups_and_limits_to_window() {
+        let boards = vec![
+            ("Europaplatz".to_string(), vec![dep("1", "Durlach", "08:04"), dep("2", "Wolfartsweier", "08:30")]),
+            (
+                "Europaplatz/Postgalerie".to_string(),
+                vec![dep("1", "Durlach", "08:04"), dep("S2", "Spöck", "08:02")],
+            ),
+        ];
+        let merged = merge_boards(boards, at("08:00"), 15);
+        let rows: Vec<_> = merged.iter().map(|d| (d.stop_name.as_str(), d.departure.line.as_str())).collect();
+        assert_eq!(rows, [("Europaplatz/Postgalerie", "S2"), ("Europaplatz", "1")]);
+    }
+
+    #[test]
+    fn window_wraps_around_midnight() {
+        let mut after_midnight = dep("S1", "Hochstetten", "00:05");
+        after_midnight.time += TimeDelta::days(1);
+        let boards = vec![("Marktplatz".to_string(), vec![after_midnight, dep("1", "Durlach", "23:50")])];
+        let merged = merge_boards(boards, at("23:55"), 15);
+        assert_eq!(merged.len(), 1);
+        assert_eq!(merged[0].departure.line, "S1");
+    }
+
+    #[tokio::test]
+    async fn area_boards_come_from_the_stops_around() {
+        let server = MockServer::start().await;
+        for (endpoint, body) in [
+            ("XML_COORD_REQUEST", include_str!("../testdata/coord.json")),
+            ("XSLT_DM_REQUEST", include_str!("../testdata/departures.xml")),
+        ] {
+            Mock::given(method("GET"))
+                .and(path(format!("/{endpoint}")))
+                .respond_with(ResponseTemplate::new(200).set_body_string(body))
+                .mount(&server)
+                .await;
+        }
+        let now = NaiveDate::from_ymd_opt(2024, 1, 15).unwrap().and_hms_opt(8, 1, 0).unwrap();
+        let client = EfaClient::new(server.uri(), HttpTransport);
+        let merged = client.departures_around(Coord { lat: 49.002, lon: 8.384 }, 500, 30, now).await.expect("area board");
+        // Every stop got the same board: each departure once, at the nearest stop.
+        let rows: Vec<_> = merged.iter().map(|d| (d.stop_name.as_str(), d.departure.line.as_str())).collect();
+        assert_eq!(rows, [("ZKM", "S2"), ("ZKM", "2"), ("ZKM", "S5")]);
+    }
+}
@@ -0,0 +1,187 @@
+use std::borrow::Cow;
+
+use crate::efa::TransportMode;
+
+/// Outline of a line badge.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BadgeShape {
+    /// Square corners, used for trams.
+    Square,
+    /// Slightly rounded corners, used for S-Bahn lines.
+    Rounded,
+    /// Fully rounded ends, used for buses and night lines.
+    Pill,
+}
+
+/// Colors and shape of a line badge.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BadgeStyle {
+    pub shape: BadgeShape,
+    pub background: Cow<'static, str>,
+    pub text: Cow<'static, str>,
+}
+
+// KVV line colors: (line, background, text).
+const LINE_COLORS: &[(&str, &str, &str)] = &[
+    ("1", "#ed1c24", "#ffffff"),
+    ("2", "#0071bc", "#ffffff"),
+    ("3", "#947139", "#ffffff"),
+    ("4", "#ffcb04", "#000000"),
+    ("5", "#00c0f3", "#ffffff"),
+    ("8", "#f7931d", "#ffffff"),
+    ("S1", "#00a76d", "#ffffff"),
+    ("S11", "#00a76d", "#ffffff"),
+    ("S2", "#a066aa", "#ffffff"),
+    ("S3", "#00a99d", "#ffffff"),
+    ("S4", "#9f184c", "#ffffff"),
+    ("S5", "#f69795", "#000000"),
+    ("S51", "#f69795", "#000000"),
+    ("S52", "#f69795", "#000000"),
+    ("S6", "#282268", "#ffffff"),
+    ("S7", "#fff200", "#000000"),
+    ("S8", "#6e692a", "#ffffff"),
+];
+
+const HEIGHT: u32 = 24;
+const MIN_WIDTH: u32 = 32;
+
+fn bundled_colors(line: &str) -> Option<(&'static str, &'static str)> {
+    LINE_COLORS.iter().find(|(name, _, _)| *name == line).map(|(_, bg, fg)| (*bg, *fg))
+}
+
+/// Default KVV style for a line name.
+pub fn style_for(line: &str) -> BadgeStyle {
+    let shape = if line.starts_with('S') && line[1..].chars().all(|c| c.is_ascii_digit()) && line.len() > 1 {
+        BadgeShape::Rounded
+    } else if line.parse::<u32>().is_ok_and(|n| n < 10) {
+        BadgeShape::Square
+    } else {
+        BadgeShape::Pill
+    };
+    let (background, text) = bundled_colors(line).unwrap_or(match shape {
+            BadgeShape::Rounded => ("#00a76d", "#ffffff"),
+            BadgeShape::Square => ("#ed1c24", "#ffffff"),
+            // Buses are purple, night lines dark blue.
+            BadgeShape::Pill if line.starts_with("NL") => ("#1c2a5a", "#ffffff"),
+            BadgeShape::Pill => ("#7a3a96", "#ffffff"),
+        });
+    BadgeStyle { shape, background: background.into(), text: text.into() }
+}
+
+/// KVV style for `line` of `mode`, e.g. the green S-Bahn or red tram chip.
+/// The mode settles what the name alone can't: "S5" is an S-Bahn, but
+/// "RE45" a regional train and "SEV" a replacement bus.
+pub fn line_style(line: &str, mode: TransportMode) -> BadgeStyle {
+    let (shape, fallback) = match mode {
+        TransportMode::SBahn | TransportMode::LightRail => (BadgeShape::Rounded, ("#00a76d", "#ffffff")),
+        TransportMode::Train | TransportMode::RegionalTrain | TransportMode::LongDistanceTrain => {
+            (BadgeShape::Rounded, ("#6e6e6e", "#ffffff"))
+        }
+        TransportMode::Tram | TransportMode::Subway => (BadgeShape::Square, ("#ed1c24", "#ffffff")),
+        TransportMode::ReplacementBus => (BadgeShape::Pill, ("#f39200", "#000000")),
+        TransportMode::Bus | TransportMode::OnDemand | TransportMode::CableCar | TransportMode::Ferry => {
+            (BadgeShape::Pill, if line.starts_with("NL") { ("#1c2a5a", "#ffffff") } else { ("#7a3a96", "#ffffff") })
+        }
+        TransportMode::Other => return style_for(line),
+    };
+    let (background, text) = bundled_colors(line).unwrap_or(fallback);
+    BadgeStyle { shape, background: background.into(), text: text.into() }
+}
+
+/// `line_style` with the line color EFA reported (see `ServedLine::color`),
+/// which wins over the bundled table as it follows timetable changes.
+/// Invalid colors are ignored.
+pub fn line_style_with(line: &str, mode: TransportMode, reported: Option<&str>) -> BadgeStyle {
+    let mut style = line_style(line, mode);
+    if let Some((r, g, b)) = reported.and_then(parse_hex) {
+        style.background = format!("#{r:02x}{g:02x}{b:02x}").into();
+        // Dark text on light colors such as the yellow S7.
+        let luminance = 0.2126 * f64::from(r) + 0.7152 * f64::from(g) + 0.0722 * f64::from(b);
+        style.text = if luminance > 160.0 { "#000000" } else { "#ffffff" }.into();
+    }
+    style
+}
+
+// "#rrggbb" or "rrggbb".
+fn parse_hex(color: &str) -> Option<(u8, u8, u8)> {
+    let hex = color.trim().trim_start_matches('#');
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    Some((channel(0)?, channel(2)?, channel(4)?))
+}
+
+/// Render a line badge as a standalone inline SVG element.
+pub fn render_svg(line: &str, style: &BadgeStyle) -> String {
+    let chars = line.chars().count() as u32;
+    // Long names like "NL1" or "S52" get a wider badge and a smaller font.
+    let font_size = if chars >= 3 { 12 } else { 14 };
+    let width = MIN_WIDTH.max(12 + chars * font_size * 7 / 10);
+    let radius = match style.shape {
+        BadgeShape::Square => 0,
+        BadgeShape::Rounded => 5,
+        BadgeShape::Pill => HEIGHT / 2,
+    };
+    format!(
+        concat!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}" role="img" aria-label="{label}">"#,
+            r#"<rect width="{w}" height="{h}" rx="{r}" fill="{bg}"/>"#,
+            r#"<text x="{cx}" y="{cy}" fill="{fg}" font-family="Helvetica, Arial, sans-serif" font-size="{fs}" font-weight="bold" text-anchor="middle" dominant-baseline="central">{label}</text>"#,
+            "</svg>"
+        ),
+        w = width,
+        h = HEIGHT,
+        r = radius,
+        bg = style.background,
+        fg = style.text,
+        cx = width as f32 / 2.0,
+        cy = HEIGHT / 2,
+        fs = font_size,
+        label = escape(line),
+    )
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{line_style, line_style_with, render_svg, style_for, BadgeShape};
+    use crate::efa::TransportMode;
+
+    fn line_badge(line: &str) -> String {
+        render_svg(line, &style_for(line))
+    }
+
+    #[test]
+    fn badges_match_golden_files() {
+        assert_eq!(line_badge("S2"), include_str!("../testdata/badges/S2.svg").trim_end());
+        assert_eq!(line_badge("4"), include_str!("../testdata/badges/4.svg").trim_end());
+        assert_eq!(line_badge("NL1"), include_str!("../testdata/badges/NL1.svg").trim_end());
+    }
+
+    #[test]
+    fn shapes_follow_line_kind() {
+        assert_eq!(style_for("S51").shape, BadgeShape::Rounded);
+        assert_eq!(style_for("2").shape, BadgeShape::Square);
+        assert_eq!(style_for("107").shape, BadgeShape::Pill);
+        assert_eq!(style_for("SEV").shape, BadgeShape::Pill);
+        assert!(line_badge("<x>").contains("&lt;x&gt;"));
+    }
+
+    #[test]
+    fn mode_and_reported_colors_refine_the_style() {
+        let s2 = line_style("S2", TransportMode::SBahn);
+        assert_eq!((s2.shape, s2.background.as_ref()), (BadgeShape::Rounded, "#a066aa"));
+        let sev = line_style("SEV", TransportMode::ReplacementBus);
+        assert_eq!((sev.shape, sev.background.as_ref()), (BadgeShape::Pill, "#f39200"));
+        assert_eq!(line_style("RE45", TransportMode::RegionalTrain).shape, BadgeShape::Rounded);
+        assert_eq!(line_style("X1", TransportMode::Other), style_for("X1"));
+
+        let reported = line_style_with("S2", TransportMode::SBahn, Some("B5E4F0"));
+        assert_eq!((reported.background.as_ref(), reported.text.as_ref()), ("#b5e4f0", "#000000"));
+        assert_eq!(line_style_with("S2", TransportMode::SBahn, Some("purple")), s2);
+    }
+}
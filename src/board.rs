@@ -0,0 +1,550 @@
+use std::collections::HashSet;
+use std::rc::Rc;
+use std::time::Duration;
+
+use chrono::{NaiveDateTime, Timelike};
+use futures::future::{AbortHandle, Abortable};
+use futures::StreamExt;
+use leptos::ev::MouseEvent;
+use leptos::prelude::*;
+use leptos::task::spawn_local;
+
+use crate::badge::{line_style_with, render_svg};
+use crate::columns::{BoardColumns, Column};
+use crate::diff::{diff, settle, DepartureKey, RowOp};
+use crate::disruptions::{self, Disruption};
+use crate::efa::{self, Departure, EfaClient, EfaError, RequestSlot, StopSuggestion};
+use crate::export::{data_url, ical};
+use crate::format::{delay_class, delay_label, delay_state};
+use crate::hafas::{HafasClient, RegionalFallback};
+use crate::lines::{self, ServedLine, Termini};
+use crate::live::{self, LiveBoard, LIVE_BOARD_SIZE};
+use crate::lookahead::{self, DayBoard};
+use crate::messages::error_message;
+use crate::operators;
+use crate::pinning::RowKey;
+use crate::platforms;
+use crate::priority::{prioritized, Priority};
+use crate::provider::Backend;
+use crate::punctuality::{day_number, delay_hint, typical_delay};
+use crate::stops::{self, StopDetails};
+use crate::store::use_store;
+use crate::template::Template;
+use crate::tz;
+use crate::validation::{validate, CheckedBoard};
+
+// Boards are cached for 20 seconds, so polling faster gains nothing.
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+// Value format of `<input type="datetime-local">`.
+const PICKER_FORMAT: &str = "%Y-%m-%dT%H:%M";
+
+// Onward stops shown for an opened row.
+const VIA_STOPS: usize = 3;
+
+// Column choices of this board, see `ColumnLayouts`.
+const VIEW: &str = "board";
+
+/// Live departures of `stop`, polled while the board is shown.
+#[component]
+pub fn Board(stop: StopSuggestion) -> impl IntoView {
+    let store = use_store();
+    let live = RwSignal::new(None::<LiveBoard>);
+    let stop_id = StoredValue::new(stop.id.clone());
+    let stop_name = StoredValue::new(stop.name.clone());
+    // One client for all of the board's requests, so that its later pages
+    // continue the session of the board they follow.
+    let client = StoredValue::new_local(EfaClient::kvv().with_param("stateless", "0"));
+    // Arrivals, later pages, routes and what the board shows around the
+    // departures come from KVV's EFA, which only knows its own stop ids.
+    let chosen = store.settings.with_untracked(|s| s.backend.provider(s.language));
+    let efa_backend = chosen.is_none();
+    let (abort, registration) = AbortHandle::new_pair();
+    let updates = match chosen.clone() {
+        Some(provider) => live::provider_stream(provider, &stop.id, POLL_INTERVAL).boxed_local(),
+        None => client.with_value(|c| c.departures_stream(&stop.id, LIVE_BOARD_SIZE, POLL_INTERVAL)).boxed_local(),
+    };
+    let provider = StoredValue::new_local(chosen.unwrap_or_else(|| Rc::new(client.get_value())));
+    spawn_local(async move {
+        let shown = updates.for_each(|update| {
+            live.set(Some(update));
+            async {}
+        });
+        let _ = Abortable::new(shown, registration).await;
+    });
+    // Another stop was selected: stop polling this one.
+    on_cleanup(move || abort.abort());
+    // Delays seen here feed the "usually late" hints, if the user wants them.
+    Effect::new(move |_| {
+        let Some(departures) = live.with(|b| b.as_ref().filter(|b| b.error.is_none()).map(|b| b.departures.clone())) else { return };
+        if store.settings.with_untracked(|s| s.punctuality_hints) {
+            stop_id.with_value(|id| store.punctuality.update(|log| departures.iter().for_each(|dep| log.record(id, dep))));
+        }
+    });
+    // Departures from a time the user picked instead of now, e.g. tomorrow
+    // morning, or arrivals to pick someone up. Picking another time cancels
+    // the request still running.
+    let at = RwSignal::new(None::<NaiveDateTime>);
+    let arriving = RwSignal::new(false);
+    let picked = RwSignal::new(None::<CheckedBoard>);
+    let failed = RwSignal::new(None::<String>);
+    let requests = StoredValue::new_local(RequestSlot::default());
+    // Pages of later departures, loaded on demand below the board; they go
+    // with the time the board starts from.
+    let later = RwSignal::new(Vec::<Departure>::new());
+    let pages = StoredValue::new_local(RequestSlot::default());
+    Effect::new(move |_| {
+        let (when, arrivals) = (at.get(), arriving.get());
+        pages.with_value(RequestSlot::cancel);
+        later.set(Vec::new());
+        if when.is_none() && !arrivals {
+            return;
+        }
+        picked.set(None);
+        failed.set(None);
+        let id = stop_id.get_value();
+        let (client, provider) = (client.get_value(), provider.get_value());
+        spawn_local(async move {
+            let request = async {
+                if !arrivals {
+                    return provider.departures_checked(&id, LIVE_BOARD_SIZE, when.unwrap_or_else(tz::now)).await;
+                }
+                let arrivals = client.arrivals(&id, LIVE_BOARD_SIZE, when).await?;
+                let anomalies = validate(&arrivals, when.unwrap_or_else(tz::now));
+                Ok(CheckedBoard { departures: arrivals, anomalies })
+            };
+            match requests.get_value().run(request).await {
+                Ok(board) => picked.set(Some(board)),
+                Err(EfaError::Cancelled) => {}
+                Err(e) => failed.set(Some(error_message(&e, store.settings.with_untracked(|s| s.language), None))),
+            }
+        });
+    });
+    on_cleanup(move || {
+        requests.try_with_value(RequestSlot::cancel);
+        pages.try_with_value(RequestSlot::cancel);
+    });
+    // Where the stop is and how accessible, for its header.
+    let details = RwSignal::new(None::<StopDetails>);
+    if efa_backend {
+        spawn_local(async move {
+            if let Ok(found) = prioritized(Priority::Background, stops::stop_details(&stop_id.get_value())).await {
+                details.set(found);
+            }
+        });
+    }
+    // Construction and strike notices for this stop, next to its departures.
+    let notices = RwSignal::new(Vec::<Disruption>::new());
+    if efa_backend {
+        spawn_local(async move {
+            if let Ok(found) = prioritized(Priority::Background, disruptions::infos(&stop_id.get_value())).await {
+                notices.set(found);
+            }
+        });
+    }
+    // While the board can't be updated, regional trains from HAFAS stand in
+    // for it if the user set up a server, see `RegionalFallback`.
+    let regional = RwSignal::new(None::<Vec<Departure>>);
+    let fallback = StoredValue::new_local(store.settings.with_untracked(|s| {
+        let profile = s.hafas_fallback.clone().filter(|p| !p.endpoint.is_empty() && s.backend == Backend::Efa)?;
+        Some(Rc::new(RegionalFallback::new(client.get_value(), HafasClient::new(profile, efa::transport()).with_language(s.language.code()))))
+    }));
+    let stop = StoredValue::new(stop);
+    Effect::new(move |_| {
+        if !live.with(|b| b.as_ref().is_some_and(|b| b.error.is_some())) {
+            regional.set(None);
+            return;
+        }
+        let Some(fallback) = fallback.get_value() else { return };
+        spawn_local(async move {
+            if let Ok(trains) = fallback.departures(&stop.get_value(), LIVE_BOARD_SIZE, None).await {
+                regional.set(Some(trains));
+            }
+        });
+    });
+    // The board with what looks implausible in it, see `validate`, and the
+    // later pages after it.
+    let board = Memo::new(move |_| {
+        let mut board = match (at.get(), arriving.get()) {
+            (None, false) => {
+                let update = live.get()?;
+                let departures = match regional.get() {
+                    Some(trains) if update.error.is_some() => trains,
+                    _ if update.fetched_at.is_none() => return None,
+                    _ => update.departures,
+                };
+                CheckedBoard { anomalies: validate(&departures, tz::now()), departures }
+            }
+            _ => picked.get()?,
+        };
+        // A refreshed first page may reach into the pages loaded before.
+        let first: HashSet<DepartureKey> = board.departures.iter().map(DepartureKey::of).collect();
+        later.with(|later| board.departures.extend(later.iter().filter(|d| !first.contains(&DepartureKey::of(d))).cloned()));
+        Some(board)
+    });
+    let load_later = move |_| {
+        let shown = board.with_untracked(|b| b.as_ref().map(|b| b.departures.clone()).unwrap_or_default());
+        let (id, client) = (stop_id.get_value(), client.get_value());
+        failed.set(None);
+        spawn_local(async move {
+            match pages.get_value().run(client.departures_after(&id, LIVE_BOARD_SIZE, &shown)).await {
+                Ok(more) => later.update(|later| later.extend(more)),
+                Err(EfaError::Cancelled) => {}
+                Err(e) => failed.set(Some(error_message(&e, store.settings.with_untracked(|s| s.language), None))),
+            }
+        });
+    };
+    // Why the live board couldn't be updated, and since when it shows the
+    // same departures; `None` for the time of a board never received.
+    let live_error = move || {
+        if at.with(Option::is_some) || arriving.get() {
+            return None;
+        }
+        live.with(|b| b.as_ref().and_then(|b| Some((b.error.clone()?, b.fetched_at))))
+    };
+    // Line colors and termini EFA reports for this stop; the board doesn't
+    // wait for them.
+    let served = RwSignal::new(Vec::<ServedLine>::new());
+    if efa_backend {
+        spawn_local(async move {
+            if let Ok(lines) = prioritized(Priority::Background, lines::lines_at(&stop_id.get_value())).await {
+                served.set(lines);
+            }
+        });
+    }
+
+    // Directions as the stop's canonical termini, see `Termini`, so pins
+    // keep matching; then the operator filter, and pinned rows first, within
+    // their platform if the stop is shown by platform.
+    let grouped = move || stop_id.with_value(|id| store.platform_layout.with(|l| l.is_grouped(id)));
+    let departures = move || {
+        let mut departures = board.with(|b| b.as_ref().map(|b| b.departures.clone()).unwrap_or_default());
+        served.with(|s| Termini::new(s).normalize(&mut departures));
+        let mut departures = store.operator_filter.with(|f| f.departures(departures));
+        stop_id.with_value(|id| store.pins.with(|p| p.apply(id, &mut departures)));
+        platforms::board(departures, grouped()).into_iter().flat_map(|group| group.departures).collect::<Vec<_>>()
+    };
+    // Rows with their animations after each refresh, see `diff`. Once the
+    // animations are over the rows settle, unless a newer refresh came.
+    let rows = RwSignal::new(Vec::<RowOp>::new());
+    let shown = StoredValue::new(Vec::<Departure>::new());
+    let refreshes = StoredValue::new(0u32);
+    Effect::new(move |_| {
+        let new = departures();
+        let ops = shown.with_value(|old| diff(old, &new));
+        shown.set_value(new);
+        let settled_after = ops.iter().map(|op| op.delay_ms + op.change.duration_ms()).max().unwrap_or(0);
+        rows.set(ops);
+        refreshes.update_value(|n| *n += 1);
+        let refresh = refreshes.get_value();
+        set_timeout(
+            move || {
+                if refreshes.try_get_value() == Some(refresh) {
+                    rows.update(|rows| *rows = settle(std::mem::take(rows)));
+                }
+            },
+            Duration::from_millis(settled_after.into()),
+        );
+    });
+    // Sparse stops often have nothing left today: the next service days then.
+    let ahead = RwSignal::new(Vec::<DayBoard>::new());
+    Effect::new(move |_| {
+        let empty = board.with(|b| b.as_ref().is_some_and(|b| b.departures.is_empty()));
+        if !empty || !efa_backend || at.with(Option::is_some) || arriving.get() || !ahead.with_untracked(Vec::is_empty) {
+            return;
+        }
+        let id = stop_id.get_value();
+        spawn_local(async move {
+            if let Ok(days) = lookahead::departures_ahead(&id, LIVE_BOARD_SIZE).await {
+                ahead.set(days);
+            }
+        });
+    });
+    // The opened row and where it goes on from here; `None` while loading.
+    let route = RwSignal::new(None::<(DepartureKey, Option<String>)>);
+    let open_route = move |key: DepartureKey| {
+        if !efa_backend {
+            return;
+        }
+        if route.with_untracked(|r| r.as_ref().is_some_and(|(open, _)| *open == key)) {
+            route.set(None);
+            return;
+        }
+        route.set(Some((key.clone(), None)));
+        let (id, client) = (stop_id.get_value(), client.get_value());
+        spawn_local(async move {
+            let board = client.departures_with_route(&id, LIVE_BOARD_SIZE, Some(key.planned_time)).await;
+            let via = board.ok().and_then(|b| b.into_iter().find(|d| DepartureKey::of(d) == key)).and_then(|d| d.continues_via(VIA_STOPS));
+            let text = via.map_or_else(|| "No further stops known.".to_string(), |via| format!("Continues via {via}"));
+            route.update(|r| {
+                // Unless another row was opened meanwhile.
+                if let Some((_, pending)) = r.as_mut().filter(|(open, _)| *open == key) {
+                    *pending = Some(text);
+                }
+            });
+        });
+    };
+    let columns = move || store.columns.with(|c| c.get(VIEW));
+    let operators = move || board.with(|b| b.as_ref().map(|b| operators::operators(&b.departures)).unwrap_or_default());
+    let several_operators = move || operators().len() > 1;
+    let filtered = move || {
+        store.operator_filter.with(|f| f.is_active()) && departures().len() < board.with(|b| b.as_ref().map_or(0, |b| b.departures.len()))
+    };
+    view! {
+        <section class="board">
+            { move || details.get().map(|stop| {
+                let step_free = match stop.accessibility.step_free {
+                    Some(true) => Some("Step-free access"),
+                    Some(false) => Some("Not step-free"),
+                    None => None,
+                };
+                let tactile = stop.accessibility.tactile_guidance.filter(|&t| t).map(|_| "Tactile guidance");
+                let map = stop.coord.map(|c| format!("https://www.openstreetmap.org/?mlat={}&mlon={}#map=18/{}/{}", c.lat, c.lon, c.lat, c.lon));
+                view! {
+                    <header class="stop">
+                        <h3>{ stop.name }</h3>
+                        { stop.place.map(|place| view! { <p class="hint">{ place }</p> }) }
+                        <p class="hint">{ [step_free, tactile].into_iter().flatten().collect::<Vec<_>>().join(" · ") }</p>
+                        { map.map(|href| view! { <a href=href target="_blank" rel="noopener">"Show on a map"</a> }) }
+                    </header>
+                }
+            }) }
+            <div class="row when">
+                <label>
+                    { move || if arriving.get() { "Arrivals from " } else { "Departures from " } }
+                    <input
+                        type="datetime-local"
+                        prop:value=move || at.get().map(|at| at.format(PICKER_FORMAT).to_string()).unwrap_or_default()
+                        on:change=move |ev| at.set(NaiveDateTime::parse_from_str(&event_target_value(&ev), PICKER_FORMAT).ok())
+                    />
+                </label>
+                <Show when=move || at.with(Option::is_some)>
+                    <button on:click=move |_| at.set(None)>"Now"</button>
+                </Show>
+                <Show when=move || efa_backend>
+                    <label>
+                        <input type="checkbox" prop:checked=move || arriving.get() on:change=move |ev| arriving.set(event_target_checked(&ev))/>
+                        " Arrivals"
+                    </label>
+                </Show>
+            </div>
+            { move || failed.get().map(|message| view! { <p class="warning">{ message }</p> }) }
+            <ul class="notices">
+                { move || notices.get().into_iter().map(|notice| {
+                    let valid = match (&notice.valid_from, &notice.valid_to) {
+                        (Some(from), Some(to)) => format!("{} – {}", validity(from), validity(to)),
+                        (Some(from), None) => format!("From {}", validity(from)),
+                        (None, Some(to)) => format!("Until {}", validity(to)),
+                        (None, None) => String::new(),
+                    };
+                    view! {
+                        <li>
+                            <details>
+                                <summary>{ notice.title }</summary>
+                                <p>{ notice.text }</p>
+                                <p class="hint">{ valid }</p>
+                            </details>
+                        </li>
+                    }
+                }).collect::<Vec<_>>() }
+            </ul>
+            { move || live_error().map(|(error, fetched_at)| {
+                let message = if regional.with(Option::is_some) {
+                    "KVV's server can't be reached. These are regional trains from HAFAS only.".to_string()
+                } else {
+                    let locale = store.settings.with(|s| s.language);
+                    error_message(&error, locale, fetched_at.map(|at| tz::show(&at)).as_deref())
+                };
+                view! { <p class="warning">{ message }</p> }
+            }) }
+            { move || {
+                let locale = store.settings.with(|s| s.language);
+                board.with(|b| b.as_ref().and_then(|b| b.hint(locale))).map(|hint| view! { <p class="hint">{ hint }</p> })
+            } }
+            { move || match board.with(|b| b.as_ref().map(|b| b.departures.is_empty())) {
+                None => (failed.with(Option::is_none) && live_error().is_none()).then(|| view! { <p>"Loading departures…"</p> }).into_any(),
+                Some(true) => view! {
+                    <p>"No departures in the next hours."</p>
+                    { move || {
+                        let (now, locale) = (tz::now(), store.settings.with(|s| s.language));
+                        ahead.get().into_iter().map(|day| view! {
+                            <h4>{ day.label(now, locale) }</h4>
+                            <ul class="ahead">
+                                { day.departures.into_iter().map(|dep| {
+                                    let direction = dep.direction.map(|d| format!(" → {d}")).unwrap_or_default();
+                                    view! { <li>{ format!("{} {}{direction}", tz::show(&dep.time), dep.line) }</li> }
+                                }).collect::<Vec<_>>() }
+                            </ul>
+                        }).collect::<Vec<_>>()
+                    } }
+                }.into_any(),
+                Some(false) => view! {
+                    <Show when=move || board.with(|b| b.as_ref().is_some_and(|b| platforms::has_platforms(&b.departures)))>
+                        <label class="platforms">
+                            <input
+                                type="checkbox"
+                                prop:checked=grouped
+                                on:change=move |_| stop_id.with_value(|id| store.platform_layout.update(|l| { l.toggle(id); }))
+                            />
+                            " By platform"
+                        </label>
+                    </Show>
+                    <Show when=several_operators>
+                        <div class="row operators" role="group" aria-label="Operators">
+                            { move || operators().into_iter().map(|op| {
+                                let pressed = store.operator_filter.with(|f| f.shows_only(&op));
+                                view! {
+                                    <button class="chip" aria-pressed=pressed.to_string() on:click=move |_| store.operator_filter.update(|f| f.toggle_only(&op))>
+                                        { op.clone() }
+                                    </button>
+                                }
+                            }).collect::<Vec<_>>() }
+                        </div>
+                    </Show>
+                    <Show when=filtered>
+                        <p class="hint">"Some departures are hidden by your filter."</p>
+                    </Show>
+                    <table>
+                        <thead>
+                            <tr>
+                                { move || columns().columns().iter().map(|c| view! { <th>{ c.header() }</th> }).collect::<Vec<_>>() }
+                                <th></th>
+                                <th></th>
+                            </tr>
+                        </thead>
+                        <tbody>
+                            { move || {
+                                let (columns, now) = (columns(), tz::now());
+                                let shapes = store.settings.with(|s| s.delay_shapes);
+                                let grouped = grouped();
+                                let hints = store.settings.with(|s| s.punctuality_hints.then_some(s.language));
+                                let today = day_number(now.date());
+                                let mut platform = None;
+                                rows.get().into_iter().map(|op| {
+                                    // Grouped rows come sorted by platform: a heading where it changes.
+                                    let heading = (grouped && op.departure.platform != platform).then(|| {
+                                        platform = op.departure.platform.clone();
+                                        let name = platform.clone().unwrap_or_else(|| "Other platforms".to_string());
+                                        view! { <tr class="platform"><th colspan=columns.columns().len() + 2>{ name }</th></tr> }
+                                    });
+                                    let dep = &op.departure;
+                                    let key = RowKey::of(dep);
+                                    let hint = hints.and_then(|locale| {
+                                        let typical = stop_id.with_value(|id| {
+                                            store.punctuality.with(|log| typical_delay(&log.observations, id, &dep.line, dep.planned_time.hour() as u8, today))
+                                        });
+                                        delay_hint(typical?, locale)
+                                    });
+                                    let pinned = stop_id.with_value(|id| store.pins.with(|p| p.is_pinned(id, &key)));
+                                    let row = op.key.clone();
+                                    let opened = route.with(|r| r.as_ref().filter(|(open, _)| *open == op.key).map(|(_, text)| text.clone()));
+                                    let expanded = opened.as_ref().map(|text| view! {
+                                        <tr class="route">
+                                            <td colspan=columns.columns().len() + 2>{ text.clone().unwrap_or_else(|| "Loading the route…".to_string()) }</td>
+                                        </tr>
+                                    });
+                                    view! {
+                                        { heading }
+                                        <tr
+                                            class=op.change.class()
+                                            class:cancelled=dep.cancelled
+                                            style=op.style()
+                                            aria-expanded=opened.is_some().to_string()
+                                            on:click=move |_| open_route(row.clone())
+                                        >
+                                            { served.with(|served| cells(&columns, dep, now, shapes, served)) }
+                                            <td class="hint">{ hint }</td>
+                                            <td>
+                                                <button
+                                                    class="pin"
+                                                    aria-label=if pinned { "Unpin" } else { "Pin to the top" }
+                                                    aria-pressed=pinned.to_string()
+                                                    on:click=move |ev: MouseEvent| {
+                                                        // Pinning doesn't open the row's route.
+                                                        ev.stop_propagation();
+                                                        stop_id.with_value(|id| store.pins.update(|p| { p.toggle(id, key.clone()); }));
+                                                    }
+                                                >"📌"</button>
+                                            </td>
+                                        </tr>
+                                        { expanded }
+                                    }
+                                }).collect::<Vec<_>>()
+                            } }
+                        </tbody>
+                    </table>
+                    <div class="row">
+                        <Show when=move || efa_backend && !arriving.get()>
+                            <button class="later" on:click=load_later>"Later departures"</button>
+                        </Show>
+                        <a
+                            class="calendar"
+                            download=move || stop_name.with_value(|name| format!("{name}.ics"))
+                            href=move || stop_name.with_value(|name| data_url("text/calendar", &ical::departures(name, &departures(), tz::now())))
+                        >"Add to calendar"</a>
+                    </div>
+                    { move || {
+                        let source = store.settings.with(|s| s.ticker.clone());
+                        (!source.trim().is_empty()).then(|| match Template::parse(&source) {
+                            Ok(template) => view! { <pre class="ticker">{ template.render_board(&departures(), tz::now()) }</pre> }.into_any(),
+                            Err(e) => view! { <p class="warning">{ format!("Ticker: {e}") }</p> }.into_any(),
+                        })
+                    } }
+                }.into_any(),
+            } }
+            <details class="columns">
+                <summary>"Columns"</summary>
+                <ul>
+                    { Column::ALL.into_iter().map(|column| view! {
+                        <li>
+                            <label>
+                                <input
+                                    type="checkbox"
+                                    prop:checked=move || columns().shows(column)
+                                    on:change=move |_| store.columns.update(|c| c.update(VIEW, |c| c.toggle(column)))
+                                />
+                                { format!(" {} ", column.header()) }
+                            </label>
+                            <Show when=move || columns().shows(column)>
+                                <button aria-label="Move left" on:click=move |_| store.columns.update(|c| c.update(VIEW, |c| c.shift(column, -1)))>"◀"</button>
+                                <button aria-label="Move right" on:click=move |_| store.columns.update(|c| c.update(VIEW, |c| c.shift(column, 1)))>"▶"</button>
+                            </Show>
+                        </li>
+                    }).collect::<Vec<_>>() }
+                </ul>
+                <label>
+                    "Ticker "
+                    <input
+                        placeholder="{line} {dir} {cd}m"
+                        prop:value=move || store.settings.with(|s| s.ticker.clone())
+                        on:change=move |ev| store.settings.update(|s| s.ticker = event_target_value(&ev))
+                    />
+                </label>
+            </details>
+        </section>
+    }
+}
+
+// Cells of one row: delays in their state's color (and shape, with the
+// setting), lines as badges.
+fn cells(columns: &BoardColumns, dep: &Departure, now: NaiveDateTime, shapes: bool, served: &[ServedLine]) -> Vec<AnyView> {
+    columns.columns().iter().zip(columns.row(dep, now)).map(|(&column, text)| match (column, dep.delay_minutes) {
+        (Column::Delay, Some(delay)) => view! {
+            <td class=delay_class(delay_state(delay))>{ delay_label(delay, shapes) }</td>
+        }.into_any(),
+        (Column::Line, _) => view! { <td class="line" inner_html=badge(dep, served)></td> }.into_any(),
+        _ => view! { <td>{ text }</td> }.into_any(),
+    }).collect()
+}
+
+// "2024-01-15T08:00:00" as shown to the user; other forms as they are.
+fn validity(iso: &str) -> String {
+    NaiveDateTime::parse_from_str(iso, "%Y-%m-%dT%H:%M:%S").map_or_else(|_| iso.to_string(), |t| t.format("%d.%m.%Y %H:%M").to_string())
+}
+
+/// The line of `dep` as an SVG badge, in the color EFA reports for it at
+/// this stop if any.
+fn badge(dep: &Departure, served: &[ServedLine]) -> String {
+    let reported = served.iter().find(|l| l.line == dep.line && l.mode == dep.mode).and_then(|l| l.color.as_deref());
+    render_svg(&dep.line, &line_style_with(&dep.line, dep.mode, reported))
+}
@@ -0,0 +1,177 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::rc::Rc;
+use std::time::Duration;
+
+use chrono::NaiveDateTime;
+use leptos::prelude::*;
+use leptos::task::spawn_local;
+use leptos::web_sys::{self, AudioContext};
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::JsValue;
+
+use crate::diff::DepartureKey;
+use crate::efa::{departures, Departure};
+use crate::pinning::RowKey;
+use crate::store::Slice;
+use crate::tz;
+
+// Departures are cached for 20 s, so checking more often gains nothing.
+const CHECK_EVERY: Duration = Duration::from_secs(30);
+
+/// Chime when a departure at `stop_id` is `minutes` away.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Watch {
+    pub stop_id: String,
+    /// Only this line and direction; `None` chimes for every departure,
+    /// like a station display.
+    pub row: Option<RowKey>,
+    pub minutes: u32,
+}
+
+impl Watch {
+    fn matches(&self, stop_id: &str, dep: &Departure) -> bool {
+        self.stop_id == stop_id && self.row.as_ref().is_none_or(|row| *row == RowKey::of(dep))
+    }
+}
+
+/// The user's chime watches, at most one per stop and row.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Chimes {
+    watches: Vec<Watch>,
+}
+
+impl Slice for Chimes {
+    const KEY: &'static str = "kvv.chimes";
+    const VERSION: u32 = 1;
+}
+
+impl Chimes {
+    pub fn get(&self, stop_id: &str, row: Option<&RowKey>) -> Option<&Watch> {
+        self.watches.iter().find(|w| w.stop_id == stop_id && w.row.as_ref() == row)
+    }
+
+    /// Chime `minutes` before departures at `stop_id` (of `row` only, if given);
+    /// `None` removes the watch.
+    pub fn set(&mut self, stop_id: &str, row: Option<RowKey>, minutes: Option<u32>) {
+        self.watches.retain(|w| !(w.stop_id == stop_id && w.row == row));
+        if let Some(minutes) = minutes {
+            self.watches.push(Watch { stop_id: stop_id.to_string(), row, minutes });
+        }
+    }
+
+    pub fn watches_stop(&self, stop_id: &str) -> bool {
+        self.watches.iter().any(|w| w.stop_id == stop_id)
+    }
+
+    /// Lead time for `dep`: the shortest of all watches matching it.
+    fn minutes_for(&self, stop_id: &str, dep: &Departure) -> Option<u32> {
+        self.watches.iter().filter(|w| w.matches(stop_id, dep)).map(|w| w.minutes).min()
+    }
+}
+
+/// Decides which departures to chime for, each one only once.
+#[derive(Debug, Default)]
+pub struct Chimer {
+    rung: HashSet<DepartureKey>,
+}
+
+impl Chimer {
+    /// Watched departures of `stop_id` that are now at most their lead time
+    /// away and haven't been chimed for yet. Cancelled ones never chime.
+    pub fn due<'a>(&mut self, chimes: &Chimes, stop_id: &str, deps: &'a [Departure], now: NaiveDateTime) -> Vec<&'a Departure> {
+        // Forget departures that left the board, so the set stays small.
+        self.rung.retain(|key| deps.iter().any(|d| DepartureKey::of(d) == *key));
+        deps.iter()
+            .filter(|d| !d.cancelled)
+            .filter(|d| {
+                let Some(minutes) = chimes.minutes_for(stop_id, d) else { return false };
+                let ahead = (d.time - now).num_minutes();
+                (0..=i64::from(minutes)).contains(&ahead) && self.rung.insert(DepartureKey::of(d))
+            })
+            .collect()
+    }
+}
+
+thread_local! {
+    // Browsers allow only a few audio contexts per page; reuse one.
+    static AUDIO: RefCell<Option<AudioContext>> = const { RefCell::new(None) };
+}
+
+/// Two-tone gong through the Web Audio API.
+pub fn ring() -> Result<(), JsValue> {
+    AUDIO.with(|audio| {
+        let mut audio = audio.borrow_mut();
+        if audio.is_none() {
+            *audio = Some(AudioContext::new()?);
+        }
+        let ctx = audio.as_ref().expect("just created");
+        let start = ctx.current_time();
+        for (i, frequency) in [660.0, 550.0].into_iter().enumerate() {
+            let at = start + i as f64 * 0.45;
+            let tone = ctx.create_oscillator()?;
+            tone.frequency().set_value_at_time(frequency, at)?;
+            let gain = ctx.create_gain()?;
+            gain.gain().set_value_at_time(0.4, at)?;
+            gain.gain().exponential_ramp_to_value_at_time(0.001, at + 1.2)?;
+            tone.connect_with_audio_node(&gain)?;
+            gain.connect_with_audio_node(&ctx.destination())?;
+            tone.start_with_when(at)?;
+            tone.stop_with_when(at + 1.2)?;
+        }
+        Ok(())
+    })
+}
+
+/// Check the watched departures of `stop_id` every 30 seconds and chime when
+/// one is due, until the returned handle is cleared.
+pub fn start(stop_id: String, chimes: RwSignal<Chimes>) -> Result<IntervalHandle, JsValue> {
+    let chimer = Rc::new(RefCell::new(Chimer::default()));
+    let tick = move || {
+        let (stop_id, chimer) = (stop_id.clone(), chimer.clone());
+        spawn_local(async move {
+            let Ok(deps) = departures(&stop_id, 10, None).await else { return };
+            let now = tz::now();
+            let due = chimes.with_untracked(|c| chimer.borrow_mut().due(c, &stop_id, &deps, now).len());
+            if due > 0
+                && let Err(e) = ring()
+            {
+                web_sys::console::log_1(&e);
+            }
+        });
+    };
+    tick();
+    set_interval_with_handle(tick, CHECK_EVERY)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Chimer, Chimes};
+    use crate::fixtures::{at, dep};
+    use crate::pinning::RowKey;
+
+    #[test]
+    fn watched_departures_chime_once_at_their_lead_time() {
+        let s2 = dep("S2", "Spöck", "08:05");
+        let mut chimes = Chimes::default();
+        chimes.set("7001004", Some(RowKey::of(&s2)), Some(3));
+        let deps = vec![dep("1", "Durlach", "08:02"), s2, dep("S2", "Spöck", "08:25")];
+
+        let mut chimer = Chimer::default();
+        assert!(chimer.due(&chimes, "7001004", &deps, at("08:01")).is_empty());
+        let due: Vec<_> = chimer.due(&chimes, "7001004", &deps, at("08:02")).iter().map(|d| d.time).collect();
+        assert_eq!(due, [at("08:05")]);
+        assert!(chimer.due(&chimes, "7001004", &deps, at("08:03")).is_empty());
+        assert!(chimer.due(&chimes, "7000001", &deps, at("08:02")).is_empty());
+
+        // A watch without a row chimes for the whole board.
+        chimes.set("7001004", None, Some(1));
+        let due: Vec<_> = chimer.due(&chimes, "7001004", &deps, at("08:01")).iter().map(|d| d.line.clone()).collect();
+        assert_eq!(due, ["1"]);
+
+        chimes.set("7001004", None, None);
+        assert!(chimes.get("7001004", None).is_none());
+        assert!(chimes.watches_stop("7001004"));
+    }
+}
@@ -0,0 +1,186 @@
+use std::collections::BTreeMap;
+
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+use crate::efa::Departure;
+use crate::format::delay_label;
+use crate::store::Slice;
+use crate::tz;
+
+/// One piece of information about a departure, shown as a board column
+/// or inserted by a template placeholder.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Column {
+    Line,
+    Direction,
+    /// Expected departure, "HH:MM".
+    Time,
+    /// Timetabled departure, "HH:MM".
+    Planned,
+    /// Minutes until departure.
+    Countdown,
+    Delay,
+    Platform,
+    Operator,
+}
+
+impl Column {
+    pub const ALL: [Column; 8] = [
+        Column::Line,
+        Column::Direction,
+        Column::Time,
+        Column::Planned,
+        Column::Countdown,
+        Column::Delay,
+        Column::Platform,
+        Column::Operator,
+    ];
+
+    pub fn header(self) -> &'static str {
+        match self {
+            Column::Line => "Line",
+            Column::Direction => "Direction",
+            Column::Time => "Departs",
+            Column::Planned => "Planned",
+            Column::Countdown => "In",
+            Column::Delay => "Delay",
+            Column::Platform => "Platform",
+            Column::Operator => "Operator",
+        }
+    }
+
+    /// Name in templates, e.g. `{dir}`.
+    pub fn placeholder(self) -> &'static str {
+        match self {
+            Column::Line => "line",
+            Column::Direction => "dir",
+            Column::Time => "time",
+            Column::Planned => "planned",
+            Column::Countdown => "cd",
+            Column::Delay => "delay",
+            Column::Platform => "platform",
+            Column::Operator => "operator",
+        }
+    }
+
+    pub fn from_placeholder(name: &str) -> Option<Column> {
+        Column::ALL.into_iter().find(|c| c.placeholder() == name)
+    }
+
+    /// Text of this column for `dep` at `now` (Berlin time, see `tz::now`);
+    /// empty where there is no data.
+    pub fn value(self, dep: &Departure, now: NaiveDateTime) -> String {
+        match self {
+            Column::Line => dep.line.clone(),
+            Column::Direction => dep.direction.clone().unwrap_or_default(),
+            Column::Time => tz::show(&dep.time),
+            Column::Planned => tz::show(&dep.planned_time),
+            Column::Countdown => (dep.time - now).num_minutes().max(0).to_string(),
+            Column::Delay => dep.delay_minutes.map(|d| delay_label(d, false)).unwrap_or_default(),
+            Column::Platform => dep.platform.clone().unwrap_or_default(),
+            Column::Operator => dep.operator.clone().unwrap_or_default(),
+        }
+    }
+}
+
+/// Visible columns of a board, in display order.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BoardColumns(Vec<Column>);
+
+impl Default for BoardColumns {
+    fn default() -> Self {
+        BoardColumns(vec![Column::Line, Column::Direction, Column::Time, Column::Delay, Column::Platform])
+    }
+}
+
+impl BoardColumns {
+    pub fn columns(&self) -> &[Column] {
+        &self.0
+    }
+
+    pub fn shows(&self, column: Column) -> bool {
+        self.0.contains(&column)
+    }
+
+    /// Show or hide `column`; newly shown columns go last.
+    pub fn toggle(&mut self, column: Column) {
+        match self.0.iter().position(|&c| c == column) {
+            Some(i) => {
+                self.0.remove(i);
+            }
+            None => self.0.push(column),
+        }
+    }
+
+    /// Move a visible column one place to the left (`-1`) or right (`1`).
+    pub fn shift(&mut self, column: Column, by: isize) {
+        let Some(i) = self.0.iter().position(|&c| c == column) else { return };
+        let j = i.saturating_add_signed(by).min(self.0.len() - 1);
+        self.0.swap(i, j);
+    }
+
+    /// Cells of one board row.
+    pub fn row(&self, dep: &Departure, now: NaiveDateTime) -> Vec<String> {
+        self.0.iter().map(|c| c.value(dep, now)).collect()
+    }
+}
+
+/// Column choices per view, e.g. "board" or a dashboard name. Views without
+/// a choice use the default columns.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ColumnLayouts {
+    views: BTreeMap<String, BoardColumns>,
+}
+
+impl Slice for ColumnLayouts {
+    const KEY: &'static str = "kvv.columns";
+    const VERSION: u32 = 1;
+}
+
+impl ColumnLayouts {
+    pub fn get(&self, view: &str) -> BoardColumns {
+        self.views.get(view).cloned().unwrap_or_default()
+    }
+
+    pub fn update(&mut self, view: &str, change: impl FnOnce(&mut BoardColumns)) {
+        change(self.views.entry(view.to_string()).or_default());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Column, ColumnLayouts};
+    use crate::efa::Departure;
+    use chrono::{NaiveDate, NaiveDateTime};
+
+    fn at(hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 1, 1).unwrap().and_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn columns_are_chosen_and_ordered_per_view() {
+        let dep = Departure {
+            line: "S2".to_string(),
+            direction: Some("Spöck".to_string()),
+            time: at(8, 7),
+            planned_time: at(8, 5),
+            delay_minutes: Some(2),
+            operator: Some("AVG".to_string()),
+            ..Default::default()
+        };
+        let mut layouts = ColumnLayouts::default();
+        layouts.update("lobby", |c| {
+            c.toggle(Column::Platform);
+            c.toggle(Column::Operator);
+            c.toggle(Column::Countdown);
+            c.shift(Column::Countdown, -1);
+            c.shift(Column::Line, -1);
+        });
+        let lobby = layouts.get("lobby");
+        assert_eq!(lobby.row(&dep, at(8, 1)), ["S2", "Spöck", "08:07", "+2", "6", "AVG"]);
+        assert!(!lobby.shows(Column::Platform));
+        assert_eq!(layouts.get("board").columns().len(), 5);
+    }
+}
@@ -0,0 +1,138 @@
+use std::future::Future;
+use std::pin::Pin;
+
+use serde_json::Value;
+
+use crate::efa::{fetch_text, Coord};
+
+pub type EstimateFuture<'a> = Pin<Box<dyn Future<Output = Option<u32>> + 'a>>;
+
+/// Estimates door-to-door travel time for an alternative mode (e.g. bike).
+pub trait DurationEstimator {
+    /// Label shown next to the estimate, e.g. "Bike".
+    fn label(&self) -> &str;
+    /// Travel time in minutes, or `None` if no estimate is available.
+    fn estimate<'a>(&'a self, from: Coord, to: Coord) -> EstimateFuture<'a>;
+}
+
+/// Offline estimate from straight-line distance, average speed and a detour factor.
+#[derive(Clone, Debug)]
+pub struct DistanceEstimator {
+    pub label: String,
+    pub speed_kmh: f64,
+    /// Street distance relative to the straight line (cities are ~1.3).
+    pub detour_factor: f64,
+}
+
+impl DistanceEstimator {
+    pub fn bike() -> Self {
+        DistanceEstimator { label: "Bike".to_string(), speed_kmh: 15.0, detour_factor: 1.3 }
+    }
+
+    fn minutes(&self, from: Coord, to: Coord) -> u32 {
+        let km = from.distance_m(&to) * self.detour_factor / 1000.0;
+        (km / self.speed_kmh * 60.0).ceil() as u32
+    }
+}
+
+impl DurationEstimator for DistanceEstimator {
+    fn label(&self) -> &str {
+        &self.label
+    }
+
+    fn estimate<'a>(&'a self, from: Coord, to: Coord) -> EstimateFuture<'a> {
+        let minutes = self.minutes(from, to);
+        Box::pin(async move { Some(minutes) })
+    }
+}
+
+/// Routed estimate from an OSRM-compatible HTTP service (OSRM, or Valhalla's OSRM output).
+#[derive(Clone, Debug)]
+pub struct OsrmEstimator {
+    pub label: String,
+    /// Service root, e.g. "https://routing.example.org/".
+    pub base_url: String,
+    /// Routing profile, e.g. "bike".
+    pub profile: String,
+}
+
+impl DurationEstimator for OsrmEstimator {
+    fn label(&self) -> &str {
+        &self.label
+    }
+
+    fn estimate<'a>(&'a self, from: Coord, to: Coord) -> EstimateFuture<'a> {
+        Box::pin(async move {
+            let url = format!(
+                "{}route/v1/{}/{},{};{},{}",
+                self.base_url, self.profile, from.lon, from.lat, to.lon, to.lat
+            );
+            let params = vec![("overview", "false".to_string())];
+            let body = fetch_text(&url, &params).await.ok()?;
+            let json: Value = serde_json::from_str(&body).ok()?;
+            let seconds = json.get("routes")?.get(0)?.get("duration")?.as_f64()?;
+            Some((seconds / 60.0).ceil() as u32)
+        })
+    }
+}
+
+/// One row of the comparison card.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Comparison {
+    pub label: String,
+    pub minutes: u32,
+}
+
+/// Run all estimators for the trip; the transit journey itself is not included.
+pub async fn compare(estimators: &[Box<dyn DurationEstimator>], from: Coord, to: Coord) -> Vec<Comparison> {
+    let mut rows = Vec::new();
+    for estimator in estimators {
+        if let Some(minutes) = estimator.estimate(from, to).await {
+            rows.push(Comparison { label: estimator.label().to_string(), minutes });
+        }
+    }
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compare, DistanceEstimator, DurationEstimator, OsrmEstimator};
+    use crate::efa::Coord;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    const ZKM: Coord = Coord { lat: 49.00191, lon: 8.38386 };
+    const DURLACHER_TOR: Coord = Coord { lat: 49.00927, lon: 8.41614 };
+
+    #[tokio::test]
+    async fn distance_model_estimates_bike_time() {
+        // ~2.5 km straight line -> ~3.2 km on streets -> 13 min at 15 km/h
+        let minutes = DistanceEstimator::bike().estimate(ZKM, DURLACHER_TOR).await;
+        assert_eq!(minutes, Some(13));
+    }
+
+    #[tokio::test]
+    async fn osrm_estimator_reads_route_duration() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/route/v1/bike/8.38386,49.00191;8.41614,49.00927"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_string(r#"{"code":"Ok","routes":[{"duration":731.4,"distance":3120.0}]}"#),
+            )
+            .mount(&server)
+            .await;
+
+        let estimators: Vec<Box<dyn DurationEstimator>> = vec![
+            Box::new(OsrmEstimator {
+                label: "Bike (routed)".to_string(),
+                base_url: format!("{}/", server.uri()),
+                profile: "bike".to_string(),
+            }),
+            Box::new(DistanceEstimator::bike()),
+        ];
+        let rows = compare(&estimators, ZKM, DURLACHER_TOR).await;
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].label, "Bike (routed)");
+        assert_eq!(rows[0].minutes, 13);
+    }
+}
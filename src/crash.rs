@@ -0,0 +1,149 @@
+use leptos::ev::MouseEvent;
+use leptos::prelude::*;
+use leptos::task::spawn_local;
+use leptos::web_sys;
+use serde::Serialize;
+use wasm_bindgen::JsValue;
+
+use crate::diagnostics::{self, FrontendReport};
+use crate::efa::{self, Capture, Exchange};
+use crate::tauri::invoke;
+
+#[derive(Serialize)]
+struct BundleArgs<'a> {
+    frontend: &'a FrontendReport,
+}
+
+/// Log panics as before, remember them for the report and replace the
+/// (now dead) app with a crash page instead of leaving a white screen.
+pub fn install_panic_hook() {
+    std::panic::set_hook(Box::new(|info| {
+        console_error_panic_hook::hook(info);
+        diagnostics::record_panic(info.to_string());
+        show_crash_page();
+    }));
+}
+
+// Plain DOM: the reactive runtime can't be trusted after a panic.
+fn show_crash_page() {
+    let Some(document) = web_sys::window().and_then(|w| w.document()) else { return };
+    let Some(body) = document.body() else { return };
+    body.set_text_content(None);
+    let report = serde_json::to_string_pretty(&diagnostics::report()).unwrap_or_default();
+    for (tag, text) in [
+        ("h2", "Something went wrong"),
+        ("p", "Please restart the app. If this keeps happening, send us the report below. It contains no personal data."),
+        ("pre", report.as_str()),
+    ] {
+        if let Ok(el) = document.create_element(tag) {
+            el.set_text_content(Some(text));
+            let _ = body.append_child(&el);
+        }
+    }
+}
+
+/// Diagnostic bundle from the backend (app version, platform) around the
+/// frontend report. Outside Tauri only the frontend part is available.
+pub async fn bundle() -> String {
+    let frontend = diagnostics::report();
+    let args = serde_wasm_bindgen::to_value(&BundleArgs { frontend: &frontend }).unwrap_or(JsValue::NULL);
+    match invoke("diagnostic_bundle", args).await {
+        Ok(text) if text.is_string() => text.as_string().unwrap_or_default(),
+        _ => serde_json::to_string_pretty(&frontend).unwrap_or_default(),
+    }
+}
+
+/// "Report a problem": builds the bundle and lets the user copy it.
+#[component]
+pub fn ReportPanel() -> impl IntoView {
+    let (text, set_text) = signal(None::<String>);
+    let create = move |_: MouseEvent| {
+        spawn_local(async move { set_text.set(Some(bundle().await)) });
+    };
+    let copy = move |_: MouseEvent| {
+        let (Some(text), Some(window)) = (text.get_untracked(), web_sys::window()) else { return };
+        let _ = window.navigator().clipboard().write_text(&text);
+    };
+    view! {
+        <div class="report">
+            <button on:click=create>"Create problem report"</button>
+            <Show when=move || text.get().is_some()>
+                <textarea readonly rows="10">{ move || text.get().unwrap_or_default() }</textarea>
+                <button on:click=copy>"Copy report"</button>
+            </Show>
+        </div>
+    }
+}
+
+// Requests debug mode keeps.
+const CAPTURED_REQUESTS: usize = 20;
+
+/// Hidden debug screen (`#debug` in the address): raw requests and
+/// responses, to reproduce parse failures users report.
+#[component]
+pub fn DebugPanel() -> impl IntoView {
+    let (capturing, set_capturing) = signal(false);
+    let (exchanges, set_exchanges) = signal(Vec::<Exchange>::new());
+    let toggle = move |ev| {
+        let on = event_target_checked(&ev);
+        efa::configure(|kvv| kvv.with_capture(on.then(|| Capture::new(CAPTURED_REQUESTS))));
+        set_capturing.set(on);
+        set_exchanges.set(Vec::new());
+    };
+    let clear = move |_: MouseEvent| {
+        efa::clear_captured();
+        set_exchanges.set(Vec::new());
+    };
+    view! {
+        <div class="debug">
+            <label>
+                <input type="checkbox" prop:checked=move || capturing.get() on:change=toggle/>
+                { format!(" Keep the last {CAPTURED_REQUESTS} raw responses") }
+            </label>
+            <button on:click=move |_: MouseEvent| set_exchanges.set(efa::captured())>"Show"</button>
+            <button on:click=clear>"Clear"</button>
+            <ul>
+                { move || exchanges.get().into_iter().rev().map(|exchange| {
+                    let (status, body) = match exchange.response {
+                        Ok(body) => ("OK".to_string(), body),
+                        Err(e) => (e.to_string(), String::new()),
+                    };
+                    view! {
+                        <li>
+                            <details>
+                                <summary>{ format!("{status}: {}", exchange.url) }</summary>
+                                <textarea readonly rows="10">{ body }</textarea>
+                            </details>
+                        </li>
+                    }
+                }).collect::<Vec<_>>() }
+            </ul>
+            <h4>"Unparsable responses"</h4>
+            <ul>
+                { move || {
+                    // Re-read whenever the list above is refreshed.
+                    exchanges.track();
+                    diagnostics::payloads().into_iter().map(|p| view! {
+                        <li><details><summary>{ p.endpoint }</summary><textarea readonly rows="10">{ p.body }</textarea></details></li>
+                    }).collect::<Vec<_>>()
+                } }
+            </ul>
+        </div>
+    }
+}
+
+/// Fallback of the app's error boundary.
+#[component]
+pub fn CrashScreen(errors: ArcRwSignal<Errors>) -> impl IntoView {
+    let messages = move || errors.get().into_iter().map(|(_, e)| e.to_string()).collect::<Vec<_>>();
+    for message in messages() {
+        diagnostics::record_warning(format!("error boundary: {message}"));
+    }
+    view! {
+        <main class="container crash">
+            <h2>"Something went wrong"</h2>
+            <ul>{ move || messages().into_iter().map(|m| view! { <li>{ m }</li> }).collect::<Vec<_>>() }</ul>
+            <ReportPanel/>
+        </main>
+    }
+}
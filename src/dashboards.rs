@@ -0,0 +1,156 @@
+use leptos::ev::{Event, MouseEvent};
+use leptos::prelude::*;
+use leptos::task::spawn_local;
+use leptos::web_sys::{self, HtmlInputElement};
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::JsValue;
+use wasm_bindgen_futures::JsFuture;
+
+use crate::efa::StopSuggestion;
+use crate::import::{parse_stop_list, resolve_stops, ImportReview, MAX_IMPORT};
+use crate::messages::error_message;
+use crate::store::{use_store, Slice};
+
+/// Several stops shown together, e.g. on a display in an office lobby.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Dashboard {
+    pub name: String,
+    pub stops: Vec<StopSuggestion>,
+}
+
+/// The user's dashboards, in the order they were created.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Dashboards {
+    pub boards: Vec<Dashboard>,
+}
+
+impl Slice for Dashboards {
+    const KEY: &'static str = "kvv.dashboards";
+    const VERSION: u32 = 1;
+}
+
+impl Dashboards {
+    /// Create dashboard `name`, replacing the stops of one with that name.
+    pub fn put(&mut self, name: &str, stops: Vec<StopSuggestion>) {
+        match self.boards.iter_mut().find(|b| b.name == name) {
+            Some(board) => board.stops = stops,
+            None => self.boards.push(Dashboard { name: name.to_string(), stops }),
+        }
+    }
+}
+
+async fn read_promise(promise: js_sys::Promise) -> Option<String> {
+    JsFuture::from(promise).await.ok().and_then(|text: JsValue| text.as_string())
+}
+
+/// Bulk setup of a dashboard from a list of stop names or ids, pasted or
+/// read from a text file. Every line is looked up and the matches are
+/// shown for review before the dashboard is created.
+#[component]
+pub fn ImportPanel() -> impl IntoView {
+    let store = use_store();
+    let (name, set_name) = signal("Dashboard".to_string());
+    let (text, set_text) = signal(String::new());
+    let (review, set_review) = signal(None::<ImportReview>);
+    let (status, set_status) = signal(String::new());
+
+    let paste = move |_: MouseEvent| {
+        let Some(window) = web_sys::window() else { return };
+        let promise = window.navigator().clipboard().read_text();
+        spawn_local(async move {
+            match read_promise(promise).await {
+                Some(pasted) => set_text.set(pasted),
+                None => set_status.set("The clipboard could not be read. Paste into the field instead.".to_string()),
+            }
+        });
+    };
+    let open_file = move |ev: Event| {
+        let Some(file) = event_target::<HtmlInputElement>(&ev).files().and_then(|f| f.get(0)) else { return };
+        spawn_local(async move {
+            match read_promise(file.text()).await {
+                Some(content) => set_text.set(content),
+                None => set_status.set(format!("{} could not be read.", file.name())),
+            }
+        });
+    };
+    let look_up = move |_: MouseEvent| {
+        let queries = parse_stop_list(&text.get_untracked());
+        if queries.is_empty() {
+            set_status.set("Enter one stop name or number per line.".to_string());
+            return;
+        }
+        set_status.set(format!("Looking up {} stops…", queries.len().min(MAX_IMPORT)));
+        set_review.set(None);
+        spawn_local(async move {
+            match resolve_stops(queries).await {
+                Ok(result) => {
+                    set_status.set(String::new());
+                    set_review.set(Some(result));
+                }
+                Err(e) => set_status.set(error_message(&e, store.settings.with_untracked(|s| s.language), None)),
+            }
+        });
+    };
+    let create = move |_: MouseEvent| {
+        let Some(stops) = review.with_untracked(|r| r.as_ref().map(ImportReview::stops)) else { return };
+        let count = stops.len();
+        let name = name.get_untracked();
+        store.dashboards.update(|d| d.put(&name, stops));
+        set_review.set(None);
+        set_status.set(format!("\"{name}\" created with {count} stops."));
+    };
+
+    view! {
+        <div class="import">
+            <label>"Name " <input prop:value=move || name.get() on:input=move |ev| set_name.set(event_target_value(&ev)) /></label>
+            <textarea
+                rows="8"
+                placeholder="One stop name or number per line"
+                prop:value=move || text.get()
+                on:input=move |ev| set_text.set(event_target_value(&ev))
+            ></textarea>
+            <div class="row">
+                <button on:click=paste>"Paste"</button>
+                <input type="file" accept=".txt,.csv,text/plain" on:change=open_file />
+                <button on:click=look_up>"Look up stops"</button>
+            </div>
+            <p>{ move || status.get() }</p>
+            { move || review.get().map(|r| {
+                let unresolved = r.unresolved().join(", ");
+                let ignored = r.ignored;
+                view! {
+                    <ul>
+                        { r.entries.into_iter().enumerate().filter(|(_, e)| !e.candidates.is_empty()).map(|(i, entry)| {
+                            let chosen = entry.chosen;
+                            let needs_review = entry.needs_review();
+                            view! {
+                                <li class:review=needs_review>
+                                    { entry.query } " → "
+                                    <select on:change=move |ev| {
+                                        let candidate = event_target_value(&ev).parse().ok();
+                                        set_review.update(|r| if let Some(r) = r { r.choose(i, candidate) });
+                                    }>
+                                        { entry.candidates.into_iter().enumerate().map(|(c, stop)| {
+                                            let label = match stop.place {
+                                                Some(place) => format!("{} ({place})", stop.name),
+                                                None => stop.name,
+                                            };
+                                            view! { <option value=c.to_string() selected={ chosen == Some(c) }>{ label }</option> }
+                                        }).collect::<Vec<_>>() }
+                                        <option value="skip" selected={ chosen.is_none() }>"Leave out"</option>
+                                    </select>
+                                </li>
+                            }
+                        }).collect::<Vec<_>>() }
+                    </ul>
+                    { (!unresolved.is_empty()).then(|| view! { <p class="warning">"Not found: " { unresolved }</p> }) }
+                    { (ignored > 0).then(|| view! {
+                        <p class="warning">{ format!("Only the first {MAX_IMPORT} lines were looked up; {ignored} more were left out.") }</p>
+                    }) }
+                    <button on:click=create>"Create dashboard"</button>
+                }
+            }) }
+        </div>
+    }
+}
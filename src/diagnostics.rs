@@ -0,0 +1,199 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+
+use serde::Serialize;
+
+use crate::efa::EfaError;
+
+// How many recent requests and warnings end up in a report.
+const MAX_REQUESTS: usize = 20;
+const MAX_WARNINGS: usize = 20;
+// Unparsable responses kept for debugging, and how much of each.
+const MAX_PAYLOADS: usize = 3;
+const MAX_PAYLOAD_BYTES: usize = 64 * 1024;
+
+/// Metadata of one EFA request. Only the endpoint is kept: query strings
+/// contain searched names and coordinates.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct RequestRecord {
+    /// When the request finished (ms since the epoch).
+    pub at_ms: f64,
+    pub endpoint: String,
+    /// HTTP status, `None` if the request never got a response.
+    pub status: Option<u16>,
+    pub duration_ms: u32,
+    pub error: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Warning {
+    pub at_ms: f64,
+    pub message: String,
+}
+
+/// What the frontend knows about its recent past, for bug reports.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct FrontendReport {
+    pub user_agent: Option<String>,
+    pub requests: VecDeque<RequestRecord>,
+    pub warnings: VecDeque<Warning>,
+    pub panic: Option<String>,
+}
+
+/// A response body we could not parse, kept to reproduce the failure.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Payload {
+    pub at_ms: f64,
+    pub endpoint: String,
+    /// The body, cut off after 64 KiB.
+    pub body: String,
+}
+
+thread_local! {
+    static REPORT: RefCell<FrontendReport> = RefCell::new(FrontendReport::default());
+    // Not part of the report: a board names the stop it was loaded for.
+    static PAYLOADS: RefCell<VecDeque<Payload>> = const { RefCell::new(VecDeque::new()) };
+}
+
+fn push_bounded<T>(list: &mut VecDeque<T>, item: T, max: usize) {
+    if list.len() == max {
+        list.pop_front();
+    }
+    list.push_back(item);
+}
+
+/// Last path segment of `url` without its query, e.g. "XSLT_DM_REQUEST".
+fn endpoint_of(url: &str) -> String {
+    let path = url.split(['?', '#']).next().unwrap_or("");
+    path.trim_end_matches('/').rsplit('/').next().unwrap_or("").to_string()
+}
+
+/// HTTP status behind `result`, `None` if no response arrived.
+pub fn status_of(result: &Result<String, EfaError>) -> Option<u16> {
+    match result {
+        Ok(_) => Some(200),
+        Err(EfaError::Http { status }) => Some(*status),
+        Err(EfaError::RateLimited { .. }) => Some(429),
+        Err(_) => None,
+    }
+}
+
+pub fn record_request(url: &str, result: &Result<String, EfaError>, duration_ms: f64) {
+    let record = RequestRecord {
+        at_ms: now_ms(),
+        endpoint: endpoint_of(url),
+        status: status_of(result),
+        duration_ms: duration_ms.max(0.0) as u32,
+        error: result.as_ref().err().map(|e| e.to_string()),
+    };
+    REPORT.with(|r| push_bounded(&mut r.borrow_mut().requests, record, MAX_REQUESTS));
+}
+
+/// Note data we had to skip or could not make sense of.
+pub fn record_warning(message: impl Into<String>) {
+    let warning = Warning { at_ms: now_ms(), message: message.into() };
+    REPORT.with(|r| push_bounded(&mut r.borrow_mut().warnings, warning, MAX_WARNINGS));
+}
+
+/// Keep the body of a response from `url` that failed to parse.
+pub fn record_payload(url: &str, body: &str) {
+    let mut end = body.len().min(MAX_PAYLOAD_BYTES);
+    while !body.is_char_boundary(end) {
+        end -= 1;
+    }
+    let payload = Payload { at_ms: now_ms(), endpoint: endpoint_of(url), body: body[..end].to_string() };
+    PAYLOADS.with(|p| push_bounded(&mut p.borrow_mut(), payload, MAX_PAYLOADS));
+}
+
+/// The unparsable responses kept so far, oldest first.
+pub fn payloads() -> Vec<Payload> {
+    PAYLOADS.with(|p| p.borrow().iter().cloned().collect())
+}
+
+/// Forget requests, warnings and payloads recorded before `cutoff_ms`.
+pub fn prune(cutoff_ms: f64) {
+    REPORT.with(|r| {
+        let mut report = r.borrow_mut();
+        report.requests.retain(|req| req.at_ms >= cutoff_ms);
+        report.warnings.retain(|w| w.at_ms >= cutoff_ms);
+    });
+    PAYLOADS.with(|p| p.borrow_mut().retain(|payload| payload.at_ms >= cutoff_ms));
+}
+
+pub fn record_panic(message: String) {
+    REPORT.with(|r| r.borrow_mut().panic = Some(message));
+}
+
+/// Snapshot of everything recorded so far.
+pub fn report() -> FrontendReport {
+    let mut report = REPORT.with(|r| r.borrow().clone());
+    report.user_agent = user_agent();
+    report
+}
+
+fn user_agent() -> Option<String> {
+    #[cfg(target_arch = "wasm32")]
+    {
+        leptos::web_sys::window().and_then(|w| w.navigator().user_agent().ok())
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        None
+    }
+}
+
+/// Milliseconds on a monotonic-enough clock, for request durations.
+pub fn now_ms() -> f64 {
+    #[cfg(target_arch = "wasm32")]
+    {
+        js_sys::Date::now()
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        use std::time::{SystemTime, UNIX_EPOCH};
+        SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs_f64() * 1000.0).unwrap_or(0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{endpoint_of, now_ms, payloads, prune, push_bounded, record_payload, record_request, record_warning, report};
+    use crate::efa::EfaError;
+    use std::collections::VecDeque;
+
+    #[test]
+    fn endpoint_drops_query_with_personal_data() {
+        assert_eq!(
+            endpoint_of("https://projekte.kvv-efa.de/sl3/XML_STOPFINDER_REQUEST?name_sf=Kaiserstra%C3%9Fe+12"),
+            "XML_STOPFINDER_REQUEST"
+        );
+        assert_eq!(endpoint_of("http://127.0.0.1:4321/XSLT_DM_REQUEST"), "XSLT_DM_REQUEST");
+    }
+
+    #[test]
+    fn keeps_only_the_most_recent_entries() {
+        let mut list = VecDeque::new();
+        for i in 0..5 {
+            push_bounded(&mut list, i, 3);
+        }
+        assert_eq!(list, [2, 3, 4]);
+
+        record_request("https://host/sl3/XSLT_TRIP_REQUEST?x=1", &Err(EfaError::Http { status: 503 }), 120.6);
+        let last = report().requests.pop_back().expect("request recorded");
+        assert_eq!(last.endpoint, "XSLT_TRIP_REQUEST");
+        assert_eq!(last.status, Some(503));
+        assert_eq!(last.duration_ms, 120);
+    }
+
+    #[test]
+    fn prune_forgets_old_entries() {
+        record_warning("skipped departure without time");
+        record_payload("https://host/sl3/XSLT_DM_REQUEST?name_dm=7001004", "<itdRequest>");
+        assert_eq!(payloads()[0].endpoint, "XSLT_DM_REQUEST");
+        prune(now_ms() + 1.0);
+        let report = report();
+        assert!(report.requests.is_empty());
+        assert!(report.warnings.is_empty());
+        assert!(payloads().is_empty());
+    }
+}
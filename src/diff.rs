@@ -0,0 +1,182 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::NaiveDateTime;
+
+use crate::efa::Departure;
+
+// Rows entering together slide in one after another, this far apart.
+const STAGGER_MS: u32 = 40;
+// Beyond this the last rows would appear noticeably late.
+const MAX_DELAY_MS: u32 = 320;
+
+/// Identity of one trip on a board across refreshes, used as the key of
+/// keyed (`<For>`) rendering. Line and direction alone are shared by every
+/// trip of a route, so the planned time is part of it.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct DepartureKey {
+    pub line: String,
+    pub direction: Option<String>,
+    pub planned_time: NaiveDateTime,
+}
+
+impl DepartureKey {
+    pub fn of(dep: &Departure) -> Self {
+        DepartureKey { line: dep.line.clone(), direction: dep.direction.clone(), planned_time: dep.planned_time }
+    }
+}
+
+/// What happened to a row between two snapshots of a board.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Change {
+    /// New on the board: slides in.
+    Enter,
+    Stay,
+    /// Still there, but time, platform or cancellation changed: flashes.
+    Update,
+    /// Gone, usually because it departed: fades out in place.
+    Leave,
+}
+
+impl Change {
+    /// CSS class running the animation, see styles.css.
+    pub fn class(self) -> &'static str {
+        match self {
+            Change::Enter => "row-enter",
+            Change::Stay => "",
+            Change::Update => "row-update",
+            Change::Leave => "row-leave",
+        }
+    }
+
+    /// Length of the animation; leaving rows can be dropped after it.
+    pub fn duration_ms(self) -> u32 {
+        match self {
+            Change::Enter => 250,
+            Change::Stay => 0,
+            Change::Update => 600,
+            Change::Leave => 300,
+        }
+    }
+}
+
+/// One row of the board after a refresh.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RowOp {
+    pub key: DepartureKey,
+    pub departure: Departure,
+    pub change: Change,
+    /// When the animation starts, relative to the refresh.
+    pub delay_ms: u32,
+}
+
+impl RowOp {
+    /// Inline style carrying the timing hint.
+    pub fn style(&self) -> String {
+        format!("animation-delay: {}ms; animation-duration: {}ms", self.delay_ms, self.change.duration_ms())
+    }
+}
+
+/// Rows to render when the board goes from `old` to `new`: the rows of `new`
+/// in order, with rows that left kept at their old place so they can fade
+/// out there. Keys are unique, as keyed rendering needs.
+pub fn diff(old: &[Departure], new: &[Departure]) -> Vec<RowOp> {
+    let new_keys: HashSet<DepartureKey> = new.iter().map(DepartureKey::of).collect();
+    let old_rows: HashMap<DepartureKey, &Departure> = old.iter().map(|d| (DepartureKey::of(d), d)).collect();
+
+    // Leaving rows, grouped by the row they followed (`None`: the top).
+    let mut leaving: HashMap<Option<DepartureKey>, Vec<&Departure>> = HashMap::new();
+    let mut anchor = None;
+    for dep in old {
+        let key = DepartureKey::of(dep);
+        if new_keys.contains(&key) {
+            anchor = Some(key);
+        } else {
+            leaving.entry(anchor.clone()).or_default().push(dep);
+        }
+    }
+    let leave = |ops: &mut Vec<RowOp>, after: Option<DepartureKey>| {
+        for dep in leaving.get(&after).into_iter().flatten() {
+            ops.push(RowOp { key: DepartureKey::of(dep), departure: (*dep).clone(), change: Change::Leave, delay_ms: 0 });
+        }
+    };
+
+    let mut ops = Vec::with_capacity(old.len() + new.len());
+    let mut seen = HashSet::new();
+    let mut entering = 0;
+    leave(&mut ops, None);
+    for dep in new {
+        let key = DepartureKey::of(dep);
+        if !seen.insert(key.clone()) {
+            continue;
+        }
+        let (change, delay_ms) = match old_rows.get(&key) {
+            None => {
+                entering += 1;
+                (Change::Enter, ((entering - 1) * STAGGER_MS).min(MAX_DELAY_MS))
+            }
+            Some(before) if changed(before, dep) => (Change::Update, 0),
+            Some(_) => (Change::Stay, 0),
+        };
+        ops.push(RowOp { key: key.clone(), departure: dep.clone(), change, delay_ms });
+        leave(&mut ops, Some(key));
+    }
+    ops
+}
+
+/// The board once all animations are over: leaving rows dropped, the rest at rest.
+pub fn settle(ops: Vec<RowOp>) -> Vec<RowOp> {
+    ops.into_iter()
+        .filter(|op| op.change != Change::Leave)
+        .map(|op| RowOp { change: Change::Stay, delay_ms: 0, ..op })
+        .collect()
+}
+
+fn changed(before: &Departure, after: &Departure) -> bool {
+    before.time != after.time || before.platform != after.platform || before.cancelled != after.cancelled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{diff, settle, Change};
+    use crate::fixtures::{at, dep};
+
+    #[test]
+    fn departed_rows_fade_out_in_place_and_new_ones_slide_in() {
+        let old = vec![
+            dep("1", "Durlach", "08:01"),
+            dep("2", "Wolfartsweier", "08:03"),
+            dep("S1", "Hochstetten", "08:05"),
+            dep("4", "Waldstadt", "08:07"),
+        ];
+        let mut delayed = dep("S1", "Hochstetten", "08:05");
+        delayed.time = at("08:08");
+        let new = vec![
+            dep("2", "Wolfartsweier", "08:03"),
+            delayed,
+            dep("5", "Rheinhafen", "08:10"),
+            dep("1", "Durlach", "08:11"),
+            dep("2", "Wolfartsweier", "08:13"),
+        ];
+
+        let ops = diff(&old, &new);
+        let rows: Vec<_> = ops.iter().map(|op| (op.departure.line.as_str(), op.change, op.delay_ms)).collect();
+        assert_eq!(
+            rows,
+            [
+                ("1", Change::Leave, 0),
+                ("2", Change::Stay, 0),
+                ("S1", Change::Update, 0),
+                ("4", Change::Leave, 0),
+                ("5", Change::Enter, 0),
+                ("1", Change::Enter, 40),
+                ("2", Change::Enter, 80),
+            ]
+        );
+        assert_eq!(ops[6].style(), "animation-delay: 80ms; animation-duration: 250ms");
+
+        let settled = settle(ops);
+        assert_eq!(settled.len(), new.len());
+        assert!(settled.iter().all(|op| op.change == Change::Stay));
+        assert!(diff(&new, &new).iter().all(|op| op.change == Change::Stay));
+    }
+}
@@ -0,0 +1,198 @@
+use serde_json::Value;
+
+use crate::efa::{decode_text, EfaClient, EfaError, EfaRequest};
+
+/// A service notice (construction, strike, ...) from the EFA info system.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Disruption {
+    pub id: String,
+    /// Affected lines; empty when the notice applies to the whole network.
+    pub lines: Vec<String>,
+    pub title: String,
+    /// Plain text body, HTML stripped.
+    pub text: String,
+    /// Start of validity as ISO 8601 local time, if given.
+    pub valid_from: Option<String>,
+    /// End of validity as ISO 8601 local time, if given.
+    pub valid_to: Option<String>,
+}
+
+impl Disruption {
+    /// Whether the notice concerns `line` (network-wide notices concern every line).
+    pub fn affects(&self, line: &str) -> bool {
+        self.lines.is_empty() || self.lines.iter().any(|l| l == line)
+    }
+}
+
+/// All currently published notices.
+pub async fn disruptions() -> Result<Vec<Disruption>, EfaError> {
+    EfaClient::kvv().infos(None).await
+}
+
+/// Currently published notices affecting the stop `station_id`.
+pub async fn infos(station_id: &str) -> Result<Vec<Disruption>, EfaError> {
+    EfaClient::kvv().infos(Some(station_id)).await
+}
+
+impl EfaClient {
+    /// Currently published notices, all of them or those affecting `station_id`.
+    pub async fn infos(&self, station_id: Option<&str>) -> Result<Vec<Disruption>, EfaError> {
+        let body = self.fetch_request(&AddInfoRequest { station_id }).await?;
+        parse_addinfo_json(&body)
+    }
+}
+
+/// Published notices (XML_ADDINFO_REQUEST).
+pub(crate) struct AddInfoRequest<'a> {
+    /// Only notices affecting this stop; `None` for all.
+    pub station_id: Option<&'a str>,
+}
+
+impl EfaRequest for AddInfoRequest<'_> {
+    fn endpoint(&self) -> &'static str {
+        "XML_ADDINFO_REQUEST"
+    }
+
+    fn to_params(&self) -> Vec<(&'static str, String)> {
+        let mut params = vec![
+            ("outputFormat", "JSON".to_string()),
+            ("filterPublicationStatus", "current".to_string()),
+            ("filterValid", "1".to_string()),
+            ("filterShowLineList", "1".to_string()),
+            ("filterShowStopList", "1".to_string()),
+        ];
+        if let Some(id) = self.station_id {
+            params.push(("itdLPxx_selStop", id.to_string()));
+        }
+        params
+    }
+}
+
+fn parse_info(info: &Value) -> Option<Disruption> {
+    let str_field = |key: &str| info.get(key).and_then(|v| v.as_str()).filter(|s| !s.is_empty());
+    let id = str_field("id")?.to_string();
+    let title = decode_text(str_field("subtitle").or_else(|| str_field("infoLinkText")).or_else(|| str_field("urlText"))?);
+    let text = str_field("content").map(strip_html).unwrap_or_default();
+
+    let mut lines: Vec<String> = Vec::new();
+    if let Some(Value::Array(affected)) = info.get("affected").and_then(|a| a.get("lines")) {
+        for line in affected {
+            let name = line.get("number").or_else(|| line.get("name")).and_then(|n| n.as_str());
+            if let Some(name) = name {
+                // Both directions of a line are listed separately.
+                if !lines.iter().any(|l| l == name) {
+                    lines.push(name.to_string());
+                }
+            }
+        }
+    }
+
+    let validity = info.get("timestamps").and_then(|t| t.get("validity")).and_then(|v| v.get(0));
+    let timestamp = |key: &str| validity.and_then(|v| v.get(key)).and_then(|t| t.as_str()).map(str::to_string);
+
+    Some(Disruption { id, lines, title, text, valid_from: timestamp("from"), valid_to: timestamp("to") })
+}
+
+fn parse_addinfo_json(body: &str) -> Result<Vec<Disruption>, EfaError> {
+    let json: Value = serde_json::from_str(body).map_err(|e| EfaError::Parse(e.to_string()))?;
+    let current = json.get("infos").and_then(|i| i.get("current"));
+    Ok(match current {
+        Some(Value::Array(items)) => items.iter().filter_map(parse_info).collect(),
+        _ => Vec::new(),
+    })
+}
+
+/// Plain text from the small HTML snippets EFA uses (paragraphs, bold, links).
+fn strip_html(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    let mut tag = String::new();
+    for c in html.chars() {
+        match c {
+            '<' => {
+                in_tag = true;
+                tag.clear();
+            }
+            '>' if in_tag => {
+                in_tag = false;
+                let name = tag.trim_start_matches('/').split_whitespace().next().unwrap_or("").to_ascii_lowercase();
+                if matches!(name.as_str(), "p" | "br" | "br/" | "li" | "div") && !text.ends_with('\n') && !text.is_empty() {
+                    text.push('\n');
+                }
+            }
+            _ if in_tag => tag.push(c),
+            _ => text.push(c),
+        }
+    }
+    decode_text(text.trim())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_addinfo_json, AddInfoRequest};
+    use crate::efa::{EfaClient, EfaRequest, HttpTransport};
+    use wiremock::matchers::{method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    const ADDINFO_JSON: &str = include_str!("../testdata/addinfo.json");
+
+    #[test]
+    fn addinfo_request_parameters() {
+        let params = AddInfoRequest { station_id: Some("7001004") }.to_params();
+        let params: Vec<_> = params.iter().map(|(k, v)| (*k, v.as_str())).collect();
+        assert_eq!(
+            params,
+            [
+                ("outputFormat", "JSON"),
+                ("filterPublicationStatus", "current"),
+                ("filterValid", "1"),
+                ("filterShowLineList", "1"),
+                ("filterShowStopList", "1"),
+                ("itdLPxx_selStop", "7001004"),
+            ]
+        );
+        assert_eq!(AddInfoRequest { station_id: None }.to_params().len(), 5);
+    }
+
+    #[test]
+    fn parse_addinfo_json_extracts_disruptions() {
+        let infos = parse_addinfo_json(ADDINFO_JSON).expect("parse succeeds");
+        assert_eq!(infos.len(), 2);
+
+        let works = &infos[0];
+        assert_eq!(works.id, "KVV_12345");
+        assert_eq!(works.lines, ["S2"]);
+        assert_eq!(works.title, "S2: Schienenersatzverkehr zwischen Durlach und Spöck");
+        assert_eq!(
+            works.text,
+            "Wegen Bauarbeiten fährt die S2 zwischen Durlach und Spöck nicht.\nBitte nutzen Sie die Ersatzbusse."
+        );
+        assert_eq!(works.valid_from.as_deref(), Some("2024-01-13T04:00:00"));
+        assert_eq!(works.valid_to.as_deref(), Some("2024-01-22T01:00:00"));
+        assert!(works.affects("S2"));
+        assert!(!works.affects("S1"));
+
+        let strike = &infos[1];
+        assert_eq!(strike.title, "Warnstreik am Montag");
+        assert!(strike.lines.is_empty());
+        assert!(strike.affects("S1"));
+        assert_eq!(strike.valid_to, None);
+    }
+
+    #[tokio::test]
+    async fn infos_filters_by_stop() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/XML_ADDINFO_REQUEST"))
+            .and(query_param("itdLPxx_selStop", "7000090"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(ADDINFO_JSON))
+            .mount(&server)
+            .await;
+
+        let infos = EfaClient::new(server.uri(), HttpTransport)
+            .infos(Some("7000090"))
+            .await
+            .expect("info request succeeds");
+        assert_eq!(infos.len(), 2);
+    }
+}
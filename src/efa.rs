@@ -1,306 +1,1194 @@
 use quick_xml::events::Event;
 use quick_xml::Reader;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use html_escape::decode_html_entities;
-use serde_urlencoded;
+use chrono::NaiveDateTime;
+use futures::future::{join_all, AbortHandle, Abortable, Aborted};
+use tracing::{field, Instrument};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::time::Duration;
 
-/// Cross-platform fetch helper: uses gloo-net on wasm32 and reqwest otherwise
-async fn fetch_text(url: &str, params: &Vec<(&str, String)>) -> Result<String, String> {
-    // serialize params into query string
+use crate::diagnostics;
+use crate::diff::DepartureKey;
+use crate::gtfs::Feed;
+use crate::gtfs_rt::TripUpdates;
+use crate::priority::{self, RateLimit, Scheduler, TokenBucket, Usage};
+use crate::tz;
+
+pub use efa_core::{
+    hhmm, Coord, Departure, EfaError, Location, LocationType, NearbyStop, StopHits, StopSuggestion, TransportMode,
+};
+pub(crate) use efa_core::{decode_text, is_replacement, parse_coords, parse_stop_point, parse_time_from_attrs, server_message};
+use efa_core::{decode_body, parse_assigned_stop, parse_coord_json, parse_departures_json, parse_locations_json, parse_stopfinder_json};
+
+// Responses kept at most; the oldest is dropped first.
+const MAX_CACHED: usize = 64;
+
+/// How long a response of `endpoint` may be reused: stop names and the
+/// lines serving a stop hardly change, departure boards go stale within a
+/// refresh or two.
+fn cache_ttl(endpoint: &str) -> Option<Duration> {
+    match endpoint {
+        "XML_STOPFINDER_REQUEST" | "XML_SERVINGLINES_REQUEST" => Some(Duration::from_secs(60 * 60)),
+        "XSLT_DM_REQUEST" => Some(Duration::from_secs(20)),
+        _ => None,
+    }
+}
+
+/// Successful response bodies by full request URL.
+#[derive(Debug, Default)]
+struct ResponseCache {
+    // URL -> (stored at ms, expires at ms, body)
+    entries: HashMap<String, (f64, f64, String)>,
+}
+
+impl ResponseCache {
+    fn get(&mut self, key: &str, now_ms: f64) -> Option<String> {
+        match self.entries.get(key) {
+            Some((_, expires, body)) if now_ms < *expires => Some(body.clone()),
+            Some(_) => {
+                self.entries.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn put(&mut self, key: String, body: String, now_ms: f64, ttl: Duration) {
+        if self.entries.len() >= MAX_CACHED && !self.entries.contains_key(&key) {
+            let oldest = self.entries.iter().min_by(|a, b| a.1 .0.total_cmp(&b.1 .0)).map(|(k, _)| k.clone());
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.entries.insert(key, (now_ms, now_ms + ttl.as_secs_f64() * 1000.0, body));
+    }
+}
+
+thread_local! {
+    static CACHE: RefCell<ResponseCache> = RefCell::new(ResponseCache::default());
+}
+
+/// Requests to EFA in flight at once; queued requests start by priority.
+static SCHEDULER: Scheduler = Scheduler::new(4);
+
+/// Requests made to EFA during the last hour against the budget.
+pub fn api_usage() -> Usage {
+    SCHEDULER.usage(diagnostics::now_ms())
+}
+
+pub fn set_api_budget(per_hour: u32) {
+    SCHEDULER.set_budget(per_hour);
+}
+
+/// How long to wait for a response and how often to try again.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RetryPolicy {
+    pub timeout: Duration,
+    /// Attempts including the first one.
+    pub max_attempts: u32,
+    /// Wait before the first retry; doubled for every further one.
+    pub base_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy { timeout: Duration::from_secs(10), max_attempts: 3, base_delay: Duration::from_millis(500) }
+    }
+}
+
+impl RetryPolicy {
+    /// Wait before retry number `retry` (1 = first retry). `jitter` in 0..1
+    /// scales it between half and one and a half times the nominal delay so
+    /// clients that failed together don't retry together.
+    fn backoff(&self, retry: u32, jitter: f64) -> Duration {
+        let nominal = self.base_delay * 2u32.saturating_pow(retry.saturating_sub(1));
+        nominal.mul_f64(0.5 + jitter.clamp(0.0, 1.0))
+    }
+}
+
+// Pause after a 429 without (readable) Retry-After, and the longest we honor.
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(60);
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60 * 60);
+
+/// Wait demanded by a Retry-After header, given as seconds or as an HTTP date.
+fn retry_after(header: Option<&str>, now_ms: f64) -> Duration {
+    let parsed = header.map(str::trim).and_then(|value| {
+        value.parse::<u64>().ok().map(Duration::from_secs).or_else(|| {
+            let at = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+            Some(Duration::from_millis((at.timestamp_millis() as f64 - now_ms).max(0.0) as u64))
+        })
+    });
+    parsed.unwrap_or(DEFAULT_RETRY_AFTER).min(MAX_RETRY_AFTER)
+}
+
+/// Errors worth another attempt: the network dropped or the server had a hiccup.
+/// A 429 is not: retrying is exactly what the server asked us not to do.
+pub(crate) fn is_transient(err: &EfaError) -> bool {
+    match err {
+        EfaError::Network(_) => true,
+        EfaError::Http { status } => *status >= 500,
+        _ => false,
+    }
+}
+
+pub type TransportFuture<'a> = Pin<Box<dyn Future<Output = Result<String, EfaError>> + 'a>>;
+
+/// Sends a GET request and returns the response body. `HttpTransport` goes
+/// to the network; tests plug in canned responses instead.
+pub trait Transport {
+    /// Body of a successful response to `url` (query included), giving up
+    /// after `timeout`.
+    fn get<'a>(&'a self, url: &'a str, timeout: Duration) -> TransportFuture<'a>;
+
+    /// Body of a successful response to POSTing `body`, of type
+    /// `content_type`, to `url`. EFA only needs GET; transports that don't
+    /// send anything else fail these requests.
+    fn post<'a>(&'a self, url: &'a str, content_type: &'a str, body: &'a str, timeout: Duration) -> TransportFuture<'a> {
+        let _ = (url, content_type, body, timeout);
+        Box::pin(async { Err(EfaError::Network("POST requests are not supported by this transport".to_string())) })
+    }
+}
+
+impl<T: Transport + ?Sized> Transport for Rc<T> {
+    fn get<'a>(&'a self, url: &'a str, timeout: Duration) -> TransportFuture<'a> {
+        (**self).get(url, timeout)
+    }
+
+    fn post<'a>(&'a self, url: &'a str, content_type: &'a str, body: &'a str, timeout: Duration) -> TransportFuture<'a> {
+        (**self).post(url, content_type, body, timeout)
+    }
+}
+
+/// The real network: gloo-net on wasm32 and reqwest otherwise.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct HttpTransport;
+
+impl Transport for HttpTransport {
+    fn get<'a>(&'a self, url: &'a str, timeout: Duration) -> TransportFuture<'a> {
+        Box::pin(send(url, None, timeout))
+    }
+
+    fn post<'a>(&'a self, url: &'a str, content_type: &'a str, body: &'a str, timeout: Duration) -> TransportFuture<'a> {
+        Box::pin(send(url, Some((content_type, body)), timeout))
+    }
+}
+
+/// Runs one request at a time: starting a request cancels the one still
+/// running, e.g. the stop search for what the user typed before. The
+/// cancelled request gets `EfaError::Cancelled`, and its HTTP request is
+/// aborted as its future is dropped.
+#[derive(Clone, Debug, Default)]
+pub struct RequestSlot {
+    current: Rc<RefCell<Option<AbortHandle>>>,
+}
+
+impl RequestSlot {
+    pub async fn run<T>(&self, request: impl Future<Output = Result<T, EfaError>>) -> Result<T, EfaError> {
+        let (handle, registration) = AbortHandle::new_pair();
+        if let Some(previous) = self.current.replace(Some(handle)) {
+            previous.abort();
+        }
+        match Abortable::new(request, registration).await {
+            Ok(result) => result,
+            Err(Aborted) => Err(EfaError::Cancelled),
+        }
+    }
+
+    /// Cancel the running request, if any.
+    pub fn cancel(&self) {
+        if let Some(current) = self.current.take() {
+            current.abort();
+        }
+    }
+}
+
+/// A request and the raw response to it, kept in debug mode.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Exchange {
+    pub at_ms: f64,
+    /// Full request URL, query included.
+    pub url: String,
+    pub response: Result<String, EfaError>,
+}
+
+/// The last requests of the clients sharing it with their raw responses,
+/// so parse failures users run into can be reproduced.
+#[derive(Clone, Debug)]
+pub struct Capture {
+    limit: usize,
+    exchanges: Rc<RefCell<VecDeque<Exchange>>>,
+}
+
+impl Capture {
+    /// Keep the last `limit` requests.
+    pub fn new(limit: usize) -> Self {
+        Capture { limit, exchanges: Rc::default() }
+    }
+
+    fn record(&self, exchange: Exchange) {
+        let mut exchanges = self.exchanges.borrow_mut();
+        exchanges.push_back(exchange);
+        while exchanges.len() > self.limit {
+            exchanges.pop_front();
+        }
+    }
+
+    /// Requests kept so far, oldest first.
+    pub fn exchanges(&self) -> Vec<Exchange> {
+        self.exchanges.borrow().iter().cloned().collect()
+    }
+
+    pub fn clear(&self) {
+        self.exchanges.borrow_mut().clear();
+    }
+}
+
+thread_local! {
+    // What `EfaClient::kvv()` hands out copies of, as the app configured it.
+    static KVV: RefCell<EfaClient> = RefCell::new(EfaClient::new(API_BASE, HttpTransport));
+}
+
+/// Change the client every `EfaClient::kvv()` starts out as from now on,
+/// e.g. `efa::configure(|kvv| kvv.with_language("en"))` when the user
+/// switches languages. Clients handed out before keep their configuration.
+pub fn configure(change: impl FnOnce(EfaClient) -> EfaClient) {
+    let current = KVV.with(|kvv| kvv.borrow().clone());
+    let changed = change(current);
+    KVV.with(|kvv| *kvv.borrow_mut() = changed);
+}
+
+/// What debug mode, a capture set up with `configure`, kept so far,
+/// oldest first.
+pub fn captured() -> Vec<Exchange> {
+    KVV.with(|kvv| kvv.borrow().capture.as_ref().map(Capture::exchanges).unwrap_or_default())
+}
+
+pub fn clear_captured() {
+    KVV.with(|kvv| {
+        if let Some(capture) = kvv.borrow().capture.as_ref() {
+            capture.clear();
+        }
+    });
+}
+
+/// The transport configured for KVV. The other backends' clients send
+/// their requests over it too, so `configure` covers every provider.
+pub fn transport() -> Rc<dyn Transport> {
+    KVV.with(|kvv| kvv.borrow().transport.clone())
+}
+
+/// Cross-platform fetch helper with the default retry policy.
+pub(crate) async fn fetch_text(url: &str, params: &Vec<(&str, String)>) -> Result<String, EfaError> {
+    fetch_text_with(&HttpTransport, url, params, &RetryPolicy::default()).await
+}
+
+/// Fetch `url` with `params` over `transport`, retrying transient failures
+/// with jittered exponential backoff.
+#[tracing::instrument(skip(transport, params, policy))]
+pub(crate) async fn fetch_text_with(
+    transport: &dyn Transport,
+    url: &str,
+    params: &Vec<(&str, String)>,
+    policy: &RetryPolicy,
+) -> Result<String, EfaError> {
+    let full = full_url(url, params)?;
+    fetch_with_retries(transport, None, url, &full, None, policy).await
+}
+
+/// POST `body`, of type `content_type`, to `url` over `transport`, retrying
+/// like `fetch_text_with`.
+#[tracing::instrument(skip(transport, body, policy))]
+pub(crate) async fn post_text_with(
+    transport: &dyn Transport,
+    url: &str,
+    content_type: &str,
+    body: &str,
+    policy: &RetryPolicy,
+) -> Result<String, EfaError> {
+    fetch_with_retries(transport, None, url, url, Some((content_type, body)), policy).await
+}
+
+// Every attempt, retries included, takes a token from `limiter` if there is one.
+async fn fetch_with_retries(
+    transport: &dyn Transport,
+    limiter: Option<&RefCell<TokenBucket>>,
+    url: &str,
+    full: &str,
+    post: Option<(&str, &str)>,
+    policy: &RetryPolicy,
+) -> Result<String, EfaError> {
+    let mut attempt = 1;
+    loop {
+        if let Some(limiter) = limiter {
+            throttle(limiter).await;
+        }
+        match fetch_once(transport, url, full, post, policy.timeout).await {
+            Err(e) if attempt < policy.max_attempts && is_transient(&e) => {
+                sleep(policy.backoff(attempt, jitter())).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+// Wait until `limiter` has a token for a request at the current priority;
+// lower priorities wait longer, see `TokenBucket::take`.
+async fn throttle(limiter: &RefCell<TokenBucket>) {
+    loop {
+        let taken = limiter.borrow_mut().take(diagnostics::now_ms(), priority::current());
+        match taken {
+            Ok(()) => return,
+            Err(wait) => sleep(wait).await,
+        }
+    }
+}
+
+pub(crate) fn full_url(url: &str, params: &[(&str, String)]) -> Result<String, EfaError> {
+    // serialize params into query string; a failure here is our bug, not
+    // the network's, so it must not be retried
     let qpairs: Vec<(&str, &str)> = params.iter().map(|(k, v)| (*k, v.as_str())).collect();
-    let query = serde_urlencoded::to_string(&qpairs).map_err(|e| e.to_string())?;
-    let full = if query.is_empty() { url.to_string() } else { format!("{}?{}", url, query) };
+    let query = serde_urlencoded::to_string(&qpairs).map_err(|e| EfaError::Parse(format!("query string: {e}")))?;
+    Ok(if query.is_empty() { url.to_string() } else { format!("{}?{}", url, query) })
+}
+
+// One attempt. The request slot is only held while the request is running,
+// not while waiting to retry.
+async fn fetch_once(transport: &dyn Transport, url: &str, full: &str, post: Option<(&str, &str)>, timeout: Duration) -> Result<String, EfaError> {
+    let _permit = SCHEDULER.acquire(priority::current()).await;
+    let started = diagnostics::now_ms();
+    // Don't even ask while the server wants this endpoint left alone.
+    if let Some(retry_after) = SCHEDULER.paused_for(url, started) {
+        return Err(EfaError::RateLimited { retry_after });
+    }
+    SCHEDULER.note_request(started);
+    let span = tracing::info_span!("efa_request", url = full, status = field::Empty, bytes = field::Empty, duration_ms = field::Empty);
+    let response = match post {
+        None => transport.get(full, timeout),
+        Some((content_type, body)) => transport.post(full, content_type, body, timeout),
+    };
+    let result = response.instrument(span.clone()).await.and_then(|body| {
+        if body.trim().is_empty() {
+            Err(EfaError::EmptyResponse)
+        } else {
+            Ok(body)
+        }
+    });
+    let duration_ms = diagnostics::now_ms() - started;
+    diagnostics::record_request(url, &result, duration_ms);
+    span.record("duration_ms", duration_ms.max(0.0) as u64);
+    if let Some(status) = diagnostics::status_of(&result) {
+        span.record("status", status);
+    }
+    match &result {
+        Ok(body) => {
+            span.record("bytes", body.len());
+        }
+        Err(e) => span.in_scope(|| tracing::warn!(error = %e, "EFA request failed")),
+    }
+    if let Err(EfaError::RateLimited { retry_after }) = &result {
+        SCHEDULER.pause(url, started + retry_after.as_millis() as f64);
+    }
+    result
+}
+
+fn timed_out(timeout: Duration) -> EfaError {
+    EfaError::Network(format!("no response within {} s", timeout.as_secs_f64()))
+}
+
+async fn send(full: &str, post: Option<(&str, &str)>, timeout: Duration) -> Result<String, EfaError> {
+    let (bytes, content_type) = send_raw(full, post, timeout).await?;
+    Ok(decode_body(&bytes, content_type.as_deref()))
+}
 
+/// Body of a binary download, e.g. a timetable feed. Goes past the rate
+/// limit and the response cache, so use it for rare, large files only.
+pub(crate) async fn fetch_bytes(url: &str, timeout: Duration) -> Result<Vec<u8>, EfaError> {
+    Ok(send_raw(url, None, timeout).await?.0)
+}
+
+// Body and Content-Type of a successful response to a GET, or a POST of
+// `post`: (content type, body).
+async fn send_raw(full: &str, post: Option<(&str, &str)>, timeout: Duration) -> Result<(Vec<u8>, Option<String>), EfaError> {
     #[cfg(target_arch = "wasm32")]
     {
         use gloo_net::http::Request;
-        let resp = Request::get(&full).send().await.map_err(|e| e.to_string())?;
-        let txt = resp.text().await.map_err(|e| e.to_string())?;
-        Ok(txt)
+        use leptos::web_sys::{self, AbortController};
+        use wasm_bindgen::closure::Closure;
+        use wasm_bindgen::JsCast;
+
+        // fetch has no timeout of its own: abort it from a timer instead.
+        let window = web_sys::window().ok_or_else(|| EfaError::Network("no window".to_string()))?;
+        let controller = AbortController::new().map_err(|e| EfaError::Network(format!("{e:?}")))?;
+        let signal = controller.signal();
+        // Dropping this future, e.g. for a `RequestSlot`, aborts the fetch.
+        struct AbortOnDrop(AbortController);
+        impl Drop for AbortOnDrop {
+            fn drop(&mut self) {
+                self.0.abort();
+            }
+        }
+        let _abort_on_drop = AbortOnDrop(controller.clone());
+        let abort = Closure::once_into_js(move || controller.abort());
+        let timer = window
+            .set_timeout_with_callback_and_timeout_and_arguments_0(abort.unchecked_ref(), timeout.as_millis() as i32)
+            .ok();
+        let result = async {
+            let request = match post {
+                None => Request::get(full).abort_signal(Some(&signal)).build(),
+                Some((content_type, body)) => Request::post(full).header("Content-Type", content_type).abort_signal(Some(&signal)).body(body),
+            };
+            let resp = match request {
+                Ok(request) => request.send().await,
+                Err(e) => Err(e),
+            };
+            let resp = resp.map_err(|e| if signal.aborted() { timed_out(timeout) } else { EfaError::Network(e.to_string()) })?;
+            if resp.status() == 429 {
+                let header = resp.headers().get("Retry-After");
+                return Err(EfaError::RateLimited { retry_after: retry_after(header.as_deref(), diagnostics::now_ms()) });
+            }
+            if !resp.ok() {
+                return Err(EfaError::Http { status: resp.status() });
+            }
+            let content_type = resp.headers().get("Content-Type");
+            let bytes = resp
+                .binary()
+                .await
+                .map_err(|e| if signal.aborted() { timed_out(timeout) } else { EfaError::Network(e.to_string()) })?;
+            Ok((bytes, content_type))
+        }
+        .await;
+        if let Some(timer) = timer {
+            window.clear_timeout_with_handle(timer);
+        }
+        result
     }
 
+    // reqwest cancels a request when its future is dropped.
     #[cfg(not(target_arch = "wasm32"))]
     {
-        let client = reqwest::Client::new();
-        let resp = client.get(&full).send().await.map_err(|e| e.to_string())?;
-        let txt = resp.text().await.map_err(|e| e.to_string())?;
-        Ok(txt)
+        let network = |e: reqwest::Error| if e.is_timeout() { timed_out(timeout) } else { EfaError::Network(e.to_string()) };
+        let request = match post {
+            None => native_client().get(full),
+            Some((content_type, body)) => native_client().post(full).header(reqwest::header::CONTENT_TYPE, content_type).body(body.to_string()),
+        };
+        let resp = request.timeout(timeout).send().await.map_err(network)?;
+        if resp.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            let header = resp.headers().get(reqwest::header::RETRY_AFTER).and_then(|v| v.to_str().ok());
+            return Err(EfaError::RateLimited { retry_after: retry_after(header, diagnostics::now_ms()) });
+        }
+        if !resp.status().is_success() {
+            return Err(EfaError::Http { status: resp.status().as_u16() });
+        }
+        let content_type = resp.headers().get(reqwest::header::CONTENT_TYPE).and_then(|v| v.to_str().ok()).map(str::to_string);
+        let bytes = resp.bytes().await.map_err(network)?;
+        Ok((bytes.to_vec(), content_type))
     }
 }
 
-const API_BASE: &str = "https://projekte.kvv-efa.de/sl3/";
+pub(crate) async fn sleep(duration: Duration) {
+    #[cfg(target_arch = "wasm32")]
+    {
+        let promise = js_sys::Promise::new(&mut |resolve, _| {
+            if let Some(window) = leptos::web_sys::window() {
+                let _ = window.set_timeout_with_callback_and_timeout_and_arguments_0(&resolve, duration.as_millis() as i32);
+            }
+        });
+        let _ = wasm_bindgen_futures::JsFuture::from(promise).await;
+    }
 
-#[derive(Clone, Debug, PartialEq)]
-pub struct StopSuggestion {
-    pub id: String,
-    pub name: String,
-    pub place: Option<String>,
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        tokio::time::sleep(duration).await;
+    }
 }
 
-#[derive(Clone, Debug, PartialEq)]
-pub struct Departure {
-    pub line: String,
-    pub direction: Option<String>,
-    pub time: String,
-    pub planned_time: String,
-    pub realtime_time: Option<String>,
-}
-
-fn common_params() -> Vec<(&'static str, String)> {
-    vec![
-        ("language", "de".to_string()),
-        ("stateless", "1".to_string()),
-        ("coordOutputFormat", "WGS84[DD.ddddd]".to_string()),
-        ("coordOutputFormatTail", "7".to_string()),
-    ]
-}
-
-pub async fn stopfinder(query: &str, max: usize) -> Result<Vec<StopSuggestion>, String> {
-    let mut params = common_params();
-    params.push(("outputFormat", "JSON".to_string()));
-    params.push(("locationServerActive", "1".to_string()));
-    params.push(("regionID_sf", "1".to_string()));
-    params.push(("type_sf", "any".to_string()));
-    params.push(("name_sf", query.to_string()));
-    params.push(("anyObjFilter_sf", "2".to_string())); // stops only
-    params.push(("reducedAnyPostcodeObjFilter_sf", "64".to_string()));
-    params.push(("reducedAnyTooManyObjFilter_sf", "2".to_string()));
-    params.push(("useHouseNumberList", "true".to_string()));
-    params.push(("anyMaxSizeHitList", max.to_string()));
-
-    let url = format!("{API_BASE}XML_STOPFINDER_REQUEST");
-    let body = fetch_text(&url, &params).await?;
-    parse_stopfinder_json(&body)
-}
-
-fn parse_stop_point(point: &Value) -> Option<StopSuggestion> {
-    let typ = point.get("type")?.as_str()?;
-    let typ = if typ == "any" {
-        point.get("anyType")?.as_str()?
+// Uniform-ish value in 0..1 for backoff jitter.
+fn jitter() -> f64 {
+    #[cfg(target_arch = "wasm32")]
+    {
+        js_sys::Math::random()
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        use std::hash::{BuildHasher, Hasher};
+        let random = std::collections::hash_map::RandomState::new().build_hasher().finish();
+        (random % 1000) as f64 / 1000.0
+    }
+}
+
+/// Shared native HTTP client. Its cookie jar carries EFA session cookies
+/// between a search and its follow-up requests.
+#[cfg(not(target_arch = "wasm32"))]
+fn native_client() -> &'static reqwest::Client {
+    static CLIENT: std::sync::OnceLock<reqwest::Client> = std::sync::OnceLock::new();
+    CLIENT.get_or_init(|| {
+        reqwest::Client::builder()
+            .cookie_store(true)
+            .build()
+            .expect("HTTP client configuration is valid")
+    })
+}
+
+/// EFA session to carry from a request to its follow-ups (e.g. paging).
+///
+/// Browsers do not let us manage the EFA cookies on wasm, so the session id
+/// announced in a response is passed explicitly as `sessionID` instead.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Session {
+    pub id: Option<String>,
+}
+
+impl Session {
+    /// Remember the session id announced in a response body, if any.
+    pub fn update_from(&mut self, body: &str) {
+        if let Some(id) = session_id_from_body(body) {
+            self.id = Some(id);
+        }
+    }
+
+    /// Add the session parameters for a follow-up request.
+    pub fn apply(&self, params: &mut Vec<(&'static str, String)>) {
+        if let Some(id) = &self.id {
+            params.retain(|(k, _)| *k != "stateless" && *k != "sessionID");
+            params.push(("sessionID", id.clone()));
+        }
+    }
+}
+
+/// Session id from an XML (`<itdRequest sessionID="..">`) or JSON
+/// (`"parameters": [{"name": "sessionID", ..}]`) response. "0" means stateless.
+fn session_id_from_body(body: &str) -> Option<String> {
+    let id = if body.trim_start().starts_with('{') {
+        let json: Value = serde_json::from_str(body).ok()?;
+        json.get("parameters")?
+            .as_array()?
+            .iter()
+            .find(|p| p.get("name").and_then(|n| n.as_str()) == Some("sessionID"))?
+            .get("value")?
+            .as_str()?
+            .to_string()
     } else {
-        typ
-    };
-    if typ != "stop" {
-        return None;
-    }
-    let name = decode_text(point.get("name")?.as_str()?);
-    let reference = point.get("ref")?;
-    let id = reference.get("id")?.as_str()?.to_string();
-    let place = reference
-        .get("place")
-        .and_then(|p| p.as_str())
-        .map(decode_text)
-        .filter(|p| !p.is_empty());
-    Some(StopSuggestion { id, name, place })
-}
-
-fn parse_stopfinder_json(body: &str) -> Result<Vec<StopSuggestion>, String> {
-    let json: Value = serde_json::from_str(body).map_err(|e| e.to_string())?;
-    let points = json
-        .get("stopFinder")
-        .and_then(|sf| sf.get("points"))
-        .or_else(|| json.get("stopFinder"));
-
-    let mut stops = Vec::new();
-    match points {
-        Some(Value::Object(map)) => {
-            if let Some(point) = map.get("point") {
-                if let Some(stop) = parse_stop_point(point) {
-                    stops.push(stop);
+        let mut reader = Reader::from_str(body);
+        let mut buf = Vec::new();
+        loop {
+            match reader.read_event_into(&mut buf).ok()? {
+                Event::Start(e) | Event::Empty(e) if e.name().as_ref() == b"itdRequest" => {
+                    let attr = e.try_get_attribute("sessionID").ok()??;
+                    break String::from_utf8_lossy(&attr.value).to_string();
                 }
+                Event::Eof => return None,
+                _ => {}
             }
+            buf.clear();
         }
-        Some(Value::Array(arr)) => {
-            for item in arr {
-                if let Some(stop) = parse_stop_point(item) {
-                    stops.push(stop);
-                }
-            }
+    };
+    (!id.is_empty() && id != "0").then_some(id)
+}
+
+const API_BASE: &str = "https://projekte.kvv-efa.de/sl3/";
+
+/// Client for one EFA deployment. `EfaClient::kvv()` talks to the KVV;
+/// other deployments (VRN, VVS, MVV, ...) or a mock server only differ in
+/// base URL, language and the parameters sent with every request.
+#[derive(Clone)]
+pub struct EfaClient {
+    base_url: String,
+    language: String,
+    default_params: Vec<(&'static str, String)>,
+    retry: RetryPolicy,
+    transport: Rc<dyn Transport>,
+    // Shared by clones, so creating a client per request doesn't get
+    // around the rate limit.
+    limiter: Rc<RefCell<TokenBucket>>,
+    capture: Option<Capture>,
+    board_format: BoardFormat,
+    schedule: Option<Rc<Feed>>,
+    realtime: Option<Rc<TripUpdates>>,
+    // Shared by clones, so the page after a board continues its session.
+    session: Rc<RefCell<Session>>,
+}
+
+impl fmt::Debug for EfaClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EfaClient")
+            .field("base_url", &self.base_url)
+            .field("language", &self.language)
+            .field("default_params", &self.default_params)
+            .field("retry", &self.retry)
+            .field("rate_limit", &self.limiter.borrow().limit())
+            .field("board_format", &self.board_format)
+            .field("offline_schedule", &self.schedule.is_some())
+            .field("offline_realtime", &self.realtime.is_some())
+            .field("session", &self.session.borrow().id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl EfaClient {
+    /// Client for the EFA at `base_url`, e.g. "https://www.vvs.de/mngvvs/",
+    /// sending its requests over `transport`, usually `HttpTransport`.
+    pub fn new(base_url: impl Into<String>, transport: impl Transport + 'static) -> Self {
+        let mut base_url = base_url.into();
+        if !base_url.ends_with('/') {
+            base_url.push('/');
+        }
+        EfaClient {
+            base_url,
+            language: "de".to_string(),
+            default_params: vec![
+                ("stateless", "1".to_string()),
+                ("coordOutputFormat", "WGS84[DD.ddddd]".to_string()),
+                ("coordOutputFormatTail", "7".to_string()),
+            ],
+            retry: RetryPolicy::default(),
+            transport: Rc::new(transport),
+            limiter: Rc::new(RefCell::new(TokenBucket::new(RateLimit::default()))),
+            capture: None,
+            board_format: BoardFormat::default(),
+            schedule: None,
+            realtime: None,
+            session: Rc::default(),
+        }
+    }
+
+    /// Client for the KVV, as set up with `configure`. Its session is its own.
+    pub fn kvv() -> Self {
+        KVV.with(|kvv| EfaClient { session: Rc::default(), ..kvv.borrow().clone() })
+    }
+
+    /// Language of names and messages in responses, e.g. "en".
+    pub fn with_language(mut self, language: impl Into<String>) -> Self {
+        self.language = language.into();
+        self
+    }
+
+    /// Send `key=value` with every request, replacing an earlier default for
+    /// `key`, e.g. `stateless=0` for boards that page.
+    pub fn with_param(mut self, key: &'static str, value: impl Into<String>) -> Self {
+        self.default_params.retain(|(k, _)| *k != key);
+        self.default_params.push((key, value.into()));
+        self
+    }
+
+    /// How long to wait for responses and how often to try again.
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Keep the raw requests and responses of this client in `capture`,
+    /// e.g. in debug mode; `None` keeps nothing.
+    pub fn with_capture(mut self, capture: Option<Capture>) -> Self {
+        self.capture = capture;
+        self
+    }
+
+    /// Ask for departure boards in `format`.
+    pub fn with_board_format(mut self, format: BoardFormat) -> Self {
+        self.board_format = format;
+        self
+    }
+
+    /// Fall back to the planned times of `schedule` for stop searches and
+    /// departure boards when EFA or the network is down; `None` doesn't.
+    pub fn with_schedule(mut self, schedule: Option<Rc<Feed>>) -> Self {
+        self.schedule = schedule;
+        self
+    }
+
+    /// Apply the delays and cancellations of a GTFS-Realtime feed to the
+    /// offline timetable; `None` shows its planned times only.
+    pub fn with_realtime(mut self, updates: Option<Rc<TripUpdates>>) -> Self {
+        self.realtime = updates;
+        self
+    }
+
+    pub fn board_format(&self) -> BoardFormat {
+        self.board_format
+    }
+
+    /// Parameters every request starts out with.
+    pub(crate) fn params(&self) -> Vec<(&'static str, String)> {
+        let mut params = vec![("language", self.language.clone())];
+        params.extend(self.default_params.iter().cloned());
+        params
+    }
+
+    pub(crate) fn url(&self, endpoint: &str) -> String {
+        format!("{}{endpoint}", self.base_url)
+    }
+
+    /// The client's defaults followed by the parameters of `request`.
+    pub(crate) fn request_params(&self, request: &impl EfaRequest) -> Vec<(&'static str, String)> {
+        let mut params = self.params();
+        params.extend(request.to_params());
+        params
+    }
+
+    pub(crate) async fn fetch_request(&self, request: &impl EfaRequest) -> Result<String, EfaError> {
+        self.fetch(request.endpoint(), &self.request_params(request)).await
+    }
+
+    /// Fetch `endpoint` with `params` under this client's retry policy,
+    /// answering from the response cache while an earlier answer is fresh.
+    /// Every attempt that does go out, retries included, takes a token from
+    /// the rate limit.
+    pub(crate) async fn fetch(&self, endpoint: &str, params: &Vec<(&str, String)>) -> Result<String, EfaError> {
+        let url = self.url(endpoint);
+        let Some(ttl) = cache_ttl(endpoint) else {
+            return self.fetch_uncached(&url, params).await;
+        };
+        let key = full_url(&url, params)?;
+        if let Some(body) = CACHE.with(|c| c.borrow_mut().get(&key, diagnostics::now_ms())) {
+            return Ok(body);
+        }
+        let body = self.fetch_uncached(&url, params).await?;
+        CACHE.with(|c| c.borrow_mut().put(key, body.clone(), diagnostics::now_ms(), ttl));
+        Ok(body)
+    }
+
+    // A request that goes out to the server, captured in debug mode.
+    async fn fetch_uncached(&self, url: &str, params: &Vec<(&str, String)>) -> Result<String, EfaError> {
+        let full = full_url(url, params);
+        let response = match &full {
+            Ok(full) => fetch_with_retries(self.transport.as_ref(), Some(&self.limiter), url, full, None, &self.retry).await,
+            Err(e) => Err(e.clone()),
+        };
+        if let Some(capture) = &self.capture {
+            let url = full.unwrap_or_else(|_| url.to_string());
+            capture.record(Exchange { at_ms: diagnostics::now_ms(), url, response: response.clone() });
         }
-        _ => {}
+        response
     }
 
-    Ok(stops)
+    /// Drop the cached response to `request`, e.g. one that turned out to
+    /// be unparsable, so the next fetch asks the server again.
+    pub(crate) fn uncache(&self, request: &impl EfaRequest) {
+        if let Ok(key) = full_url(&self.url(request.endpoint()), &self.request_params(request)) {
+            CACHE.with(|c| c.borrow_mut().entries.remove(&key));
+        }
+    }
 }
 
-pub async fn departures(station_id: &str, max: usize) -> Result<Vec<Departure>, String> {
-    let mut params = common_params();
-    params.push(("outputFormat", "XML".to_string()));
-    params.push(("type_dm", "stop".to_string()));
-    params.push(("name_dm", station_id.to_string()));
-    params.push(("useRealtime", "1".to_string()));
-    params.push(("mode", "direct".to_string()));
-    params.push(("ptOptionsActive", "1".to_string()));
-    params.push(("deleteAssignedStops_dm", "1".to_string()));
-    params.push(("useProxFootSearch", "0".to_string()));
-    params.push(("mergeDep", "1".to_string()));
-    params.push(("limit", max.to_string()));
+impl Default for EfaClient {
+    fn default() -> Self {
+        EfaClient::kvv()
+    }
+}
 
-    let url = format!("{API_BASE}XSLT_DM_REQUEST");
-    let body = fetch_text(&url, &params).await?;
-    parse_departures_xml(&body)
+/// One request to an EFA endpoint. Building the parameters needs no
+/// network, so tests can pin them down exactly.
+pub(crate) trait EfaRequest {
+    fn endpoint(&self) -> &'static str;
+    /// Parameters sent after the client's defaults.
+    fn to_params(&self) -> Vec<(&'static str, String)>;
 }
 
-pub async fn departures_live(station_id: &str, max: usize) -> Result<Vec<Departure>, String> {
-    departures(station_id, max).await
+pub async fn stopfinder(query: &str, max: usize) -> Result<Vec<StopSuggestion>, EfaError> {
+    EfaClient::kvv().stopfinder(query, max).await
 }
 
-fn parse_departures_xml(xml: &str) -> Result<Vec<Departure>, String> {
-    let mut reader = Reader::from_str(xml);
+/// `EfaClient::stopfinder_hits` against the KVV.
+pub async fn stopfinder_hits(query: &str, max: usize) -> Result<StopHits, EfaError> {
+    EfaClient::kvv().stopfinder_hits(query, max).await
+}
 
-    let mut buf = Vec::new();
-    let mut in_departure = false;
-    let mut in_datetime = false;
-    let mut in_rt_datetime = false;
+/// Search by name (XML_STOPFINDER_REQUEST) for the given kinds of place.
+pub(crate) struct StopfinderRequest<'a> {
+    pub query: &'a str,
+    pub max: usize,
+    pub types: &'a [LocationType],
+}
 
-    let mut current_line: Option<String> = None;
-    let mut current_direction: Option<String> = None;
-    let mut current_time: Option<String> = None;
-    let mut planned_time: Option<String> = None;
-    let mut realtime_time: Option<String> = None;
-    let mut departures = Vec::new();
+impl EfaRequest for StopfinderRequest<'_> {
+    fn endpoint(&self) -> &'static str {
+        "XML_STOPFINDER_REQUEST"
+    }
 
-    loop {
-        match reader.read_event_into(&mut buf) {
-            Ok(Event::Start(e)) => match e.name().as_ref() {
-                b"itdDeparture" => {
-                    in_departure = true;
-                    current_line = None;
-                    current_direction = None;
-                    current_time = None;
-                    planned_time = None;
-                    realtime_time = None;
-                }
-                b"itdDateTime" if in_departure => {
-                    if current_time.is_none() {
-                        in_datetime = true;
-                    }
-                }
-                b"itdRTDateTime" if in_departure => {
-                    if realtime_time.is_none() {
-                        in_rt_datetime = true;
-                    }
-                }
-                b"itdTime" if in_departure && in_datetime => {
-                    if let Some(t) = parse_time_from_attrs(&e) {
-                        planned_time = Some(t.clone());
-                        if realtime_time.is_none() {
-                            current_time = Some(t);
-                        }
-                    }
-                }
-                b"itdTime" if in_departure && in_rt_datetime => {
-                    if let Some(t) = parse_time_from_attrs(&e) {
-                        realtime_time = Some(t.clone());
-                        current_time = Some(t);
-                    }
-                }
-                b"itdServingLine" if in_departure => {
-                    parse_serving_line_attrs(&e, &mut current_line, &mut current_direction);
-                }
-                _ => {}
-            },
-            Ok(Event::Empty(e)) => match e.name().as_ref() {
-                b"itdTime" if in_departure && in_datetime => {
-                    if let Some(t) = parse_time_from_attrs(&e) {
-                        planned_time = Some(t.clone());
-                        if realtime_time.is_none() {
-                            current_time = Some(t);
-                        }
-                    }
-                }
-                b"itdTime" if in_departure && in_rt_datetime => {
-                    if let Some(t) = parse_time_from_attrs(&e) {
-                        realtime_time = Some(t.clone());
-                        current_time = Some(t);
-                    }
-                }
-                b"itdServingLine" if in_departure => {
-                    parse_serving_line_attrs(&e, &mut current_line, &mut current_direction);
-                }
-                _ => {}
-            },
-            Ok(Event::End(e)) => match e.name().as_ref() {
-                b"itdDateTime" => {
-                    in_datetime = false;
-                }
-                b"itdRTDateTime" => {
-                    in_rt_datetime = false;
-                }
-                b"itdDeparture" => {
-                    if let (Some(line), Some(time), Some(planned)) = (
-                        current_line.take(),
-                        current_time.take(),
-                        planned_time.take(),
-                    ) {
-                        departures.push(Departure {
-                            line,
-                            direction: current_direction.take(),
-                            time,
-                            planned_time: planned,
-                            realtime_time: realtime_time.take(),
-                        });
-                    }
-                    in_departure = false;
-                }
-                _ => {}
-            },
-            Ok(Event::Eof) => break,
-            Err(e) => return Err(e.to_string()),
-            _ => {}
+    fn to_params(&self) -> Vec<(&'static str, String)> {
+        let filter = self.types.iter().fold(0, |bits, t| bits | t.filter_bits());
+        vec![
+            ("outputFormat", "JSON".to_string()),
+            ("locationServerActive", "1".to_string()),
+            ("regionID_sf", "1".to_string()),
+            ("type_sf", "any".to_string()),
+            ("name_sf", self.query.to_string()),
+            ("anyObjFilter_sf", filter.to_string()),
+            ("reducedAnyPostcodeObjFilter_sf", "64".to_string()),
+            ("reducedAnyTooManyObjFilter_sf", "2".to_string()),
+            ("useHouseNumberList", "true".to_string()),
+            ("anyMaxSizeHitList", self.max.to_string()),
+        ]
+    }
+}
+
+/// `EfaClient::stopfinder_best` against the KVV.
+pub async fn stopfinder_best(query: &str) -> Result<Option<StopSuggestion>, EfaError> {
+    EfaClient::kvv().stopfinder_best(query).await
+}
+
+// EFA's "best" flag isn't always on the first hit; look at a few.
+const BEST_CANDIDATES: usize = 5;
+
+impl EfaClient {
+    #[tracing::instrument(skip(self))]
+    pub async fn stopfinder(&self, query: &str, max: usize) -> Result<Vec<StopSuggestion>, EfaError> {
+        Ok(self.stopfinder_hits(query, max).await?.stops)
+    }
+
+    /// Stops matching `query`, at most `max`, and whether there are more.
+    pub async fn stopfinder_hits(&self, query: &str, max: usize) -> Result<StopHits, EfaError> {
+        let body = match self.fetch_request(&StopfinderRequest { query, max, types: &[LocationType::Stop] }).await {
+            Err(e) => {
+                let schedule = self.offline(&e).ok_or(e)?;
+                return Ok(StopHits { stops: schedule.stopfinder(query, max), truncated: false, max });
+            }
+            body => body?,
+        };
+        parse_stopfinder_json(&body, max)
+    }
+
+    /// The stop `query` most likely means, for searches that go straight
+    /// to a board.
+    pub async fn stopfinder_best(&self, query: &str) -> Result<Option<StopSuggestion>, EfaError> {
+        Ok(self.stopfinder(query, BEST_CANDIDATES).await?.into_iter().next())
+    }
+
+    /// Places of the kinds in `types` matching `query`, at most `max`, e.g.
+    /// addresses and POIs as trip origins.
+    pub async fn locations(&self, query: &str, max: usize, types: &[LocationType]) -> Result<Vec<Location>, EfaError> {
+        let body = self.fetch_request(&StopfinderRequest { query, max, types }).await?;
+        Ok(parse_locations_json(&body, types)?.0)
+    }
+}
+
+/// Stops around a coordinate (XML_COORD_REQUEST).
+pub(crate) struct CoordRequest {
+    pub coord: Coord,
+    pub radius_m: u32,
+    pub max: usize,
+}
+
+impl EfaRequest for CoordRequest {
+    fn endpoint(&self) -> &'static str {
+        "XML_COORD_REQUEST"
+    }
+
+    fn to_params(&self) -> Vec<(&'static str, String)> {
+        let Coord { lat, lon } = self.coord;
+        vec![
+            ("outputFormat", "JSON".to_string()),
+            ("coord", format!("{lon:.5}:{lat:.5}:WGS84[DD.ddddd]")),
+            ("inclFilter", "1".to_string()),
+            ("type_1", "STOP".to_string()),
+            ("radius_1", self.radius_m.to_string()),
+            ("max", self.max.to_string()),
+        ]
+    }
+}
+
+/// `EfaClient::stops_near` against the KVV.
+pub async fn stops_near(lat: f64, lon: f64, radius_m: u32, max: usize) -> Result<Vec<NearbyStop>, EfaError> {
+    EfaClient::kvv().stops_near(lat, lon, radius_m, max).await
+}
+
+impl EfaClient {
+    /// Stops within `radius_m` meters of a WGS84 position, nearest first.
+    pub async fn stops_near(&self, lat: f64, lon: f64, radius_m: u32, max: usize) -> Result<Vec<NearbyStop>, EfaError> {
+        let request = CoordRequest { coord: Coord { lat, lon }, radius_m, max };
+        let body = self.fetch_request(&request).await?;
+        let mut stops = parse_coord_json(&body)?;
+        stops.truncate(max);
+        Ok(stops)
+    }
+}
+
+/// Stopfinder in coord mode: the stops EFA assigns to a position.
+pub(crate) struct CoordStopfinderRequest {
+    pub coord: Coord,
+}
+
+impl EfaRequest for CoordStopfinderRequest {
+    fn endpoint(&self) -> &'static str {
+        "XML_STOPFINDER_REQUEST"
+    }
+
+    fn to_params(&self) -> Vec<(&'static str, String)> {
+        let Coord { lat, lon } = self.coord;
+        vec![
+            ("outputFormat", "JSON".to_string()),
+            ("locationServerActive", "1".to_string()),
+            ("type_sf", "coord".to_string()),
+            ("name_sf", format!("{lon:.5}:{lat:.5}:WGS84[DD.ddddd]")),
+        ]
+    }
+}
+
+/// `EfaClient::stopfinder_by_coord` against the KVV.
+pub async fn stopfinder_by_coord(lat: f64, lon: f64) -> Result<Option<NearbyStop>, EfaError> {
+    EfaClient::kvv().stopfinder_by_coord(lat, lon).await
+}
+
+impl EfaClient {
+    /// The named stop nearest to a WGS84 position, e.g. to show departures
+    /// from where the user is standing. `None` if EFA assigns no stop.
+    pub async fn stopfinder_by_coord(&self, lat: f64, lon: f64) -> Result<Option<NearbyStop>, EfaError> {
+        let body = self.fetch_request(&CoordStopfinderRequest { coord: Coord { lat, lon } }).await?;
+        parse_assigned_stop(&body)
+    }
+}
+
+/// `EfaClient::departures` against the KVV.
+pub async fn departures(station_id: &str, max: usize, when: Option<NaiveDateTime>) -> Result<Vec<Departure>, EfaError> {
+    EfaClient::kvv().departures(station_id, max, when).await
+}
+
+/// `EfaClient::departures_batch` against the KVV.
+pub async fn departures_batch(station_ids: &[String], max: usize) -> Vec<Result<Vec<Departure>, EfaError>> {
+    EfaClient::kvv().departures_batch(station_ids, max).await
+}
+
+impl EfaClient {
+    /// Next departures at `station_id`, starting at `when` (local time) or now.
+    #[tracing::instrument(skip(self))]
+    pub async fn departures(
+        &self,
+        station_id: &str,
+        max: usize,
+        when: Option<NaiveDateTime>,
+    ) -> Result<Vec<Departure>, EfaError> {
+        self.departures_with(station_id, max, when, DeparturesOptions::default()).await
+    }
+
+    /// Like `departures`, with control over how the board is put together.
+    pub async fn departures_with(
+        &self,
+        station_id: &str,
+        max: usize,
+        when: Option<NaiveDateTime>,
+        options: DeparturesOptions,
+    ) -> Result<Vec<Departure>, EfaError> {
+        self.board(&DmRequest { station_id, max, when, arrivals: false, stop_sequences: false, options, format: self.board_format }, false).await
+    }
+
+    /// The next `max` departures at each of `station_ids`, e.g. for an
+    /// overview of several stops. The boards are asked for side by side;
+    /// one result per stop, in order.
+    pub async fn departures_batch(&self, station_ids: &[String], max: usize) -> Vec<Result<Vec<Departure>, EfaError>> {
+        join_all(station_ids.iter().map(|id| self.departures(id, max, None))).await
+    }
+
+    /// The `max` departures following `shown`, the board as loaded so far,
+    /// for a "later departures" button. Continues from the last planned time
+    /// instead of fetching a longer board from the start; an empty `shown`
+    /// gives the first page.
+    pub async fn departures_after(&self, station_id: &str, max: usize, shown: &[Departure]) -> Result<Vec<Departure>, EfaError> {
+        let Some(last) = shown.iter().map(|d| d.planned_time).max() else {
+            return self.departures(station_id, max, None).await;
+        };
+        // Departures in the minute of the last row come again; ask for enough
+        // to still fill the page.
+        let repeated = shown.iter().filter(|d| d.planned_time == last).count();
+        let request = DmRequest {
+            station_id,
+            max: max + repeated,
+            when: Some(last),
+            arrivals: false,
+            stop_sequences: false,
+            options: DeparturesOptions::default(),
+            format: self.board_format,
+        };
+        let more = self.board(&request, true).await?;
+        Ok(next_page(shown, more, max))
+    }
+
+    // A departure board, from the offline timetable while EFA is down.
+    async fn board(&self, request: &DmRequest<'_>, follow_up: bool) -> Result<Vec<Departure>, EfaError> {
+        let body = match self.fetch_board(request, follow_up).await {
+            Err(e) => {
+                let schedule = self.offline(&e).ok_or(e)?;
+                let when = request.when.unwrap_or_else(tz::now);
+                return Ok(match &self.realtime {
+                    Some(realtime) => schedule.live_departures(request.station_id, request.max, when, realtime),
+                    None => schedule.departures(request.station_id, request.max, when),
+                });
+            }
+            body => body?,
+        };
+        self.parse_board(&body, request.when.unwrap_or_else(tz::now))
+    }
+
+    /// The response to a departure monitor request. The board's session is
+    /// kept; a `follow_up` (the next page) continues it.
+    pub(crate) async fn fetch_board(&self, request: &DmRequest<'_>, follow_up: bool) -> Result<String, EfaError> {
+        let mut params = self.request_params(request);
+        if follow_up {
+            self.session.borrow().apply(&mut params);
+        }
+        let body = self.fetch(request.endpoint(), &params).await?;
+        if self.stateful() {
+            self.session.borrow_mut().update_from(&body);
+        }
+        Ok(body)
+    }
+
+    // Stateless requests (KVV's default) have no session to carry.
+    fn stateful(&self) -> bool {
+        !self.default_params.iter().any(|(k, v)| *k == "stateless" && v == "1")
+    }
+
+    /// Like `departures`, but with the previous and onward stops of every
+    /// departure. The response is considerably larger.
+    pub async fn departures_with_route(
+        &self,
+        station_id: &str,
+        max: usize,
+        when: Option<NaiveDateTime>,
+    ) -> Result<Vec<Departure>, EfaError> {
+        let body = self.fetch_board(&DmRequest { station_id, max, when, arrivals: false, stop_sequences: true, options: DeparturesOptions::default(), format: self.board_format }, false).await?;
+        self.parse_board(&body, when.unwrap_or_else(tz::now))
+    }
+
+    /// Next arrivals at `station_id`, e.g. to pick someone up. Times are
+    /// arrival times; everything else is as for `departures`.
+    pub async fn arrivals(
+        &self,
+        station_id: &str,
+        max: usize,
+        when: Option<NaiveDateTime>,
+    ) -> Result<Vec<Departure>, EfaError> {
+        let body = self.fetch_board(&DmRequest { station_id, max, when, arrivals: true, stop_sequences: false, options: DeparturesOptions::default(), format: self.board_format }, false).await?;
+        self.parse_board(&body, when.unwrap_or_else(tz::now))
+    }
+}
+
+/// `efa_core::parse_departures_xml`, with skipped rows kept for problem reports.
+pub(crate) fn parse_departures_xml(xml: &str, since: NaiveDateTime) -> Result<Vec<Departure>, EfaError> {
+    efa_core::parse_departures_xml(xml, since, diagnostics::record_warning)
+}
+
+impl EfaClient {
+    /// The offline timetable to answer from instead, if `err` means EFA
+    /// can't be reached rather than that the request was wrong.
+    fn offline(&self, err: &EfaError) -> Option<&Feed> {
+        let schedule = self.schedule.as_deref().filter(|_| is_transient(err))?;
+        diagnostics::record_warning(format!("EFA unavailable, showing the timetable: {err}"));
+        Some(schedule)
+    }
+
+    /// A departure monitor response in this client's `BoardFormat`, to a
+    /// request for the board at `since` (local time).
+    pub(crate) fn parse_board(&self, body: &str, since: NaiveDateTime) -> Result<Vec<Departure>, EfaError> {
+        match self.board_format {
+            BoardFormat::Xml => parse_departures_xml(body, since),
+            BoardFormat::Json => parse_departures_json(body, diagnostics::record_warning),
         }
-        buf.clear();
     }
+}
+
+// `more` without the rows already in `shown`, at most `max`.
+fn next_page(shown: &[Departure], more: Vec<Departure>, max: usize) -> Vec<Departure> {
+    let seen: HashSet<DepartureKey> = shown.iter().map(DepartureKey::of).collect();
+    more.into_iter().filter(|d| !seen.contains(&DepartureKey::of(d))).take(max).collect()
+}
+
+/// Which stops a departure board covers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DeparturesOptions {
+    /// One board for all platforms and sub-stops of the station. Without,
+    /// large stations like Hbf list every sub-stop's departures separately.
+    pub merge: bool,
+    /// Also list the stops assigned to the station, e.g. the Bahnhofsvorplatz
+    /// bus stops at Hbf.
+    pub assigned_stops: bool,
+}
+
+impl Default for DeparturesOptions {
+    fn default() -> Self {
+        DeparturesOptions { merge: true, assigned_stops: false }
+    }
+}
 
-    Ok(departures)
+/// Response format of the departure monitor. Both give the same boards;
+/// JSON responses are smaller.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BoardFormat {
+    #[default]
+    Xml,
+    Json,
 }
 
-fn parse_time_from_attrs(e: &quick_xml::events::BytesStart<'_>) -> Option<String> {
-    let mut hour = None;
-    let mut minute = None;
-    for attr in e.attributes().flatten() {
-        match attr.key.as_ref() {
-            b"hour" => hour = Some(String::from_utf8_lossy(&attr.value).to_string()),
-            b"minute" => minute = Some(String::from_utf8_lossy(&attr.value).to_string()),
-            _ => {}
-        }
-    }
-    if let (Some(h), Some(m)) = (hour, minute) {
-        if let (Ok(hh), Ok(mm)) = (h.parse::<u8>(), m.parse::<u8>()) {
-            return Some(format!("{hh:02}:{mm:02}"));
-        }
-    }
-    None
+/// Departure or arrival board of a stop (XSLT_DM_REQUEST).
+pub(crate) struct DmRequest<'a> {
+    pub station_id: &'a str,
+    pub max: usize,
+    /// Start of the board (local time); `None` means now.
+    pub when: Option<NaiveDateTime>,
+    pub arrivals: bool,
+    /// Ask for `itdPrevStopSeq`/`itdOnwardStopSeq` of every row.
+    pub stop_sequences: bool,
+    pub options: DeparturesOptions,
+    pub format: BoardFormat,
 }
 
-fn parse_serving_line_attrs(
-    e: &quick_xml::events::BytesStart<'_>,
-    current_line: &mut Option<String>,
-    current_direction: &mut Option<String>,
-) {
-    let mut symbol = None;
-    let mut number = None;
-    let mut direction = None;
-    for attr in e.attributes().flatten() {
-        match attr.key.as_ref() {
-            b"symbol" => symbol = Some(String::from_utf8_lossy(&attr.value).to_string()),
-            b"number" => number = Some(String::from_utf8_lossy(&attr.value).to_string()),
-            b"direction" => direction = Some(decode_text(&String::from_utf8_lossy(&attr.value))),
-            _ => {}
-        }
+impl EfaRequest for DmRequest<'_> {
+    fn endpoint(&self) -> &'static str {
+        "XSLT_DM_REQUEST"
     }
-    *current_line = symbol.or(number);
-    *current_direction = direction;
-}
 
-fn decode_text(input: &str) -> String {
-    decode_html_entities(input).to_string()
+    fn to_params(&self) -> Vec<(&'static str, String)> {
+        let mut params = vec![
+            ("outputFormat", match self.format { BoardFormat::Xml => "XML", BoardFormat::Json => "JSON" }.to_string()),
+            ("type_dm", "stop".to_string()),
+            ("name_dm", self.station_id.to_string()),
+            ("useRealtime", "1".to_string()),
+            ("mode", "direct".to_string()),
+            ("ptOptionsActive", "1".to_string()),
+            ("deleteAssignedStops_dm", if self.options.assigned_stops { "0" } else { "1" }.to_string()),
+            ("useProxFootSearch", "0".to_string()),
+            ("mergeDep", if self.options.merge { "1" } else { "0" }.to_string()),
+            ("limit", self.max.to_string()),
+            ("itdDateTimeDepArr", if self.arrivals { "arr" } else { "dep" }.to_string()),
+        ];
+        if let Some(when) = self.when {
+            params.push(("itdDate", when.format("%Y%m%d").to_string()));
+            params.push(("itdTime", when.format("%H%M").to_string()));
+        }
+        if self.stop_sequences {
+            params.push(("includeCompleteStopSeq", "1".to_string()));
+        }
+        params
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{parse_departures_xml, parse_stopfinder_json, departures, stopfinder};
-    use tokio::time::{timeout, Duration};
+    use super::{
+        cache_ttl, hhmm, is_transient, next_page, parse_departures_xml, parse_locations_json, parse_stopfinder_json,
+        retry_after, BoardFormat, Capture, Coord, CoordRequest, Departure, DeparturesOptions, DmRequest, EfaClient,
+        EfaError, EfaRequest, HttpTransport, Location, LocationType, RateLimit, RequestSlot, ResponseCache, RetryPolicy, Session,
+        StopHits, StopfinderRequest, TokenBucket, Transport, TransportFuture, TransportMode, MAX_CACHED,
+    };
+    use chrono::{NaiveDate, NaiveDateTime};
+    use efa_core::CallingPoint;
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    // When the boards in testdata/ were asked for.
+    fn asked_at() -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 1, 15).unwrap().and_hms_opt(8, 1, 0).unwrap()
+    }
 
     #[test]
     fn parse_departures_xml_extracts_line_time_direction() {
@@ -319,7 +1207,7 @@ mod tests {
                     </itdRTDateTime>
                     <itdServingLine symbol="S1" direction="Hbf" motType="1" />
                   </itdDeparture>
-                  <itdDeparture stopID="1002">
+                  <itdDeparture stopID="1002" platform="3">
                     <itdDateTime>
                       <itdDate year="2024" month="01" day="01" weekday="1" />
                       <itdTime hour="09" minute="30" />
@@ -331,20 +1219,401 @@ mod tests {
             </itdRequest>
         "#;
 
-        let departures = parse_departures_xml(xml).expect("parse succeeds");
+        let departures = parse_departures_xml(xml, asked_at()).expect("parse succeeds");
         assert_eq!(departures.len(), 2);
 
-        assert_eq!(departures[0].time, "08:07");
-        assert_eq!(departures[0].planned_time, "08:05");
-        assert_eq!(departures[0].realtime_time.as_deref(), Some("08:07"));
+        let day = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        assert_eq!(departures[0].time, day.and_hms_opt(8, 7, 0).unwrap());
+        assert_eq!(departures[0].planned_time, day.and_hms_opt(8, 5, 0).unwrap());
+        assert_eq!(departures[0].realtime_time.map(|t| hhmm(&t)).as_deref(), Some("08:07"));
         assert_eq!(departures[0].line, "S1");
         assert_eq!(departures[0].direction.as_deref(), Some("Hbf"));
+        assert_eq!(departures[0].delay_minutes, Some(2));
+        assert!(!departures[0].cancelled);
 
-        assert_eq!(departures[1].time, "09:30");
-        assert_eq!(departures[1].planned_time, "09:30");
+        assert_eq!(hhmm(&departures[1].time), "09:30");
+        assert_eq!(departures[1].planned_time, departures[1].time);
         assert_eq!(departures[1].realtime_time, None);
         assert_eq!(departures[1].line, "2");
         assert_eq!(departures[1].direction.as_deref(), Some("Durlach"));
+        assert_eq!(departures[1].delay_minutes, None);
+        assert_eq!(departures[0].platform, None);
+        assert_eq!(departures[1].platform.as_deref(), Some("3"));
+        assert_eq!(departures[0].mode, TransportMode::SBahn);
+        assert_eq!(departures[1].mode, TransportMode::LightRail);
+    }
+
+    #[test]
+    fn next_page_skips_rows_already_shown() {
+        let at = |h, m| NaiveDate::from_ymd_opt(2024, 1, 1).unwrap().and_hms_opt(h, m, 0).unwrap();
+        let dep = |line: &str, h, m| Departure { line: line.to_string(), time: at(h, m), planned_time: at(h, m), ..Default::default() };
+        let shown = vec![dep("S1", 8, 5), dep("2", 8, 10), dep("S2", 8, 10)];
+        // The next board starts at 08:10 and repeats its rows.
+        let more = vec![dep("2", 8, 10), dep("S2", 8, 10), dep("5", 8, 11), dep("S1", 8, 25), dep("2", 8, 30)];
+        let page: Vec<_> = next_page(&shown, more, 2).into_iter().map(|d| d.line).collect();
+        assert_eq!(page, ["5", "S1"]);
+    }
+
+    #[test]
+    fn parse_departures_xml_reads_operator_and_replacement_services() {
+        let xml = r#"
+            <itdDepartureMonitorRequest>
+              <itdDepartureList>
+              <itdDeparture stopID="1001">
+                <itdDateTime><itdDate year="2024" month="1" day="1" /><itdTime hour="8" minute="4" /></itdDateTime>
+                <itdServingLine symbol="SEV S31" direction="Bruchsal" motType="5">
+                  <itdNoTrain name="Bus">Bus</itdNoTrain>
+                  <itdOperator><code>07</code><name>DB Regio Bus</name></itdOperator>
+                </itdServingLine>
+              </itdDeparture>
+              <itdDeparture stopID="1001">
+                <itdDateTime><itdDate year="2024" month="1" day="1" /><itdTime hour="8" minute="9" /></itdDateTime>
+                <itdServingLine symbol="S1" direction="Hochstetten" motType="17" />
+              </itdDeparture>
+              <itdDeparture stopID="1001">
+                <itdDateTime><itdDate year="2024" month="1" day="1" /><itdTime hour="8" minute="12" /></itdDateTime>
+                <itdServingLine symbol="107" direction="Durlach" motType="5" />
+              </itdDeparture>
+              </itdDepartureList>
+            </itdDepartureMonitorRequest>
+        "#;
+
+        let departures = parse_departures_xml(xml, asked_at()).expect("parse succeeds");
+        assert_eq!(departures[0].operator.as_deref(), Some("DB Regio Bus"));
+        assert_eq!(departures[1].operator, None, "the operator must not leak into the next departure");
+        let replacement: Vec<_> = departures.iter().map(Departure::is_replacement).collect();
+        assert_eq!(replacement, [true, true, false]);
+    }
+
+    #[test]
+    fn parse_departures_xml_reads_reported_delay_and_cancellations() {
+        let xml = r#"
+            <itdDepartureMonitorRequest>
+              <itdDateTime>
+                <itdDate year="2024" month="1" day="1" />
+                <itdTime hour="23" minute="50" />
+              </itdDateTime>
+              <itdDepartureList>
+              <itdDeparture stopID="1001">
+                <itdDateTime><itdTime hour="23" minute="58" /></itdDateTime>
+                <itdRTDateTime><itdDate year="2024" month="1" day="2" /><itdTime hour="0" minute="3" /></itdRTDateTime>
+                <itdServingLine symbol="S1" direction="Hochstetten" delay="5" />
+              </itdDeparture>
+              <itdDeparture stopID="1001">
+                <itdDateTime><itdTime hour="8" minute="10" /></itdDateTime>
+                <itdServingLine symbol="S2" direction="Spöck" delay="-9999" />
+              </itdDeparture>
+              <itdDeparture stopID="1001" realtimeTripStatus="TRIP_CANCELLED">
+                <itdDateTime><itdTime hour="8" minute="20" /></itdDateTime>
+                <itdServingLine symbol="2" direction="Wolfartsweier" />
+              </itdDeparture>
+              </itdDepartureList>
+            </itdDepartureMonitorRequest>
+        "#;
+
+        let departures = parse_departures_xml(xml, asked_at()).expect("parse succeeds");
+        assert_eq!(departures.len(), 3);
+        assert_eq!(departures[0].delay_minutes, Some(5));
+        assert_eq!(departures[0].time, NaiveDate::from_ymd_opt(2024, 1, 2).unwrap().and_hms_opt(0, 3, 0).unwrap());
+        assert!(!departures[0].cancelled);
+        assert!(departures[1].cancelled);
+        assert_eq!(departures[1].delay_minutes, None);
+        assert!(departures[2].cancelled);
+        assert_eq!(departures[2].time, NaiveDate::from_ymd_opt(2024, 1, 1).unwrap().and_hms_opt(8, 20, 0).unwrap());
+    }
+
+    #[test]
+    fn error_message_without_departures_is_reported() {
+        let xml = r#"
+            <itdRequest>
+              <itdMessageList>
+                <itdMessage type="error" module="BROKER" code="-4050">stop not found</itdMessage>
+              </itdMessageList>
+              <itdDepartureMonitorRequest><itdDepartureList /></itdDepartureMonitorRequest>
+            </itdRequest>
+        "#;
+        assert_eq!(parse_departures_xml(xml, asked_at()), Err(EfaError::ServerMessage("stop not found".to_string())));
+
+        let coded = r#"<itdRequest><itdMessage type="error" code="-4050" /></itdRequest>"#;
+        assert_eq!(parse_departures_xml(coded, asked_at()), Err(EfaError::ServerMessage("code -4050".to_string())));
+        let info = r#"<itdRequest><itdMessage type="info" code="1" /></itdRequest>"#;
+        assert_eq!(parse_departures_xml(info, asked_at()), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn stop_sequences_do_not_touch_departure_times() {
+        let xml = r#"
+            <itdDepartureList>
+              <itdDeparture stopID="7000090">
+                <itdDateTime>
+                  <itdDate year="2024" month="1" day="15" />
+                  <itdTime hour="8" minute="3" />
+                </itdDateTime>
+                <itdServingLine symbol="2" direction="Wolfartsweier" />
+                <itdPrevStopSeq>
+                  <itdPoint stopID="7000044" name="Karlsruhe, Europaplatz" nameWO="Europaplatz">
+                    <itdDateTime>
+                      <itdDate year="2024" month="1" day="15" />
+                      <itdTime hour="7" minute="58" />
+                    </itdDateTime>
+                  </itdPoint>
+                </itdPrevStopSeq>
+                <itdOnwardStopSeq>
+                  <itdPoint stopID="7000001" name="Karlsruhe, Marktplatz (Pyramide U)" nameWO="Marktplatz (Pyramide U)">
+                    <itdRTDateTime>
+                      <itdDate year="2024" month="1" day="15" />
+                      <itdTime hour="8" minute="9" />
+                    </itdRTDateTime>
+                  </itdPoint>
+                  <itdPoint stopID="7000002" name="Durlacher Tor" />
+                  <itdPoint name="unknown stop" />
+                </itdOnwardStopSeq>
+              </itdDeparture>
+            </itdDepartureList>
+        "#;
+
+        let departures = parse_departures_xml(xml, asked_at()).expect("parse succeeds");
+        let departure = &departures[0];
+        assert_eq!(hhmm(&departure.time), "08:03");
+        assert_eq!(departure.realtime_time, None);
+        let names = |stops: &[CallingPoint]| stops.iter().map(|s| s.name.clone()).collect::<Vec<_>>();
+        assert_eq!(names(&departure.previous_stops), ["Europaplatz"]);
+        assert_eq!(names(&departure.onward_stops), ["Marktplatz (Pyramide U)", "Durlacher Tor"]);
+        assert_eq!(departure.continues_via(1).as_deref(), Some("Marktplatz (Pyramide U)"));
+        assert_eq!(departure.continues_via(5).as_deref(), Some("Marktplatz (Pyramide U) → Durlacher Tor"));
+        assert_eq!(Departure::default().continues_via(5), None);
+    }
+
+    #[test]
+    fn realtime_after_midnight_counts_as_delay() {
+        let xml = r#"
+            <itdDepartureList>
+              <itdDeparture stopID="1001">
+                <itdDateTime>
+                  <itdDate year="2024" month="12" day="31" />
+                  <itdTime hour="23" minute="58" />
+                </itdDateTime>
+                <itdRTDateTime>
+                  <itdDate year="2025" month="1" day="1" />
+                  <itdTime hour="0" minute="3" />
+                </itdRTDateTime>
+                <itdServingLine symbol="S1" direction="Hochstetten" />
+              </itdDeparture>
+            </itdDepartureList>
+        "#;
+
+        let departures = parse_departures_xml(xml, asked_at()).expect("parse succeeds");
+        assert_eq!(departures[0].delay_minutes, Some(5));
+        assert_eq!(departures[0].time, NaiveDate::from_ymd_opt(2025, 1, 1).unwrap().and_hms_opt(0, 3, 0).unwrap());
+    }
+
+    #[test]
+    fn undated_times_are_taken_near_the_time_asked_for() {
+        let xml = r#"
+            <itdDepartureList>
+              <itdDeparture stopID="1001">
+                <itdDateTime><itdTime hour="23" minute="58" /></itdDateTime>
+                <itdRTDateTime><itdTime hour="0" minute="3" /></itdRTDateTime>
+                <itdServingLine symbol="S1" direction="Hochstetten" />
+              </itdDeparture>
+              <itdDeparture stopID="1001">
+                <itdDateTime><itdTime hour="0" minute="10" /></itdDateTime>
+                <itdServingLine symbol="2" direction="Wolfartsweier" />
+              </itdDeparture>
+            </itdDepartureList>
+        "#;
+
+        let new_years_eve = NaiveDate::from_ymd_opt(2024, 12, 31).unwrap();
+        let departures = parse_departures_xml(xml, new_years_eve.and_hms_opt(23, 50, 0).unwrap()).expect("parse succeeds");
+        let new_year = NaiveDate::from_ymd_opt(2025, 1, 1).unwrap();
+        assert_eq!(departures.len(), 2, "rows without itdDate are kept");
+        assert_eq!(departures[0].planned_time, new_years_eve.and_hms_opt(23, 58, 0).unwrap());
+        assert_eq!(departures[0].time, new_year.and_hms_opt(0, 3, 0).unwrap());
+        assert_eq!(departures[0].delay_minutes, Some(5));
+        assert_eq!(departures[1].time, new_year.and_hms_opt(0, 10, 0).unwrap());
+    }
+
+    #[test]
+    fn cached_responses_expire_and_are_bounded() {
+        let mut cache = ResponseCache::default();
+        cache.put("a".to_string(), "board".to_string(), 0.0, Duration::from_secs(20));
+        assert_eq!(cache.get("a", 19_999.0).as_deref(), Some("board"));
+        assert_eq!(cache.get("a", 20_000.0), None);
+
+        for i in 0..=MAX_CACHED {
+            cache.put(i.to_string(), String::new(), i as f64, Duration::from_secs(60));
+        }
+        assert_eq!(cache.entries.len(), MAX_CACHED);
+        assert_eq!(cache.get("0", 100.0), None);
+        assert!(cache.get(&MAX_CACHED.to_string(), 100.0).is_some());
+        assert_eq!(cache_ttl("XSLT_TRIP_REQUEST"), None);
+    }
+
+    #[test]
+    fn retries_back_off_exponentially_with_jitter() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.backoff(1, 0.5), Duration::from_millis(500));
+        assert_eq!(policy.backoff(3, 0.5), Duration::from_millis(2000));
+        assert_eq!(policy.backoff(1, 0.0), Duration::from_millis(250));
+        assert_eq!(policy.backoff(1, 1.0), Duration::from_millis(750));
+
+        assert!(is_transient(&EfaError::Network("reset".to_string())));
+        assert!(is_transient(&EfaError::Http { status: 502 }));
+        assert!(!is_transient(&EfaError::Http { status: 404 }));
+        assert!(!is_transient(&EfaError::Parse("eof".to_string())));
+    }
+
+    #[test]
+    fn arrival_boards_parse_like_departure_boards() {
+        let arrivals = parse_departures_xml(include_str!("../testdata/arrivals.xml"), asked_at()).expect("parse succeeds");
+        let lines: Vec<_> = arrivals.iter().map(|a| (a.line.as_str(), hhmm(&a.time))).collect();
+        assert_eq!(lines, [("2", "08:04".to_string()), ("S4", "08:10".to_string())]);
+    }
+
+    #[test]
+    fn departures_round_trip_through_json() {
+        let deps = parse_departures_xml(include_str!("../testdata/departures.xml"), asked_at()).expect("parse succeeds");
+        let json = serde_json::to_value(&deps).expect("departures serialize");
+        assert!(json[0]["planned_time"].as_str().is_some_and(|t| t.contains('T')));
+        let back: Vec<Departure> = serde_json::from_value(json).expect("departures deserialize");
+        assert_eq!(back, deps);
+    }
+
+    fn as_str<'a>(params: &'a [(&'static str, String)]) -> Vec<(&'static str, &'a str)> {
+        params.iter().map(|(k, v)| (*k, v.as_str())).collect()
+    }
+
+    #[test]
+    fn stopfinder_and_coord_parameters() {
+        let stopfinder = StopfinderRequest { query: "Karlsruhe Hbf", max: 5, types: &[LocationType::Stop] }.to_params();
+        assert_eq!(
+            as_str(&stopfinder),
+            [
+                ("outputFormat", "JSON"),
+                ("locationServerActive", "1"),
+                ("regionID_sf", "1"),
+                ("type_sf", "any"),
+                ("name_sf", "Karlsruhe Hbf"),
+                ("anyObjFilter_sf", "2"),
+                ("reducedAnyPostcodeObjFilter_sf", "64"),
+                ("reducedAnyTooManyObjFilter_sf", "2"),
+                ("useHouseNumberList", "true"),
+                ("anyMaxSizeHitList", "5"),
+            ]
+        );
+
+        let coord = CoordRequest { coord: Coord { lat: 49.009, lon: 8.4037 }, radius_m: 600, max: 5 }.to_params();
+        assert_eq!(
+            as_str(&coord),
+            [
+                ("outputFormat", "JSON"),
+                ("coord", "8.40370:49.00900:WGS84[DD.ddddd]"),
+                ("inclFilter", "1"),
+                ("type_1", "STOP"),
+                ("radius_1", "600"),
+                ("max", "5"),
+            ]
+        );
+    }
+
+    #[test]
+    fn departure_monitor_parameters() {
+        let now = DmRequest { station_id: "7001004", max: 10, when: None, arrivals: false, stop_sequences: false, options: DeparturesOptions::default(), format: BoardFormat::Xml }.to_params();
+        assert_eq!(
+            as_str(&now),
+            [
+                ("outputFormat", "XML"),
+                ("type_dm", "stop"),
+                ("name_dm", "7001004"),
+                ("useRealtime", "1"),
+                ("mode", "direct"),
+                ("ptOptionsActive", "1"),
+                ("deleteAssignedStops_dm", "1"),
+                ("useProxFootSearch", "0"),
+                ("mergeDep", "1"),
+                ("limit", "10"),
+                ("itdDateTimeDepArr", "dep"),
+            ]
+        );
+
+        let when = NaiveDate::from_ymd_opt(2024, 1, 16).unwrap().and_hms_opt(7, 5, 0);
+        let later = DmRequest { station_id: "7001004", max: 10, when, arrivals: true, stop_sequences: false, options: DeparturesOptions::default(), format: BoardFormat::Json }.to_params();
+        assert_eq!(as_str(&later[..1]), [("outputFormat", "JSON")]);
+        assert_eq!(as_str(&later[10..]), [("itdDateTimeDepArr", "arr"), ("itdDate", "20240116"), ("itdTime", "0705")]);
+
+        let route = DmRequest { station_id: "7001004", max: 10, when: None, arrivals: false, stop_sequences: true, options: DeparturesOptions::default(), format: BoardFormat::Xml };
+        assert_eq!(as_str(&route.to_params()[11..]), [("includeCompleteStopSeq", "1")]);
+
+        let options = DeparturesOptions { merge: false, assigned_stops: true };
+        let split = DmRequest { station_id: "7000090", max: 10, when: None, arrivals: false, stop_sequences: false, options, format: BoardFormat::Xml };
+        assert_eq!(
+            as_str(&split.to_params()[6..9]),
+            [("deleteAssignedStops_dm", "0"), ("useProxFootSearch", "0"), ("mergeDep", "0")]
+        );
+    }
+
+    #[test]
+    fn client_defaults_come_first() {
+        let client = EfaClient::new("https://www.vvs.de/mngvvs", HttpTransport).with_language("en");
+        let params = client.request_params(&StopfinderRequest { query: "Hbf", max: 1, types: &[LocationType::Stop] });
+        assert_eq!(
+            as_str(&params[..5]),
+            [
+                ("language", "en"),
+                ("stateless", "1"),
+                ("coordOutputFormat", "WGS84[DD.ddddd]"),
+                ("coordOutputFormatTail", "7"),
+                ("outputFormat", "JSON"),
+            ]
+        );
+    }
+
+    #[test]
+    fn clients_follow_the_app_language() {
+        assert_eq!(EfaClient::kvv().params()[0], ("language", "de".to_string()));
+        super::configure(|kvv| kvv.with_language("en"));
+        assert_eq!(EfaClient::kvv().params()[0], ("language", "en".to_string()));
+        let german = EfaClient::kvv().with_language("de");
+        assert_eq!(german.params()[0], ("language", "de".to_string()));
+    }
+
+    #[test]
+    fn retry_after_accepts_seconds_and_dates() {
+        let now = chrono::DateTime::parse_from_rfc2822("Mon, 15 Jan 2024 08:00:00 GMT").unwrap().timestamp_millis() as f64;
+        assert_eq!(retry_after(Some("120"), now), Duration::from_secs(120));
+        assert_eq!(retry_after(Some("Mon, 15 Jan 2024 08:01:30 GMT"), now), Duration::from_secs(90));
+        assert_eq!(retry_after(Some("Mon, 15 Jan 2024 07:00:00 GMT"), now), Duration::ZERO);
+        assert_eq!(retry_after(None, now), Duration::from_secs(60));
+        assert_eq!(retry_after(Some("soon"), now), Duration::from_secs(60));
+        assert_eq!(retry_after(Some("86400"), now), Duration::from_secs(60 * 60));
+    }
+
+    #[test]
+    fn client_is_configurable_per_deployment() {
+        let vvs = EfaClient::new("https://www.vvs.de/mngvvs", HttpTransport).with_language("en").with_param("stateless", "0");
+        assert_eq!(vvs.url("XSLT_DM_REQUEST"), "https://www.vvs.de/mngvvs/XSLT_DM_REQUEST");
+        let params = vvs.params();
+        assert_eq!(params[0], ("language", "en".to_string()));
+        assert_eq!(params.iter().filter(|(k, _)| *k == "stateless").count(), 1);
+        assert!(params.contains(&("stateless", "0".to_string())));
+        assert_eq!(EfaClient::default().url("XSLT_DM_REQUEST"), "https://projekte.kvv-efa.de/sl3/XSLT_DM_REQUEST");
+    }
+
+    #[test]
+    fn session_id_is_read_from_xml_and_json() {
+        let mut session = Session::default();
+        session.update_from(r#"<?xml version="1.0"?><itdRequest sessionID="0" serverID="efa10"></itdRequest>"#);
+        assert_eq!(session.id, None);
+
+        session.update_from(r#"<itdRequest sessionID="KVV2_1234" serverID="efa10"></itdRequest>"#);
+        assert_eq!(session.id.as_deref(), Some("KVV2_1234"));
+
+        session.update_from(r#"{"parameters":[{"name":"serverID","value":"efa10"},{"name":"sessionID","value":"KVV2_5678"}]}"#);
+        assert_eq!(session.id.as_deref(), Some("KVV2_5678"));
+
+        let mut params = vec![("stateless", "1".to_string()), ("limit", "5".to_string())];
+        session.apply(&mut params);
+        assert_eq!(params, vec![("limit", "5".to_string()), ("sessionID", "KVV2_5678".to_string())]);
     }
 
     #[test]
@@ -374,13 +1643,255 @@ mod tests {
         }
         "#;
 
-        let stops = parse_stopfinder_json(json).expect("parse succeeds");
+        let hits = parse_stopfinder_json(json, 10).expect("parse succeeds");
+        assert!(!hits.truncated);
+        assert_eq!(hits.more(), None);
+        let stops = hits.stops;
         assert_eq!(stops.len(), 1);
         assert_eq!(stops[0].id, "7000101");
         assert_eq!(stops[0].name, "Karlsruhe Hbf");
         assert_eq!(stops[0].place.as_deref(), Some("Karlsruhe"));
     }
 
+    #[test]
+    fn capped_stopfinder_lists_are_truncated() {
+        let json = include_str!("../testdata/stopfinder.json");
+        // Three hits, one of them a POI, for a limit of three.
+        let hits = parse_stopfinder_json(json, 3).expect("parse succeeds");
+        assert_eq!(hits.stops.len(), 2);
+        assert!(hits.truncated);
+        assert_eq!(hits.more(), Some(6));
+        assert!(!parse_stopfinder_json(json, 4).expect("parse succeeds").truncated);
+
+        let long = parse_stopfinder_json(json, 3).map(|h| StopHits { max: 40, ..h }).unwrap();
+        assert_eq!(long.more(), None);
+    }
+
+    #[test]
+    fn stopfinder_ranks_the_best_match_first() {
+        let point = |id: &str, quality: &str, best: &str| {
+            format!(r#"{{"type":"any","anyType":"stop","name":"Stop {id}","quality":"{quality}","best":"{best}","ref":{{"id":"{id}"}}}}"#)
+        };
+        let json = format!(
+            r#"{{"stopFinder":{{"points":[{},{},{},{}]}}}}"#,
+            point("1", "700", "0"),
+            point("2", "820", "0"),
+            point("3", "650", "1"),
+            point("4", "820", "0"),
+        );
+        let stops = parse_stopfinder_json(&json, 10).expect("parse succeeds").stops;
+        let ids: Vec<_> = stops.iter().map(|s| s.id.as_str()).collect();
+        assert_eq!(ids, ["3", "2", "4", "1"]);
+        assert!(stops[0].best);
+        assert_eq!(stops[1].quality, Some(820));
+
+        // Favorites keep only the stop itself.
+        let stored = serde_json::to_string(&stops[0]).unwrap();
+        assert!(!stored.contains("quality"));
+    }
+
+    #[test]
+    fn stopfinder_keeps_the_requested_location_types() {
+        let json = include_str!("../testdata/stopfinder.json");
+        let (pois, hits) = parse_locations_json(json, &[LocationType::Poi]).expect("parse succeeds");
+        assert_eq!(hits, 3);
+        assert_eq!(pois.len(), 1);
+        assert_eq!(pois[0].name(), "ZKM (Zentrum für Kunst und Medien)");
+        assert!(matches!(&pois[0], Location::Poi { coord: Some(_), .. }));
+
+        let (all, _) = parse_locations_json(json, &[LocationType::Stop, LocationType::Poi]).expect("parse succeeds");
+        let types: Vec<_> = all.iter().map(Location::location_type).collect();
+        assert_eq!(types, [LocationType::Stop, LocationType::Stop, LocationType::Poi]);
+
+        let request = StopfinderRequest { query: "ZKM", max: 5, types: &[LocationType::Address, LocationType::Poi] };
+        let params = request.to_params();
+        assert!(params.contains(&("anyObjFilter_sf", "44".to_string())));
+        assert!(parse_locations_json(json, &[LocationType::Address]).expect("parse succeeds").0.is_empty());
+    }
+
+    /// Answers requests from a script and remembers what was asked for.
+    struct Canned {
+        responses: RefCell<VecDeque<Result<String, EfaError>>>,
+        requested: Rc<RefCell<Vec<String>>>,
+    }
+
+    impl Canned {
+        fn new(responses: impl IntoIterator<Item = Result<String, EfaError>>) -> (Self, Rc<RefCell<Vec<String>>>) {
+            let requested = Rc::new(RefCell::new(Vec::new()));
+            let canned = Canned { responses: RefCell::new(responses.into_iter().collect()), requested: requested.clone() };
+            (canned, requested)
+        }
+    }
+
+    impl Transport for Canned {
+        fn get<'a>(&'a self, url: &'a str, _timeout: Duration) -> TransportFuture<'a> {
+            self.requested.borrow_mut().push(url.to_string());
+            let response = self.responses.borrow_mut().pop_front().unwrap_or(Err(EfaError::EmptyResponse));
+            Box::pin(async move { response })
+        }
+    }
+
+    #[tokio::test]
+    async fn client_uses_the_injected_transport() {
+        let (canned, requested) = Canned::new([Ok(include_str!("../testdata/stopfinder.json").to_string())]);
+        let client = EfaClient::new("http://canned.test/stopfinder", canned);
+        let stops = client.stopfinder("Karlsruhe", 5).await.expect("canned stopfinder succeeds");
+        assert!(!stops.is_empty());
+        let requested = requested.borrow();
+        assert_eq!(requested.len(), 1);
+        assert!(requested[0].starts_with("http://canned.test/stopfinder/XML_STOPFINDER_REQUEST?"));
+        assert!(requested[0].contains("name_sf=Karlsruhe"));
+    }
+
+    #[tokio::test]
+    async fn batches_answer_per_stop_in_order() {
+        let (canned, requested) =
+            Canned::new([Ok(include_str!("../testdata/departures.xml").to_string()), Err(EfaError::Http { status: 404 })]);
+        let client = EfaClient::new("http://canned.test/batch", canned);
+        let boards = client.departures_batch(&["7001004".to_string(), "7000090".to_string()], 3).await;
+        let lines: Vec<_> = boards[0].as_ref().expect("first board").iter().map(|d| d.line.as_str()).collect();
+        assert_eq!(lines, ["S2", "2", "S5"]);
+        assert_eq!(boards[1], Err(EfaError::Http { status: 404 }));
+        assert!(requested.borrow()[1].contains("name_dm=7000090"));
+    }
+
+    #[tokio::test]
+    async fn rate_limited_endpoint_is_paused() {
+        let limited = EfaError::RateLimited { retry_after: Duration::from_secs(30) };
+        let (canned, requested) = Canned::new([Err(limited.clone()), Ok("never fetched".to_string())]);
+        let client = EfaClient::new("http://canned.test/limited", canned);
+        assert_eq!(client.departures("7001004", 3, None).await, Err(limited));
+        let again = client.departures("7001004", 3, None).await;
+        assert!(matches!(again, Err(EfaError::RateLimited { retry_after }) if retry_after <= Duration::from_secs(30)));
+        assert_eq!(requested.borrow().len(), 1);
+    }
+
+    /// Answers after a while, counting the answers it got to send.
+    struct Slow(Rc<RefCell<usize>>);
+
+    impl Transport for Slow {
+        fn get<'a>(&'a self, _url: &'a str, _timeout: Duration) -> TransportFuture<'a> {
+            Box::pin(async move {
+                super::sleep(Duration::from_millis(30)).await;
+                *self.0.borrow_mut() += 1;
+                Ok(include_str!("../testdata/stopfinder.json").to_string())
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn newer_requests_cancel_older_ones() {
+        let answered = Rc::new(RefCell::new(0));
+        let client = EfaClient::new("http://canned.test/typing", Slow(answered.clone()));
+        let slot = RequestSlot::default();
+        let typed_on = async {
+            super::sleep(Duration::from_millis(5)).await;
+            slot.run(client.stopfinder("Karlsruhe, ZK", 5)).await
+        };
+        let (stale, latest) = tokio::join!(slot.run(client.stopfinder("Karlsruhe, Z", 5)), typed_on);
+        assert_eq!(stale, Err(EfaError::Cancelled));
+        assert!(latest.is_ok());
+        assert_eq!(*answered.borrow(), 1, "the stale request is dropped, not awaited");
+    }
+
+    #[tokio::test]
+    async fn transient_transport_errors_are_retried() {
+        let (canned, requested) = Canned::new([
+            Err(EfaError::Http { status: 503 }),
+            Ok(include_str!("../testdata/departures.xml").to_string()),
+        ]);
+        let retry = RetryPolicy { base_delay: Duration::from_millis(1), ..RetryPolicy::default() };
+        let client = EfaClient::new("http://canned.test/retry", canned).with_retry(retry);
+        let deps = client.departures("7001004", 3, None).await.expect("second attempt succeeds");
+        assert!(!deps.is_empty());
+        assert_eq!(requested.borrow().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn json_boards_are_asked_for_when_configured() {
+        let (canned, requested) = Canned::new([Ok(include_str!("../testdata/departures.json").to_string())]);
+        let client = EfaClient::new("http://canned.test/json-board", canned).with_board_format(BoardFormat::Json);
+        let deps = client.departures("7001004", 3, None).await.expect("JSON board");
+        assert_eq!(deps, parse_departures_xml(include_str!("../testdata/departures.xml"), asked_at()).unwrap());
+        assert!(requested.borrow()[0].contains("outputFormat=JSON"));
+    }
+
+    #[tokio::test]
+    async fn unreachable_efa_falls_back_to_the_timetable() {
+        let schedule = crate::gtfs::Feed::from_files(|name| match name {
+            "stops.txt" => Some(include_str!("../testdata/gtfs/stops.txt").into()),
+            "routes.txt" => Some(include_str!("../testdata/gtfs/routes.txt").into()),
+            "trips.txt" => Some(include_str!("../testdata/gtfs/trips.txt").into()),
+            "stop_times.txt" => Some(include_str!("../testdata/gtfs/stop_times.txt").into()),
+            "calendar.txt" => Some(include_str!("../testdata/gtfs/calendar.txt").into()),
+            _ => None,
+        })
+        .expect("valid feed");
+        let (canned, _) = Canned::new([
+            Err(EfaError::Network("offline".to_string())),
+            Err(EfaError::Network("offline".to_string())),
+            Err(EfaError::Parse("bad query".to_string())),
+        ]);
+        let retry = RetryPolicy { max_attempts: 1, ..RetryPolicy::default() };
+        let client = EfaClient::new("http://canned.test/offline", canned).with_retry(retry).with_schedule(Some(Rc::new(schedule)));
+        let monday = chrono::NaiveDate::from_ymd_opt(2024, 1, 15).unwrap().and_hms_opt(8, 0, 0);
+        let deps = client.departures("7001004", 3, monday).await.expect("timetable board");
+        assert_eq!(deps.iter().map(|d| d.line.as_str()).collect::<Vec<_>>(), ["S2", "2", "S2"]);
+        assert_eq!(client.stopfinder("ZKM", 5).await.expect("timetable stops")[0].id, "de:08212:1004");
+        // Errors EFA would give again are not papered over.
+        assert_eq!(client.stopfinder("ZKM", 5).await, Err(EfaError::Parse("bad query".to_string())));
+    }
+
+    #[tokio::test]
+    async fn debug_capture_keeps_the_latest_raw_responses() {
+        let board = include_str!("../testdata/departures.xml").to_string();
+        let (canned, _) = Canned::new([Ok(board.clone()), Err(EfaError::Http { status: 502 })]);
+        let capture = Capture::new(1);
+        let client = EfaClient::new("http://canned.test/capture", canned).with_capture(Some(capture.clone()));
+        client.departures("7001004", 3, None).await.expect("board");
+        assert_eq!(capture.exchanges()[0].response, Ok(board));
+        let retry = RetryPolicy { max_attempts: 1, ..RetryPolicy::default() };
+        assert!(client.with_retry(retry).departures("7000090", 3, None).await.is_err());
+        let exchanges = capture.exchanges();
+        assert_eq!(exchanges.len(), 1);
+        assert!(exchanges[0].url.contains("name_dm=7000090"));
+        assert_eq!(exchanges[0].response, Err(EfaError::Http { status: 502 }));
+    }
+
+    #[tokio::test]
+    async fn requests_beyond_the_burst_wait_for_the_rate_limit() {
+        let board = include_str!("../testdata/departures.xml").to_string();
+        let (canned, requested) = Canned::new([Ok(board.clone()), Ok(board)]);
+        let limiter = Rc::new(RefCell::new(TokenBucket::new(RateLimit { burst: 1, per_second: 20.0 })));
+        let client = EfaClient { limiter, ..EfaClient::new("http://canned.test/throttled", canned) };
+        let started = std::time::Instant::now();
+        client.departures("7001004", 3, None).await.expect("first board");
+        client.departures("7000090", 3, None).await.expect("second board");
+        assert!(started.elapsed() >= Duration::from_millis(40), "second request waited for a token");
+        assert_eq!(requested.borrow().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn retries_take_a_token_each() {
+        let board = include_str!("../testdata/departures.xml").to_string();
+        let (canned, requested) = Canned::new([Err(EfaError::Http { status: 502 }), Ok(board)]);
+        let limiter = Rc::new(RefCell::new(TokenBucket::new(RateLimit { burst: 1, per_second: 5.0 })));
+        let retry = RetryPolicy { base_delay: Duration::ZERO, ..RetryPolicy::default() };
+        let client = EfaClient { limiter, ..EfaClient::new("http://canned.test/retried", canned).with_retry(retry) };
+        let started = std::time::Instant::now();
+        client.departures("7001004", 3, None).await.expect("board after a retry");
+        assert!(started.elapsed() >= Duration::from_millis(190), "the retry waited for a token");
+        assert_eq!(requested.borrow().len(), 2);
+    }
+}
+
+/// Tests against the real KVV server. They need internet access and a
+/// working API, so they only run with `cargo test --features live-tests`.
+#[cfg(all(test, feature = "live-tests"))]
+mod live_tests {
+    use super::{departures, stopfinder};
+    use tokio::time::{timeout, Duration};
+
     #[tokio::test]
     async fn live_stopfinder_returns_results() {
         let result = timeout(Duration::from_secs(15), stopfinder("Karlsruhe, ZKM", 5))
@@ -397,10 +1908,385 @@ mod tests {
             .expect("stopfinder timed out")
             .expect("stopfinder request failed");
         let stop = stops.first().expect("expected at least one stop");
-        let departures = timeout(Duration::from_secs(15), departures(&stop.id, 5))
+        let departures = timeout(Duration::from_secs(15), departures(&stop.id, 5, None))
             .await
             .expect("departures timed out")
             .expect("departures request failed");
         assert!(!departures.is_empty(), "expected departures");
     }
-}
\ No newline at end of file
+}
+
+/// Offline integration tests: the real client talks to a local mock EFA server
+/// serving the canned responses from `testdata/`.
+#[cfg(test)]
+mod mock_server_tests {
+    use super::{hhmm, EfaClient, EfaError, HttpTransport, RetryPolicy, TransportMode};
+    use chrono::NaiveDate;
+    use futures::StreamExt;
+    use tokio::time::{timeout, Duration};
+    use wiremock::matchers::{method, path, query_param, query_param_is_missing};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    const STOPFINDER_JSON: &str = include_str!("../testdata/stopfinder.json");
+    const DEPARTURES_XML: &str = include_str!("../testdata/departures.xml");
+    const LATER_DEPARTURES_XML: &str = include_str!("../testdata/departures_later.xml");
+    const ARRIVALS_XML: &str = include_str!("../testdata/arrivals.xml");
+    const COORD_JSON: &str = include_str!("../testdata/coord.json");
+    const STOPFINDER_COORD_JSON: &str = include_str!("../testdata/stopfinder_coord.json");
+
+    fn client(server: &MockServer) -> EfaClient {
+        EfaClient::new(server.uri(), HttpTransport)
+    }
+
+    async fn serve(server: &MockServer, endpoint: &str, response: ResponseTemplate) {
+        Mock::given(method("GET"))
+            .and(path(format!("/{endpoint}")))
+            .respond_with(response)
+            .mount(server)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn stopfinder_parses_mock_response() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/XML_STOPFINDER_REQUEST"))
+            .and(query_param("name_sf", "Karlsruhe, ZKM"))
+            .and(query_param("outputFormat", "JSON"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(STOPFINDER_JSON))
+            .mount(&server)
+            .await;
+
+        let stops = client(&server).stopfinder("Karlsruhe, ZKM", 5)
+            .await
+            .expect("stopfinder succeeds");
+        assert_eq!(stops.len(), 2, "the POI must be filtered out");
+        assert_eq!(stops[0].id, "7001004");
+        assert_eq!(stops[0].name, "Karlsruhe, ZKM");
+        assert_eq!(stops[0].place.as_deref(), Some("Karlsruhe"));
+    }
+
+    #[tokio::test]
+    async fn stopfinder_by_coord_picks_the_nearest_assigned_stop() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/XML_STOPFINDER_REQUEST"))
+            .and(query_param("type_sf", "coord"))
+            .and(query_param("name_sf", "8.38500:49.00250:WGS84[DD.ddddd]"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(STOPFINDER_COORD_JSON))
+            .mount(&server)
+            .await;
+
+        let nearest = client(&server).stopfinder_by_coord(49.0025, 8.385)
+            .await
+            .expect("stopfinder succeeds")
+            .expect("a stop is assigned");
+        assert_eq!(nearest.stop.id, "7001004");
+        assert_eq!(nearest.distance_m, 108);
+        assert_eq!(nearest.coord.map(|c| c.lat), Some(49.00191));
+    }
+
+    #[tokio::test]
+    async fn departures_parses_mock_response() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/XSLT_DM_REQUEST"))
+            .and(query_param("name_dm", "7001004"))
+            .and(query_param("limit", "3"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(DEPARTURES_XML))
+            .mount(&server)
+            .await;
+
+        let deps = client(&server).departures("7001004", 3, None)
+            .await
+            .expect("departures succeeds");
+        assert_eq!(deps.len(), 3);
+        assert_eq!(deps[0].line, "S2");
+        assert_eq!(deps[0].direction.as_deref(), Some("Spöck"));
+        assert_eq!(hhmm(&deps[0].planned_time), "08:05");
+        assert_eq!(deps[0].realtime_time.map(|t| hhmm(&t)).as_deref(), Some("08:07"));
+        assert_eq!(deps[0].delay_minutes, Some(2));
+        assert_eq!(deps[0].platform.as_deref(), Some("Gleis 1"));
+        assert_eq!(deps[1].platform.as_deref(), Some("Gleis 2"));
+        assert_eq!(deps[1].mode, TransportMode::Tram);
+        assert_eq!(deps[1].line, "2");
+        assert_eq!(deps[1].realtime_time, None);
+        assert_eq!(deps[0].operator.as_deref(), Some("AVG"));
+        assert_eq!(deps[1].operator.as_deref(), Some("VBK"));
+        assert_eq!(deps[2].operator, None);
+    }
+
+    #[tokio::test]
+    async fn later_departures_continue_from_the_last_row() {
+        let server = MockServer::start().await;
+        // The next page starts at the last row shown; the first page is
+        // whatever else is asked for.
+        Mock::given(method("GET"))
+            .and(path("/XSLT_DM_REQUEST"))
+            .and(query_param("itdDate", "20240115"))
+            .and(query_param("itdTime", "0817"))
+            .and(query_param("limit", "4"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(LATER_DEPARTURES_XML))
+            .expect(1)
+            .mount(&server)
+            .await;
+        serve(&server, "XSLT_DM_REQUEST", ResponseTemplate::new(200).set_body_string(DEPARTURES_XML)).await;
+
+        let client = client(&server);
+        let shown = client.departures("7001004", 3, None).await.expect("departures succeeds");
+        assert_eq!(shown.last().map(|d| hhmm(&d.planned_time)).as_deref(), Some("08:17"));
+        let page = client.departures_after("7001004", 3, &shown).await.expect("departures_after succeeds");
+        let rows: Vec<_> = page.iter().map(|d| (d.line.as_str(), hhmm(&d.planned_time))).collect();
+        assert_eq!(
+            rows,
+            [("S2", "08:25".to_string()), ("2", "08:30".to_string()), ("S5", "08:37".to_string())],
+            "the 08:17 row already shown is dropped"
+        );
+    }
+
+    #[tokio::test]
+    async fn later_departures_continue_the_session_of_the_board() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/XSLT_DM_REQUEST"))
+            .and(query_param("itdTime", "0817"))
+            .and(query_param("sessionID", "KVV2_1234"))
+            .and(query_param_is_missing("stateless"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(LATER_DEPARTURES_XML))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let board = DEPARTURES_XML.replace(r#"sessionID="0""#, r#"sessionID="KVV2_1234""#);
+        Mock::given(method("GET"))
+            .and(path("/XSLT_DM_REQUEST"))
+            .and(query_param("stateless", "0"))
+            .and(query_param_is_missing("sessionID"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(board))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = client(&server).with_param("stateless", "0");
+        let shown = client.departures("7001004", 3, None).await.expect("departures succeeds");
+        let page = client.departures_after("7001004", 3, &shown).await.expect("departures_after succeeds");
+        assert_eq!(page.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn later_departures_continue_the_session_of_the_live_board() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/XSLT_DM_REQUEST"))
+            .and(query_param("sessionID", "KVV2_5678"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(LATER_DEPARTURES_XML))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let board = DEPARTURES_XML.replace(r#"sessionID="0""#, r#"sessionID="KVV2_5678""#);
+        Mock::given(method("GET"))
+            .and(path("/XSLT_DM_REQUEST"))
+            .and(query_param_is_missing("sessionID"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(board))
+            .mount(&server)
+            .await;
+
+        let client = client(&server).with_param("stateless", "0");
+        let live = client.departures_stream("7001004", 3, Duration::from_secs(60));
+        let shown = std::pin::pin!(live).next().await.expect("streams never end").departures;
+        let page = client.departures_after("7001004", 3, &shown).await.expect("departures_after succeeds");
+        assert_eq!(page.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn repeated_departures_are_served_from_cache() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/XSLT_DM_REQUEST"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(DEPARTURES_XML))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let first = client(&server).departures("7001004", 3, None).await.expect("departures succeeds");
+        let again = client(&server).departures("7001004", 3, None).await.expect("cached departures");
+        assert_eq!(first, again);
+    }
+
+    #[tokio::test]
+    async fn arrivals_parses_mock_response() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/XSLT_DM_REQUEST"))
+            .and(query_param("name_dm", "7000090"))
+            .and(query_param("itdDateTimeDepArr", "arr"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(ARRIVALS_XML))
+            .mount(&server)
+            .await;
+
+        let arrivals = client(&server).arrivals("7000090", 5, None)
+            .await
+            .expect("arrivals succeeds");
+        assert_eq!(arrivals.len(), 2);
+        assert_eq!(arrivals[0].line, "2");
+        assert_eq!(hhmm(&arrivals[0].planned_time), "08:03");
+        assert_eq!(hhmm(&arrivals[0].time), "08:04");
+        assert_eq!(arrivals[0].delay_minutes, Some(1));
+        assert_eq!(arrivals[1].platform.as_deref(), Some("Gleis 1"));
+        assert_eq!(arrivals[1].mode, TransportMode::LightRail);
+    }
+
+    #[tokio::test]
+    async fn departures_for_a_later_time_send_date_and_time() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/XSLT_DM_REQUEST"))
+            .and(query_param("itdDateTimeDepArr", "dep"))
+            .and(query_param("itdDate", "20240116"))
+            .and(query_param("itdTime", "0730"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(DEPARTURES_XML))
+            .mount(&server)
+            .await;
+
+        let tomorrow = NaiveDate::from_ymd_opt(2024, 1, 16).unwrap().and_hms_opt(7, 30, 0).unwrap();
+        let deps = client(&server).departures("7001004", 3, Some(tomorrow))
+            .await
+            .expect("departures succeeds");
+        assert_eq!(deps.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn server_error_is_reported_as_http_error() {
+        let server = MockServer::start().await;
+        serve(&server, "XSLT_DM_REQUEST", ResponseTemplate::new(500)).await;
+
+        let err = client(&server).departures("7001004", 3, None).await.unwrap_err();
+        assert_eq!(err, EfaError::Http { status: 500 });
+    }
+
+    #[tokio::test]
+    async fn truncated_xml_is_reported_as_parse_error() {
+        let cut = DEPARTURES_XML.find("<itdServingLine").expect("fixture has a serving line") + 8;
+        let server = MockServer::start().await;
+        serve(
+            &server,
+            "XSLT_DM_REQUEST",
+            ResponseTemplate::new(200).set_body_string(&DEPARTURES_XML[..cut]),
+        )
+        .await;
+
+        let err = client(&server).departures("7001004", 3, None).await.unwrap_err();
+        assert!(matches!(err, EfaError::Parse(_)), "unexpected error: {err:?}");
+    }
+
+    #[tokio::test]
+    async fn invalid_json_is_reported_as_parse_error() {
+        let server = MockServer::start().await;
+        serve(
+            &server,
+            "XML_STOPFINDER_REQUEST",
+            ResponseTemplate::new(200).set_body_string("<html>Wartungsarbeiten</html>"),
+        )
+        .await;
+
+        let err = client(&server).stopfinder("ZKM", 5).await.unwrap_err();
+        assert!(matches!(err, EfaError::Parse(_)), "unexpected error: {err:?}");
+    }
+
+    #[tokio::test]
+    async fn blank_body_is_reported_as_empty_response() {
+        let server = MockServer::start().await;
+        serve(&server, "XSLT_DM_REQUEST", ResponseTemplate::new(200).set_body_string("\n")).await;
+
+        let err = client(&server).departures("7001004", 3, None).await.unwrap_err();
+        assert_eq!(err, EfaError::EmptyResponse);
+    }
+
+    #[tokio::test]
+    async fn stopfinder_error_message_is_reported() {
+        let server = MockServer::start().await;
+        let body = r#"{"stopFinder":{"message":[{"name":"code","value":"-8011"},{"name":"error","value":"name not found"}],"points":null}}"#;
+        serve(&server, "XML_STOPFINDER_REQUEST", ResponseTemplate::new(200).set_body_string(body)).await;
+
+        let err = client(&server).stopfinder("Nirgendwo", 5).await.unwrap_err();
+        assert_eq!(err, EfaError::ServerMessage("name not found".to_string()));
+    }
+
+    #[tokio::test]
+    async fn stops_near_sends_coordinate_and_sorts_by_distance() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/XML_COORD_REQUEST"))
+            .and(query_param("coord", "8.38400:49.00200:WGS84[DD.ddddd]"))
+            .and(query_param("radius_1", "500"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(COORD_JSON))
+            .mount(&server)
+            .await;
+
+        let stops = client(&server).stops_near(49.002, 8.384, 500, 2)
+            .await
+            .expect("coord request succeeds");
+        let names: Vec<_> = stops.iter().map(|s| (s.stop.name.as_str(), s.distance_m)).collect();
+        assert_eq!(names, [("ZKM", 87), ("Kolpingplatz", 265)]);
+        assert_eq!(stops[0].stop.id, "7001004");
+        assert_eq!(stops[0].stop.place.as_deref(), Some("Karlsruhe"));
+        assert_eq!(stops[0].coord.map(|c| c.lat), Some(49.00191));
+    }
+
+    #[tokio::test]
+    async fn slow_server_times_out() {
+        let server = MockServer::start().await;
+        serve(
+            &server,
+            "XSLT_DM_REQUEST",
+            ResponseTemplate::new(200)
+                .set_body_string(DEPARTURES_XML)
+                .set_delay(Duration::from_secs(5)),
+        )
+        .await;
+
+        let retry = RetryPolicy { timeout: Duration::from_millis(200), max_attempts: 1, base_delay: Duration::ZERO };
+        let result = timeout(Duration::from_secs(2), client(&server).with_retry(retry).departures("7001004", 3, None))
+            .await
+            .expect("the client gives up on its own");
+        assert!(matches!(result, Err(EfaError::Network(_))), "unexpected result: {result:?}");
+    }
+
+    #[tokio::test]
+    async fn too_many_requests_honors_retry_after() {
+        let server = MockServer::start().await;
+        // Pauses outlive the test and wiremock hands its servers, ports
+        // included, to later tests; a path of its own keeps them unaffected.
+        Mock::given(method("GET"))
+            .and(path("/rate-limited/XSLT_DM_REQUEST"))
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "120"))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = EfaClient::new(format!("{}/rate-limited", server.uri()), HttpTransport);
+        let result = client.departures("7001004", 3, None).await;
+        assert_eq!(result, Err(EfaError::RateLimited { retry_after: Duration::from_secs(120) }));
+        // Paused: the second call must not reach the server.
+        assert!(matches!(client.departures("7001004", 3, None).await, Err(EfaError::RateLimited { .. })));
+    }
+
+    #[tokio::test]
+    async fn server_hiccup_is_retried() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/XSLT_DM_REQUEST"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        serve(&server, "XSLT_DM_REQUEST", ResponseTemplate::new(200).set_body_string(DEPARTURES_XML)).await;
+
+        let retry = RetryPolicy { base_delay: Duration::from_millis(10), ..RetryPolicy::default() };
+        let deps = client(&server)
+            .with_retry(retry)
+            .departures("7001004", 3, None)
+            .await
+            .expect("second attempt succeeds");
+        assert_eq!(deps.len(), 3);
+    }
+}
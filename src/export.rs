@@ -0,0 +1,24 @@
+// Formats boards and journeys can be handed to other apps in.
+pub mod ical;
+
+/// `text` as a `data:` URL of type `mime`, e.g. for a download link.
+pub fn data_url(mime: &str, text: &str) -> String {
+    let mut url = format!("data:{mime};charset=utf-8,");
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => url.push(byte as char),
+            _ => url.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    url
+}
+
+#[cfg(test)]
+mod tests {
+    use super::data_url;
+
+    #[test]
+    fn data_urls_escape_everything_but_unreserved_characters() {
+        assert_eq!(data_url("text/calendar", "A b,ö\r\n"), "data:text/calendar;charset=utf-8,A%20b%2C%C3%B6%0D%0A");
+    }
+}
@@ -0,0 +1,272 @@
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime, TimeDelta};
+use efa_core::{berlin, hhmm};
+
+use crate::efa::Departure;
+use crate::trip::{Journey, Leg, TripStop};
+
+// Longest content line before it is folded, in bytes (RFC 5545, 3.1).
+const LINE_LIMIT: usize = 75;
+
+/// `departures` from the stop called `stop` as an iCalendar file, one
+/// event per departure at its expected time. `created` is the local time
+/// of the export.
+pub fn departures(stop: &str, departures: &[Departure], created: NaiveDateTime) -> String {
+    let mut calendar = Calendar::new(created);
+    for dep in departures {
+        let mut description = vec![match dep.realtime_time {
+            Some(realtime) if realtime != dep.planned_time => format!("Planned {}, expected {}", hhmm(&dep.planned_time), hhmm(&realtime)),
+            _ => format!("Planned {}", hhmm(&dep.planned_time)),
+        }];
+        description.extend(dep.operator.as_ref().map(|op| format!("Operated by {op}")));
+        calendar.event(Event {
+            uid: format!("{}-{}-{stop}", dep.planned_time.format("%Y%m%dT%H%M"), dep.line),
+            start: dep.time,
+            end: None,
+            summary: match &dep.direction {
+                Some(direction) => format!("{} → {direction}", dep.line),
+                None => dep.line.clone(),
+            },
+            location: at(stop, dep.platform.as_deref()),
+            description: description.join("\n"),
+            cancelled: dep.cancelled,
+        });
+    }
+    calendar.finish()
+}
+
+/// `journey`, departing on `date`, as an iCalendar file with one event from
+/// its departure to its arrival; the rides and walks are in the event's
+/// description. `created` is the local time of the export.
+pub fn journey(journey: &Journey, date: NaiveDate, created: NaiveDateTime) -> String {
+    let mut calendar = Calendar::new(created);
+    let (Some(origin), Some(destination)) = (journey.departure(), journey.arrival()) else {
+        return calendar.finish();
+    };
+    let mut clock = Clock { date, last: None };
+    let mut legs = Vec::new();
+    for leg in &journey.legs {
+        let (Some(from), Some(to)) = (clock.at(leg.origin.time()), clock.at(leg.destination.time())) else {
+            return calendar.finish();
+        };
+        legs.push((leg, from, to));
+    }
+    let (start, end) = (legs[0].1, legs[legs.len() - 1].2);
+    calendar.event(Event {
+        uid: format!("{}-{}-{}", start.format("%Y%m%dT%H%M"), origin.id, destination.id),
+        start,
+        end: Some(end),
+        summary: format!("{} → {}", origin.name, destination.name),
+        location: at(&origin.name, origin.platform.as_deref()),
+        description: legs.iter().map(|(leg, from, to)| describe(leg, from, to)).collect::<Vec<_>>().join("\n"),
+        cancelled: false,
+    });
+    calendar.finish()
+}
+
+/// "08:05 S2 → Spöck from Karlsruhe ZKM (1), 08:40 at Spöck" or a walk.
+fn describe(leg: &Leg, from: &NaiveDateTime, to: &NaiveDateTime) -> String {
+    let stop = |stop: &TripStop| at(&stop.name, stop.platform.as_deref());
+    match (&leg.line, &leg.direction) {
+        (None, _) => format!("{} Walk to {}, {}", hhmm(from), leg.destination.name, hhmm(to)),
+        (Some(line), direction) => {
+            let ride = direction.as_ref().map_or(line.clone(), |d| format!("{line} → {d}"));
+            format!("{} {ride} from {}, {} at {}", hhmm(from), stop(&leg.origin), hhmm(to), stop(&leg.destination))
+        }
+    }
+}
+
+fn at(stop: &str, platform: Option<&str>) -> String {
+    match platform {
+        Some(platform) => format!("{stop} ({platform})"),
+        None => stop.to_string(),
+    }
+}
+
+// Dates of the "HH:MM" times of a journey, in order: a time earlier than
+// the one before is on the next day.
+struct Clock {
+    date: NaiveDate,
+    last: Option<NaiveDateTime>,
+}
+
+impl Clock {
+    fn at(&mut self, time: &str) -> Option<NaiveDateTime> {
+        let mut at = self.date.and_time(NaiveTime::parse_from_str(time, "%H:%M").ok()?);
+        // Realtime times may be a bit earlier than the planned time before them.
+        if self.last.is_some_and(|last| at < last - TimeDelta::hours(12)) {
+            at += TimeDelta::days(1);
+            self.date = at.date();
+        }
+        self.last = Some(at);
+        Some(at)
+    }
+}
+
+struct Event {
+    uid: String,
+    start: NaiveDateTime,
+    end: Option<NaiveDateTime>,
+    summary: String,
+    location: String,
+    description: String,
+    cancelled: bool,
+}
+
+// Content lines of a VCALENDAR. Times are written in UTC, so calendar apps
+// need no time zone definition for them.
+struct Calendar {
+    lines: Vec<String>,
+    stamp: String,
+}
+
+impl Calendar {
+    fn new(created: NaiveDateTime) -> Self {
+        let lines = ["BEGIN:VCALENDAR", "VERSION:2.0", "PRODID:-//FriwiDev//kvv//EN", "CALSCALE:GREGORIAN", "METHOD:PUBLISH"];
+        Calendar { lines: lines.map(str::to_string).to_vec(), stamp: utc(created) }
+    }
+
+    fn event(&mut self, event: Event) {
+        self.lines.push("BEGIN:VEVENT".to_string());
+        self.line("UID", &format!("{}@kvv", escape(&event.uid)));
+        self.line("DTSTAMP", &self.stamp.clone());
+        self.line("DTSTART", &utc(event.start));
+        if let Some(end) = event.end {
+            self.line("DTEND", &utc(end));
+        }
+        self.line("SUMMARY", &escape(&event.summary));
+        self.line("LOCATION", &escape(&event.location));
+        self.line("DESCRIPTION", &escape(&event.description));
+        self.line("STATUS", if event.cancelled { "CANCELLED" } else { "CONFIRMED" });
+        self.lines.push("END:VEVENT".to_string());
+    }
+
+    /// `NAME:value`, folded onto continuation lines where too long.
+    fn line(&mut self, name: &str, value: &str) {
+        let mut line = format!("{name}:{value}");
+        while line.len() > LINE_LIMIT {
+            let mut cut = LINE_LIMIT;
+            while !line.is_char_boundary(cut) {
+                cut -= 1;
+            }
+            let rest = format!(" {}", &line[cut..]);
+            line.truncate(cut);
+            self.lines.push(std::mem::replace(&mut line, rest));
+        }
+        self.lines.push(line);
+    }
+
+    fn finish(mut self) -> String {
+        self.lines.push("END:VCALENDAR".to_string());
+        let mut text = self.lines.join("\r\n");
+        text.push_str("\r\n");
+        text
+    }
+}
+
+/// A local (Berlin) time as iCalendar UTC time, e.g. "20240115T070500Z".
+fn utc(local: NaiveDateTime) -> String {
+    berlin(local).naive_utc().format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// TEXT value with its separators escaped.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' | ';' | ',' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{departures, journey};
+    use crate::efa::{Departure, TransportMode};
+    use crate::trip::{Journey, Leg, TripStop};
+    use chrono::{NaiveDate, NaiveDateTime};
+
+    fn at(day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 1, day).unwrap().and_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    // Content lines with folded lines joined again.
+    fn unfold(calendar: &str) -> Vec<String> {
+        assert!(calendar.ends_with("\r\n") && !calendar.replace("\r\n", "").contains('\n'), "CRLF line ends");
+        calendar.replace("\r\n ", "").split_terminator("\r\n").map(str::to_string).collect()
+    }
+
+    #[test]
+    fn departures_become_events() {
+        let s2 = Departure {
+            line: "S2".to_string(),
+            direction: Some("Spöck, Richard-Hecht-Schule".to_string()),
+            time: at(15, 8, 7),
+            planned_time: at(15, 8, 5),
+            realtime_time: Some(at(15, 8, 7)),
+            platform: Some("Gleis 1".to_string()),
+            operator: Some("AVG".to_string()),
+            ..Default::default()
+        };
+        let cancelled = Departure { line: "2".to_string(), time: at(15, 8, 10), planned_time: at(15, 8, 10), cancelled: true, ..Default::default() };
+        let calendar = departures("Karlsruhe ZKM", &[s2, cancelled], at(15, 8, 0));
+        for line in calendar.split("\r\n") {
+            assert!(line.len() <= 75, "{line}");
+        }
+        let lines = unfold(&calendar);
+        assert_eq!(lines.first().map(String::as_str), Some("BEGIN:VCALENDAR"));
+        assert_eq!(lines.last().map(String::as_str), Some("END:VCALENDAR"));
+        assert!(lines.contains(&"UID:20240115T0805-S2-Karlsruhe ZKM@kvv".to_string()));
+        assert!(lines.contains(&"DTSTAMP:20240115T070000Z".to_string()));
+        assert!(lines.contains(&"DTSTART:20240115T070700Z".to_string()));
+        assert!(lines.contains(&"SUMMARY:S2 → Spöck\\, Richard-Hecht-Schule".to_string()));
+        assert!(lines.contains(&"LOCATION:Karlsruhe ZKM (Gleis 1)".to_string()));
+        assert!(lines.contains(&"DESCRIPTION:Planned 08:05\\, expected 08:07\\nOperated by AVG".to_string()));
+        assert_eq!(lines.iter().filter(|l| *l == "BEGIN:VEVENT").count(), 2);
+        assert_eq!(lines.iter().filter(|l| *l == "STATUS:CANCELLED").count(), 1);
+    }
+
+    #[test]
+    fn journeys_past_midnight_end_the_next_day() {
+        let stop = |id: &str, name: &str, time: &str| TripStop {
+            id: id.to_string(),
+            name: name.to_string(),
+            platform: None,
+            planned_time: time.to_string(),
+            realtime_time: None,
+        };
+        let leg = |line: Option<&str>, origin, destination| Leg {
+            line: line.map(str::to_string),
+            direction: line.map(|_| "Spöck".to_string()),
+            origin,
+            destination,
+            mode: TransportMode::SBahn,
+            operator: None,
+            path: Vec::new(),
+            vehicle: Default::default(),
+        };
+        let late = Journey {
+            legs: vec![
+                leg(Some("S2"), stop("7001004", "Karlsruhe ZKM", "23:55"), stop("7000090", "Marktplatz", "00:04")),
+                leg(None, stop("7000090", "Marktplatz", "00:04"), stop("7000091", "Marktplatz (Kaiserstraße)", "00:09")),
+            ],
+            changes: 0,
+            duration: Some("00:14".to_string()),
+            fare: None,
+        };
+        let lines = unfold(&journey(&late, NaiveDate::from_ymd_opt(2024, 7, 1).unwrap(), at(15, 8, 0)));
+        // Summer time: two hours ahead of UTC.
+        assert!(lines.contains(&"DTSTART:20240701T215500Z".to_string()));
+        assert!(lines.contains(&"DTEND:20240701T220900Z".to_string()));
+        assert!(lines.contains(&"SUMMARY:Karlsruhe ZKM → Marktplatz (Kaiserstraße)".to_string()));
+        assert!(lines.contains(
+            &"DESCRIPTION:23:55 S2 → Spöck from Karlsruhe ZKM\\, 00:04 at Marktplatz\\n00:04 Walk to Marktplatz (Kaiserstraße)\\, 00:09".to_string()
+        ));
+    }
+}
@@ -0,0 +1,67 @@
+use serde::{Deserialize, Serialize};
+
+use crate::efa::StopSuggestion;
+use crate::store::Slice;
+
+/// The user's favorite stops, in the order they were added.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Favorites {
+    pub stops: Vec<StopSuggestion>,
+}
+
+impl Slice for Favorites {
+    const KEY: &'static str = "kvv.favorites";
+    const VERSION: u32 = 1;
+}
+
+impl Favorites {
+    pub fn contains(&self, stop_id: &str) -> bool {
+        self.stops.iter().any(|s| s.id == stop_id)
+    }
+
+    /// Add `stop` unless it is already a favorite.
+    pub fn add(&mut self, stop: StopSuggestion) {
+        if !self.contains(&stop.id) {
+            self.stops.push(stop);
+        }
+    }
+
+    /// Remove the favorite `stop_id`, returning it.
+    pub fn remove(&mut self, stop_id: &str) -> Option<StopSuggestion> {
+        let pos = self.stops.iter().position(|s| s.id == stop_id)?;
+        Some(self.stops.remove(pos))
+    }
+
+    /// Put `stop` back at `index` (or at the end if the list got shorter).
+    pub fn insert(&mut self, index: usize, stop: StopSuggestion) {
+        if !self.contains(&stop.id) {
+            self.stops.insert(index.min(self.stops.len()), stop);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Favorites;
+    use crate::efa::StopSuggestion;
+
+    fn stop(id: &str) -> StopSuggestion {
+        StopSuggestion { id: id.to_string(), name: format!("Stop {id}"), place: None, ..Default::default() }
+    }
+
+    #[test]
+    fn remove_and_insert_restore_the_order() {
+        let mut favorites = Favorites::default();
+        favorites.add(stop("7001004"));
+        favorites.add(stop("7000090"));
+        favorites.add(stop("7001004"));
+        assert_eq!(favorites.stops.len(), 2);
+
+        let removed = favorites.remove("7001004").expect("is a favorite");
+        assert!(!favorites.contains("7001004"));
+        favorites.insert(0, removed);
+        let ids: Vec<_> = favorites.stops.iter().map(|s| s.id.as_str()).collect();
+        assert_eq!(ids, ["7001004", "7000090"]);
+    }
+}
@@ -0,0 +1,39 @@
+//! Times and departures the unit tests build their boards from.
+
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+
+use crate::efa::Departure;
+
+/// `time` ("HH:MM") on an arbitrary fixed day, 2024-01-01.
+pub(crate) fn at(time: &str) -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(2024, 1, 1).unwrap().and_time(NaiveTime::parse_from_str(time, "%H:%M").unwrap())
+}
+
+/// `hour:minute` on January `day`, 2024, for tests across several days.
+pub(crate) fn on(day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(2024, 1, day).unwrap().and_hms_opt(hour, minute, 0).unwrap()
+}
+
+/// `line` to `direction`, on time at `time` ("HH:MM").
+pub(crate) fn dep(line: &str, direction: &str, time: &str) -> Departure {
+    Departure {
+        line: line.to_string(),
+        direction: Some(direction.to_string()),
+        time: at(time),
+        planned_time: at(time),
+        ..Default::default()
+    }
+}
+
+/// `line` planned at `planned`, expected at `realtime` if there is a
+/// prediction.
+pub(crate) fn delayed(line: &str, planned: NaiveDateTime, realtime: Option<NaiveDateTime>) -> Departure {
+    Departure {
+        line: line.to_string(),
+        time: realtime.unwrap_or(planned),
+        planned_time: planned,
+        realtime_time: realtime,
+        delay_minutes: realtime.map(|r| (r - planned).num_minutes() as i32),
+        ..Default::default()
+    }
+}
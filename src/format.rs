@@ -0,0 +1,66 @@
+/// Punctuality class of a departure, used for colors and symbols alike.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DelayState {
+    Early,
+    OnTime,
+    Slight,
+    Late,
+}
+
+// Delays at or above this many minutes count as "late" rather than "slight".
+const LATE_FROM: i32 = 5;
+
+pub fn delay_state(delay: i32) -> DelayState {
+    match delay {
+        d if d < 0 => DelayState::Early,
+        0 => DelayState::OnTime,
+        d if d < LATE_FROM => DelayState::Slight,
+        _ => DelayState::Late,
+    }
+}
+
+/// CSS class carrying the color of a delay state.
+pub fn delay_class(state: DelayState) -> &'static str {
+    match state {
+        DelayState::Early => "delay-early",
+        DelayState::OnTime => "delay-on-time",
+        DelayState::Slight => "delay-slight",
+        DelayState::Late => "delay-late",
+    }
+}
+
+/// Text for a delay, e.g. "+5". With `shapes` (the color-blind-safe
+/// setting) the state is also encoded as a symbol, e.g. "▲ +5", so it never
+/// depends on color alone.
+pub fn delay_label(delay: i32, shapes: bool) -> String {
+    let text = match delay {
+        0 => "±0".to_string(),
+        d if d > 0 => format!("+{d}"),
+        d => format!("−{}", -d),
+    };
+    if !shapes {
+        return text;
+    }
+    let symbol = match delay_state(delay) {
+        DelayState::Early => "▼",
+        DelayState::OnTime => "●",
+        DelayState::Slight => "△",
+        DelayState::Late => "▲",
+    };
+    format!("{symbol} {text}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{delay_label, delay_state, DelayState};
+
+    #[test]
+    fn labels_with_and_without_shapes() {
+        assert_eq!(delay_label(5, false), "+5");
+        assert_eq!(delay_label(5, true), "▲ +5");
+        assert_eq!(delay_label(2, true), "△ +2");
+        assert_eq!(delay_label(0, true), "● ±0");
+        assert_eq!(delay_label(-1, true), "▼ −1");
+        assert_eq!(delay_state(4), DelayState::Slight);
+    }
+}
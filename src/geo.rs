@@ -0,0 +1,241 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use wasm_bindgen::JsValue;
+
+use crate::efa::{Coord, StopSuggestion};
+use crate::store::Slice;
+use crate::tauri::invoke;
+
+// A geofence is only left this far outside its radius, so GPS jitter at the
+// edge doesn't switch the board back and forth.
+const HYSTERESIS_M: f64 = 75.0;
+const FENCE_RADIUS_M: f64 = 200.0;
+
+/// Location permission as reported by the geolocation plugin.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Permission {
+    Granted,
+    Denied,
+    /// Not decided yet; asking shows the system prompt.
+    Prompt,
+}
+
+/// Why the app wants a position.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Trigger {
+    /// App startup. Never prompts.
+    Launch,
+    /// A feature the user just opened needs the position.
+    Feature,
+}
+
+/// What to do about a position request.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Decision {
+    Locate,
+    AskThenLocate,
+    Skip,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum GeoError {
+    /// The user denied location access.
+    Denied,
+    /// The plugin call failed or returned something unexpected.
+    Plugin(String),
+}
+
+impl fmt::Display for GeoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GeoError::Denied => write!(f, "Location access is turned off. Allow it in the system settings to see nearby stops."),
+            GeoError::Plugin(msg) => write!(f, "Could not determine your position: {msg}"),
+        }
+    }
+}
+
+/// The policy: on launch only locate if the user opted into `auto_nearby`
+/// and access is already granted; features may ask for access first.
+pub fn decide(trigger: Trigger, permission: Permission, auto_nearby: bool) -> Decision {
+    match (trigger, permission) {
+        (Trigger::Launch, Permission::Granted) if auto_nearby => Decision::Locate,
+        (Trigger::Launch, _) => Decision::Skip,
+        (Trigger::Feature, Permission::Granted) => Decision::Locate,
+        (Trigger::Feature, Permission::Prompt) => Decision::AskThenLocate,
+        (Trigger::Feature, Permission::Denied) => Decision::Skip,
+    }
+}
+
+/// Position for `trigger`, or `None` when the policy says not to locate.
+pub async fn locate(trigger: Trigger, auto_nearby: bool) -> Result<Option<Coord>, GeoError> {
+    match decide(trigger, permission().await?, auto_nearby) {
+        Decision::Locate => current_position().await.map(Some),
+        Decision::AskThenLocate if request_permission().await? == Permission::Granted => {
+            current_position().await.map(Some)
+        }
+        Decision::AskThenLocate => Err(GeoError::Denied),
+        Decision::Skip if trigger == Trigger::Feature => Err(GeoError::Denied),
+        Decision::Skip => Ok(None),
+    }
+}
+
+pub async fn permission() -> Result<Permission, GeoError> {
+    parse_permission(&call("plugin:geolocation|check_permissions").await?)
+}
+
+pub async fn request_permission() -> Result<Permission, GeoError> {
+    parse_permission(&call("plugin:geolocation|request_permissions").await?)
+}
+
+pub async fn current_position() -> Result<Coord, GeoError> {
+    let val = call("plugin:geolocation|get_current_position").await?;
+    let coords = val.get("coords");
+    let field = |key: &str| coords.and_then(|c| c.get(key)).and_then(|v| v.as_f64());
+    match (field("latitude"), field("longitude")) {
+        (Some(lat), Some(lon)) => Ok(Coord { lat, lon }),
+        _ => Err(GeoError::Plugin(format!("invalid position {val}"))),
+    }
+}
+
+async fn call(cmd: &str) -> Result<Value, GeoError> {
+    let jsv = invoke(cmd, JsValue::NULL).await.map_err(|e| {
+        let msg = e.as_string().or_else(|| js_sys::JSON::stringify(&e).ok().and_then(|s| s.as_string()));
+        GeoError::Plugin(msg.unwrap_or_else(|| format!("{e:?}")))
+    })?;
+    serde_wasm_bindgen::from_value(jsv).map_err(|e| GeoError::Plugin(e.to_string()))
+}
+
+fn parse_permission(val: &Value) -> Result<Permission, GeoError> {
+    match val.get("location").and_then(|v| v.as_str()) {
+        Some("granted") => Ok(Permission::Granted),
+        Some("denied") => Ok(Permission::Denied),
+        Some("prompt" | "prompt-with-rationale") => Ok(Permission::Prompt),
+        _ => Err(GeoError::Plugin(format!("unexpected permission state {val}"))),
+    }
+}
+
+/// Area around a favorite stop, e.g. "Home": entering it shows the
+/// stop's board.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Geofence {
+    pub label: String,
+    pub stop: StopSuggestion,
+    pub center: Coord,
+    pub radius_m: f64,
+}
+
+impl Geofence {
+    /// Fence of the default size around `center`, usually where the user
+    /// stands when adding it.
+    pub fn new(label: impl Into<String>, stop: StopSuggestion, center: Coord) -> Self {
+        Geofence { label: label.into(), stop, center, radius_m: FENCE_RADIUS_M }
+    }
+
+    fn distance_m(&self, pos: &Coord) -> f64 {
+        self.center.distance_m(pos)
+    }
+}
+
+/// The user's geofences and whether they switch the board.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Geofences {
+    pub enabled: bool,
+    pub fences: Vec<Geofence>,
+}
+
+impl Slice for Geofences {
+    const KEY: &'static str = "kvv.geofences";
+    const VERSION: u32 = 1;
+}
+
+impl Geofences {
+    /// Add `fence`, replacing one with the same label.
+    pub fn put(&mut self, fence: Geofence) {
+        match self.fences.iter_mut().find(|f| f.label == fence.label) {
+            Some(existing) => *existing = fence,
+            None => self.fences.push(fence),
+        }
+    }
+
+    pub fn remove(&mut self, label: &str) {
+        self.fences.retain(|f| f.label != label);
+    }
+}
+
+/// Which geofence the user is in, followed from position to position.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GeofenceTracker {
+    inside: Option<String>,
+}
+
+impl GeofenceTracker {
+    /// The fence the user just entered at `pos`, whose board to switch to.
+    /// `None` while staying in the same fence or outside all of them. A fence
+    /// is only left beyond its radius plus some slack; where fences overlap,
+    /// the nearest center wins.
+    pub fn update<'a>(&mut self, fences: &'a [Geofence], pos: Coord) -> Option<&'a Geofence> {
+        let current = self.inside.as_ref().and_then(|label| fences.iter().find(|f| &f.label == label));
+        if current.is_some_and(|f| f.distance_m(&pos) <= f.radius_m + HYSTERESIS_M) {
+            return None;
+        }
+        let entered = fences
+            .iter()
+            .filter(|f| f.distance_m(&pos) <= f.radius_m)
+            .min_by(|a, b| a.distance_m(&pos).total_cmp(&b.distance_m(&pos)));
+        self.inside = entered.map(|f| f.label.clone());
+        entered
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decide, parse_permission, Decision, Geofence, GeofenceTracker, Permission, Trigger};
+    use crate::efa::{Coord, StopSuggestion};
+    use serde_json::json;
+
+    #[test]
+    fn geofences_switch_once_and_resist_jitter() {
+        let fence = |label: &str, id: &str, lat: f64| Geofence {
+            label: label.to_string(),
+            stop: StopSuggestion { id: id.to_string(), name: label.to_string(), ..Default::default() },
+            center: Coord { lat, lon: 8.4 },
+            radius_m: 150.0,
+        };
+        // About 1.1 km apart.
+        let fences = [fence("Home", "7000001", 49.0), fence("Work", "7000002", 49.01)];
+        // Latitude `m` meters north of `lat`.
+        let at = |lat: f64, m: f64| Coord { lat: lat + m / 111_195.0, lon: 8.4 };
+        let mut tracker = GeofenceTracker::default();
+
+        assert_eq!(tracker.update(&fences, at(49.0, 100.0)).map(|f| f.stop.id.as_str()), Some("7000001"));
+        assert_eq!(tracker.update(&fences, at(49.0, 120.0)), None, "still home");
+        assert_eq!(tracker.update(&fences, at(49.0, 200.0)), None, "jitter past the edge");
+        assert_eq!(tracker.update(&fences, at(49.0, 140.0)), None, "back inside, no second switch");
+        assert_eq!(tracker.update(&fences, at(49.0, 500.0)), None, "left, nowhere");
+        assert_eq!(tracker.update(&fences, at(49.01, -50.0)).map(|f| f.label.as_str()), Some("Work"));
+    }
+
+    #[test]
+    fn launch_never_prompts() {
+        assert_eq!(decide(Trigger::Launch, Permission::Prompt, true), Decision::Skip);
+        assert_eq!(decide(Trigger::Launch, Permission::Granted, false), Decision::Skip);
+        assert_eq!(decide(Trigger::Launch, Permission::Granted, true), Decision::Locate);
+    }
+
+    #[test]
+    fn features_ask_when_undecided() {
+        assert_eq!(decide(Trigger::Feature, Permission::Prompt, false), Decision::AskThenLocate);
+        assert_eq!(decide(Trigger::Feature, Permission::Granted, false), Decision::Locate);
+        assert_eq!(decide(Trigger::Feature, Permission::Denied, true), Decision::Skip);
+    }
+
+    #[test]
+    fn parses_plugin_permission_states() {
+        assert_eq!(parse_permission(&json!({"location": "prompt-with-rationale"})), Ok(Permission::Prompt));
+        assert_eq!(parse_permission(&json!({"location": "granted", "coarseLocation": "granted"})), Ok(Permission::Granted));
+        assert!(parse_permission(&json!({})).is_err());
+    }
+}
@@ -0,0 +1,219 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+
+use serde_json::Value;
+
+use crate::diagnostics;
+use crate::efa::{self, fetch_text_with, Coord, EfaClient, EfaError, Location, LocationType, RetryPolicy, Transport};
+use crate::settings::Settings;
+
+const NOMINATIM_BASE: &str = "https://nominatim.openstreetmap.org/";
+// Results per lookup; the first is used, the rest are offered to pick from.
+const MAX_PLACES: usize = 5;
+// Karlsruhe and surroundings as "west,north,east,south", preferred by Nominatim.
+const KVV_VIEWBOX: &str = "7.9,49.4,9.0,48.6";
+
+/// A position an address was resolved to.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Place {
+    /// Name as reported by the geocoder, e.g. "Karlsruhe, Kaiserstraße 12".
+    pub name: String,
+    pub coord: Coord,
+}
+
+pub type GeocodeFuture<'a> = Pin<Box<dyn Future<Output = Result<Vec<Place>, EfaError>> + 'a>>;
+
+/// Turns a street address into coordinates.
+pub trait Geocoder {
+    /// Name for diagnostics, e.g. "EFA".
+    fn name(&self) -> &str;
+    /// Places matching `address`, best first; empty if nothing matched.
+    fn geocode<'a>(&'a self, address: &'a str) -> GeocodeFuture<'a>;
+}
+
+/// Address and POI search of the EFA stopfinder.
+pub struct EfaGeocoder(pub EfaClient);
+
+impl Geocoder for EfaGeocoder {
+    fn name(&self) -> &str {
+        "EFA"
+    }
+
+    fn geocode<'a>(&'a self, address: &'a str) -> GeocodeFuture<'a> {
+        Box::pin(async move {
+            match self.0.locations(address, MAX_PLACES, &[LocationType::Address, LocationType::Poi]).await {
+                Ok(locations) => Ok(locations.into_iter().filter_map(place_of).collect()),
+                Err(EfaError::ServerMessage(_)) => Ok(Vec::new()),
+                Err(e) => Err(e),
+            }
+        })
+    }
+}
+
+fn place_of(location: Location) -> Option<Place> {
+    match location {
+        Location::Address { name, coord, .. } | Location::Poi { name, coord, .. } => Some(Place { name, coord: coord? }),
+        Location::Stop(_) => None,
+    }
+}
+
+/// OpenStreetMap's Nominatim search. Addresses are sent to a third party,
+/// so this is only used when the user opted in.
+pub struct NominatimGeocoder {
+    base_url: String,
+    transport: Rc<dyn Transport>,
+}
+
+impl Default for NominatimGeocoder {
+    fn default() -> Self {
+        NominatimGeocoder::new(NOMINATIM_BASE, efa::transport())
+    }
+}
+
+impl NominatimGeocoder {
+    pub fn new(base_url: impl Into<String>, transport: impl Transport + 'static) -> Self {
+        let mut base_url = base_url.into();
+        if !base_url.ends_with('/') {
+            base_url.push('/');
+        }
+        NominatimGeocoder { base_url, transport: Rc::new(transport) }
+    }
+}
+
+impl Geocoder for NominatimGeocoder {
+    fn name(&self) -> &str {
+        "Nominatim"
+    }
+
+    fn geocode<'a>(&'a self, address: &'a str) -> GeocodeFuture<'a> {
+        Box::pin(async move {
+            let params = vec![
+                ("q", address.to_string()),
+                ("format", "jsonv2".to_string()),
+                ("countrycodes", "de".to_string()),
+                ("viewbox", KVV_VIEWBOX.to_string()),
+                ("limit", MAX_PLACES.to_string()),
+            ];
+            // Nominatim allows one request per second and no bulk lookups;
+            // a single try keeps well within that.
+            let policy = RetryPolicy { max_attempts: 1, ..RetryPolicy::default() };
+            let url = format!("{}search", self.base_url);
+            let body = fetch_text_with(self.transport.as_ref(), &url, &params, &policy).await?;
+            parse_nominatim(&body)
+        })
+    }
+}
+
+fn parse_nominatim(body: &str) -> Result<Vec<Place>, EfaError> {
+    let json: Value = serde_json::from_str(body).map_err(|e| EfaError::Parse(e.to_string()))?;
+    let Value::Array(results) = json else {
+        return Err(EfaError::Parse("expected a list of places".to_string()));
+    };
+    Ok(results
+        .iter()
+        .filter_map(|r| {
+            let lat = r.get("lat")?.as_str()?.parse().ok()?;
+            let lon = r.get("lon")?.as_str()?.parse().ok()?;
+            Some(Place { name: r.get("display_name")?.as_str()?.to_string(), coord: Coord { lat, lon } })
+        })
+        .collect())
+}
+
+/// Geocoders tried in order until one finds the address.
+#[derive(Default)]
+pub struct GeocoderChain {
+    geocoders: Vec<Box<dyn Geocoder>>,
+}
+
+impl GeocoderChain {
+    /// EFA first, then Nominatim if the user allowed it.
+    pub fn standard(settings: &Settings) -> Self {
+        let mut chain = GeocoderChain::default();
+        chain.push(EfaGeocoder(EfaClient::kvv()));
+        if settings.nominatim_fallback {
+            chain.push(NominatimGeocoder::default());
+        }
+        chain
+    }
+
+    pub fn push(&mut self, geocoder: impl Geocoder + 'static) {
+        self.geocoders.push(Box::new(geocoder));
+    }
+
+    /// Places from the first geocoder that found any. A failing geocoder
+    /// falls through to the next; its error is returned only if no later
+    /// one found the address either.
+    pub async fn geocode(&self, address: &str) -> Result<Vec<Place>, EfaError> {
+        let mut error = None;
+        for geocoder in &self.geocoders {
+            match geocoder.geocode(address).await {
+                Ok(places) if !places.is_empty() => return Ok(places),
+                Ok(_) => {}
+                Err(e) => {
+                    diagnostics::record_warning(format!("geocoder {}: {e}", geocoder.name()));
+                    error.get_or_insert(e);
+                }
+            }
+        }
+        error.map_or(Ok(Vec::new()), Err)
+    }
+}
+
+/// `GeocoderChain::standard` for `address`.
+pub async fn geocode(address: &str, settings: &Settings) -> Result<Vec<Place>, EfaError> {
+    GeocoderChain::standard(settings).geocode(address).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EfaGeocoder, Geocoder, GeocoderChain, NominatimGeocoder};
+    use crate::efa::{EfaClient, EfaError, Transport, TransportFuture};
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+    use std::time::Duration;
+
+    struct Script(RefCell<VecDeque<Result<String, EfaError>>>);
+
+    impl Transport for Script {
+        fn get<'a>(&'a self, _url: &'a str, _timeout: Duration) -> TransportFuture<'a> {
+            let response = self.0.borrow_mut().pop_front().unwrap_or(Err(EfaError::EmptyResponse));
+            Box::pin(async move { response })
+        }
+    }
+
+    fn script(responses: impl IntoIterator<Item = Result<String, EfaError>>) -> Script {
+        Script(RefCell::new(responses.into_iter().collect()))
+    }
+
+    const NOMINATIM: &str = r#"[{"lat":"49.0094","lon":"8.4037","display_name":"Kaiserstraße 12, Karlsruhe"}]"#;
+
+    #[tokio::test]
+    async fn efa_addresses_carry_coordinates() {
+        let body = r#"{"stopFinder":{"points":{"point":{"type":"any","name":"Karlsruhe, Kaiserstraße 12","anyType":"singlehouse",
+            "ref":{"coords":"8.40372,49.00941"}}}}}"#;
+        let efa = EfaGeocoder(EfaClient::new("http://canned.test/address", script([Ok(body.to_string())])));
+        let places = efa.geocode("Kaiserstr. 12").await.expect("valid response");
+        assert_eq!(places[0].name, "Karlsruhe, Kaiserstraße 12");
+        assert_eq!(places[0].coord.lat, 49.00941);
+    }
+
+    #[tokio::test]
+    async fn nominatim_steps_in_when_efa_finds_nothing() {
+        let mut chain = GeocoderChain::default();
+        let efa = script([Err(EfaError::ServerMessage("no match".to_string()))]);
+        chain.push(EfaGeocoder(EfaClient::new("http://canned.test/geocode", efa)));
+        chain.push(NominatimGeocoder::new("http://canned.test/nominatim", script([Ok(NOMINATIM.to_string())])));
+        let places = chain.geocode("Kaiserstr. 12").await.expect("fallback finds the address");
+        assert_eq!(places[0].coord.lon, 8.4037);
+
+        let mut efa_only = GeocoderChain::default();
+        let efa = script([Err(EfaError::ServerMessage("no match".to_string()))]);
+        efa_only.push(EfaGeocoder(EfaClient::new("http://canned.test/geocode-only", efa)));
+        assert_eq!(efa_only.geocode("Kaiserstr. 12").await, Ok(Vec::new()));
+
+        let mut offline = GeocoderChain::default();
+        offline.push(NominatimGeocoder::new("http://canned.test/offline", script([Err(EfaError::Parse("bad".to_string()))])));
+        assert!(offline.geocode("Kaiserstr. 12").await.is_err());
+    }
+}
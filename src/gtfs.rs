@@ -0,0 +1,628 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+use chrono::{Datelike, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta};
+
+#[cfg(target_arch = "wasm32")]
+use crate::diagnostics;
+use crate::efa::{Departure, EfaError, StopSuggestion, TransportMode};
+use crate::gtfs_rt::{Expected, TripUpdates};
+
+/// The GTFS feed KVV publishes, updated with every timetable change.
+pub const KVV_FEED_URL: &str = "https://projekte.kvv-efa.de/GTFS/google_transit.zip";
+// The feed is tens of megabytes.
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(120);
+// Cache Storage bucket the feed is kept in between launches.
+#[cfg(target_arch = "wasm32")]
+const FEED_CACHE: &str = "kvv-gtfs";
+// How long before the requested time a delayed departure may be planned.
+const MAX_DELAY: TimeDelta = TimeDelta::hours(2);
+
+/// Planned service of a GTFS feed, to answer stop searches and show
+/// timetabled departures without EFA, e.g. when it or the network is down.
+/// Times are local (Berlin) time like EFA's; realtime data comes from a
+/// separate GTFS-Realtime feed, see `live_departures`.
+#[derive(Debug, Default)]
+pub struct Feed {
+    stops: Vec<Stop>,
+    by_id: HashMap<String, usize>,
+    routes: Vec<Route>,
+    trips: Vec<Trip>,
+    services: Vec<Service>,
+    /// Per stop: (seconds after midnight of the service day, trip, stop
+    /// sequence), by time. Times past 24:00 belong to trips running after
+    /// midnight.
+    departures: Vec<Vec<(u32, usize, u32)>>,
+}
+
+#[derive(Debug)]
+struct Stop {
+    id: String,
+    name: String,
+    station: Option<usize>,
+    platform: Option<String>,
+}
+
+#[derive(Debug)]
+struct Route {
+    line: String,
+    mode: TransportMode,
+    operator: Option<String>,
+}
+
+#[derive(Debug)]
+struct Trip {
+    id: String,
+    route: usize,
+    service: usize,
+    headsign: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct Service {
+    /// Monday first.
+    weekdays: [bool; 7],
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+    added: HashSet<NaiveDate>,
+    removed: HashSet<NaiveDate>,
+}
+
+impl Service {
+    fn runs_on(&self, day: NaiveDate) -> bool {
+        if self.added.contains(&day) {
+            return true;
+        }
+        let in_range = self.from.is_some_and(|from| from <= day) && self.to.is_some_and(|to| day <= to);
+        in_range && self.weekdays[day.weekday().num_days_from_monday() as usize] && !self.removed.contains(&day)
+    }
+}
+
+/// Download and load the feed at `url`, e.g. `KVV_FEED_URL`. In the app
+/// the archive is kept between launches, and only downloaded again when the
+/// server says it changed.
+pub async fn download(url: &str) -> Result<Feed, EfaError> {
+    #[cfg(target_arch = "wasm32")]
+    let zip = cached_download(url).await?;
+    #[cfg(not(target_arch = "wasm32"))]
+    let zip = crate::efa::fetch_bytes(url, DOWNLOAD_TIMEOUT).await?;
+    Feed::from_zip(&zip)
+}
+
+// The archive kept in Cache Storage, if the server has no newer one: the
+// request carries the kept copy's ETag and Last-Modified, and a 304 answer
+// means the copy is current. Without a connection the kept copy is used too.
+#[cfg(target_arch = "wasm32")]
+async fn cached_download(url: &str) -> Result<Vec<u8>, EfaError> {
+    use leptos::web_sys::{self, AbortSignal, Cache, Headers, RequestCache, RequestInit, Response};
+    use wasm_bindgen::{JsCast, JsValue};
+    use wasm_bindgen_futures::JsFuture;
+
+    let failed = |e: JsValue| EfaError::Network(format!("{e:?}"));
+    let window = web_sys::window().ok_or_else(|| EfaError::Network("no window".to_string()))?;
+    let cache: Cache = JsFuture::from(window.caches().map_err(failed)?.open(FEED_CACHE)).await.map_err(failed)?.unchecked_into();
+    let kept = JsFuture::from(cache.match_with_str(url)).await.map_err(failed)?.dyn_into::<Response>().ok();
+
+    let headers = Headers::new().map_err(failed)?;
+    for (validator, condition) in [("ETag", "If-None-Match"), ("Last-Modified", "If-Modified-Since")] {
+        if let Some(value) = kept.as_ref().and_then(|kept| kept.headers().get(validator).ok().flatten()) {
+            headers.set(condition, &value).map_err(failed)?;
+        }
+    }
+    let init = RequestInit::new();
+    init.set_headers(&headers);
+    // Cache Storage is the cache here; the browser's own would hold a second copy.
+    init.set_cache(RequestCache::NoStore);
+    init.set_signal(Some(&AbortSignal::timeout_with_u32(DOWNLOAD_TIMEOUT.as_millis() as u32)));
+    let fetched = JsFuture::from(window.fetch_with_str_and_init(url, &init)).await.map(JsCast::unchecked_into::<Response>);
+    let response = match (fetched, kept) {
+        (Ok(fresh), Some(kept)) if fresh.status() == 304 => kept,
+        (Ok(fresh), _) if fresh.ok() => {
+            JsFuture::from(cache.put_with_str(url, &fresh.clone().map_err(failed)?)).await.map_err(failed)?;
+            fresh
+        }
+        (Ok(fresh), _) => return Err(EfaError::Http { status: fresh.status() }),
+        (Err(e), Some(kept)) => {
+            diagnostics::record_warning(format!("GTFS feed not checked for updates: {e:?}"));
+            kept
+        }
+        (Err(e), None) => return Err(failed(e)),
+    };
+    let body = JsFuture::from(response.array_buffer().map_err(failed)?).await.map_err(failed)?;
+    Ok(js_sys::Uint8Array::new(&body).to_vec())
+}
+
+impl Feed {
+    /// Load a feed from its zip archive.
+    pub fn from_zip(zip: &[u8]) -> Result<Feed, EfaError> {
+        let files = unzip(zip)?;
+        Feed::from_files(|name| files.get(name).map(|bytes| String::from_utf8_lossy(bytes)))
+    }
+
+    /// Load a feed from its text files, `file("stops.txt")` and so on.
+    pub fn from_files<'a>(file: impl Fn(&str) -> Option<Cow<'a, str>>) -> Result<Feed, EfaError> {
+        let required = |name: &str| file(name).ok_or_else(|| EfaError::Parse(format!("GTFS feed without {name}")));
+        let mut feed = Feed::default();
+
+        let mut agencies = HashMap::new();
+        if let Some(text) = file("agency.txt") {
+            read_csv(&text, &["agency_id", "agency_name"], |row| {
+                agencies.insert(row[0].to_string(), row[1].to_string());
+            })?;
+        }
+
+        let mut parents = Vec::new();
+        read_csv(&required("stops.txt")?, &["stop_id", "stop_name", "parent_station", "platform_code"], |row| {
+            feed.by_id.insert(row[0].to_string(), feed.stops.len());
+            parents.push(row[2].to_string());
+            feed.stops.push(Stop {
+                id: row[0].to_string(),
+                name: row[1].to_string(),
+                station: None,
+                platform: non_empty(row[3]),
+            });
+        })?;
+        // Stations may come after their platforms.
+        for (stop, parent) in parents.iter().enumerate() {
+            feed.stops[stop].station = feed.by_id.get(parent).copied();
+        }
+
+        let mut route_ids = HashMap::new();
+        read_csv(&required("routes.txt")?, &["route_id", "agency_id", "route_short_name", "route_long_name", "route_type"], |row| {
+            route_ids.insert(row[0].to_string(), feed.routes.len());
+            feed.routes.push(Route {
+                line: non_empty(row[2]).unwrap_or_else(|| row[3].to_string()),
+                mode: mode_of(row[4]),
+                // Feeds with a single agency may leave agency_id out.
+                operator: agencies.get(row[1]).or_else(|| agencies.values().next().filter(|_| agencies.len() == 1)).cloned(),
+            });
+        })?;
+
+        let mut service_ids = HashMap::new();
+        let mut service = |id: &str, services: &mut Vec<Service>| {
+            *service_ids.entry(id.to_string()).or_insert_with(|| {
+                services.push(Service::default());
+                services.len() - 1
+            })
+        };
+        if let Some(text) = file("calendar.txt") {
+            let days = ["monday", "tuesday", "wednesday", "thursday", "friday", "saturday", "sunday"];
+            let columns: Vec<&str> = ["service_id", "start_date", "end_date"].into_iter().chain(days).collect();
+            read_csv(&text, &columns, |row| {
+                let index = service(row[0], &mut feed.services);
+                let entry = &mut feed.services[index];
+                entry.from = parse_date(row[1]);
+                entry.to = parse_date(row[2]);
+                for (day, runs) in entry.weekdays.iter_mut().zip(&row[3..]) {
+                    *day = *runs == "1";
+                }
+            })?;
+        }
+        if let Some(text) = file("calendar_dates.txt") {
+            read_csv(&text, &["service_id", "date", "exception_type"], |row| {
+                let index = service(row[0], &mut feed.services);
+                let Some(date) = parse_date(row[1]) else { return };
+                match row[2] {
+                    "1" => feed.services[index].added.insert(date),
+                    "2" => feed.services[index].removed.insert(date),
+                    _ => false,
+                };
+            })?;
+        }
+
+        let mut trip_ids = HashMap::new();
+        read_csv(&required("trips.txt")?, &["trip_id", "route_id", "service_id", "trip_headsign"], |row| {
+            let Some(&route) = route_ids.get(row[1]) else { return };
+            trip_ids.insert(row[0].to_string(), feed.trips.len());
+            feed.trips.push(Trip { id: row[0].to_string(), route, service: service(row[2], &mut feed.services), headsign: non_empty(row[3]) });
+        })?;
+
+        // (trip, sequence, stop, departure); a trip's last stop is no departure.
+        let mut calls = Vec::new();
+        let mut last_call: HashMap<usize, u32> = HashMap::new();
+        read_csv(&required("stop_times.txt")?, &["trip_id", "departure_time", "stop_id", "stop_sequence", "pickup_type"], |row| {
+            let (Some(&trip), Some(&stop), Ok(sequence)) = (trip_ids.get(row[0]), feed.by_id.get(row[2]), row[3].parse::<u32>()) else {
+                return;
+            };
+            let last = last_call.entry(trip).or_default();
+            *last = (*last).max(sequence);
+            // pickup_type 1: passengers can only get off here.
+            if let (Some(time), false) = (parse_time(row[1]), row[4] == "1") {
+                calls.push((trip, sequence, stop, time));
+            }
+        })?;
+        feed.departures = vec![Vec::new(); feed.stops.len()];
+        for (trip, sequence, stop, time) in calls {
+            if last_call.get(&trip) != Some(&sequence) {
+                feed.departures[stop].push((time, trip, sequence));
+            }
+        }
+        for departures in &mut feed.departures {
+            departures.sort_unstable();
+        }
+        Ok(feed)
+    }
+
+    /// Stations whose name contains every word of `query`, at most `max`;
+    /// names starting with the query come first.
+    pub fn stopfinder(&self, query: &str, max: usize) -> Vec<StopSuggestion> {
+        let query = query.to_lowercase();
+        let words: Vec<&str> = query.split_whitespace().collect();
+        if words.is_empty() {
+            return Vec::new();
+        }
+        let mut hits: Vec<(bool, &Stop)> = self
+            .stops
+            .iter()
+            .filter(|s| s.station.is_none())
+            .filter_map(|s| {
+                let name = s.name.to_lowercase();
+                words.iter().all(|w| name.contains(w)).then(|| (!name.starts_with(&query), s))
+            })
+            .collect();
+        hits.sort_by_key(|(later, s)| (*later, s.name.len()));
+        hits.into_iter()
+            .take(max)
+            .map(|(_, s)| StopSuggestion { id: s.id.clone(), name: s.name.clone(), ..Default::default() })
+            .collect()
+    }
+
+    /// The next `max` timetabled departures at or after `when` from stop or
+    /// station `id`, including all platforms of a station.
+    pub fn departures(&self, id: &str, max: usize, when: NaiveDateTime) -> Vec<Departure> {
+        self.board(id, max, when, None)
+    }
+
+    /// Like `departures`, with the delays and cancellations of `realtime`, a
+    /// TripUpdates feed for this timetable.
+    pub fn live_departures(&self, id: &str, max: usize, when: NaiveDateTime, realtime: &TripUpdates) -> Vec<Departure> {
+        self.board(id, max, when, Some(realtime))
+    }
+
+    fn board(&self, id: &str, max: usize, when: NaiveDateTime, realtime: Option<&TripUpdates>) -> Vec<Departure> {
+        let Some(station) = self.station(id) else { return Vec::new() };
+        let stops: Vec<usize> = (0..self.stops.len())
+            .filter(|&s| s == station || self.stops[s].station == Some(station))
+            .collect();
+        let mut rows = Vec::new();
+        // Trips of the day before may still run after midnight.
+        for day in [when.date() - TimeDelta::days(1), when.date()] {
+            let midnight = day.and_time(NaiveTime::MIN);
+            // Delayed departures planned earlier may still be to come.
+            let earliest = if realtime.is_some() { when - MAX_DELAY } else { when };
+            let from = u32::try_from((earliest - midnight).num_seconds()).unwrap_or(0);
+            for &stop in &stops {
+                let departures = &self.departures[stop];
+                let first = departures.partition_point(|&(time, ..)| time < from);
+                let running = departures[first..]
+                    .iter()
+                    .filter(|&&(_, trip, _)| self.services[self.trips[trip].service].runs_on(day))
+                    .map(|&(time, trip, sequence)| {
+                        let planned = midnight + TimeDelta::seconds(time.into());
+                        let update = realtime.and_then(|r| r.get(&self.trips[trip].id, day));
+                        let expected = update.map_or(Expected::Unknown, |u| u.expected(sequence, &self.stops[stop].id, planned));
+                        self.departure(stop, trip, planned, expected)
+                    })
+                    .filter(|d| d.time >= when);
+                rows.extend(running.take(max));
+            }
+        }
+        rows.sort_by_key(|d| d.time);
+        rows.truncate(max);
+        rows
+    }
+
+    fn departure(&self, stop: usize, trip: usize, planned: NaiveDateTime, expected: Expected) -> Departure {
+        let trip = &self.trips[trip];
+        let route = &self.routes[trip.route];
+        let realtime_time = match expected {
+            Expected::At(time) => Some(time),
+            Expected::Unknown | Expected::Cancelled => None,
+        };
+        Departure {
+            line: route.line.clone(),
+            direction: trip.headsign.clone(),
+            time: realtime_time.unwrap_or(planned),
+            planned_time: planned,
+            realtime_time,
+            delay_minutes: realtime_time.map(|time| (time - planned).num_minutes() as i32),
+            cancelled: expected == Expected::Cancelled,
+            mode: route.mode,
+            platform: self.stops[stop].platform.clone(),
+            operator: route.operator.clone(),
+            ..Default::default()
+        }
+    }
+
+    /// Stop `id` of the feed, or the KVV stop with EFA id `id`: those are 7
+    /// followed by the stop number of the DHID, e.g. 7001004 for de:08212:1004.
+    fn station(&self, id: &str) -> Option<usize> {
+        if let Some(&stop) = self.by_id.get(id) {
+            return Some(stop);
+        }
+        let number: u32 = id.strip_prefix('7').filter(|n| n.len() == 6)?.parse().ok()?;
+        self.stops.iter().position(|s| {
+            let mut parts = s.id.split(':');
+            s.station.is_none() && parts.next() == Some("de") && parts.nth(1).and_then(|n| n.parse().ok()) == Some(number) && parts.next().is_none()
+        })
+    }
+}
+
+/// `route_type`, basic or extended (as in the German feeds).
+fn mode_of(route_type: &str) -> TransportMode {
+    match route_type.parse::<u32>().unwrap_or(u32::MAX) {
+        0 | 900..=999 => TransportMode::Tram,
+        1 | 401..=402 => TransportMode::Subway,
+        400 | 403..=405 => TransportMode::LightRail,
+        2 | 100 | 107..=108 | 110..=117 => TransportMode::Train,
+        101..=102 => TransportMode::LongDistanceTrain,
+        103..=106 => TransportMode::RegionalTrain,
+        109 => TransportMode::SBahn,
+        714 => TransportMode::ReplacementBus,
+        715 => TransportMode::OnDemand,
+        3 | 11 | 200..=299 | 700..=799 => TransportMode::Bus,
+        4 | 1000..=1099 | 1200 => TransportMode::Ferry,
+        5..=7 | 12 | 1300..=1499 => TransportMode::CableCar,
+        _ => TransportMode::Other,
+    }
+}
+
+fn non_empty(field: &str) -> Option<String> {
+    (!field.is_empty()).then(|| field.to_string())
+}
+
+/// `YYYYMMDD`.
+fn parse_date(field: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(field, "%Y%m%d").ok()
+}
+
+/// `H:MM:SS` as seconds after midnight; may be past 24:00.
+fn parse_time(field: &str) -> Option<u32> {
+    let mut parts = field.trim().split(':').map(|p| p.parse::<u32>().ok());
+    let (h, m, s) = (parts.next()??, parts.next()??, parts.next()??);
+    Some(h * 3600 + m * 60 + s)
+}
+
+/// Calls `row` with the fields named `columns` of every record of a GTFS
+/// CSV file, in that order; columns the file doesn't have are empty.
+fn read_csv(text: &str, columns: &[&str], mut row: impl FnMut(&[&str])) -> Result<(), EfaError> {
+    let mut records = Records { text: text.trim_start_matches('\u{feff}'), pos: 0 };
+    let header = records.next().ok_or_else(|| EfaError::Parse("GTFS file without header".to_string()))??;
+    let index: Vec<Option<usize>> = columns.iter().map(|c| header.iter().position(|h| h.trim() == *c)).collect();
+    for record in records {
+        let record = record?;
+        let values: Vec<&str> = index.iter().map(|i| i.and_then(|i| record.get(i)).map_or("", |f| f.as_ref())).collect();
+        row(&values);
+    }
+    Ok(())
+}
+
+// Records of RFC 4180 CSV: quoted fields may contain commas, line breaks
+// and doubled quotes. Empty lines are skipped.
+struct Records<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> Iterator for Records<'a> {
+    type Item = Result<Vec<Cow<'a, str>>, EfaError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let bytes = self.text.as_bytes();
+        while bytes.get(self.pos).is_some_and(|b| *b == b'\n' || *b == b'\r') {
+            self.pos += 1;
+        }
+        if self.pos >= bytes.len() {
+            return None;
+        }
+        let mut fields = Vec::new();
+        loop {
+            if bytes.get(self.pos) == Some(&b'"') {
+                let mut field = String::new();
+                let mut start = self.pos + 1;
+                loop {
+                    let Some(end) = self.text[start..].find('"').map(|i| start + i) else {
+                        self.pos = bytes.len();
+                        return Some(Err(EfaError::Parse("GTFS file with unterminated quote".to_string())));
+                    };
+                    field.push_str(&self.text[start..end]);
+                    if bytes.get(end + 1) == Some(&b'"') {
+                        field.push('"');
+                        start = end + 2;
+                    } else {
+                        self.pos = end + 1;
+                        break;
+                    }
+                }
+                fields.push(Cow::Owned(field));
+            } else {
+                let end = self.text[self.pos..].find([',', '\n', '\r']).map_or(bytes.len(), |i| self.pos + i);
+                fields.push(Cow::Borrowed(&self.text[self.pos..end]));
+                self.pos = end;
+            }
+            match bytes.get(self.pos) {
+                Some(b',') => self.pos += 1,
+                _ => {
+                    // Skip whatever follows a closing quote up to the line end.
+                    self.pos = self.text[self.pos..].find('\n').map_or(bytes.len(), |i| self.pos + i + 1);
+                    return Some(Ok(fields));
+                }
+            }
+        }
+    }
+}
+
+// Just enough of the zip format for GTFS feeds: the central directory and
+// stored or deflated files. Zip64 archives, for more than 4 GiB or 65535
+// files, are refused rather than misread.
+fn unzip(zip: &[u8]) -> Result<HashMap<String, Vec<u8>>, EfaError> {
+    let invalid = |what: &str| EfaError::Parse(format!("GTFS feed is not a valid zip archive: {what}"));
+    let u16_at = |at: usize| zip.get(at..at + 2).map(|b| u16::from_le_bytes([b[0], b[1]]) as usize);
+    let u32_at = |at: usize| zip.get(at..at + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize);
+
+    // The end of central directory record is followed by a comment of up to 64 KiB.
+    let search_from = zip.len().saturating_sub(22 + usize::from(u16::MAX));
+    let end = (search_from..zip.len().saturating_sub(21))
+        .rev()
+        .find(|&at| u32_at(at) == Some(0x0605_4b50))
+        .ok_or_else(|| invalid("no central directory"))?;
+    let zip64 = || EfaError::Parse("GTFS feed is a zip64 archive, which is not supported".to_string());
+    if end >= 20 && u32_at(end - 20) == Some(0x0706_4b50) {
+        return Err(zip64());
+    }
+    let count = u16_at(end + 10).ok_or_else(|| invalid("truncated"))?;
+    let mut at = u32_at(end + 16).ok_or_else(|| invalid("truncated"))?;
+    if count == 0xFFFF || at == 0xFFFF_FFFF {
+        return Err(zip64());
+    }
+
+    let mut files = HashMap::new();
+    for _ in 0..count {
+        if u32_at(at) != Some(0x0201_4b50) {
+            return Err(invalid("broken central directory"));
+        }
+        let entry = (u16_at(at + 10), u32_at(at + 20), u16_at(at + 28), u16_at(at + 30), u16_at(at + 32), u32_at(at + 42));
+        let (Some(method), Some(size), Some(name_len), Some(extra_len), Some(comment_len), Some(local)) = entry else {
+            return Err(invalid("truncated"));
+        };
+        if size == 0xFFFF_FFFF || local == 0xFFFF_FFFF {
+            return Err(zip64());
+        }
+        let name = zip.get(at + 46..at + 46 + name_len).ok_or_else(|| invalid("truncated"))?;
+        let name = String::from_utf8_lossy(name);
+        at += 46 + name_len + extra_len + comment_len;
+
+        // The local header may have an extra field of its own.
+        let (Some(local_name_len), Some(local_extra_len)) = (u16_at(local + 26), u16_at(local + 28)) else {
+            return Err(invalid("truncated"));
+        };
+        let start = local + 30 + local_name_len + local_extra_len;
+        let data = zip.get(start..start + size).ok_or_else(|| invalid("truncated"))?;
+        let content = match method {
+            0 => data.to_vec(),
+            8 => miniz_oxide::inflate::decompress_to_vec(data).map_err(|e| invalid(&format!("{name}: {e:?}")))?,
+            _ => return Err(invalid(&format!("{name}: compression method {method}"))),
+        };
+        // Some feeds put their files into a folder.
+        let file = name.rsplit('/').next().unwrap_or_default().to_string();
+        files.insert(file, content);
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_csv, Feed};
+    use crate::efa::{hhmm, EfaError, TransportMode};
+    use chrono::{NaiveDate, NaiveDateTime};
+
+    const FILES: [(&str, &str); 7] = [
+        ("agency.txt", include_str!("../testdata/gtfs/agency.txt")),
+        ("stops.txt", include_str!("../testdata/gtfs/stops.txt")),
+        ("routes.txt", include_str!("../testdata/gtfs/routes.txt")),
+        ("trips.txt", include_str!("../testdata/gtfs/trips.txt")),
+        ("stop_times.txt", include_str!("../testdata/gtfs/stop_times.txt")),
+        ("calendar.txt", include_str!("../testdata/gtfs/calendar.txt")),
+        ("calendar_dates.txt", include_str!("../testdata/gtfs/calendar_dates.txt")),
+    ];
+
+    // A zip archive of FILES, stops.txt stored and the others deflated.
+    fn feed_zip() -> Vec<u8> {
+        let mut zip = Vec::new();
+        let mut directory = Vec::new();
+        for (name, text) in FILES {
+            let (method, data) = match name {
+                "stops.txt" => (0u16, text.as_bytes().to_vec()),
+                _ => (8, miniz_oxide::deflate::compress_to_vec(text.as_bytes(), 6)),
+            };
+            let header = |signature: u32, central: bool| {
+                let mut h = signature.to_le_bytes().to_vec();
+                if central {
+                    h.extend(20u16.to_le_bytes());
+                }
+                h.extend(20u16.to_le_bytes());
+                h.extend(0u16.to_le_bytes());
+                h.extend(method.to_le_bytes());
+                h.extend([0; 8]);
+                h.extend((data.len() as u32).to_le_bytes());
+                h.extend((text.len() as u32).to_le_bytes());
+                h.extend((name.len() as u16).to_le_bytes());
+                h.extend(0u16.to_le_bytes());
+                h
+            };
+            let mut central = header(0x0201_4b50, true);
+            central.extend([0; 10]);
+            central.extend((zip.len() as u32).to_le_bytes());
+            central.extend(name.as_bytes());
+            directory.extend(central);
+            zip.extend(header(0x0403_4b50, false));
+            zip.extend(name.as_bytes());
+            zip.extend(data);
+        }
+        let offset = zip.len() as u32;
+        zip.extend(&directory);
+        zip.extend(0x0605_4b50u32.to_le_bytes());
+        zip.extend([0; 4]);
+        zip.extend((FILES.len() as u16).to_le_bytes());
+        zip.extend((FILES.len() as u16).to_le_bytes());
+        zip.extend((directory.len() as u32).to_le_bytes());
+        zip.extend(offset.to_le_bytes());
+        zip.extend(0u16.to_le_bytes());
+        zip
+    }
+
+    fn at(day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 1, day).unwrap().and_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn csv_fields_may_be_quoted() {
+        let mut rows = Vec::new();
+        let text = "\u{feff}a,b,c\r\n1,\"x, \"\"y\"\"\",3\r\n\r\n4,\"multi\nline\"\n";
+        read_csv(text, &["c", "b", "missing"], |row| rows.push(row.join("|"))).unwrap();
+        assert_eq!(rows, ["3|x, \"y\"|", "|multi\nline|"]);
+    }
+
+    #[test]
+    fn scheduled_departures_come_from_the_feed() {
+        let feed = Feed::from_zip(&feed_zip()).expect("valid feed");
+        // Tuesday morning, by EFA id: both platforms, no realtime data.
+        let board = feed.departures("7001004", 5, at(16, 8, 0));
+        let lines: Vec<(&str, String)> = board.iter().map(|d| (d.line.as_str(), hhmm(&d.time))).collect();
+        assert_eq!(lines, [("S2", "08:05".to_string()), ("2", "08:10".to_string()), ("S2", "23:55".to_string())]);
+        assert_eq!(board[0].mode, TransportMode::SBahn);
+        assert_eq!(board[1].platform.as_deref(), Some("2"));
+        assert_eq!(board[1].operator.as_deref(), Some("VBK"));
+        assert_eq!(board[0].realtime_time, None);
+
+        // The late S2 reaches Marktplatz after midnight; the tram only lets
+        // passengers off there and Spöck is where the S2 ends.
+        let marktplatz: Vec<_> = feed.departures("de:08212:90", 5, at(17, 0, 0)).into_iter().map(|d| d.time).collect();
+        assert_eq!(marktplatz, [at(17, 0, 4), at(17, 8, 13), at(18, 0, 4)]);
+        assert!(feed.departures("de:08215:238", 5, at(16, 0, 0)).is_empty());
+
+        // New Year's Day runs the weekend timetable.
+        let holiday = feed.departures("7001004", 5, at(1, 8, 0));
+        assert_eq!(holiday[0].time, at(1, 9, 5));
+    }
+
+    #[test]
+    fn zip64_archives_are_refused_by_name() {
+        let mut zip = feed_zip();
+        let end = zip.len() - 22;
+        // A zip64 end of central directory locator right before the record.
+        zip.splice(end..end, 0x0706_4b50u32.to_le_bytes().into_iter().chain([0; 16]));
+        let refused = Feed::from_zip(&zip).err();
+        assert!(matches!(&refused, Some(EfaError::Parse(m)) if m.contains("zip64")), "{refused:?}");
+    }
+
+    #[test]
+    fn stations_are_found_by_name() {
+        let feed = Feed::from_zip(&feed_zip()).expect("valid feed");
+        let hits = feed.stopfinder("karlsruhe zkm", 5);
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].id, "de:08212:1004");
+        assert_eq!(feed.stopfinder("pyramide", 5)[0].name, "Karlsruhe Marktplatz (Pyramide U)");
+    }
+}
@@ -0,0 +1,394 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, TimeDelta};
+use efa_core::berlin_offset;
+
+use crate::efa::{fetch_bytes, EfaError};
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Realtime state of the trips in a GTFS-Realtime feed, as published next
+/// to a static feed. Applied to its timetable with `Feed::live_departures`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TripUpdates {
+    /// When the feed was produced (local time), if it says.
+    pub produced_at: Option<NaiveDateTime>,
+    // By trip id and start date; updates without a date apply to every day.
+    trips: HashMap<(String, Option<NaiveDate>), TripUpdate>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct TripUpdate {
+    cancelled: bool,
+    /// Delay of the whole trip in seconds, for stops without an update.
+    delay: Option<i64>,
+    /// In trip order, as the feed must list them.
+    stops: Vec<StopTimeUpdate>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+struct StopTimeUpdate {
+    sequence: Option<u32>,
+    stop_id: Option<String>,
+    arrival: Option<StopTimeEvent>,
+    departure: Option<StopTimeEvent>,
+    skipped: bool,
+    no_data: bool,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct StopTimeEvent {
+    /// Seconds late, negative when early.
+    delay: Option<i64>,
+    /// POSIX time.
+    time: Option<i64>,
+}
+
+/// What the realtime feed says about one planned departure.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Expected {
+    /// No realtime data: show the planned time only.
+    Unknown,
+    Cancelled,
+    At(NaiveDateTime),
+}
+
+/// Download and parse the TripUpdates feed at `url`.
+pub async fn fetch(url: &str) -> Result<TripUpdates, EfaError> {
+    TripUpdates::parse(&fetch_bytes(url, FETCH_TIMEOUT).await?)
+}
+
+impl TripUpdates {
+    /// Parse a `FeedMessage`; vehicle positions and alerts are skipped.
+    pub fn parse(bytes: &[u8]) -> Result<TripUpdates, EfaError> {
+        let mut updates = TripUpdates::default();
+        for (field, value) in Message::new(bytes) {
+            match (field?, value) {
+                (1, Value::Bytes(header)) => {
+                    for (field, value) in Message::new(header) {
+                        if let (3, Value::Int(timestamp)) = (field?, value) {
+                            updates.produced_at = local_time(timestamp as i64);
+                        }
+                    }
+                }
+                (2, Value::Bytes(entity)) => updates.add_entity(entity)?,
+                _ => {}
+            }
+        }
+        Ok(updates)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.trips.is_empty()
+    }
+
+    /// Update of trip `trip_id` running on service day `day`.
+    pub(crate) fn get(&self, trip_id: &str, day: NaiveDate) -> Option<&TripUpdate> {
+        let key = |day| (trip_id.to_string(), day);
+        self.trips.get(&key(Some(day))).or_else(|| self.trips.get(&key(None)))
+    }
+
+    fn add_entity(&mut self, entity: &[u8]) -> Result<(), EfaError> {
+        let mut deleted = false;
+        let mut update = None;
+        for (field, value) in Message::new(entity) {
+            match (field?, value) {
+                (2, Value::Int(flag)) => deleted = flag != 0,
+                (3, Value::Bytes(trip_update)) => update = Some(parse_trip_update(trip_update)?),
+                _ => {}
+            }
+        }
+        match update {
+            Some((key, update)) if !deleted => {
+                self.trips.insert(key, update);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+fn parse_trip_update(bytes: &[u8]) -> Result<((String, Option<NaiveDate>), TripUpdate), EfaError> {
+    let mut trip_id = String::new();
+    let mut start_date = None;
+    let mut update = TripUpdate::default();
+    for (field, value) in Message::new(bytes) {
+        match (field?, value) {
+            (1, Value::Bytes(descriptor)) => {
+                for (field, value) in Message::new(descriptor) {
+                    match (field?, value) {
+                        (1, Value::Bytes(id)) => trip_id = String::from_utf8_lossy(id).into_owned(),
+                        (3, Value::Bytes(date)) => start_date = NaiveDate::parse_from_str(&String::from_utf8_lossy(date), "%Y%m%d").ok(),
+                        // CANCELED, and DELETED for trips that shouldn't even be shown.
+                        (4, Value::Int(3 | 7)) => update.cancelled = true,
+                        _ => {}
+                    }
+                }
+            }
+            (2, Value::Bytes(stop)) => update.stops.push(parse_stop_time_update(stop)?),
+            (5, Value::Int(delay)) => update.delay = Some(i64::from(delay as i32)),
+            _ => {}
+        }
+    }
+    Ok(((trip_id, start_date), update))
+}
+
+fn parse_stop_time_update(bytes: &[u8]) -> Result<StopTimeUpdate, EfaError> {
+    let mut stop = StopTimeUpdate::default();
+    for (field, value) in Message::new(bytes) {
+        match (field?, value) {
+            (1, Value::Int(sequence)) => stop.sequence = u32::try_from(sequence).ok(),
+            (2, Value::Bytes(event)) => stop.arrival = Some(parse_event(event)?),
+            (3, Value::Bytes(event)) => stop.departure = Some(parse_event(event)?),
+            (4, Value::Bytes(id)) => stop.stop_id = Some(String::from_utf8_lossy(id).into_owned()),
+            (5, Value::Int(1)) => stop.skipped = true,
+            (5, Value::Int(2)) => stop.no_data = true,
+            _ => {}
+        }
+    }
+    Ok(stop)
+}
+
+fn parse_event(bytes: &[u8]) -> Result<StopTimeEvent, EfaError> {
+    let mut event = StopTimeEvent::default();
+    for (field, value) in Message::new(bytes) {
+        match (field?, value) {
+            // int32 is sign-extended to 64 bits on the wire.
+            (1, Value::Int(delay)) => event.delay = Some(i64::from(delay as i32)),
+            (2, Value::Int(time)) => event.time = Some(time as i64),
+            _ => {}
+        }
+    }
+    Ok(event)
+}
+
+impl TripUpdate {
+    /// The departure planned at `planned` from the stop `stop_id`, number
+    /// `sequence` of the trip. Stops without an update of their own take
+    /// the delay of the last stop before them that has one.
+    pub(crate) fn expected(&self, sequence: u32, stop_id: &str, planned: NaiveDateTime) -> Expected {
+        if self.cancelled {
+            return Expected::Cancelled;
+        }
+        let own = self.stops.iter().find(|s| s.sequence.map_or(s.stop_id.as_deref() == Some(stop_id), |seq| seq == sequence));
+        if let Some(stop) = own {
+            if stop.skipped {
+                return Expected::Cancelled;
+            }
+            if stop.no_data {
+                return Expected::Unknown;
+            }
+            // Arriving late at the last stop of a trip still means leaving late.
+            if let Some(event) = stop.departure.or(stop.arrival) {
+                if let Some(at) = event.time.and_then(local_time) {
+                    return Expected::At(at);
+                }
+                if let Some(delay) = event.delay {
+                    return Expected::At(planned + TimeDelta::seconds(delay));
+                }
+            }
+        }
+        // Skipped stops don't say anything about the stops after them.
+        let before = self.stops.iter().rev().find(|s| s.sequence.is_some_and(|seq| seq < sequence) && !s.skipped);
+        let delay = match before {
+            Some(stop) if stop.no_data => return Expected::Unknown,
+            Some(stop) => stop.departure.or(stop.arrival).and_then(|e| e.delay),
+            None => None,
+        };
+        match delay.or(self.delay) {
+            Some(delay) => Expected::At(planned + TimeDelta::seconds(delay)),
+            None => Expected::Unknown,
+        }
+    }
+}
+
+/// POSIX time as Berlin wall-clock time, like EFA's and the timetable's.
+fn local_time(timestamp: i64) -> Option<NaiveDateTime> {
+    let utc = DateTime::from_timestamp(timestamp, 0)?.naive_utc();
+    Some(utc + berlin_offset(utc))
+}
+
+// Protocol buffers wire format, as far as GTFS-Realtime needs it.
+enum Value<'a> {
+    /// Varint and fixed-size fields; signed values are two's complement.
+    Int(u64),
+    Bytes(&'a [u8]),
+}
+
+// Fields of one message: `(number, value)`, a broken message ends with an error.
+struct Message<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Message<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Message { bytes, pos: 0 }
+    }
+
+    fn varint(&mut self) -> Option<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = *self.bytes.get(self.pos)?;
+            self.pos += 1;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.bytes.get(self.pos..self.pos.checked_add(len)?)?;
+        self.pos += len;
+        Some(bytes)
+    }
+
+    fn field(&mut self) -> Option<(u32, Value<'a>)> {
+        let key = self.varint()?;
+        let value = match key & 7 {
+            0 => Value::Int(self.varint()?),
+            1 => Value::Int(u64::from_le_bytes(self.take(8)?.try_into().ok()?)),
+            2 => {
+                let len = usize::try_from(self.varint()?).ok()?;
+                Value::Bytes(self.take(len)?)
+            }
+            5 => Value::Int(u32::from_le_bytes(self.take(4)?.try_into().ok()?).into()),
+            _ => return None,
+        };
+        Some((u32::try_from(key >> 3).ok()?, value))
+    }
+}
+
+impl<'a> Iterator for Message<'a> {
+    type Item = (Result<u32, EfaError>, Value<'a>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pos >= self.bytes.len() {
+            return None;
+        }
+        match self.field() {
+            Some((number, value)) => Some((Ok(number), value)),
+            None => {
+                self.pos = self.bytes.len();
+                Some((Err(EfaError::Parse("broken GTFS-Realtime message".to_string())), Value::Int(0)))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Expected, TripUpdates};
+    use crate::gtfs::Feed;
+    use chrono::{NaiveDate, NaiveDateTime};
+
+    // Protocol buffers encoding of the test feeds.
+    fn varint(mut value: u64, out: &mut Vec<u8>) {
+        while value >= 0x80 {
+            out.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        out.push(value as u8);
+    }
+
+    fn int(field: u64, value: i64) -> Vec<u8> {
+        let mut out = Vec::new();
+        varint(field << 3, &mut out);
+        varint(value as u64, &mut out);
+        out
+    }
+
+    fn bytes(field: u64, value: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        varint(field << 3 | 2, &mut out);
+        varint(value.len() as u64, &mut out);
+        out.extend(value);
+        out
+    }
+
+    fn message(fields: &[Vec<u8>]) -> Vec<u8> {
+        fields.concat()
+    }
+
+    fn at(day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 1, day).unwrap().and_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    fn feed() -> Vec<u8> {
+        let trip = |id: &str, relationship: i64| message(&[bytes(1, id.as_bytes()), bytes(3, b"20240115"), int(4, relationship)]);
+        let stop = |sequence: i64, event: Vec<u8>| message(&[int(1, sequence), bytes(3, &event)]);
+        // 08:30 CET
+        let posix = NaiveDate::from_ymd_opt(2024, 1, 15).unwrap().and_hms_opt(7, 30, 0).unwrap().and_utc().timestamp();
+        let late = message(&[
+            bytes(1, &trip("s2-0805", 0)),
+            bytes(2, &stop(1, int(1, 120))),
+            bytes(2, &message(&[int(1, 2), int(5, 1)])),
+            bytes(2, &stop(3, int(2, posix))),
+        ]);
+        let early = message(&[bytes(1, &trip("t2-0810", 0)), bytes(2, &stop(1, int(1, -60)))]);
+        let cancelled = message(&[bytes(1, &trip("s2-2355", 3))]);
+        message(&[
+            bytes(1, &message(&[bytes(1, b"2.0"), int(3, posix)])),
+            bytes(2, &message(&[bytes(1, b"1"), bytes(3, &late)])),
+            bytes(2, &message(&[bytes(1, b"2"), bytes(3, &early)])),
+            bytes(2, &message(&[bytes(1, b"3"), bytes(3, &cancelled)])),
+            // Vehicle positions are skipped.
+            bytes(2, &message(&[bytes(1, b"4"), bytes(4, &int(5, 1))])),
+        ])
+    }
+
+    #[test]
+    fn trip_updates_give_expected_times() {
+        let updates = TripUpdates::parse(&feed()).expect("valid feed");
+        assert_eq!(updates.trips.len(), 3);
+        assert_eq!(updates.produced_at, Some(at(15, 8, 30)));
+        let monday = NaiveDate::from_ymd_opt(2024, 1, 15).unwrap();
+        let late = updates.get("s2-0805", monday).expect("update");
+        assert_eq!(late.expected(1, "de:08212:1004:1:1", at(15, 8, 5)), Expected::At(at(15, 8, 7)));
+        assert_eq!(late.expected(2, "de:08212:90:1:1", at(15, 8, 13)), Expected::Cancelled);
+        assert_eq!(late.expected(3, "de:08215:238", at(15, 8, 40)), Expected::At(at(15, 8, 30)));
+        // Negative delays are sign-extended varints.
+        let early = updates.get("t2-0810", monday).expect("update");
+        assert_eq!(early.expected(1, "de:08212:1004:2:2", at(15, 8, 10)), Expected::At(at(15, 8, 9)));
+        assert_eq!(early.expected(3, "de:08212:461", at(15, 8, 30)), Expected::At(at(15, 8, 29)));
+        assert_eq!(updates.get("s2-2355", monday).expect("update").expected(1, "x", at(15, 23, 55)), Expected::Cancelled);
+        // Updates are for the trip's start date only.
+        assert!(updates.get("s2-0805", NaiveDate::from_ymd_opt(2024, 1, 16).unwrap()).is_none());
+    }
+
+    #[test]
+    fn timetable_departures_get_realtime_data() {
+        let schedule = Feed::from_files(|name| match name {
+            "stops.txt" => Some(include_str!("../testdata/gtfs/stops.txt").into()),
+            "routes.txt" => Some(include_str!("../testdata/gtfs/routes.txt").into()),
+            "trips.txt" => Some(include_str!("../testdata/gtfs/trips.txt").into()),
+            "stop_times.txt" => Some(include_str!("../testdata/gtfs/stop_times.txt").into()),
+            "calendar.txt" => Some(include_str!("../testdata/gtfs/calendar.txt").into()),
+            _ => None,
+        })
+        .expect("valid feed");
+        let updates = TripUpdates::parse(&feed()).expect("valid feed");
+        // The S2 planned at 08:05 leaves at 08:07, so it is still to come.
+        let board = schedule.live_departures("7001004", 5, at(15, 8, 6), &updates);
+        let rows: Vec<_> = board.iter().map(|d| (d.line.as_str(), d.time, d.delay_minutes, d.cancelled)).collect();
+        assert_eq!(rows, [
+            ("S2", at(15, 8, 7), Some(2), false),
+            ("2", at(15, 8, 9), Some(-1), false),
+            ("S2", at(15, 23, 55), None, true),
+        ]);
+        assert_eq!(board[0].planned_time, at(15, 8, 5));
+        // Other days have no updates.
+        assert_eq!(schedule.live_departures("7001004", 1, at(16, 8, 0), &updates)[0].realtime_time, None);
+    }
+
+    #[test]
+    fn broken_messages_are_rejected() {
+        let mut truncated = feed();
+        truncated.truncate(truncated.len() - 3);
+        assert!(TripUpdates::parse(&truncated).is_err());
+        assert_eq!(TripUpdates::parse(&[]), Ok(TripUpdates::default()));
+    }
+}
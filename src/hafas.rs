@@ -0,0 +1,403 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime, TimeDelta};
+use leptos::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::diagnostics;
+use crate::efa::{is_transient, post_text_with, Departure, EfaClient, EfaError, RetryPolicy, StopSuggestion, Transport, TransportMode};
+use crate::provider::{ProviderFuture, TransitProvider};
+use crate::store::use_store;
+use crate::tz;
+
+// Product classes of DB-style HAFAS profiles, which bwegt shares.
+const CLASS_REGIONAL: u64 = 8;
+const CLASS_SBAHN: u64 = 16;
+
+/// How to talk to one HAFAS "mgate" endpoint, e.g. DB's or bwegt's. The
+/// values are the ones the operator's own apps send; endpoints that want
+/// a request checksum (`mic`/`mac`) are not supported.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct HafasProfile {
+    /// URL of mgate, usually ending in "/mgate.exe" or "/gate".
+    pub endpoint: String,
+    /// The `client` object, e.g. `{"id": "DB", "type": "WEB", "name": "webapp"}`.
+    pub client: Value,
+    /// Access id sent as `{"type": "AID", "aid": ...}`.
+    pub aid: String,
+    /// Protocol version, e.g. "1.16".
+    pub version: String,
+    pub ext: Option<String>,
+}
+
+/// Client for a HAFAS mgate endpoint: stop search and departure boards,
+/// normalized into the same `Departure` as EFA's. Stop ids are HAFAS
+/// location ids ("lid").
+#[derive(Clone)]
+pub struct HafasClient {
+    profile: HafasProfile,
+    language: String,
+    retry: RetryPolicy,
+    transport: Rc<dyn Transport>,
+}
+
+impl HafasClient {
+    /// Client for the server in `profile`, sending its requests over `transport`.
+    pub fn new(profile: HafasProfile, transport: impl Transport + 'static) -> Self {
+        HafasClient { profile, language: "de".to_string(), retry: RetryPolicy::default(), transport: Rc::new(transport) }
+    }
+
+    /// Language of names and messages in responses, e.g. "en".
+    pub fn with_language(mut self, language: impl Into<String>) -> Self {
+        self.language = language.into();
+        self
+    }
+
+    /// Stations matching `query`, best first, at most `max` (LocMatch).
+    pub async fn stopfinder(&self, query: &str, max: usize) -> Result<Vec<StopSuggestion>, EfaError> {
+        let request = json!({ "input": { "loc": { "type": "S", "name": format!("{query}?") }, "maxLoc": max, "field": "S" } });
+        let res = self.request("LocMatch", request).await?;
+        Ok(parse_locations(&res, max))
+    }
+
+    /// The next `max` departures from the station `lid` at or after `when`
+    /// (local time), `None` meaning now (StationBoard).
+    pub async fn departures(&self, lid: &str, max: usize, when: Option<NaiveDateTime>) -> Result<Vec<Departure>, EfaError> {
+        self.board(lid, max, when, None).await
+    }
+
+    /// Like `departures`, with regional trains and S-Bahn only.
+    pub async fn regional_departures(&self, lid: &str, max: usize, when: Option<NaiveDateTime>) -> Result<Vec<Departure>, EfaError> {
+        self.board(lid, max, when, Some(CLASS_REGIONAL | CLASS_SBAHN)).await
+    }
+
+    async fn board(&self, lid: &str, max: usize, when: Option<NaiveDateTime>, classes: Option<u64>) -> Result<Vec<Departure>, EfaError> {
+        let when = when.unwrap_or_else(tz::now);
+        let mut request = json!({
+            "type": "DEP",
+            "date": when.format("%Y%m%d").to_string(),
+            "time": when.format("%H%M%S").to_string(),
+            "stbLoc": { "lid": lid, "type": "S" },
+            "maxJny": max,
+        });
+        if let Some(classes) = classes {
+            request["jnyFltrL"] = json!([{ "type": "PROD", "mode": "INC", "value": classes.to_string() }]);
+        }
+        let res = self.request("StationBoard", request).await?;
+        parse_board(&res, max)
+    }
+
+    // `res` of the one service request `meth`.
+    async fn request(&self, meth: &str, request: Value) -> Result<Value, EfaError> {
+        let mut body = json!({
+            "lang": self.language,
+            "svcReqL": [{ "meth": meth, "req": request }],
+            "client": self.profile.client,
+            "ver": self.profile.version,
+            "auth": { "type": "AID", "aid": self.profile.aid },
+        });
+        if let Some(ext) = &self.profile.ext {
+            body["ext"] = json!(ext);
+        }
+        let text = post_text_with(self.transport.as_ref(), &self.profile.endpoint, "application/json", &body.to_string(), &self.retry).await?;
+        parse_response(&text)
+    }
+}
+
+impl TransitProvider for HafasClient {
+    fn name(&self) -> &str {
+        "HAFAS"
+    }
+
+    fn stopfinder<'a>(&'a self, query: &'a str, max: usize) -> ProviderFuture<'a, Vec<StopSuggestion>> {
+        Box::pin(HafasClient::stopfinder(self, query, max))
+    }
+
+    fn departures<'a>(&'a self, station_id: &'a str, max: usize, when: Option<NaiveDateTime>) -> ProviderFuture<'a, Vec<Departure>> {
+        Box::pin(HafasClient::departures(self, station_id, max, when))
+    }
+}
+
+/// Departure boards from EFA, and the regional trains of the station from
+/// HAFAS while EFA can't be reached. Buses and trams only EFA knows about
+/// are missing from those boards.
+pub struct RegionalFallback {
+    efa: EfaClient,
+    hafas: HafasClient,
+    // HAFAS station of every EFA stop asked for, `None` if there is none.
+    stations: RefCell<HashMap<String, Option<String>>>,
+}
+
+impl RegionalFallback {
+    pub fn new(efa: EfaClient, hafas: HafasClient) -> Self {
+        RegionalFallback { efa, hafas, stations: RefCell::new(HashMap::new()) }
+    }
+
+    /// The board of `stop`, as `EfaClient::departures` gives it. Errors of
+    /// HAFAS, or stops without a station there, leave EFA's error as it was.
+    pub async fn departures(&self, stop: &StopSuggestion, max: usize, when: Option<NaiveDateTime>) -> Result<Vec<Departure>, EfaError> {
+        match self.efa.departures(&stop.id, max, when).await {
+            Err(e) if is_transient(&e) => {
+                let Ok(Some(lid)) = self.station(stop).await else { return Err(e) };
+                let board = self.hafas.regional_departures(&lid, max, when).await.map_err(|_| e.clone())?;
+                diagnostics::record_warning(format!("EFA unavailable, showing regional trains from HAFAS: {e}"));
+                Ok(board)
+            }
+            board => board,
+        }
+    }
+
+    async fn station(&self, stop: &StopSuggestion) -> Result<Option<String>, EfaError> {
+        if let Some(lid) = self.stations.borrow().get(&stop.id) {
+            return Ok(lid.clone());
+        }
+        let query = match &stop.place {
+            Some(place) if !stop.name.contains(place.as_str()) => format!("{place} {}", stop.name),
+            _ => stop.name.clone(),
+        };
+        let lid = self.hafas.stopfinder(&query, 1).await?.into_iter().next().map(|s| s.id);
+        self.stations.borrow_mut().insert(stop.id.clone(), lid.clone());
+        Ok(lid)
+    }
+}
+
+/// Settings of the HAFAS fallback. There are no defaults: users enter the
+/// endpoint and the values the operator's own apps send themselves.
+#[component]
+pub fn FallbackSettings() -> impl IntoView {
+    let store = use_store();
+    let enabled = move || store.settings.with(|s| s.hafas_fallback.is_some());
+    let shown = move |field: fn(&HafasProfile) -> String| {
+        move || store.settings.with(|s| s.hafas_fallback.as_ref().map(field).unwrap_or_default())
+    };
+    let edit = move |ev, field: fn(&mut HafasProfile, String)| {
+        let value = event_target_value(&ev).trim().to_string();
+        store.settings.update(|s| s.hafas_fallback.iter_mut().for_each(|profile| field(profile, value.clone())));
+    };
+    view! {
+        <label>
+            <input
+                type="checkbox"
+                prop:checked=enabled
+                on:change=move |ev| store.settings.update(|s| {
+                    s.hafas_fallback = event_target_checked(&ev).then(HafasProfile::default);
+                })
+            />
+            " Show regional trains from a HAFAS server while KVV's can't be reached"
+        </label>
+        <Show when=enabled>
+            <div class="row">
+                <input placeholder="mgate URL" prop:value=shown(|p| p.endpoint.clone())
+                    on:change=move |ev| edit(ev, |p, v| p.endpoint = v) />
+                <input placeholder="aid" prop:value=shown(|p| p.aid.clone())
+                    on:change=move |ev| edit(ev, |p, v| p.aid = v) />
+                <input placeholder="Version, e.g. 1.16" prop:value=shown(|p| p.version.clone())
+                    on:change=move |ev| edit(ev, |p, v| p.version = v) />
+            </div>
+            <div class="row">
+                <input placeholder="client object (JSON)" prop:value=shown(|p| if p.client.is_null() { String::new() } else { p.client.to_string() })
+                    on:change=move |ev| edit(ev, |p, v| p.client = serde_json::from_str(&v).unwrap_or(Value::Null)) />
+                <input placeholder="ext (optional)" prop:value=shown(|p| p.ext.clone().unwrap_or_default())
+                    on:change=move |ev| edit(ev, |p, v| p.ext = Some(v).filter(|v| !v.is_empty())) />
+            </div>
+        </Show>
+    }
+}
+
+/// The `res` of the first service result, or the error HAFAS gave instead.
+fn parse_response(text: &str) -> Result<Value, EfaError> {
+    let mut json: Value = serde_json::from_str(text).map_err(|e| EfaError::Parse(e.to_string()))?;
+    let error = |json: &Value| match json.get("err").and_then(Value::as_str) {
+        None | Some("OK") => None,
+        Some(code) => Some(EfaError::ServerMessage(json.get("errTxt").and_then(Value::as_str).unwrap_or(code).to_string())),
+    };
+    if let Some(e) = error(&json) {
+        return Err(e);
+    }
+    let result = json.get_mut("svcResL").and_then(|l| l.get_mut(0)).ok_or_else(|| EfaError::Parse("HAFAS response without result".to_string()))?;
+    match error(result) {
+        // H890: no departures found.
+        Some(EfaError::ServerMessage(_)) if result.get("err").and_then(Value::as_str) == Some("H890") => Ok(json!({})),
+        Some(e) => Err(e),
+        None => Ok(result.get_mut("res").map(Value::take).unwrap_or_default()),
+    }
+}
+
+fn parse_locations(res: &Value, max: usize) -> Vec<StopSuggestion> {
+    let locations = res.pointer("/match/locL").and_then(Value::as_array).map(Vec::as_slice).unwrap_or_default();
+    let mut stops: Vec<StopSuggestion> = locations
+        .iter()
+        .filter(|l| l.get("type").and_then(Value::as_str) == Some("S"))
+        .filter_map(|l| {
+            Some(StopSuggestion {
+                id: l.get("lid")?.as_str()?.to_string(),
+                name: l.get("name")?.as_str()?.to_string(),
+                ..Default::default()
+            })
+        })
+        .take(max)
+        .collect();
+    if let Some(first) = stops.first_mut() {
+        first.best = true;
+    }
+    stops
+}
+
+fn parse_board(res: &Value, max: usize) -> Result<Vec<Departure>, EfaError> {
+    let common = |list: &str| res.pointer(&format!("/common/{list}")).and_then(Value::as_array).map(Vec::as_slice).unwrap_or_default();
+    let (products, operators) = (common("prodL"), common("opL"));
+    let journeys = res.get("jnyL").and_then(Value::as_array).map(Vec::as_slice).unwrap_or_default();
+    let mut departures = Vec::new();
+    for journey in journeys {
+        let stop = journey.get("stbStop").unwrap_or(&Value::Null);
+        let text = |value: &Value, key: &str| value.get(key).and_then(Value::as_str).filter(|s| !s.is_empty()).map(str::to_string);
+        let Some(date) = text(journey, "date").and_then(|d| NaiveDate::parse_from_str(&d, "%Y%m%d").ok()) else { continue };
+        let Some(planned) = text(stop, "dTimeS").and_then(|t| parse_time(date, &t)) else { continue };
+        let realtime = text(stop, "dTimeR").and_then(|t| parse_time(date, &t));
+        let product = stop.get("dProdX").or_else(|| journey.get("prodX")).and_then(Value::as_u64).and_then(|x| products.get(x as usize));
+        let product = product.unwrap_or(&Value::Null);
+        let line = product
+            .pointer("/prodCtx/line")
+            .or_else(|| product.get("line"))
+            .or_else(|| product.get("name"))
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        // Newer profiles send platforms as objects.
+        let platform = |key: &str| text(stop, &format!("dPlatf{key}")).or_else(|| stop.pointer(&format!("/dPltf{key}/txt")).and_then(Value::as_str).map(str::to_string));
+        departures.push(Departure {
+            line: line.split_whitespace().collect::<Vec<_>>().join(" "),
+            direction: text(journey, "dirTxt"),
+            time: realtime.unwrap_or(planned),
+            planned_time: planned,
+            realtime_time: realtime,
+            mode: mode_of(product.get("cls").and_then(Value::as_u64).unwrap_or_default()),
+            platform: platform("R").or_else(|| platform("S")),
+            delay_minutes: realtime.map(|at| (at - planned).num_minutes() as i32),
+            cancelled: [journey.get("isCncl"), stop.get("dCncl")].into_iter().any(|c| c.and_then(Value::as_bool) == Some(true)),
+            operator: product
+                .get("oprX")
+                .and_then(Value::as_u64)
+                .and_then(|x| operators.get(x as usize))
+                .and_then(|o| text(o, "name")),
+            ..Default::default()
+        });
+    }
+    departures.truncate(max);
+    Ok(departures)
+}
+
+/// "HHMMSS" on `date`, or "DDHHMMSS" for times days after it.
+fn parse_time(date: NaiveDate, text: &str) -> Option<NaiveDateTime> {
+    let (days, time) = text.split_at(text.len().checked_sub(6).filter(|_| text.is_ascii())?);
+    let days: i64 = if days.is_empty() { 0 } else { days.parse().ok()? };
+    Some(date.and_time(NaiveTime::parse_from_str(time, "%H%M%S").ok()?) + TimeDelta::days(days))
+}
+
+/// Product class bit of DB-style profiles.
+fn mode_of(class: u64) -> TransportMode {
+    match class {
+        1 | 2 => TransportMode::LongDistanceTrain,
+        4 | CLASS_REGIONAL => TransportMode::RegionalTrain,
+        CLASS_SBAHN => TransportMode::SBahn,
+        32 => TransportMode::Bus,
+        64 => TransportMode::Ferry,
+        128 => TransportMode::Subway,
+        256 => TransportMode::Tram,
+        512 => TransportMode::OnDemand,
+        _ => TransportMode::Other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_board, parse_response, HafasClient, HafasProfile, RegionalFallback};
+    use crate::efa::{EfaClient, EfaError, RetryPolicy, StopSuggestion, Transport, TransportFuture, TransportMode};
+    use chrono::NaiveDate;
+    use serde_json::{json, Value};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    fn at(day: u32, hour: u32, minute: u32) -> chrono::NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 1, day).unwrap().and_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn station_boards_become_departures() {
+        let res = parse_response(include_str!("../testdata/hafas/stationboard.json")).expect("OK response");
+        let deps = parse_board(&res, 10).expect("valid board");
+        assert_eq!(deps.len(), 3);
+        let re = &deps[0];
+        assert_eq!((re.line.as_str(), re.direction.as_deref()), ("RE45", Some("Mannheim Hbf")));
+        assert_eq!((re.planned_time, re.time, re.delay_minutes), (at(15, 8, 5), at(15, 8, 9), Some(4)));
+        assert_eq!(re.mode, TransportMode::RegionalTrain);
+        assert_eq!(re.platform.as_deref(), Some("3"), "the changed platform");
+        assert_eq!(re.operator.as_deref(), Some("SWEG Bahn Stuttgart GmbH"));
+        assert!(deps[1].cancelled);
+        assert_eq!(deps[1].platform.as_deref(), Some("101"));
+        assert_eq!(deps[1].mode, TransportMode::SBahn);
+        // Times of the next day carry a day offset.
+        assert_eq!(deps[2].time, at(16, 0, 5));
+        assert_eq!(parse_board(&res, 1).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn hafas_errors_are_server_messages() {
+        let failed = r#"{"err":"AUTH","errTxt":"HCI Core: Authentication failed","svcResL":[]}"#;
+        assert_eq!(parse_response(failed), Err(EfaError::ServerMessage("HCI Core: Authentication failed".to_string())));
+        let empty = r#"{"err":"OK","svcResL":[{"meth":"StationBoard","err":"H890"}]}"#;
+        assert_eq!(parse_board(&parse_response(empty).unwrap(), 5), Ok(Vec::new()));
+    }
+
+    /// Fails GETs like an unreachable EFA and answers POSTs by method.
+    struct Offline(Rc<RefCell<Vec<Value>>>);
+
+    impl Transport for Offline {
+        fn get<'a>(&'a self, _url: &'a str, _timeout: Duration) -> TransportFuture<'a> {
+            Box::pin(async { Err(EfaError::Network("unreachable".to_string())) })
+        }
+
+        fn post<'a>(&'a self, _url: &'a str, _content_type: &'a str, body: &'a str, _timeout: Duration) -> TransportFuture<'a> {
+            let body: Value = serde_json::from_str(body).expect("JSON request");
+            let response = match body.pointer("/svcReqL/0/meth").and_then(Value::as_str) {
+                Some("LocMatch") => include_str!("../testdata/hafas/locmatch.json"),
+                _ => include_str!("../testdata/hafas/stationboard.json"),
+            };
+            self.0.borrow_mut().push(body);
+            Box::pin(async move { Ok(response.to_string()) })
+        }
+    }
+
+    #[tokio::test]
+    async fn regional_trains_stand_in_for_an_unreachable_efa() {
+        let requests = Rc::new(RefCell::new(Vec::new()));
+        let profile = HafasProfile {
+            endpoint: "http://canned.test/mgate".to_string(),
+            client: json!({ "id": "TEST", "type": "WEB", "name": "webapp" }),
+            aid: "secret".to_string(),
+            version: "1.16".to_string(),
+            ext: None,
+        };
+        let retry = RetryPolicy { max_attempts: 1, ..RetryPolicy::default() };
+        let efa = EfaClient::new("http://canned.test/efa", Offline(requests.clone())).with_retry(retry);
+        let fallback = RegionalFallback::new(efa, HafasClient::new(profile, Offline(requests.clone())));
+        let hbf = StopSuggestion { id: "7000090".to_string(), name: "Hauptbahnhof".to_string(), place: Some("Karlsruhe".to_string()), ..Default::default() };
+
+        let board = fallback.departures(&hbf, 5, Some(at(15, 8, 0))).await.expect("regional trains");
+        assert_eq!(board[0].line, "RE45");
+        fallback.departures(&hbf, 5, Some(at(15, 8, 0))).await.expect("regional trains");
+
+        let requests = requests.borrow();
+        let methods: Vec<_> = requests.iter().map(|r| r.pointer("/svcReqL/0/meth").and_then(Value::as_str).unwrap().to_string()).collect();
+        assert_eq!(methods, ["LocMatch", "StationBoard", "StationBoard"], "the station is looked up once");
+        assert_eq!(requests[0].pointer("/svcReqL/0/req/input/loc/name"), Some(&json!("Karlsruhe Hauptbahnhof?")));
+        let board = &requests[1]["svcReqL"][0]["req"];
+        assert_eq!(board["stbLoc"]["lid"], json!("A=1@O=Karlsruhe Hbf@X=8401848@Y=48993512@U=80@L=8000191@B=1@p=1705247002@"));
+        assert_eq!((&board["date"], &board["time"]), (&json!("20240115"), &json!("080000")));
+        assert_eq!(board["jnyFltrL"][0]["value"], json!("24"));
+        assert_eq!(requests[1]["auth"]["aid"], json!("secret"));
+    }
+}
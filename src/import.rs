@@ -0,0 +1,173 @@
+use crate::efa::{EfaClient, EfaError, StopSuggestion};
+
+// Candidates offered per line when a name is ambiguous.
+const CANDIDATES: usize = 3;
+// One stopfinder request per line; longer lists would eat the hourly budget.
+pub const MAX_IMPORT: usize = 40;
+
+/// Lines of a pasted or imported stop list: one stop name or id per line.
+/// Blank lines and `#` comments are skipped, and so are repeated lines.
+/// Spreadsheet exports work too: only the first column is used.
+pub fn parse_stop_list(text: &str) -> Vec<String> {
+    let mut queries: Vec<String> = Vec::new();
+    for line in text.trim_start_matches('\u{feff}').lines() {
+        let query = line.split(['\t', ';']).next().unwrap_or_default().trim().trim_matches('"').trim();
+        if query.is_empty() || query.starts_with('#') {
+            continue;
+        }
+        if !queries.iter().any(|q| q.eq_ignore_ascii_case(query)) {
+            queries.push(query.to_string());
+        }
+    }
+    queries
+}
+
+/// One line of an imported list and the stops it may mean.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImportEntry {
+    /// The line as written, e.g. "Karlsruhe Hbf" or "7000090".
+    pub query: String,
+    /// Matching stops, best first; empty if nothing matched.
+    pub candidates: Vec<StopSuggestion>,
+    /// Index into `candidates` to import; `None` leaves the line out.
+    pub chosen: Option<usize>,
+}
+
+impl ImportEntry {
+    fn new(query: String, candidates: Vec<StopSuggestion>) -> Self {
+        let chosen = (!candidates.is_empty()).then_some(0);
+        ImportEntry { query, candidates, chosen }
+    }
+
+    /// Several stops match and none is called exactly like the line: the
+    /// user should check the pick.
+    pub fn needs_review(&self) -> bool {
+        self.candidates.len() > 1 && !self.candidates.iter().any(|c| c.id == self.query || c.name.eq_ignore_ascii_case(&self.query))
+    }
+}
+
+/// Resolved stop list, reviewed before the stops are imported.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ImportReview {
+    pub entries: Vec<ImportEntry>,
+    /// Lines beyond `MAX_IMPORT` that were not looked up.
+    pub ignored: usize,
+}
+
+impl ImportReview {
+    /// Import candidate `candidate` of entry `index`, or leave the line out.
+    pub fn choose(&mut self, index: usize, candidate: Option<usize>) {
+        if let Some(entry) = self.entries.get_mut(index) {
+            entry.chosen = candidate.filter(|&c| c < entry.candidates.len());
+        }
+    }
+
+    /// The stops to import, in list order and each only once.
+    pub fn stops(&self) -> Vec<StopSuggestion> {
+        let mut stops: Vec<StopSuggestion> = Vec::new();
+        for entry in &self.entries {
+            let Some(stop) = entry.chosen.and_then(|c| entry.candidates.get(c)) else { continue };
+            if !stops.iter().any(|s| s.id == stop.id) {
+                stops.push(stop.clone());
+            }
+        }
+        stops
+    }
+
+    /// Lines no stop was found for, to report back.
+    pub fn unresolved(&self) -> Vec<&str> {
+        self.entries.iter().filter(|e| e.candidates.is_empty()).map(|e| e.query.as_str()).collect()
+    }
+}
+
+/// `EfaClient::resolve_stops` against the KVV.
+pub async fn resolve_stops(queries: Vec<String>) -> Result<ImportReview, EfaError> {
+    EfaClient::kvv().resolve_stops(queries).await
+}
+
+impl EfaClient {
+    /// Look up every line of a stop list. Lines EFA finds nothing for end
+    /// up unresolved; other errors, e.g. being offline, abort the import.
+    pub async fn resolve_stops(&self, mut queries: Vec<String>) -> Result<ImportReview, EfaError> {
+        let ignored = queries.len().saturating_sub(MAX_IMPORT);
+        queries.truncate(MAX_IMPORT);
+        let mut entries = Vec::with_capacity(queries.len());
+        for query in queries {
+            let candidates = match self.stopfinder(&query, CANDIDATES).await {
+                Ok(stops) => stops,
+                Err(EfaError::ServerMessage(_)) => Vec::new(),
+                Err(e) => return Err(e),
+            };
+            entries.push(ImportEntry::new(query, candidates));
+        }
+        Ok(ImportReview { entries, ignored })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_stop_list, ImportEntry, ImportReview};
+    use crate::efa::{EfaClient, EfaError, StopSuggestion, Transport, TransportFuture};
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+    use std::time::Duration;
+
+    fn stop(id: &str, name: &str) -> StopSuggestion {
+        StopSuggestion { id: id.to_string(), name: name.to_string(), place: Some("Karlsruhe".to_string()), ..Default::default() }
+    }
+
+    #[test]
+    fn stop_lists_are_read_line_by_line() {
+        let text = "\u{feff}# Displays, building A\nKarlsruhe Hbf\n\n  7001004\t\"ZKM\"\nkarlsruhe hbf\nMarktplatz;Pyramide\r\n";
+        assert_eq!(parse_stop_list(text), ["Karlsruhe Hbf", "7001004", "Marktplatz"]);
+    }
+
+    #[test]
+    fn review_picks_the_best_match_and_reports_the_rest() {
+        let mut review = ImportReview {
+            entries: vec![
+                ImportEntry::new("Hbf".to_string(), vec![stop("7000090", "Hauptbahnhof"), stop("7000091", "Hbf Süd")]),
+                ImportEntry::new("7001004".to_string(), vec![stop("7001004", "ZKM")]),
+                ImportEntry::new("Nowhere".to_string(), Vec::new()),
+                ImportEntry::new("Hauptbahnhof".to_string(), vec![stop("7000090", "Hauptbahnhof"), stop("7000091", "Hbf Süd")]),
+            ],
+            ignored: 0,
+        };
+        let review_needed: Vec<_> = review.entries.iter().map(ImportEntry::needs_review).collect();
+        assert_eq!(review_needed, [true, false, false, false]);
+        assert_eq!(review.unresolved(), ["Nowhere"]);
+        let ids = |review: &ImportReview| review.stops().into_iter().map(|s| s.id).collect::<Vec<_>>();
+        assert_eq!(ids(&review), ["7000090", "7001004"]);
+
+        review.choose(0, Some(1));
+        review.choose(1, None);
+        assert_eq!(ids(&review), ["7000091", "7000090"]);
+    }
+
+    struct Script(RefCell<VecDeque<Result<String, EfaError>>>);
+
+    impl Transport for Script {
+        fn get<'a>(&'a self, _url: &'a str, _timeout: Duration) -> TransportFuture<'a> {
+            let response = self.0.borrow_mut().pop_front().unwrap_or(Err(EfaError::EmptyResponse));
+            Box::pin(async move { response })
+        }
+    }
+
+    #[tokio::test]
+    async fn unknown_names_stay_unresolved() {
+        let script = Script(RefCell::new(VecDeque::from([
+            Ok(include_str!("../testdata/stopfinder.json").to_string()),
+            Err(EfaError::ServerMessage("no match".to_string())),
+        ])));
+        let client = EfaClient::new("http://canned.test/import", script);
+        let queries = vec!["Karlsruhe, ZKM".to_string(), "Nowhere".to_string()];
+        let review = client.resolve_stops(queries).await.expect("import succeeds");
+        assert_eq!(review.entries[0].chosen, Some(0));
+        assert_eq!(review.entries[0].candidates[0].id, "7001004");
+        assert_eq!(review.unresolved(), ["Nowhere"]);
+
+        let offline = Script(RefCell::new(VecDeque::from([Err(EfaError::Parse("bad".to_string()))])));
+        let client = EfaClient::new("http://canned.test/import-offline", offline);
+        assert!(client.resolve_stops(vec!["Hbf".to_string()]).await.is_err());
+    }
+}
@@ -0,0 +1,254 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::efa::{decode_text, Departure, EfaClient, EfaError, EfaRequest, TransportMode};
+
+/// A line stopping at a stop, with all directions it serves there.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ServedLine {
+    /// Line as shown on boards, e.g. "S2".
+    pub line: String,
+    pub mode: TransportMode,
+    /// Final destinations, in the order EFA lists them.
+    pub directions: Vec<String>,
+    /// Operating company, e.g. "AVG", if reported.
+    pub operator: Option<String>,
+    /// Line color as "#rrggbb", for EFA deployments that send one.
+    #[serde(default)]
+    pub color: Option<String>,
+}
+
+/// Canonical termini of the lines at a stop. EFA spells the same terminus
+/// differently from one response to the next ("Karlsruhe Durlach Turmberg",
+/// "Durlach (Turmberg)"); mapping directions to these keeps filters, grouping
+/// and pins stable across refreshes.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Termini {
+    lines: BTreeMap<String, Vec<String>>,
+}
+
+impl Termini {
+    pub fn new(lines: &[ServedLine]) -> Self {
+        let mut termini = Termini::default();
+        for served in lines {
+            let directions = termini.lines.entry(served.line.clone()).or_default();
+            for direction in &served.directions {
+                if !directions.contains(direction) {
+                    directions.push(direction.clone());
+                }
+            }
+        }
+        termini
+    }
+
+    /// The terminus of `line` that `direction` names. One terminus matches if
+    /// all words of one appear in the other; the one sharing most words wins.
+    /// Unknown or ambiguous directions are returned unchanged.
+    pub fn canonical<'a>(&'a self, line: &str, direction: &'a str) -> &'a str {
+        let Some(candidates) = self.lines.get(line) else { return direction };
+        let ours = words(direction);
+        let mut best: Option<(usize, &str)> = None;
+        let mut tie = false;
+        for candidate in candidates {
+            let theirs = words(candidate);
+            if !(theirs.is_subset(&ours) || ours.is_subset(&theirs)) {
+                continue;
+            }
+            let shared = theirs.intersection(&ours).count();
+            match best {
+                Some((most, _)) if shared < most => {}
+                Some((most, _)) if shared == most => tie = true,
+                _ => {
+                    best = Some((shared, candidate));
+                    tie = false;
+                }
+            }
+        }
+        match best {
+            Some((_, terminus)) if !tie => terminus,
+            _ => direction,
+        }
+    }
+
+    /// Replace every departure's direction by its canonical terminus.
+    pub fn normalize(&self, departures: &mut [Departure]) {
+        for dep in departures {
+            if let Some(direction) = &dep.direction {
+                let canonical = self.canonical(&dep.line, direction);
+                if canonical != direction {
+                    dep.direction = Some(canonical.to_string());
+                }
+            }
+        }
+    }
+}
+
+// Lowercase words, ignoring punctuation such as brackets and slashes.
+fn words(name: &str) -> BTreeSet<String> {
+    name.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()).map(str::to_lowercase).collect()
+}
+
+/// Lines serving a KVV stop, e.g. to offer line filters.
+pub async fn lines_at(station_id: &str) -> Result<Vec<ServedLine>, EfaError> {
+    EfaClient::kvv().lines_at(station_id).await
+}
+
+impl EfaClient {
+    /// Lines serving `station_id`, one entry per line and mode.
+    pub async fn lines_at(&self, station_id: &str) -> Result<Vec<ServedLine>, EfaError> {
+        let body = self.fetch_request(&ServingLinesRequest { station_id }).await?;
+        parse_serving_lines_json(&body)
+    }
+}
+
+/// Lines stopping at a stop (XML_SERVINGLINES_REQUEST).
+pub(crate) struct ServingLinesRequest<'a> {
+    pub station_id: &'a str,
+}
+
+impl EfaRequest for ServingLinesRequest<'_> {
+    fn endpoint(&self) -> &'static str {
+        "XML_SERVINGLINES_REQUEST"
+    }
+
+    fn to_params(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("outputFormat", "JSON".to_string()),
+            ("mode", "odv".to_string()),
+            ("type_sl", "stopID".to_string()),
+            ("name_sl", self.station_id.to_string()),
+            ("lsShowTrainsExplicit", "1".to_string()),
+            ("withoutTrains", "0".to_string()),
+        ]
+    }
+}
+
+fn parse_serving_lines_json(body: &str) -> Result<Vec<ServedLine>, EfaError> {
+    let json: Value = serde_json::from_str(body).map_err(|e| EfaError::Parse(e.to_string()))?;
+    let entries = match json.get("lines") {
+        Some(Value::Array(entries)) => entries.as_slice(),
+        // A single line comes as an object, no line at all as null or "".
+        Some(entry @ Value::Object(_)) => std::slice::from_ref(entry),
+        _ => &[],
+    };
+
+    // EFA lists every direction separately; merge them per line.
+    let mut lines: Vec<ServedLine> = Vec::new();
+    for mode in entries.iter().filter_map(|e| e.get("mode")) {
+        let str_field = |key: &str| mode.get(key).and_then(|v| v.as_str()).filter(|s| !s.is_empty());
+        let Some(line) = str_field("number").or_else(|| str_field("name")) else { continue };
+        let kind = TransportMode::from_mot_type(str_field("type").unwrap_or(""));
+        let destination = str_field("destination").map(decode_text);
+        let operator = mode.get("diva").and_then(|d| d.get("operator")).and_then(|o| o.as_str()).filter(|s| !s.is_empty());
+        let color = str_field("color").map(|c| format!("#{}", c.trim_start_matches('#')));
+
+        let index = match lines.iter().position(|l| l.line == line && l.mode == kind) {
+            Some(index) => index,
+            None => {
+                lines.push(ServedLine { line: line.to_string(), mode: kind, directions: Vec::new(), operator: None, color: None });
+                lines.len() - 1
+            }
+        };
+        let served = &mut lines[index];
+        if let Some(destination) = destination.filter(|d| !served.directions.contains(d)) {
+            served.directions.push(destination);
+        }
+        if served.operator.is_none() {
+            served.operator = operator.map(str::to_string);
+        }
+        if served.color.is_none() {
+            served.color = color;
+        }
+    }
+    Ok(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_serving_lines_json, ServedLine, ServingLinesRequest, Termini};
+    use crate::efa::{Departure, EfaClient, EfaRequest, HttpTransport, TransportMode};
+    use wiremock::matchers::{method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    const SERVING_LINES_JSON: &str = include_str!("../testdata/servinglines.json");
+
+    #[test]
+    fn directions_are_merged_per_line() {
+        let lines = parse_serving_lines_json(SERVING_LINES_JSON).expect("parse succeeds");
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0].line, "S2");
+        assert_eq!(lines[0].mode, TransportMode::SBahn);
+        assert_eq!(lines[0].directions, ["Spöck", "Rheinstetten"]);
+        assert_eq!(lines[0].operator.as_deref(), Some("AVG"));
+        assert_eq!(lines[1].mode, TransportMode::Tram);
+        assert_eq!(lines[2].line, "107");
+        assert_eq!(lines[2].operator, None);
+        assert_eq!(lines[0].color.as_deref(), Some("#a066aa"));
+        assert_eq!(lines[1].color, None);
+
+        assert_eq!(parse_serving_lines_json(r#"{"lines": null}"#), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn directions_map_to_canonical_termini() {
+        let served = |line: &str, directions: &[&str]| ServedLine {
+            line: line.to_string(),
+            mode: TransportMode::Tram,
+            directions: directions.iter().map(|d| d.to_string()).collect(),
+            operator: None,
+            color: None,
+        };
+        let termini = Termini::new(&[
+            served("1", &["Durlach Turmberg", "Heide"]),
+            served("2", &["Durlach", "Durlach Turmberg"]),
+        ]);
+        assert_eq!(termini.canonical("1", "Karlsruhe Durlach Turmberg"), "Durlach Turmberg");
+        assert_eq!(termini.canonical("1", "Durlach (Turmberg)"), "Durlach Turmberg");
+        assert_eq!(termini.canonical("1", "Oberreut"), "Oberreut");
+        assert_eq!(termini.canonical("4", "Heide"), "Heide");
+        // "Durlach" fits both termini of line 2 equally well.
+        assert_eq!(termini.canonical("2", "Durlach"), "Durlach");
+        assert_eq!(termini.canonical("2", "Karlsruhe-Durlach Turmberg"), "Durlach Turmberg");
+
+        let mut deps = vec![Departure {
+            line: "1".to_string(),
+            direction: Some("Durlach (Turmberg)".to_string()),
+            ..Default::default()
+        }];
+        termini.normalize(&mut deps);
+        assert_eq!(deps[0].direction.as_deref(), Some("Durlach Turmberg"));
+    }
+
+    #[test]
+    fn serving_lines_request_parameters() {
+        let params = ServingLinesRequest { station_id: "7001004" }.to_params();
+        let params: Vec<_> = params.iter().map(|(k, v)| (*k, v.as_str())).collect();
+        assert_eq!(
+            params,
+            [
+                ("outputFormat", "JSON"),
+                ("mode", "odv"),
+                ("type_sl", "stopID"),
+                ("name_sl", "7001004"),
+                ("lsShowTrainsExplicit", "1"),
+                ("withoutTrains", "0"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn lines_at_queries_the_stop() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/XML_SERVINGLINES_REQUEST"))
+            .and(query_param("name_sl", "7001004"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(SERVING_LINES_JSON))
+            .mount(&server)
+            .await;
+
+        let lines = EfaClient::new(server.uri(), HttpTransport).lines_at("7001004").await.expect("lines_at succeeds");
+        assert_eq!(lines.len(), 3);
+    }
+}
@@ -0,0 +1,199 @@
+use std::rc::Rc;
+use std::time::Duration;
+
+use chrono::NaiveDateTime;
+use futures::stream::{self, Stream};
+
+use crate::diagnostics;
+use crate::efa::{sleep, Departure, DeparturesOptions, DmRequest, EfaClient, EfaError, EfaRequest};
+use crate::provider::TransitProvider;
+use crate::tz;
+
+/// Rows of a live board, as shown on the station display.
+pub const LIVE_BOARD_SIZE: usize = 10;
+
+/// One update of a live board.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LiveBoard {
+    pub departures: Vec<Departure>,
+    /// When `departures` were polled; `None` if no poll has succeeded yet.
+    pub fetched_at: Option<NaiveDateTime>,
+    /// Why the latest poll failed, if it did. `departures` are then the
+    /// last good board, if any.
+    pub error: Option<EfaError>,
+}
+
+impl EfaClient {
+    /// The next `max` departures at `station_id`, polled every `interval`.
+    /// The first board comes right away, later ones only when something
+    /// changed. When a poll fails, e.g. offline or with a response that
+    /// doesn't parse, the last good board comes again with the error, or
+    /// just the error if there is none yet, and polling goes on. The stream
+    /// never ends; drop it to stop polling.
+    ///
+    /// Boards are cached for 20 seconds, so shorter intervals gain nothing.
+    pub fn departures_stream(
+        &self,
+        station_id: &str,
+        max: usize,
+        interval: Duration,
+    ) -> impl Stream<Item = LiveBoard> + use<> {
+        let state = (self.clone(), station_id.to_string(), None::<LiveBoard>);
+        stream::unfold(state, move |(client, station_id, mut last)| async move {
+            let mut wait = last.is_some();
+            loop {
+                if wait {
+                    sleep(interval).await;
+                }
+                wait = true;
+                let polled = noted("EFA", &station_id, client.poll_board(&station_id, max).await);
+                let (board, changed) = next_board(last.as_ref(), polled, tz::now());
+                last = Some(board.clone());
+                if changed {
+                    return Some((board, (client, station_id, last)));
+                }
+            }
+        })
+    }
+
+    // One poll of a live board. Unparsable responses are kept for debugging
+    // and dropped from the cache, so the next poll asks again.
+    async fn poll_board(&self, station_id: &str, max: usize) -> Result<Vec<Departure>, EfaError> {
+        let request = DmRequest { station_id, max, when: None, arrivals: false, stop_sequences: false, options: DeparturesOptions::default(), format: self.board_format() };
+        let body = self.fetch_board(&request, false).await?;
+        let board = self.parse_board(&body, tz::now());
+        if let Err(EfaError::Parse(_)) = board {
+            diagnostics::record_payload(&self.url(request.endpoint()), &body);
+            self.uncache(&request);
+        }
+        board
+    }
+}
+
+/// Like `EfaClient::departures_stream`, polling the live board of
+/// `station_id` from any `provider`, e.g. the simulation. Nothing is
+/// cached in between.
+pub fn provider_stream(provider: Rc<dyn TransitProvider>, station_id: &str, interval: Duration) -> impl Stream<Item = LiveBoard> + use<> {
+    let state = (provider, station_id.to_string(), None::<LiveBoard>);
+    stream::unfold(state, move |(provider, station_id, mut last)| async move {
+        let mut wait = last.is_some();
+        loop {
+            if wait {
+                sleep(interval).await;
+            }
+            wait = true;
+            let polled = noted(provider.name(), &station_id, provider.departures(&station_id, LIVE_BOARD_SIZE, None).await);
+            let (board, changed) = next_board(last.as_ref(), polled, tz::now());
+            last = Some(board.clone());
+            if changed {
+                return Some((board, (provider, station_id, last)));
+            }
+        }
+    })
+}
+
+// Failed polls go to the diagnostics, whatever the board makes of them.
+fn noted(source: &str, station_id: &str, polled: Result<Vec<Departure>, EfaError>) -> Result<Vec<Departure>, EfaError> {
+    if let Err(e) = &polled {
+        diagnostics::record_warning(format!("{source} board of {station_id}: {e}"));
+    }
+    polled
+}
+
+/// The board after a poll at `now`, given the one before, and whether it
+/// changed from what is shown: new departures, the first failure after
+/// good polls, or a recovery. Successful polls always move `fetched_at`.
+fn next_board(last: Option<&LiveBoard>, polled: Result<Vec<Departure>, EfaError>, now: NaiveDateTime) -> (LiveBoard, bool) {
+    match polled {
+        Ok(departures) => {
+            let changed = last.is_none_or(|last| last.error.is_some() || last.departures != departures);
+            (LiveBoard { departures, fetched_at: Some(now), error: None }, changed)
+        }
+        Err(e) => {
+            let changed = last.is_none_or(|last| last.error.is_none());
+            (LiveBoard { error: Some(e), ..last.cloned().unwrap_or_default() }, changed)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{next_board, provider_stream, LiveBoard};
+    use crate::efa::{Departure, EfaClient, EfaError, Transport, TransportFuture};
+    use crate::fixtures::at;
+    use crate::simulation::Simulation;
+    use crate::tz;
+    use futures::StreamExt;
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    struct Script(RefCell<VecDeque<Result<String, EfaError>>>);
+
+    impl Transport for Script {
+        fn get<'a>(&'a self, _url: &'a str, _timeout: Duration) -> TransportFuture<'a> {
+            let response = self.0.borrow_mut().pop_front().unwrap_or(Err(EfaError::EmptyResponse));
+            Box::pin(async move { response })
+        }
+    }
+
+    #[tokio::test]
+    async fn a_failed_first_poll_shows_the_error() {
+        let script = Script(RefCell::new(VecDeque::from([
+            Err(EfaError::EmptyResponse),
+            Ok(include_str!("../testdata/departures.xml").to_string()),
+        ])));
+        let client = EfaClient::new("http://canned.test/live", script);
+        let stream = client.departures_stream("7001004", 3, Duration::from_millis(1));
+        let mut stream = std::pin::pin!(stream);
+        let failed = stream.next().await.expect("streams never end");
+        assert_eq!(failed, LiveBoard { error: Some(EfaError::EmptyResponse), ..Default::default() });
+
+        let board = stream.next().await.expect("streams never end");
+        assert_eq!(board.error, None);
+        assert!(board.fetched_at.is_some());
+        let lines: Vec<_> = board.departures.iter().map(|d| d.line.as_str()).collect();
+        assert_eq!(lines, ["S2", "2", "S5"]);
+    }
+
+    #[tokio::test]
+    async fn providers_stream_boards_too() {
+        let stream = provider_stream(Rc::new(Simulation::demo(tz::now())), "7001004", Duration::from_millis(1));
+        let mut stream = std::pin::pin!(stream);
+        let board = stream.next().await.expect("streams never end");
+        assert_eq!(board.error, None);
+        assert!(!board.departures.is_empty());
+    }
+
+    #[test]
+    fn failed_polls_keep_the_last_good_board() {
+        let board = |line: &str| vec![Departure { line: line.to_string(), ..Default::default() }];
+        let parse_error = || Err(EfaError::Parse("unexpected end of file".to_string()));
+        let (first, later) = (at("08:00"), at("08:01"));
+        let (fresh, changed) = next_board(None, Ok(board("S2")), first);
+        assert!(changed);
+        let (fresh, changed) = next_board(Some(&fresh), Ok(board("S2")), later);
+        assert!(!changed);
+        assert_eq!(fresh.fetched_at, Some(later), "unchanged polls are still recent");
+
+        let (stale, changed) = next_board(Some(&fresh), parse_error(), at("08:02"));
+        assert!(changed);
+        assert_eq!(stale, LiveBoard { departures: board("S2"), fetched_at: Some(later), error: parse_error().err() });
+        assert!(!next_board(Some(&stale), parse_error(), at("08:03")).1);
+
+        let (recovered, changed) = next_board(Some(&stale), Ok(board("S2")), at("08:04"));
+        assert!(changed);
+        assert_eq!(recovered.error, None);
+    }
+
+    #[test]
+    fn failing_from_the_start_shows_the_error_once() {
+        let offline = || Err(EfaError::Network("offline".to_string()));
+        let (failed, changed) = next_board(None, offline(), at("08:00"));
+        assert!(changed);
+        assert_eq!(failed, LiveBoard { error: offline().err(), ..Default::default() });
+        assert!(!next_board(Some(&failed), offline(), at("08:01")).1);
+    }
+
+}
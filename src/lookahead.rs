@@ -0,0 +1,143 @@
+use chrono::{Datelike, NaiveDate, NaiveDateTime, TimeDelta};
+
+use crate::efa::{Departure, EfaClient, EfaError};
+use crate::messages::Locale;
+use crate::tz;
+
+// Night services until this hour still run on the previous day's timetable.
+const DAY_STARTS_AT_HOUR: u32 = 4;
+// Enough to get from Saturday evening across a Sunday without service.
+const LOOKAHEAD_DAYS: usize = 3;
+
+/// Departures of one service day.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DayBoard {
+    pub day: NaiveDate,
+    pub departures: Vec<Departure>,
+}
+
+impl DayBoard {
+    /// Heading relative to the service day of `now`, e.g. "Tomorrow" or "Sat 20.01.".
+    pub fn label(&self, now: NaiveDateTime, locale: Locale) -> String {
+        match ((self.day - service_day(now)).num_days(), locale) {
+            (0, Locale::En) => "Today".to_string(),
+            (0, Locale::De) => "Heute".to_string(),
+            (1, Locale::En) => "Tomorrow".to_string(),
+            (1, Locale::De) => "Morgen".to_string(),
+            (_, Locale::En) => self.day.format("%a %d.%m.").to_string(),
+            (_, Locale::De) => {
+                const WEEKDAYS: [&str; 7] = ["Mo", "Di", "Mi", "Do", "Fr", "Sa", "So"];
+                let weekday = WEEKDAYS[self.day.weekday().num_days_from_monday() as usize];
+                format!("{weekday} {}", self.day.format("%d.%m."))
+            }
+        }
+    }
+}
+
+/// The timetable day `t` belongs to: 01:30 on a Tuesday still counts as Monday.
+pub fn service_day(t: NaiveDateTime) -> NaiveDate {
+    (t - TimeDelta::hours(DAY_STARTS_AT_HOUR.into())).date()
+}
+
+fn day_start(day: NaiveDate) -> NaiveDateTime {
+    day.and_hms_opt(DAY_STARTS_AT_HOUR, 0, 0).expect("valid hour")
+}
+
+/// Split a board by service day, keeping its order.
+pub fn by_service_day(departures: Vec<Departure>) -> Vec<DayBoard> {
+    let mut days: Vec<DayBoard> = Vec::new();
+    for departure in departures {
+        let day = service_day(departure.time);
+        match days.last_mut() {
+            Some(board) if board.day == day => board.departures.push(departure),
+            _ => days.push(DayBoard { day, departures: vec![departure] }),
+        }
+    }
+    days
+}
+
+/// `EfaClient::departures_ahead` against the KVV, from now on.
+pub async fn departures_ahead(station_id: &str, max: usize) -> Result<Vec<DayBoard>, EfaError> {
+    EfaClient::kvv().departures_ahead(station_id, max, tz::now()).await
+}
+
+impl EfaClient {
+    /// Next departures at `station_id` from `now`, grouped by service day.
+    /// Stops with a few buses a day often have nothing left tonight; then the
+    /// next service days are tried, up to three, instead of showing an empty
+    /// board.
+    pub async fn departures_ahead(&self, station_id: &str, max: usize, now: NaiveDateTime) -> Result<Vec<DayBoard>, EfaError> {
+        let mut departures = self.departures(station_id, max, Some(now)).await?;
+        let mut day = service_day(now);
+        for _ in 0..LOOKAHEAD_DAYS {
+            if !departures.is_empty() {
+                break;
+            }
+            day += TimeDelta::days(1);
+            departures = self.departures(station_id, max, Some(day_start(day))).await?;
+        }
+        Ok(by_service_day(departures))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{by_service_day, service_day};
+    use crate::efa::{EfaClient, HttpTransport};
+    use crate::fixtures::{delayed, on};
+    use crate::messages::Locale;
+    use chrono::NaiveDate;
+    use wiremock::matchers::{method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[test]
+    fn boards_are_split_by_service_day() {
+        assert_eq!(service_day(on(16, 1, 30)), NaiveDate::from_ymd_opt(2024, 1, 15).unwrap());
+        assert_eq!(service_day(on(16, 4, 0)), NaiveDate::from_ymd_opt(2024, 1, 16).unwrap());
+
+        let times = [on(15, 23, 10), on(16, 0, 40), on(16, 6, 5), on(20, 7, 0)];
+        let days = by_service_day(times.into_iter().map(|time| delayed("X", time, None)).collect());
+        let sizes: Vec<_> = days.iter().map(|d| d.departures.len()).collect();
+        assert_eq!(sizes, [2, 1, 1]);
+
+        let now = on(15, 22, 0);
+        let labels: Vec<_> = days.iter().map(|d| d.label(now, Locale::En)).collect();
+        assert_eq!(labels, ["Today", "Tomorrow", "Sat 20.01."]);
+        assert_eq!(days[2].label(now, Locale::De), "Sa 20.01.");
+    }
+
+    #[tokio::test]
+    async fn empty_evening_board_looks_ahead_to_the_next_day() {
+        let server = MockServer::start().await;
+        let empty = "<itdRequest><itdDepartureMonitorRequest><itdDepartureList /></itdDepartureMonitorRequest></itdRequest>";
+        let tomorrow = r#"
+            <itdDepartureList>
+              <itdDeparture stopID="7019520">
+                <itdDateTime><itdDate year="2024" month="1" day="16" /><itdTime hour="6" minute="12" /></itdDateTime>
+                <itdServingLine symbol="134" direction="Bretten" />
+              </itdDeparture>
+            </itdDepartureList>
+        "#;
+        Mock::given(method("GET"))
+            .and(path("/XSLT_DM_REQUEST"))
+            .and(query_param("itdDate", "20240115"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(empty))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/XSLT_DM_REQUEST"))
+            .and(query_param("itdDate", "20240116"))
+            .and(query_param("itdTime", "0400"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(tomorrow))
+            .mount(&server)
+            .await;
+
+        let days = EfaClient::new(server.uri(), HttpTransport)
+            .departures_ahead("7019520", 5, on(15, 21, 30))
+            .await
+            .expect("departures_ahead succeeds");
+        assert_eq!(days.len(), 1);
+        assert_eq!(days[0].day, NaiveDate::from_ymd_opt(2024, 1, 16).unwrap());
+        assert_eq!(days[0].departures[0].line, "134");
+    }
+}
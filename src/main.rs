@@ -1,14 +1,87 @@
+mod announce;
 mod app;
+#[cfg(all(feature = "archive", not(target_arch = "wasm32")))]
+mod archive;
+mod area;
+mod badge;
+mod board;
+mod chime;
+mod columns;
+mod comparison;
+mod crash;
+mod dashboards;
+mod diagnostics;
+mod diff;
+mod disruptions;
 mod efa;
+mod export;
+#[cfg(test)]
+mod fixtures;
+mod favorites;
+mod format;
+mod geo;
+mod geocode;
+mod gtfs;
+mod gtfs_rt;
+mod hafas;
+mod import;
+mod lines;
+mod live;
+mod lookahead;
+mod messages;
+mod migrations;
+mod onboarding;
+mod operators;
+mod permissions;
+mod phrases;
+mod pinning;
+mod planner;
+mod platforms;
+mod popularity;
+mod priority;
+mod privacy;
+mod provider;
+mod punctuality;
+#[cfg(feature = "selftest")]
+mod selftest;
+mod settings;
+mod simulation;
+mod storage;
+mod stops;
+mod store;
+mod summary;
+mod tauri;
+mod template;
+mod trias;
+mod trip;
+mod tz;
+mod undo;
+mod validation;
 
 use app::*;
+use crash::CrashScreen;
 use leptos::prelude::*;
 
 fn main() {
-    console_error_panic_hook::set_once();
+    #[cfg(all(feature = "archive", not(target_arch = "wasm32")))]
+    if let Some(config) = archive::Config::from_args(std::env::args().skip(1)) {
+        match config {
+            Ok(config) => archive::run(config),
+            Err(e) => {
+                eprintln!("{e}");
+                std::process::exit(2);
+            }
+        }
+        return;
+    }
+    #[cfg(target_arch = "wasm32")]
+    tracing_wasm::set_as_global_default();
+    crash::install_panic_hook();
     mount_to_body(|| {
         view! {
-            <App/>
+            <ErrorBoundary fallback=|errors| view! { <CrashScreen errors=errors/> }>
+                <App/>
+            </ErrorBoundary>
         }
     })
 }
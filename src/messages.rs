@@ -0,0 +1,145 @@
+use serde::{Deserialize, Serialize};
+
+use crate::efa::EfaError;
+use crate::geo::GeoError;
+
+/// Language used for user-facing texts.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Locale {
+    #[default]
+    En,
+    De,
+}
+
+impl Locale {
+    /// ISO 639-1 code, e.g. for EFA's `language` parameter.
+    pub fn code(self) -> &'static str {
+        match self {
+            Locale::En => "en",
+            Locale::De => "de",
+        }
+    }
+}
+
+/// Turn an `EfaError` into a short, actionable message for the user.
+///
+/// `stale_since` is the time ("14:02") of the data still on screen, if any,
+/// so the message can tell the user what they are looking at.
+pub fn error_message(err: &EfaError, locale: Locale, stale_since: Option<&str>) -> String {
+    let reason = match (err, locale) {
+        (EfaError::Network(_), Locale::En) => "You appear to be offline".to_string(),
+        (EfaError::Network(_), Locale::De) => "Du scheinst offline zu sein".to_string(),
+        (EfaError::Http { status }, Locale::En) if *status >= 500 => {
+            "KVV's server is having trouble".to_string()
+        }
+        (EfaError::Http { status }, Locale::De) if *status >= 500 => {
+            "Der KVV-Server hat gerade Probleme".to_string()
+        }
+        (EfaError::Http { status }, Locale::En) => {
+            format!("KVV's server rejected the request (HTTP {status})")
+        }
+        (EfaError::Http { status }, Locale::De) => {
+            format!("Der KVV-Server hat die Anfrage abgelehnt (HTTP {status})")
+        }
+        (EfaError::Parse(_), Locale::En) => "KVV sent an answer we could not read".to_string(),
+        (EfaError::Parse(_), Locale::De) => {
+            "Die Antwort des KVV konnte nicht gelesen werden".to_string()
+        }
+        (EfaError::EmptyResponse, Locale::En) => "KVV sent an empty answer".to_string(),
+        (EfaError::EmptyResponse, Locale::De) => "Der KVV hat eine leere Antwort geschickt".to_string(),
+        (EfaError::ServerMessage(msg), Locale::En) => format!("KVV says: {msg}"),
+        (EfaError::ServerMessage(msg), Locale::De) => format!("Der KVV meldet: {msg}"),
+        (EfaError::RateLimited { .. }, Locale::En) => "KVV asked the app to slow down".to_string(),
+        (EfaError::RateLimited { .. }, Locale::De) => "Der KVV hat die App gebeten, kurz zu pausieren".to_string(),
+        (EfaError::Cancelled, Locale::En) => "The request was replaced by a newer one".to_string(),
+        (EfaError::Cancelled, Locale::De) => "Die Anfrage wurde durch eine neuere ersetzt".to_string(),
+    };
+
+    match (stale_since, locale) {
+        (Some(time), Locale::En) => format!("{reason}, showing data from {time}."),
+        (Some(time), Locale::De) => format!("{reason}, angezeigt wird der Stand von {time}."),
+        (None, _) => format!("{reason}. {}", hint(err, locale)),
+    }
+}
+
+// What the user can do about it when there is nothing older to show.
+fn hint(err: &EfaError, locale: Locale) -> &'static str {
+    match (err, locale) {
+        (EfaError::Network(_), Locale::En) => "Check your connection and try again.",
+        (EfaError::Network(_), Locale::De) => "Prüfe deine Verbindung und versuche es erneut.",
+        (EfaError::Http { .. }, Locale::En) => "Please try again in a moment.",
+        (EfaError::Http { .. }, Locale::De) => "Bitte versuche es gleich noch einmal.",
+        (EfaError::Parse(_), Locale::En) => "Please report this if it keeps happening.",
+        (EfaError::Parse(_), Locale::De) => "Bitte melde das, falls es öfter passiert.",
+        (EfaError::EmptyResponse, Locale::En) => "Please try again in a moment.",
+        (EfaError::EmptyResponse, Locale::De) => "Bitte versuche es gleich noch einmal.",
+        (EfaError::ServerMessage(_), Locale::En) => "Check your input and try again.",
+        (EfaError::ServerMessage(_), Locale::De) => "Prüfe deine Eingabe und versuche es erneut.",
+        (EfaError::RateLimited { .. }, Locale::En) => "Nothing to do, it will continue by itself shortly.",
+        (EfaError::RateLimited { .. }, Locale::De) => "Du musst nichts tun, gleich geht es von selbst weiter.",
+        (EfaError::Cancelled, Locale::En) => "Nothing to do, the newer request is on its way.",
+        (EfaError::Cancelled, Locale::De) => "Du musst nichts tun, die neuere Anfrage läuft schon.",
+    }
+}
+
+/// Turn a `GeoError` into a message for the user, like `error_message`.
+pub fn geo_error_message(err: &GeoError, locale: Locale) -> String {
+    match (err, locale) {
+        (GeoError::Denied, Locale::En) => {
+            "Location access is turned off. Allow it in the system settings to see nearby stops.".to_string()
+        }
+        (GeoError::Denied, Locale::De) => {
+            "Der Standortzugriff ist aus. Erlaube ihn in den Systemeinstellungen, um Haltestellen in der Nähe zu sehen."
+                .to_string()
+        }
+        (GeoError::Plugin(msg), Locale::En) => format!("Could not determine your position: {msg}"),
+        (GeoError::Plugin(msg), Locale::De) => format!("Dein Standort konnte nicht bestimmt werden: {msg}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{error_message, geo_error_message, Locale};
+    use crate::efa::EfaError;
+    use crate::geo::GeoError;
+    use std::time::Duration;
+
+    #[test]
+    fn network_errors_read_as_offline() {
+        let err = EfaError::Network("dns error".to_string());
+        assert_eq!(
+            error_message(&err, Locale::En, None),
+            "You appear to be offline. Check your connection and try again."
+        );
+    }
+
+    #[test]
+    fn rate_limits_read_calmly() {
+        let err = EfaError::RateLimited { retry_after: Duration::from_secs(30) };
+        assert_eq!(
+            error_message(&err, Locale::En, None),
+            "KVV asked the app to slow down. Nothing to do, it will continue by itself shortly."
+        );
+    }
+
+    #[test]
+    fn server_errors_mention_stale_data() {
+        let err = EfaError::Http { status: 503 };
+        assert_eq!(
+            error_message(&err, Locale::En, Some("14:02")),
+            "KVV's server is having trouble, showing data from 14:02."
+        );
+        assert_eq!(
+            error_message(&err, Locale::De, Some("14:02")),
+            "Der KVV-Server hat gerade Probleme, angezeigt wird der Stand von 14:02."
+        );
+    }
+
+    #[test]
+    fn location_errors_follow_the_language() {
+        assert_eq!(
+            geo_error_message(&GeoError::Plugin("timeout".to_string()), Locale::De),
+            "Dein Standort konnte nicht bestimmt werden: timeout"
+        );
+    }
+}
@@ -0,0 +1,184 @@
+use std::fmt;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+/// A piece of app state that is persisted on its own storage key.
+///
+/// To change the stored layout, bump `VERSION`, turn the previous layout
+/// into the new one in `migrate` and add a fixture of the previous layout
+/// to `testdata/migrations`.
+pub trait Slice: Clone + Default + Serialize + DeserializeOwned + Send + Sync + 'static {
+    const KEY: &'static str;
+    /// Bump when the stored layout changes and handle the old one in `migrate`.
+    const VERSION: u32;
+
+    /// Upgrade a value stored by `from_version` to `from_version + 1`.
+    /// `None` drops the stored value and starts from the default.
+    fn migrate(from_version: u32, value: Value) -> Option<Value> {
+        let _ = from_version;
+        Some(value)
+    }
+}
+
+/// Why a stored slice could not be read.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LoadError {
+    /// Not JSON, e.g. a write cut short.
+    Corrupt(String),
+    /// Written by a newer version of the app, e.g. before a rollback.
+    Newer(u32),
+    /// `Slice::migrate` gave up on the value stored by this version.
+    Migration(u32),
+    /// JSON that doesn't fit the slice after all migrations.
+    Schema(String),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Corrupt(msg) => write!(f, "corrupt: {msg}"),
+            LoadError::Newer(version) => write!(f, "stored by a newer version ({version})"),
+            LoadError::Migration(version) => write!(f, "no migration from version {version}"),
+            LoadError::Schema(msg) => write!(f, "unexpected layout: {msg}"),
+        }
+    }
+}
+
+/// Stored form of a slice: `{"version": N, "data": ...}`. Values written
+/// before slices were versioned are bare JSON and count as version 0.
+pub fn decode<T: Slice>(raw: &str) -> Result<T, LoadError> {
+    let stored: Value = serde_json::from_str(raw).map_err(|e| LoadError::Corrupt(e.to_string()))?;
+    let (mut version, mut data) = match stored {
+        Value::Object(mut obj) if obj.contains_key("version") && obj.contains_key("data") => {
+            let version = obj.get("version").and_then(Value::as_u64).ok_or_else(|| LoadError::Corrupt("version is not a number".to_string()))?;
+            (u32::try_from(version).unwrap_or(u32::MAX), obj.remove("data").unwrap_or_default())
+        }
+        bare => (0, bare),
+    };
+    // Data from a newer app version can't be understood; don't guess.
+    if version > T::VERSION {
+        return Err(LoadError::Newer(version));
+    }
+    while version < T::VERSION {
+        data = T::migrate(version, data).ok_or(LoadError::Migration(version))?;
+        version += 1;
+    }
+    serde_json::from_value(data).map_err(|e| LoadError::Schema(e.to_string()))
+}
+
+pub fn encode<T: Slice>(value: &T) -> Option<String> {
+    serde_json::to_string(&json!({ "version": T::VERSION, "data": value })).ok()
+}
+
+/// Key an unreadable value of `key` is moved to, so starting fresh doesn't
+/// overwrite what the user had.
+pub fn quarantine_key(key: &str) -> String {
+    format!("{key}.quarantine")
+}
+
+/// The slice stored as `raw`, or the default if there is none. A value that
+/// can't be read goes to `quarantine` before starting from the default.
+pub fn load<T: Slice>(raw: Option<String>, quarantine: impl FnOnce(String, LoadError)) -> T {
+    let Some(raw) = raw else { return T::default() };
+    decode(&raw).unwrap_or_else(|error| {
+        quarantine(raw, error);
+        T::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, encode, load, LoadError, Slice};
+    use crate::chime::Chimes;
+    use crate::columns::ColumnLayouts;
+    use crate::dashboards::Dashboards;
+    use crate::favorites::Favorites;
+    use crate::geo::Geofences;
+    use crate::onboarding::Onboarding;
+    use crate::operators::OperatorFilter;
+    use crate::pinning::PinStore;
+    use crate::platforms::PlatformLayout;
+    use crate::popularity::Popularity;
+    use crate::punctuality::PunctualityLog;
+    use crate::settings::Settings;
+    use serde::{Deserialize, Serialize};
+    use serde_json::Value;
+    use std::fmt::Debug;
+
+    // v1 stored `name`, v2 renamed it to `title`.
+    #[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+    struct Example {
+        title: String,
+    }
+
+    impl Slice for Example {
+        const KEY: &'static str = "test.example";
+        const VERSION: u32 = 2;
+
+        fn migrate(from_version: u32, mut value: Value) -> Option<Value> {
+            if from_version == 1 {
+                let name = value.as_object_mut()?.remove("name")?;
+                value["title"] = name;
+            }
+            Some(value)
+        }
+    }
+
+    #[test]
+    fn round_trips_current_version() {
+        let value = Example { title: "ZKM".to_string() };
+        assert_eq!(decode::<Example>(&encode(&value).unwrap()), Ok(value));
+    }
+
+    #[test]
+    fn migrates_older_and_unversioned_data() {
+        let v1 = r#"{"version":1,"data":{"name":"Marktplatz"}}"#;
+        assert_eq!(decode::<Example>(v1).unwrap().title, "Marktplatz");
+        // Bare values from before versioning start at version 0.
+        assert_eq!(decode::<Example>(r#"{"name":"Hbf"}"#).unwrap().title, "Hbf");
+    }
+
+    #[test]
+    fn rejects_corrupt_and_future_data() {
+        assert!(matches!(decode::<Example>("{not json"), Err(LoadError::Corrupt(_))));
+        assert_eq!(decode::<Example>(r#"{"version":3,"data":{"title":"x"}}"#), Err(LoadError::Newer(3)));
+        assert_eq!(decode::<Example>(r#"{"version":1,"data":{"title":"x"}}"#), Err(LoadError::Migration(1)));
+        assert!(matches!(decode::<Example>(r#"{"version":2,"data":{"title":7}}"#), Err(LoadError::Schema(_))));
+    }
+
+    #[test]
+    fn unreadable_values_are_quarantined() {
+        let mut quarantined = Vec::new();
+        let fresh: Example = load(Some("{\"version\":2,\"da".to_string()), |raw, error| quarantined.push((raw, error)));
+        assert_eq!(fresh, Example::default());
+        assert_eq!(quarantined[0].0, "{\"version\":2,\"da");
+
+        let stored = encode(&Example { title: "ZKM".to_string() });
+        assert_eq!(load::<Example>(stored, |_, _| panic!("readable")).title, "ZKM");
+        assert_eq!(load::<Example>(None, |_, _| panic!("nothing stored")), Example::default());
+    }
+
+    fn loads<T: Slice + PartialEq + Debug>(fixture: &str) {
+        let value = decode::<T>(fixture).unwrap_or_else(|e| panic!("{}: {e}", T::KEY));
+        assert_ne!(value, T::default(), "{} lost its data", T::KEY);
+    }
+
+    #[test]
+    fn every_stored_format_still_loads() {
+        loads::<Settings>(include_str!("../testdata/migrations/kvv.settings.v0.json"));
+        loads::<Settings>(include_str!("../testdata/migrations/kvv.settings.v1.json"));
+        loads::<Onboarding>(include_str!("../testdata/migrations/kvv.onboarding.v0.json"));
+        loads::<Favorites>(include_str!("../testdata/migrations/kvv.favorites.v1.json"));
+        loads::<PinStore>(include_str!("../testdata/migrations/kvv.pins.v1.json"));
+        loads::<PlatformLayout>(include_str!("../testdata/migrations/kvv.platform_layout.v1.json"));
+        loads::<Popularity>(include_str!("../testdata/migrations/kvv.popularity.v1.json"));
+        loads::<PunctualityLog>(include_str!("../testdata/migrations/kvv.punctuality.v1.json"));
+        loads::<ColumnLayouts>(include_str!("../testdata/migrations/kvv.columns.v1.json"));
+        loads::<OperatorFilter>(include_str!("../testdata/migrations/kvv.operator_filter.v1.json"));
+        loads::<Chimes>(include_str!("../testdata/migrations/kvv.chimes.v1.json"));
+        loads::<Dashboards>(include_str!("../testdata/migrations/kvv.dashboards.v1.json"));
+        loads::<Geofences>(include_str!("../testdata/migrations/kvv.geofences.v1.json"));
+    }
+}
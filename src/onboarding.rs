@@ -0,0 +1,186 @@
+use serde::{Deserialize, Serialize};
+
+use crate::efa::{stopfinder, stops_near, EfaError, NearbyStop, StopSuggestion};
+use crate::store::Slice;
+
+/// How many of the closest stops are suggested on first launch.
+const NEAREST: usize = 3;
+
+/// Steps of the first-launch wizard, in order.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WizardStep {
+    #[default]
+    Welcome,
+    /// Explains why we ask for the location before the system prompt shows up.
+    Location,
+    Notifications,
+    HomeStop,
+    Done,
+}
+
+/// Something the user did on the current wizard step.
+#[derive(Clone, Debug, PartialEq)]
+pub enum WizardEvent {
+    /// Continue, or skip the current step without answering.
+    Next,
+    Back,
+    /// Outcome of the system permission prompt of the current step.
+    Answered(bool),
+    HomeStopChosen(StopSuggestion),
+}
+
+/// State of the first-launch wizard. Persisted after every step so an app
+/// restart resumes where the user left off.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Onboarding {
+    pub step: WizardStep,
+    /// `None` while the user has not answered (or skipped) the prompt.
+    pub location: Option<bool>,
+    pub notifications: Option<bool>,
+    pub home_stop: Option<StopSuggestion>,
+}
+
+impl Slice for Onboarding {
+    const KEY: &'static str = "kvv.onboarding";
+    const VERSION: u32 = 1;
+}
+
+impl Onboarding {
+    pub fn is_done(&self) -> bool {
+        self.step == WizardStep::Done
+    }
+
+    /// Apply `event` to the current step. Events that make no sense on the
+    /// current step (e.g. a late permission answer) are ignored.
+    pub fn advance(&mut self, event: WizardEvent) {
+        use WizardStep::*;
+        self.step = match (self.step, event) {
+            (Welcome, WizardEvent::Next) => Location,
+            (Location, WizardEvent::Answered(granted)) => {
+                self.location = Some(granted);
+                Notifications
+            }
+            (Location, WizardEvent::Next) => Notifications,
+            (Notifications, WizardEvent::Answered(granted)) => {
+                self.notifications = Some(granted);
+                HomeStop
+            }
+            (Notifications, WizardEvent::Next) => HomeStop,
+            (HomeStop, WizardEvent::HomeStopChosen(stop)) => {
+                self.home_stop = Some(stop);
+                Done
+            }
+            (HomeStop, WizardEvent::Next) => Done,
+            (Location, WizardEvent::Back) => Welcome,
+            (Notifications, WizardEvent::Back) => Location,
+            (HomeStop, WizardEvent::Back) => Notifications,
+            (step, _) => step,
+        };
+    }
+}
+
+/// Suggested favorites for a first launch at the given position: the nearest
+/// stops plus the main station of the town the user is in.
+///
+/// Nothing is stored here; the caller keeps the list until the user confirms.
+pub async fn suggest_favorites(lat: f64, lon: f64) -> Result<Vec<StopSuggestion>, EfaError> {
+    let nearby = stops_near(lat, lon, 1000, NEAREST).await?;
+    let main_station = match main_station_query(&nearby) {
+        // The main station is a nice-to-have; keep the nearby stops if it fails.
+        Some(query) => stopfinder(&query, 1).await.ok().and_then(|found| found.into_iter().next()),
+        None => None,
+    };
+    Ok(merge_suggestions(nearby, main_station))
+}
+
+/// Stopfinder query for the main station of the nearest stop's town, e.g. "Karlsruhe Hbf".
+fn main_station_query(nearby: &[NearbyStop]) -> Option<String> {
+    let place = nearby.iter().find_map(|n| n.stop.place.as_deref())?;
+    Some(format!("{place} Hbf"))
+}
+
+fn merge_suggestions(nearby: Vec<NearbyStop>, main_station: Option<StopSuggestion>) -> Vec<StopSuggestion> {
+    let mut suggestions: Vec<StopSuggestion> = nearby.into_iter().take(NEAREST).map(|n| n.stop).collect();
+    if let Some(main) = main_station
+        && !suggestions.iter().any(|s| s.id == main.id)
+    {
+        suggestions.push(main);
+    }
+    suggestions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{main_station_query, merge_suggestions, Onboarding, WizardEvent, WizardStep};
+    use crate::efa::{NearbyStop, StopSuggestion};
+
+    fn stop(id: &str, name: &str) -> StopSuggestion {
+        StopSuggestion { id: id.to_string(), name: name.to_string(), place: Some("Karlsruhe".to_string()), ..Default::default() }
+    }
+
+    fn near(id: &str, name: &str, distance_m: u32) -> NearbyStop {
+        NearbyStop { stop: stop(id, name), distance_m, coord: None }
+    }
+
+    #[test]
+    fn suggests_nearest_three_plus_main_station() {
+        let nearby = vec![
+            near("7001004", "ZKM", 80),
+            near("7001012", "Kolpingplatz", 260),
+            near("7001011", "Europaplatz", 410),
+            near("7001013", "Mathystraße", 600),
+        ];
+        assert_eq!(main_station_query(&nearby).as_deref(), Some("Karlsruhe Hbf"));
+
+        let ids: Vec<_> = merge_suggestions(nearby, Some(stop("7000090", "Hauptbahnhof")))
+            .into_iter()
+            .map(|s| s.id)
+            .collect();
+        assert_eq!(ids, ["7001004", "7001012", "7001011", "7000090"]);
+    }
+
+    #[test]
+    fn main_station_nearby_is_not_suggested_twice() {
+        let nearby = vec![near("7000090", "Hauptbahnhof", 50)];
+        assert_eq!(merge_suggestions(nearby, Some(stop("7000090", "Hauptbahnhof"))).len(), 1);
+    }
+
+    #[test]
+    fn wizard_walks_through_all_steps() {
+        let mut wizard = Onboarding::default();
+        assert_eq!(wizard.step, WizardStep::Welcome);
+        wizard.advance(WizardEvent::Next);
+        wizard.advance(WizardEvent::Answered(true));
+        assert_eq!(wizard.step, WizardStep::Notifications);
+        wizard.advance(WizardEvent::Next);
+        wizard.advance(WizardEvent::HomeStopChosen(stop("7001004", "ZKM")));
+
+        assert!(wizard.is_done());
+        assert_eq!(wizard.location, Some(true));
+        assert_eq!(wizard.notifications, None);
+        assert_eq!(wizard.home_stop.map(|s| s.id).as_deref(), Some("7001004"));
+    }
+
+    #[test]
+    fn wizard_goes_back_and_ignores_stray_events() {
+        let mut wizard = Onboarding::default();
+        wizard.advance(WizardEvent::Back);
+        assert_eq!(wizard.step, WizardStep::Welcome);
+        wizard.advance(WizardEvent::Answered(false));
+        assert_eq!(wizard, Onboarding::default());
+
+        wizard.advance(WizardEvent::Next);
+        wizard.advance(WizardEvent::Next);
+        wizard.advance(WizardEvent::Back);
+        assert_eq!(wizard.step, WizardStep::Location);
+    }
+
+    #[test]
+    fn wizard_state_survives_a_restart() {
+        let mut wizard = Onboarding::default();
+        wizard.advance(WizardEvent::Next);
+        wizard.advance(WizardEvent::Answered(false));
+        let restored: Onboarding = serde_json::from_str(&serde_json::to_string(&wizard).unwrap()).unwrap();
+        assert_eq!(restored, wizard);
+    }
+}
@@ -0,0 +1,107 @@
+use std::collections::BTreeSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::efa::Departure;
+use crate::store::Slice;
+use crate::trip::Journey;
+
+/// Which services the user wants to see, applied to boards and trip
+/// results alike. EFA has no parameter for this, so it filters locally.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OperatorFilter {
+    /// Hide buses replacing rail lines (Schienenersatzverkehr).
+    pub hide_replacement: bool,
+    /// Only show services of these operators; empty shows all.
+    only: BTreeSet<String>,
+}
+
+impl Slice for OperatorFilter {
+    const KEY: &'static str = "kvv.operator_filter";
+    const VERSION: u32 = 1;
+}
+
+impl OperatorFilter {
+    pub fn is_active(&self) -> bool {
+        self.hide_replacement || !self.only.is_empty()
+    }
+
+    pub fn shows_only(&self, operator: &str) -> bool {
+        self.only.contains(operator)
+    }
+
+    /// Add `operator` to the operators shown, or remove it again.
+    pub fn toggle_only(&mut self, operator: &str) {
+        if !self.only.remove(operator) {
+            self.only.insert(operator.to_string());
+        }
+    }
+
+    /// Services without operator data are kept when filtering by operator:
+    /// hiding them would empty boards of stops EFA has no operators for.
+    fn allows(&self, operator: Option<&str>, replacement: bool) -> bool {
+        if self.hide_replacement && replacement {
+            return false;
+        }
+        self.only.is_empty() || operator.is_none_or(|op| self.only.contains(op))
+    }
+
+    pub fn departures(&self, departures: Vec<Departure>) -> Vec<Departure> {
+        departures.into_iter().filter(|d| self.allows(d.operator.as_deref(), d.is_replacement())).collect()
+    }
+
+    /// Journeys whose every ride passes the filter; walks always do.
+    pub fn journeys(&self, journeys: Vec<Journey>) -> Vec<Journey> {
+        journeys
+            .into_iter()
+            .filter(|j| {
+                j.legs.iter().filter(|l| !l.is_walk()).all(|l| self.allows(l.operator.as_deref(), l.is_replacement()))
+            })
+            .collect()
+    }
+}
+
+/// Operators named on a board, for the filter choices.
+pub fn operators(departures: &[Departure]) -> BTreeSet<String> {
+    departures.iter().filter_map(|d| d.operator.clone()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{operators, OperatorFilter};
+    use crate::efa::{Departure, TransportMode};
+
+    fn dep(line: &str, mode: TransportMode, operator: Option<&str>) -> Departure {
+        Departure { line: line.to_string(), mode, operator: operator.map(str::to_string), ..Default::default() }
+    }
+
+    #[test]
+    fn filters_replacement_services_and_operators() {
+        let deps = vec![
+            dep("S1", TransportMode::SBahn, Some("AVG")),
+            dep("SEV S31", TransportMode::Bus, Some("DB Regio Bus")),
+            dep("2", TransportMode::Tram, Some("VBK")),
+            dep("S5", TransportMode::ReplacementBus, Some("AVG")),
+            dep("107", TransportMode::Bus, None),
+        ];
+        let lines = |filter: &OperatorFilter| -> Vec<String> {
+            filter.departures(deps.clone()).into_iter().map(|d| d.line).collect()
+        };
+
+        let mut filter = OperatorFilter::default();
+        assert!(!filter.is_active());
+        assert_eq!(lines(&filter).len(), 5);
+
+        filter.hide_replacement = true;
+        assert_eq!(lines(&filter), ["S1", "2", "107"]);
+
+        filter.toggle_only("AVG");
+        assert!(filter.shows_only("AVG"));
+        assert_eq!(lines(&filter), ["S1", "107"]);
+        filter.toggle_only("AVG");
+        assert!(!filter.shows_only("AVG"));
+
+        assert_eq!(operators(&deps).into_iter().collect::<Vec<_>>(), ["AVG", "DB Regio Bus", "VBK"]);
+    }
+}
@@ -0,0 +1,181 @@
+use wasm_bindgen::JsValue;
+
+use crate::diagnostics;
+use crate::geo::{self, Permission};
+use crate::messages::Locale;
+use crate::tauri::invoke;
+
+/// A platform permission some feature depends on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Capability {
+    Location,
+    Notifications,
+}
+
+/// A feature that only works with certain permissions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Feature {
+    /// "Stops near me" and nearby stops in the onboarding.
+    Nearby,
+    /// Notifications about delayed or cancelled connections.
+    DelayAlerts,
+}
+
+impl Feature {
+    /// The matrix: permissions each feature needs, in the order they are asked for.
+    pub fn requires(self) -> &'static [Capability] {
+        match self {
+            Feature::Nearby => &[Capability::Location],
+            Feature::DelayAlerts => &[Capability::Notifications],
+        }
+    }
+}
+
+/// State of every capability at one point in time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Grants {
+    pub location: Permission,
+    pub notifications: Permission,
+}
+
+impl Grants {
+    pub fn get(&self, capability: Capability) -> Permission {
+        match capability {
+            Capability::Location => self.location,
+            Capability::Notifications => self.notifications,
+        }
+    }
+}
+
+/// Whether a feature can be used right now.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    Ready,
+    /// Usable once the user allows the capability in the system prompt.
+    Ask(Capability),
+    /// The capability was denied; only the system settings can change that.
+    Blocked(Capability),
+}
+
+/// Access to `feature` under `grants`. A denied permission wins over one
+/// not asked for yet: prompting makes no sense while the feature stays blocked.
+pub fn access(feature: Feature, grants: &Grants) -> Access {
+    let mut access = Access::Ready;
+    for &capability in feature.requires() {
+        match grants.get(capability) {
+            Permission::Denied => return Access::Blocked(capability),
+            Permission::Prompt if access == Access::Ready => access = Access::Ask(capability),
+            _ => {}
+        }
+    }
+    access
+}
+
+/// Hint shown next to a feature that can't be used yet, e.g.
+/// "Enable location to use this".
+pub fn affordance(access: Access, locale: Locale) -> Option<&'static str> {
+    let text = match (access, locale) {
+        (Access::Ready, _) => return None,
+        (Access::Ask(Capability::Location), Locale::En) => "Allow location access to use this.",
+        (Access::Ask(Capability::Location), Locale::De) => "Erlaube den Standortzugriff, um das zu nutzen.",
+        (Access::Ask(Capability::Notifications), Locale::En) => "Allow notifications to use this.",
+        (Access::Ask(Capability::Notifications), Locale::De) => "Erlaube Mitteilungen, um das zu nutzen.",
+        (Access::Blocked(Capability::Location), Locale::En) => "Enable location in the system settings to use this.",
+        (Access::Blocked(Capability::Location), Locale::De) => {
+            "Aktiviere den Standort in den Systemeinstellungen, um das zu nutzen."
+        }
+        (Access::Blocked(Capability::Notifications), Locale::En) => {
+            "Enable notifications in the system settings to use this."
+        }
+        (Access::Blocked(Capability::Notifications), Locale::De) => {
+            "Aktiviere Mitteilungen in den Systemeinstellungen, um das zu nutzen."
+        }
+    };
+    Some(text)
+}
+
+/// Current state of all capabilities. Never prompts. States that can't be
+/// determined count as not asked yet, so using the feature tries again.
+pub async fn grants() -> Grants {
+    Grants { location: state(Capability::Location).await, notifications: state(Capability::Notifications).await }
+}
+
+pub async fn state(capability: Capability) -> Permission {
+    match capability {
+        Capability::Location => geo::permission().await.unwrap_or(Permission::Prompt),
+        // The plugin only tells granted from not granted.
+        Capability::Notifications => {
+            match invoke("plugin:notification|is_permission_granted", JsValue::NULL).await {
+                Ok(granted) if granted.as_bool() == Some(true) => Permission::Granted,
+                _ => Permission::Prompt,
+            }
+        }
+    }
+}
+
+/// Show the system prompt for `capability`.
+pub async fn request(capability: Capability) -> Permission {
+    match capability {
+        Capability::Location => geo::request_permission().await.unwrap_or_else(|e| {
+            diagnostics::record_warning(format!("location permission request failed: {e}"));
+            Permission::Denied
+        }),
+        Capability::Notifications => match invoke("plugin:notification|request_permission", JsValue::NULL).await {
+            Ok(state) => match state.as_string().as_deref() {
+                Some("granted") => Permission::Granted,
+                Some("denied") => Permission::Denied,
+                _ => Permission::Prompt,
+            },
+            Err(e) => {
+                diagnostics::record_warning(format!("notification permission request failed: {e:?}"));
+                Permission::Denied
+            }
+        },
+    }
+}
+
+/// Make `feature` usable, asking for every permission it still lacks.
+/// Returns whether all of them are granted.
+pub async fn ensure(feature: Feature) -> bool {
+    for &capability in feature.requires() {
+        let granted = match state(capability).await {
+            Permission::Granted => true,
+            Permission::Prompt => request(capability).await == Permission::Granted,
+            Permission::Denied => false,
+        };
+        if !granted {
+            return false;
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{access, affordance, Access, Capability, Feature, Grants};
+    use crate::geo::Permission;
+    use crate::messages::Locale;
+
+    fn grants(location: Permission, notifications: Permission) -> Grants {
+        Grants { location, notifications }
+    }
+
+    #[test]
+    fn features_need_only_their_own_permissions() {
+        let location_only = grants(Permission::Granted, Permission::Denied);
+        assert_eq!(access(Feature::Nearby, &location_only), Access::Ready);
+        assert_eq!(access(Feature::DelayAlerts, &location_only), Access::Blocked(Capability::Notifications));
+        let undecided = grants(Permission::Prompt, Permission::Prompt);
+        assert_eq!(access(Feature::Nearby, &undecided), Access::Ask(Capability::Location));
+    }
+
+    #[test]
+    fn affordances_tell_how_to_enable_a_feature() {
+        assert_eq!(affordance(Access::Ready, Locale::En), None);
+        assert_eq!(
+            affordance(Access::Blocked(Capability::Location), Locale::En),
+            Some("Enable location in the system settings to use this.")
+        );
+        assert!(affordance(Access::Ask(Capability::Notifications), Locale::De).is_some());
+    }
+}
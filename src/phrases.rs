@@ -0,0 +1,89 @@
+use crate::efa::{Departure, TransportMode};
+use crate::messages::Locale;
+
+/// "1 minute", "3 minutes"; "1 Minute", "3 Minuten".
+pub fn minutes(n: u32, locale: Locale) -> String {
+    match (n, locale) {
+        (1, Locale::En) => "1 minute".to_string(),
+        (n, Locale::En) => format!("{n} minutes"),
+        (1, Locale::De) => "1 Minute".to_string(),
+        (n, Locale::De) => format!("{n} Minuten"),
+    }
+}
+
+/// Where `platform` is, e.g. "platform 2", "Steig B". Platforms EFA already
+/// named in full, e.g. "Gleis 1", are used as they are.
+pub fn platform(platform: &str, mode: TransportMode, locale: Locale) -> String {
+    if platform.contains(' ') {
+        return platform.to_string();
+    }
+    let bus = matches!(mode, TransportMode::Bus | TransportMode::OnDemand | TransportMode::ReplacementBus);
+    let word = match (bus, locale) {
+        (false, Locale::En) => "platform",
+        (true, Locale::En) => "stand",
+        (false, Locale::De) => "Gleis",
+        (true, Locale::De) => "Steig",
+    };
+    format!("{word} {platform}")
+}
+
+/// Predicate of a departure `minutes` away, e.g. "departs in 3 minutes from
+/// platform 2" or "fährt jetzt von Gleis 2 ab".
+pub fn departs(minutes_away: u32, from: Option<&str>, locale: Locale) -> String {
+    match (minutes_away, from, locale) {
+        (0, None, Locale::En) => "departs now".to_string(),
+        (0, Some(from), Locale::En) => format!("departs now from {from}"),
+        (n, None, Locale::En) => format!("departs in {}", minutes(n, locale)),
+        (n, Some(from), Locale::En) => format!("departs in {} from {from}", minutes(n, locale)),
+        // "abfahren" is separable: its "ab" goes last.
+        (0, None, Locale::De) => "fährt jetzt ab".to_string(),
+        (0, Some(from), Locale::De) => format!("fährt jetzt von {from} ab"),
+        (n, None, Locale::De) => format!("fährt in {}", minutes(n, locale)),
+        (n, Some(from), Locale::De) => format!("fährt in {} von {from}", minutes(n, locale)),
+    }
+}
+
+/// "S2 to Spöck", "S2 nach Spöck".
+pub fn subject(dep: &Departure, locale: Locale) -> String {
+    match (&dep.direction, locale) {
+        (Some(dir), Locale::En) => format!("{} to {dir}", dep.line),
+        (Some(dir), Locale::De) => format!("{} nach {dir}", dep.line),
+        (None, _) => dep.line.clone(),
+    }
+}
+
+/// Full sentence for a departure `minutes_away`, for notifications,
+/// announcements and screen readers alike.
+pub fn departure(dep: &Departure, minutes_away: u32, locale: Locale) -> String {
+    let from = dep.platform.as_deref().map(|p| platform(p, dep.mode, locale));
+    format!("{} {}.", subject(dep, locale), departs(minutes_away, from.as_deref(), locale))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{departure, minutes, platform};
+    use crate::efa::{Departure, TransportMode};
+    use crate::messages::Locale;
+
+    #[test]
+    fn phrases_are_pluralized_and_localized() {
+        assert_eq!(minutes(1, Locale::En), "1 minute");
+        assert_eq!(minutes(0, Locale::De), "0 Minuten");
+        assert_eq!(platform("2", TransportMode::SBahn, Locale::De), "Gleis 2");
+        assert_eq!(platform("B", TransportMode::Bus, Locale::En), "stand B");
+        assert_eq!(platform("Gleis 1", TransportMode::Train, Locale::En), "Gleis 1");
+
+        let s2 = Departure {
+            line: "S2".to_string(),
+            direction: Some("Spöck".to_string()),
+            mode: TransportMode::SBahn,
+            platform: Some("2".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(departure(&s2, 3, Locale::De), "S2 nach Spöck fährt in 3 Minuten von Gleis 2.");
+        assert_eq!(departure(&s2, 0, Locale::De), "S2 nach Spöck fährt jetzt von Gleis 2 ab.");
+        assert_eq!(departure(&s2, 1, Locale::En), "S2 to Spöck departs in 1 minute from platform 2.");
+        let bus = Departure { line: "62".to_string(), mode: TransportMode::Bus, ..Default::default() };
+        assert_eq!(departure(&bus, 0, Locale::En), "62 departs now.");
+    }
+}
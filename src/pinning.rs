@@ -0,0 +1,98 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::efa::Departure;
+use crate::store::Slice;
+
+/// Stable identity of a board row across refreshes: line plus direction.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct RowKey {
+    pub line: String,
+    pub direction: Option<String>,
+}
+
+impl RowKey {
+    pub fn of(dep: &Departure) -> Self {
+        RowKey { line: dep.line.clone(), direction: dep.direction.clone() }
+    }
+}
+
+/// Pinned rows per stop id.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct PinStore {
+    pins: BTreeMap<String, Vec<RowKey>>,
+}
+
+impl Slice for PinStore {
+    const KEY: &'static str = "kvv.pins";
+    const VERSION: u32 = 1;
+}
+
+impl PinStore {
+    pub fn is_pinned(&self, stop_id: &str, key: &RowKey) -> bool {
+        self.pins.get(stop_id).is_some_and(|keys| keys.contains(key))
+    }
+
+    /// Pin or unpin `key` at `stop_id`; returns whether it is pinned afterwards.
+    pub fn toggle(&mut self, stop_id: &str, key: RowKey) -> bool {
+        let keys = self.pins.entry(stop_id.to_string()).or_default();
+        if let Some(pos) = keys.iter().position(|k| *k == key) {
+            keys.remove(pos);
+            if keys.is_empty() {
+                self.pins.remove(stop_id);
+            }
+            false
+        } else {
+            keys.push(key);
+            true
+        }
+    }
+
+    /// Move pinned rows to the top, keeping the existing order within both groups.
+    pub fn apply(&self, stop_id: &str, departures: &mut [Departure]) {
+        departures.sort_by_key(|d| !self.is_pinned(stop_id, &RowKey::of(d)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PinStore, RowKey};
+    use crate::efa::hhmm;
+    use crate::fixtures::dep;
+
+    #[test]
+    fn pinned_rows_render_first_in_stable_order() {
+        let mut store = PinStore::default();
+        assert!(store.toggle("7001004", RowKey::of(&dep("S2", "Spöck", "08:05"))));
+
+        let mut deps = vec![
+            dep("1", "Durlach", "08:01"),
+            dep("S2", "Spöck", "08:05"),
+            dep("S2", "Rheinstetten", "08:06"),
+            dep("S2", "Spöck", "08:25"),
+        ];
+        store.apply("7001004", &mut deps);
+        let order: Vec<_> = deps.iter().map(|d| hhmm(&d.time)).collect();
+        assert_eq!(order, ["08:05", "08:25", "08:01", "08:06"]);
+
+        // Pins are per stop.
+        let mut other = vec![dep("1", "Durlach", "08:01"), dep("S2", "Spöck", "08:05")];
+        store.apply("7000001", &mut other);
+        assert_eq!(other[0].line, "1");
+    }
+
+    #[test]
+    fn toggling_twice_unpins_and_round_trips_through_json() {
+        let mut store = PinStore::default();
+        let key = RowKey::of(&dep("S2", "Spöck", "08:05"));
+        store.toggle("7001004", key.clone());
+        let json = serde_json::to_string(&store).unwrap();
+        let restored: PinStore = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, store);
+
+        assert!(!store.toggle("7001004", key.clone()));
+        assert!(!store.is_pinned("7001004", &key));
+        assert_eq!(store, PinStore::default());
+    }
+}
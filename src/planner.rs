@@ -0,0 +1,260 @@
+use std::time::Duration;
+
+use chrono::NaiveDate;
+use futures::StreamExt;
+use leptos::ev::SubmitEvent;
+use leptos::prelude::*;
+use leptos::task::spawn_local;
+
+use crate::comparison::{compare, Comparison, DistanceEstimator, DurationEstimator, OsrmEstimator};
+use crate::efa::{stopfinder_best, stopfinder_by_coord, EfaError, RequestSlot, StopSuggestion};
+use crate::export::{data_url, ical};
+use crate::geocode::geocode;
+use crate::messages::error_message;
+use crate::settings::Settings;
+use crate::stops::{stop_details, StopDetails};
+use crate::store::use_store;
+use crate::trip::{self, Fare, Journey, Leg, TripOptions, WalkSpeed};
+use crate::tz;
+
+// How often the board of the origin is checked for slips of the first ride.
+const BOARDING_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Journeys between two stops or addresses typed by name, e.g. "Durlach
+/// Bahnhof" to "Kaiserstraße 12".
+#[component]
+pub fn TripPlanner() -> impl IntoView {
+    let store = use_store();
+    let from = RwSignal::new(String::new());
+    let to = RwSignal::new(String::new());
+    // Stop names every journey has to pass through or must not, if set
+    let via = RwSignal::new(String::new());
+    let avoid = RwSignal::new(String::new());
+    let options = RwSignal::new(TripOptions::default());
+    let journeys = RwSignal::new(Vec::<Journey>::new());
+    let message = RwSignal::new(String::new());
+    let comparison = RwSignal::new(Vec::<Comparison>::new());
+    // Day the shown journeys run on, for the calendar export.
+    let day = RwSignal::new(tz::now().date());
+    // Resolving, planning and then keeping the boarding times live; a new
+    // plan cancels what is left of the previous one.
+    let planning = StoredValue::new_local(RequestSlot::default());
+    on_cleanup(move || {
+        planning.try_with_value(RequestSlot::cancel);
+    });
+    let plan = move |ev: SubmitEvent| {
+        ev.prevent_default();
+        let (from, to, mut options) = (from.get_untracked(), to.get_untracked(), options.get_untracked());
+        let (via, avoid) = (via.get_untracked(), avoid.get_untracked());
+        let settings = store.settings.get_untracked();
+        let locale = settings.language;
+        journeys.set(Vec::new());
+        comparison.set(Vec::new());
+        day.set(options.date.as_deref().and_then(|d| NaiveDate::parse_from_str(d, "%Y%m%d").ok()).unwrap_or_else(|| tz::now().date()));
+        message.set("Planning…".to_string());
+        let request = async move {
+            let (origin, destination) = futures::join!(resolve(&from, &settings), resolve(&to, &settings));
+            let (origin, destination) = match (origin?, destination?) {
+                (Some(origin), Some(destination)) => (origin, destination),
+                (origin, _) => {
+                    let unknown = if origin.is_none() { from } else { to };
+                    message.set(format!("No stop found for \"{unknown}\"."));
+                    return Ok(());
+                }
+            };
+            for (name, stop_id) in [(via, &mut options.via), (avoid, &mut options.avoid)] {
+                if name.trim().is_empty() {
+                    continue;
+                }
+                let Some(stop) = resolve(&name, &settings).await? else {
+                    message.set(format!("No stop found for \"{name}\"."));
+                    return Ok(());
+                };
+                *stop_id = Some(stop.id);
+            }
+            let found = trip::trip(&origin.id, &destination.id, &options).await?;
+            message.set(if found.is_empty() { "No connections found.".to_string() } else { String::new() });
+            journeys.set(found.clone());
+            let compared = async {
+                if settings.bike_comparison {
+                    comparison.set(compare_bike(&origin.id, &destination.id, &settings).await);
+                }
+            };
+            let live = trip::live_boarding(&origin.id, found, BOARDING_INTERVAL).for_each(|update| {
+                journeys.set(update);
+                async {}
+            });
+            futures::join!(compared, live);
+            Ok(())
+        };
+        spawn_local(async move {
+            match planning.get_value().run(request).await {
+                Ok(()) | Err(EfaError::Cancelled) => {}
+                Err(e) => message.set(error_message(&e, locale, None)),
+            }
+        });
+    };
+    // The user's operator filter applies to journeys as it does to boards.
+    let shown = move || store.operator_filter.with(|f| f.journeys(journeys.get()));
+    let filtered = move || store.operator_filter.with(|f| f.is_active()) && shown().len() < journeys.with(Vec::len);
+    view! {
+        <form class="trip" on:submit=plan>
+            <div class="row">
+                <input placeholder="From" prop:value=move || from.get() on:input=move |ev| from.set(event_target_value(&ev)) />
+                <input placeholder="To" prop:value=move || to.get() on:input=move |ev| to.set(event_target_value(&ev)) />
+                <button type="submit">"Plan"</button>
+            </div>
+            <details class="options">
+                <summary>"Options"</summary>
+                <div class="row">
+                    <input placeholder="Via" prop:value=move || via.get() on:input=move |ev| via.set(event_target_value(&ev)) />
+                    <input placeholder="Avoid" prop:value=move || avoid.get() on:input=move |ev| avoid.set(event_target_value(&ev)) />
+                </div>
+                <label>
+                    <input
+                        type="checkbox"
+                        prop:checked=move || options.with(|o| o.accessible)
+                        on:change=move |ev| options.update(|o| o.accessible = event_target_checked(&ev))
+                    />
+                    " Wheelchair-accessible vehicles only"
+                </label>
+                <label>
+                    <input
+                        type="checkbox"
+                        prop:checked=move || options.with(|o| o.with_bike)
+                        on:change=move |ev| options.update(|o| o.with_bike = event_target_checked(&ev))
+                    />
+                    " Taking a bike along"
+                </label>
+                <label>
+                    "Walking "
+                    <select on:change=move |ev| options.update(|o| o.walk_speed = match event_target_value(&ev).as_str() {
+                        "slow" => WalkSpeed::Slow,
+                        "fast" => WalkSpeed::Fast,
+                        _ => WalkSpeed::Normal,
+                    })>
+                        <option value="slow" selected=move || options.with(|o| o.walk_speed == WalkSpeed::Slow)>"slowly"</option>
+                        <option value="normal" selected=move || options.with(|o| o.walk_speed == WalkSpeed::Normal)>"normally"</option>
+                        <option value="fast" selected=move || options.with(|o| o.walk_speed == WalkSpeed::Fast)>"fast"</option>
+                    </select>
+                    ", at most "
+                    <input type="number" min="1" placeholder="any"
+                        prop:value=move || options.with(|o| o.max_walk_minutes.map(|m| m.to_string()).unwrap_or_default())
+                        on:input=move |ev| options.update(|o| o.max_walk_minutes = event_target_value(&ev).parse().ok()) />
+                    " min to and from the stops"
+                </label>
+                <label>
+                    <input
+                        type="checkbox"
+                        prop:checked=move || store.settings.with(|s| s.bike_comparison)
+                        on:change=move |ev| store.settings.update(|s| s.bike_comparison = event_target_checked(&ev))
+                    />
+                    " Compare with cycling"
+                </label>
+                <Show when=move || store.settings.with(|s| s.bike_comparison)>
+                    <label>
+                        "Bike routing service (OSRM) "
+                        <input placeholder="none, estimate from the distance"
+                            prop:value=move || store.settings.with(|s| s.bike_routing_url.clone())
+                            on:change=move |ev| store.settings.update(|s| s.bike_routing_url = event_target_value(&ev).trim().to_string()) />
+                    </label>
+                </Show>
+            </details>
+        </form>
+        <p>{ move || message.get() }</p>
+        <Show when=move || !comparison.with(Vec::is_empty)>
+            <p class="comparison">
+                { move || comparison.get().into_iter().map(|row| format!("{}: about {} min", row.label, row.minutes)).collect::<Vec<_>>().join(" · ") }
+            </p>
+        </Show>
+        <Show when=filtered>
+            <p class="hint">"Some connections are hidden by your filter."</p>
+        </Show>
+        <ol class="journeys">
+            { move || shown().iter().map(|journey| journey_item(journey, day.get())).collect::<Vec<_>>() }
+        </ol>
+    }
+}
+
+/// The stop a typed origin or destination most likely means: a stop of
+/// that name, or else the stop nearest to the address.
+async fn resolve(query: &str, settings: &Settings) -> Result<Option<StopSuggestion>, EfaError> {
+    if let Some(stop) = stopfinder_best(query).await? {
+        return Ok(Some(stop));
+    }
+    let Some(place) = geocode(query, settings).await?.into_iter().next() else { return Ok(None) };
+    Ok(stopfinder_by_coord(place.coord.lat, place.coord.lon).await?.map(|nearest| nearest.stop))
+}
+
+/// How long the trip takes by bike, between the stops it was planned
+/// between. Routed if the user set up a routing service, else estimated.
+async fn compare_bike(origin_id: &str, destination_id: &str, settings: &Settings) -> Vec<Comparison> {
+    let (origin, destination) = futures::join!(stop_details(origin_id), stop_details(destination_id));
+    let coord = |details: Result<Option<StopDetails>, EfaError>| details.ok().flatten().and_then(|d| d.coord);
+    let (Some(from), Some(to)) = (coord(origin), coord(destination)) else { return Vec::new() };
+    let mut estimators: Vec<Box<dyn DurationEstimator>> = Vec::new();
+    if !settings.bike_routing_url.is_empty() {
+        let mut base_url = settings.bike_routing_url.clone();
+        if !base_url.ends_with('/') {
+            base_url.push('/');
+        }
+        estimators.push(Box::new(OsrmEstimator { label: "Bike".to_string(), base_url, profile: "bike".to_string() }));
+    }
+    estimators.push(Box::new(DistanceEstimator::bike()));
+    // The distance estimate is only there for when routing fails.
+    compare(&estimators, from, to).await.into_iter().take(1).collect()
+}
+
+fn journey_item(journey: &Journey, day: NaiveDate) -> impl IntoView + use<> {
+    let calendar = data_url("text/calendar", &ical::journey(journey, day, tz::now()));
+    let times = match (journey.departure(), journey.arrival()) {
+        (Some(departure), Some(arrival)) => format!("{} → {}", departure.time(), arrival.time()),
+        _ => String::new(),
+    };
+    let duration = journey.duration.as_ref().map(|d| format!(" · {d} h")).unwrap_or_default();
+    let changes = match journey.changes {
+        0 => " · direct".to_string(),
+        1 => " · 1 change".to_string(),
+        n => format!(" · {n} changes"),
+    };
+    let via: Vec<String> = journey.interchanges().into_iter().map(|stop| stop.name.clone()).collect();
+    let via = if via.is_empty() { String::new() } else { format!(" via {}", via.join(", ")) };
+    view! {
+        <li>
+            <strong>{ times }</strong>{ duration }{ changes }{ via }
+            { journey.fare.as_ref().map(|fare| view! { <div class="fare">{ fare_label(fare) }</div> }) }
+            <a class="calendar" download="trip.ics" href=calendar>"Add to calendar"</a>
+            <ul class="legs">
+                { journey.legs.iter().map(|leg| view! { <li>{ leg_label(leg) }{ leg_flags(leg) }</li> }).collect::<Vec<_>>() }
+            </ul>
+        </li>
+    }
+}
+
+/// "2 zones · Einzelfahrkarte 3,00 €", tickets in EFA's order.
+fn fare_label(fare: &Fare) -> String {
+    let zones = fare.zones.map(|n| if n == 1 { "1 zone".to_string() } else { format!("{n} zones") });
+    let tickets = fare.tickets.iter().map(|t| format!("{} {}", t.name, t.price_label()));
+    zones.into_iter().chain(tickets).collect::<Vec<_>>().join(" · ")
+}
+
+/// "08:05 S2 → Spöck from ZKM (1), 08:40 at Spöck" or a walk.
+fn leg_label(leg: &Leg) -> String {
+    let (origin, destination) = (&leg.origin, &leg.destination);
+    let Some(line) = &leg.line else {
+        return format!("{} Walk to {}, {}", origin.time(), destination.name, destination.time());
+    };
+    let ride = leg.direction.as_ref().map_or(line.clone(), |d| format!("{line} → {d}"));
+    let platform = origin.platform.as_ref().map(|p| format!(" ({p})")).unwrap_or_default();
+    format!("{} {ride} from {}{platform}, {} at {}", origin.time(), origin.name, destination.time(), destination.name)
+}
+
+/// What EFA says about the vehicle of a ride, e.g. " · wheelchair accessible".
+fn leg_flags(leg: &Leg) -> String {
+    let flags = [
+        leg.vehicle.wheelchair.map(|ok| if ok { "wheelchair accessible" } else { "not wheelchair accessible" }),
+        leg.vehicle.low_floor.filter(|&low| low && leg.vehicle.wheelchair.is_none()).map(|_| "low floor"),
+        leg.vehicle.bikes.map(|ok| if ok { "bikes allowed" } else { "no bikes" }),
+    ];
+    flags.into_iter().flatten().map(|flag| format!(" · {flag}")).collect()
+}
@@ -0,0 +1,116 @@
+use std::collections::BTreeSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::efa::Departure;
+use crate::store::Slice;
+
+/// Departures leaving from one platform, in board order.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlatformGroup {
+    /// Heading such as "Gleis 1"; `None` for departures without platform data.
+    pub platform: Option<String>,
+    pub departures: Vec<Departure>,
+}
+
+/// Stops whose board is grouped by platform, chosen per stop.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlatformLayout {
+    stops: BTreeSet<String>,
+}
+
+impl Slice for PlatformLayout {
+    const KEY: &'static str = "kvv.platform_layout";
+    const VERSION: u32 = 1;
+}
+
+impl PlatformLayout {
+    pub fn is_grouped(&self, stop_id: &str) -> bool {
+        self.stops.contains(stop_id)
+    }
+
+    /// Switch grouping for `stop_id`; returns whether it is grouped afterwards.
+    pub fn toggle(&mut self, stop_id: &str) -> bool {
+        if !self.stops.remove(stop_id) {
+            self.stops.insert(stop_id.to_string());
+        }
+        self.is_grouped(stop_id)
+    }
+}
+
+/// Whether grouping makes sense: at least two platforms are known.
+pub fn has_platforms(departures: &[Departure]) -> bool {
+    let platforms: BTreeSet<_> = departures.iter().filter_map(|d| d.platform.as_deref()).collect();
+    platforms.len() > 1
+}
+
+// "Gleis 2" before "Gleis 10": order by the first number, then by name.
+fn sort_key(platform: &str) -> (u32, &str) {
+    let digits: String = platform.chars().skip_while(|c| !c.is_ascii_digit()).take_while(char::is_ascii_digit).collect();
+    (digits.parse().unwrap_or(u32::MAX), platform)
+}
+
+/// The board for a stop: one group per platform if `by_platform` is set and
+/// the stop has platform data, otherwise a single group without heading.
+/// Departures without a platform come last; order within a group is kept.
+pub fn board(departures: Vec<Departure>, by_platform: bool) -> Vec<PlatformGroup> {
+    if !by_platform || !has_platforms(&departures) {
+        return vec![PlatformGroup { platform: None, departures }];
+    }
+    let mut groups: Vec<PlatformGroup> = Vec::new();
+    for departure in departures {
+        match groups.iter_mut().find(|g| g.platform == departure.platform) {
+            Some(group) => group.departures.push(departure),
+            None => groups.push(PlatformGroup { platform: departure.platform.clone(), departures: vec![departure] }),
+        }
+    }
+    groups.sort_by(|a, b| match (&a.platform, &b.platform) {
+        (Some(a), Some(b)) => sort_key(a).cmp(&sort_key(b)),
+        (a, b) => b.is_some().cmp(&a.is_some()),
+    });
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{board, PlatformLayout};
+    use crate::efa::Departure;
+
+    fn dep(line: &str, platform: Option<&str>) -> Departure {
+        Departure { line: line.to_string(), platform: platform.map(str::to_string), ..Default::default() }
+    }
+
+    #[test]
+    fn groups_by_platform_in_natural_order() {
+        let deps = vec![
+            dep("S1", Some("Gleis 10")),
+            dep("2", Some("Gleis 2")),
+            dep("S4", None),
+            dep("1", Some("Gleis 2")),
+            dep("S5", Some("Gleis 10")),
+        ];
+        let groups = board(deps.clone(), true);
+        let rows: Vec<_> = groups
+            .iter()
+            .map(|g| (g.platform.as_deref(), g.departures.iter().map(|d| d.line.as_str()).collect::<Vec<_>>()))
+            .collect();
+        assert_eq!(
+            rows,
+            [(Some("Gleis 2"), vec!["2", "1"]), (Some("Gleis 10"), vec!["S1", "S5"]), (None, vec!["S4"])]
+        );
+
+        assert_eq!(board(deps, false).len(), 1);
+        let single_platform = vec![dep("1", Some("Gleis 1")), dep("2", None)];
+        assert_eq!(board(single_platform, true).len(), 1);
+    }
+
+    #[test]
+    fn layout_is_toggled_per_stop() {
+        let mut layout = PlatformLayout::default();
+        assert!(layout.toggle("7000090"));
+        assert!(layout.is_grouped("7000090"));
+        assert!(!layout.is_grouped("7001004"));
+        assert!(!layout.toggle("7000090"));
+    }
+}
@@ -0,0 +1,91 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::store::{Expiring, Slice};
+
+// A use counts half as much after two weeks, so old habits fade out.
+const HALF_LIFE_MS: f64 = 14.0 * 24.0 * 60.0 * 60.0 * 1000.0;
+
+/// Decayed use count of one stop.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Score {
+    pub value: f64,
+    /// When `value` was last brought up to date (ms since the epoch).
+    pub updated_ms: f64,
+}
+
+impl Score {
+    fn at(&self, now_ms: f64) -> f64 {
+        let elapsed = (now_ms - self.updated_ms).max(0.0);
+        self.value * 0.5f64.powf(elapsed / HALF_LIFE_MS)
+    }
+}
+
+/// How often and how recently the user picked each stop. Stays on the device.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Popularity {
+    stops: BTreeMap<String, Score>,
+}
+
+impl Slice for Popularity {
+    const KEY: &'static str = "kvv.popularity";
+    const VERSION: u32 = 1;
+}
+
+impl Popularity {
+    /// Count a use of `stop_id` at `now_ms`.
+    pub fn record(&mut self, stop_id: &str, now_ms: f64) {
+        let score = self.stops.entry(stop_id.to_string()).or_default();
+        *score = Score { value: score.at(now_ms) + 1.0, updated_ms: now_ms };
+    }
+
+    pub fn score(&self, stop_id: &str, now_ms: f64) -> f64 {
+        self.stops.get(stop_id).map_or(0.0, |s| s.at(now_ms))
+    }
+
+    /// Sort `items` by popularity of their stop, most used first. Stops never
+    /// used keep their relative order behind the used ones.
+    pub fn rank<T>(&self, items: &mut [T], stop_id: impl Fn(&T) -> &str, now_ms: f64) {
+        items.sort_by(|a, b| self.score(stop_id(b), now_ms).total_cmp(&self.score(stop_id(a), now_ms)));
+    }
+}
+
+impl Expiring for Popularity {
+    fn prune(&mut self, cutoff_ms: f64) {
+        self.stops.retain(|_, score| score.updated_ms >= cutoff_ms);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Popularity, HALF_LIFE_MS};
+    use crate::store::Expiring;
+
+    #[test]
+    fn recent_use_outweighs_old_habits() {
+        let mut popularity = Popularity::default();
+        for _ in 0..3 {
+            popularity.record("7000090", 0.0);
+        }
+        let now = 3.0 * HALF_LIFE_MS;
+        popularity.record("7001004", now);
+        assert!((popularity.score("7000090", now) - 3.0 / 8.0).abs() < 1e-9);
+        assert_eq!(popularity.score("7001004", now), 1.0);
+
+        let mut ids = vec!["7000001", "7000090", "7000002", "7001004"];
+        popularity.rank(&mut ids, |id| id, now);
+        assert_eq!(ids, ["7001004", "7000090", "7000001", "7000002"]);
+    }
+
+    #[test]
+    fn prune_forgets_stops_not_used_since_the_cutoff() {
+        let mut popularity = Popularity::default();
+        popularity.record("7000090", 1000.0);
+        popularity.record("7001004", 5000.0);
+        popularity.prune(2000.0);
+        assert_eq!(popularity.score("7000090", 5000.0), 0.0);
+        assert_eq!(popularity.score("7001004", 5000.0), 1.0);
+    }
+}
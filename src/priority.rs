@@ -0,0 +1,435 @@
+use std::cell::Cell;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+const HOUR_MS: f64 = 3_600_000.0;
+
+/// Requests per hour the app allows itself unless configured otherwise. The
+/// upstream blocks IPs that poll much harder than a person looking at boards.
+pub const DEFAULT_BUDGET_PER_HOUR: u32 = 600;
+
+/// Importance of a network request. Higher priorities are served first.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    /// Telemetry, cache warm-up and other work nobody is waiting for.
+    Background,
+    /// Data the user will probably need soon, e.g. favorites boards.
+    Prefetch,
+    /// Something the user just asked for.
+    #[default]
+    Interactive,
+}
+
+impl Priority {
+    // Lower priorities leave slots free so an interactive request can always start.
+    fn reserved_slots(self) -> usize {
+        match self {
+            Priority::Interactive => 0,
+            Priority::Prefetch => 1,
+            Priority::Background => 2,
+        }
+    }
+
+    // Lower priorities leave tokens in the rate limit's bucket, so a
+    // retrying refresh can't make the next search wait for the refill.
+    fn reserved_tokens(self) -> u32 {
+        match self {
+            Priority::Interactive => 0,
+            Priority::Prefetch => 1,
+            Priority::Background => 2,
+        }
+    }
+}
+
+thread_local! {
+    static CURRENT: Cell<Priority> = const { Cell::new(Priority::Interactive) };
+}
+
+/// Run `fut` with all EFA requests it makes scheduled at `priority`.
+pub fn prioritized<F: Future>(priority: Priority, fut: F) -> Prioritized<F> {
+    Prioritized { priority, fut: Box::pin(fut) }
+}
+
+pub struct Prioritized<F> {
+    priority: Priority,
+    fut: Pin<Box<F>>,
+}
+
+impl<F: Future> Future for Prioritized<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        let outer = CURRENT.with(|c| c.replace(self.priority));
+        let result = self.fut.as_mut().poll(cx);
+        CURRENT.with(|c| c.set(outer));
+        result
+    }
+}
+
+/// Priority of the request being started on this thread.
+pub fn current() -> Priority {
+    CURRENT.with(|c| c.get())
+}
+
+/// Requests made during the last hour against the hourly budget.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Usage {
+    pub used: u32,
+    pub per_hour: u32,
+}
+
+impl Usage {
+    /// Whether a view making `requests` requests every `every` would on its
+    /// own use up more than the budget.
+    pub fn would_exceed(&self, every: Duration, requests: u32) -> bool {
+        hourly_rate(every, requests) > self.per_hour as f64
+    }
+}
+
+/// Requests per hour of something making `requests` requests every `every`.
+pub fn hourly_rate(every: Duration, requests: u32) -> f64 {
+    requests as f64 * HOUR_MS / (every.as_secs_f64() * 1000.0).max(1.0)
+}
+
+/// Requests a client may make: `per_second` on average, with bursts of up
+/// to `burst`, e.g. a dashboard loading all of its boards at once.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RateLimit {
+    pub burst: u32,
+    pub per_second: f64,
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        RateLimit { burst: 10, per_second: 1.0 }
+    }
+}
+
+/// Token bucket enforcing a `RateLimit`; starts out full.
+#[derive(Clone, Debug)]
+pub struct TokenBucket {
+    limit: RateLimit,
+    tokens: f64,
+    // When tokens were last counted (ms), `None` before the first request.
+    updated_ms: Option<f64>,
+}
+
+impl TokenBucket {
+    pub fn new(limit: RateLimit) -> Self {
+        TokenBucket { limit, tokens: limit.burst as f64, updated_ms: None }
+    }
+
+    pub fn limit(&self) -> RateLimit {
+        self.limit
+    }
+
+    /// Take a token for a request at `priority` at `now_ms`, or tell how
+    /// long to wait until one is available to that priority.
+    pub fn take(&mut self, now_ms: f64, priority: Priority) -> Result<(), Duration> {
+        let per_second = self.limit.per_second.max(0.001);
+        if let Some(updated) = self.updated_ms {
+            let refilled = (now_ms - updated).max(0.0) / 1000.0 * per_second;
+            self.tokens = (self.tokens + refilled).min(self.limit.burst as f64);
+        }
+        self.updated_ms = Some(now_ms);
+        let needed = (1 + priority.reserved_tokens()).min(self.limit.burst.max(1)) as f64;
+        if self.tokens >= needed {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((needed - self.tokens) / per_second))
+        }
+    }
+}
+
+// Waiters are ordered by highest priority first, then arrival.
+type WaitKey = (std::cmp::Reverse<Priority>, u64);
+
+struct State {
+    in_flight: usize,
+    next_seq: u64,
+    waiting: BTreeMap<WaitKey, Waker>,
+    // Waiters that were handed a slot but have not been polled since.
+    granted: BTreeSet<u64>,
+    // Start times (ms) of the requests made during the last hour.
+    started: VecDeque<f64>,
+    budget_per_hour: u32,
+    // Endpoint URL -> time (ms) until which the server asked us to wait.
+    paused: BTreeMap<String, f64>,
+}
+
+impl State {
+    fn forget_older_than_an_hour(&mut self, now_ms: f64) {
+        while self.started.front().is_some_and(|t| now_ms - t >= HOUR_MS) {
+            self.started.pop_front();
+        }
+    }
+}
+
+/// Limits concurrent requests, hands out free slots by priority and keeps
+/// track of the hourly request budget.
+pub struct Scheduler {
+    max_in_flight: usize,
+    state: Mutex<State>,
+}
+
+impl Scheduler {
+    pub const fn new(max_in_flight: usize) -> Self {
+        Scheduler {
+            max_in_flight,
+            state: Mutex::new(State {
+                in_flight: 0,
+                next_seq: 0,
+                waiting: BTreeMap::new(),
+                granted: BTreeSet::new(),
+                started: VecDeque::new(),
+                budget_per_hour: DEFAULT_BUDGET_PER_HOUR,
+                paused: BTreeMap::new(),
+            }),
+        }
+    }
+
+    pub fn set_budget(&self, per_hour: u32) {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).budget_per_hour = per_hour;
+    }
+
+    /// Count a request started at `now_ms` against the budget.
+    pub fn note_request(&self, now_ms: f64) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.forget_older_than_an_hour(now_ms);
+        state.started.push_back(now_ms);
+    }
+
+    /// Budget usage as of `now_ms`.
+    pub fn usage(&self, now_ms: f64) -> Usage {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.forget_older_than_an_hour(now_ms);
+        Usage { used: state.started.len() as u32, per_hour: state.budget_per_hour }
+    }
+
+    /// Hold back requests to `endpoint` until `until_ms`, e.g. after a 429.
+    pub fn pause(&self, endpoint: &str, until_ms: f64) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let until = state.paused.entry(endpoint.to_string()).or_insert(until_ms);
+        *until = until.max(until_ms);
+    }
+
+    /// How much longer `endpoint` is paused as of `now_ms`, if at all.
+    pub fn paused_for(&self, endpoint: &str, now_ms: f64) -> Option<Duration> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.paused.retain(|_, until| *until > now_ms);
+        state.paused.get(endpoint).map(|until| Duration::from_millis((until - now_ms) as u64))
+    }
+
+    /// Wait for a request slot; the slot is freed when the permit is dropped.
+    pub fn acquire(&self, priority: Priority) -> Acquire<'_> {
+        Acquire { scheduler: self, priority, seq: None }
+    }
+
+    fn limit(&self, priority: Priority) -> usize {
+        self.max_in_flight.saturating_sub(priority.reserved_slots()).max(1)
+    }
+
+    fn release(&self, state: &mut State) {
+        state.in_flight -= 1;
+        self.grant_next(state);
+    }
+
+    fn grant_next(&self, state: &mut State) {
+        let next = state.waiting.keys().next().copied();
+        if let Some(key) = next
+            && state.in_flight < self.limit(key.0 .0)
+        {
+            let waker = state.waiting.remove(&key).expect("key was just looked up");
+            state.in_flight += 1;
+            state.granted.insert(key.1);
+            waker.wake();
+        }
+    }
+}
+
+/// Future returned by `Scheduler::acquire`.
+pub struct Acquire<'a> {
+    scheduler: &'a Scheduler,
+    priority: Priority,
+    seq: Option<u64>,
+}
+
+impl<'a> Future for Acquire<'a> {
+    type Output = Permit<'a>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Permit<'a>> {
+        let scheduler = self.scheduler;
+        let mut state = scheduler.state.lock().unwrap_or_else(|e| e.into_inner());
+        match self.seq {
+            Some(seq) if state.granted.remove(&seq) => {
+                self.seq = None;
+                Poll::Ready(Permit { scheduler })
+            }
+            Some(seq) => {
+                state.waiting.insert((std::cmp::Reverse(self.priority), seq), cx.waker().clone());
+                Poll::Pending
+            }
+            None => {
+                // Only jump ahead of waiters with a lower priority.
+                let first_in_line = state.waiting.keys().next().is_none_or(|k| k.0 .0 < self.priority);
+                if first_in_line && state.in_flight < scheduler.limit(self.priority) {
+                    state.in_flight += 1;
+                    return Poll::Ready(Permit { scheduler });
+                }
+                let seq = state.next_seq;
+                state.next_seq += 1;
+                state.waiting.insert((std::cmp::Reverse(self.priority), seq), cx.waker().clone());
+                self.seq = Some(seq);
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl Drop for Acquire<'_> {
+    fn drop(&mut self) {
+        // A request abandoned while queued must not keep its place or its slot.
+        if let Some(seq) = self.seq {
+            let mut state = self.scheduler.state.lock().unwrap_or_else(|e| e.into_inner());
+            state.waiting.remove(&(std::cmp::Reverse(self.priority), seq));
+            if state.granted.remove(&seq) {
+                self.scheduler.release(&mut state);
+            }
+        }
+    }
+}
+
+/// A request slot held for the duration of one request.
+pub struct Permit<'a> {
+    scheduler: &'a Scheduler,
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        let mut state = self.scheduler.state.lock().unwrap_or_else(|e| e.into_inner());
+        self.scheduler.release(&mut state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{current, hourly_rate, prioritized, Priority, RateLimit, Scheduler, TokenBucket, Usage};
+    use std::future::Future;
+    use std::pin::pin;
+    use std::task::{Context, Poll, Waker};
+    use std::time::Duration;
+
+    fn poll_once<F: Future>(fut: std::pin::Pin<&mut F>) -> Poll<F::Output> {
+        fut.poll(&mut Context::from_waker(Waker::noop()))
+    }
+
+    #[test]
+    fn token_bucket_allows_bursts_then_the_sustained_rate() {
+        let mut bucket = TokenBucket::new(RateLimit { burst: 3, per_second: 2.0 });
+        for _ in 0..3 {
+            assert_eq!(bucket.take(0.0, Priority::Interactive), Ok(()));
+        }
+        assert_eq!(bucket.take(0.0, Priority::Interactive), Err(Duration::from_millis(500)));
+        assert_eq!(bucket.take(250.0, Priority::Interactive), Err(Duration::from_millis(250)));
+        assert_eq!(bucket.take(500.0, Priority::Interactive), Ok(()));
+        // A long pause refills no more than the burst.
+        for _ in 0..3 {
+            assert_eq!(bucket.take(60_000.0, Priority::Interactive), Ok(()));
+        }
+        assert!(bucket.take(60_000.0, Priority::Interactive).is_err());
+    }
+
+    #[test]
+    fn background_work_leaves_tokens_for_interactive_requests() {
+        let mut bucket = TokenBucket::new(RateLimit { burst: 4, per_second: 1.0 });
+        assert_eq!(bucket.take(0.0, Priority::Background), Ok(()));
+        assert_eq!(bucket.take(0.0, Priority::Background), Ok(()));
+        assert_eq!(bucket.take(0.0, Priority::Background), Err(Duration::from_secs(1)));
+        assert_eq!(bucket.take(0.0, Priority::Prefetch), Ok(()));
+        assert_eq!(bucket.take(0.0, Priority::Interactive), Ok(()));
+        assert!(bucket.take(0.0, Priority::Interactive).is_err());
+    }
+
+    #[test]
+    fn pauses_expire_and_only_affect_their_endpoint() {
+        let scheduler = Scheduler::new(1);
+        scheduler.pause("https://host/XSLT_DM_REQUEST", 30_000.0);
+        scheduler.pause("https://host/XSLT_DM_REQUEST", 10_000.0);
+        assert_eq!(scheduler.paused_for("https://host/XSLT_DM_REQUEST", 0.0), Some(Duration::from_secs(30)));
+        assert_eq!(scheduler.paused_for("https://host/XSLT_TRIP_REQUEST", 0.0), None);
+        assert_eq!(scheduler.paused_for("https://host/XSLT_DM_REQUEST", 30_000.0), None);
+    }
+
+    #[test]
+    fn interactive_requests_overtake_queued_background_work() {
+        let scheduler = Scheduler::new(1);
+        let held = pin!(scheduler.acquire(Priority::Interactive));
+        let Poll::Ready(permit) = poll_once(held) else { panic!("slot is free") };
+
+        let mut background = pin!(scheduler.acquire(Priority::Background));
+        let mut interactive = pin!(scheduler.acquire(Priority::Interactive));
+        assert!(poll_once(background.as_mut()).is_pending());
+        assert!(poll_once(interactive.as_mut()).is_pending());
+
+        drop(permit);
+        assert!(poll_once(background.as_mut()).is_pending());
+        let Poll::Ready(next) = poll_once(interactive.as_mut()) else { panic!("interactive goes first") };
+        drop(next);
+        assert!(poll_once(background.as_mut()).is_ready());
+    }
+
+    #[test]
+    fn background_work_leaves_slots_for_interactive_requests() {
+        let scheduler = Scheduler::new(3);
+        let mut first = pin!(scheduler.acquire(Priority::Background));
+        let mut second = pin!(scheduler.acquire(Priority::Background));
+        let Poll::Ready(_running) = poll_once(first.as_mut()) else { panic!("slot is free") };
+        assert!(poll_once(second.as_mut()).is_pending());
+        assert!(poll_once(pin!(scheduler.acquire(Priority::Interactive))).is_ready());
+    }
+
+    #[test]
+    fn dropping_a_granted_waiter_passes_the_slot_on() {
+        let scheduler = Scheduler::new(1);
+        let Poll::Ready(permit) = poll_once(pin!(scheduler.acquire(Priority::Interactive))) else { panic!() };
+        let mut abandoned = Box::pin(scheduler.acquire(Priority::Interactive));
+        let mut next = pin!(scheduler.acquire(Priority::Prefetch));
+        assert!(poll_once(abandoned.as_mut()).is_pending());
+        assert!(poll_once(next.as_mut()).is_pending());
+
+        drop(permit);
+        drop(abandoned);
+        assert!(poll_once(next.as_mut()).is_ready());
+    }
+
+    #[test]
+    fn prioritized_sets_the_current_priority_while_polling() {
+        let mut fut = pin!(prioritized(Priority::Prefetch, async { current() }));
+        assert_eq!(poll_once(fut.as_mut()), Poll::Ready(Priority::Prefetch));
+        assert_eq!(current(), Priority::Interactive);
+    }
+
+    #[test]
+    fn usage_counts_requests_of_the_last_hour() {
+        let scheduler = Scheduler::new(4);
+        scheduler.set_budget(2);
+        scheduler.note_request(0.0);
+        scheduler.note_request(1_000.0);
+        scheduler.note_request(2_000.0);
+        assert_eq!(scheduler.usage(3_000.0), Usage { used: 3, per_hour: 2 });
+        assert_eq!(scheduler.usage(3_601_500.0).used, 1);
+    }
+
+    #[test]
+    fn fast_refresh_would_exceed_the_budget() {
+        let usage = Usage { used: 0, per_hour: 600 };
+        assert_eq!(hourly_rate(Duration::from_secs(60), 1), 60.0);
+        assert!(!usage.would_exceed(Duration::from_secs(60), 5));
+        assert!(usage.would_exceed(Duration::from_secs(1), 1));
+    }
+}
@@ -0,0 +1,64 @@
+use leptos::prelude::*;
+
+use crate::settings::Retention;
+use crate::store::use_store;
+
+// Choices offered for each retention limit, `None` meaning "keep".
+const CHOICES: [(Option<u32>, &str); 5] =
+    [(Some(7), "1 week"), (Some(30), "30 days"), (Some(90), "90 days"), (Some(365), "1 year"), (None, "Keep")];
+
+fn choice_value(days: Option<u32>) -> String {
+    days.map_or_else(|| "keep".to_string(), |d| d.to_string())
+}
+
+fn parse_choice(value: &str) -> Option<u32> {
+    value.parse().ok()
+}
+
+/// One "delete X after" row bound to a field of the retention settings.
+#[component]
+fn RetentionSelect(label: &'static str, field: fn(&mut Retention) -> &mut Option<u32>) -> impl IntoView {
+    let store = use_store();
+    let current = move || {
+        let mut retention = store.settings.with(|s| s.retention);
+        *field(&mut retention)
+    };
+    view! {
+        <label>
+            { label } " "
+            <select on:change=move |ev| {
+                let days = parse_choice(&event_target_value(&ev));
+                store.settings.update(|s| *field(&mut s.retention) = days);
+            }>
+                { CHOICES.into_iter().map(|(days, text)| view! {
+                    <option value=choice_value(days) selected=move || current() == days>{ text }</option>
+                }).collect::<Vec<_>>() }
+            </select>
+        </label>
+    }
+}
+
+/// Privacy settings: how long locally recorded data is kept.
+#[component]
+pub fn PrivacyPanel() -> impl IntoView {
+    view! {
+        <div class="privacy">
+            <p>"Everything below stays on this device and is deleted automatically after the chosen time."</p>
+            <RetentionSelect label="Stop history" field=|r| &mut r.history_days/>
+            <RetentionSelect label="Punctuality log" field=|r| &mut r.punctuality_days/>
+            <RetentionSelect label="Diagnostics" field=|r| &mut r.diagnostics_days/>
+        </div>
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{choice_value, parse_choice, CHOICES};
+
+    #[test]
+    fn choices_round_trip_through_select_values() {
+        for (days, _) in CHOICES {
+            assert_eq!(parse_choice(&choice_value(days)), days);
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+use crate::efa::{self, Departure, EfaClient, EfaError, StopHits, StopSuggestion};
+use crate::messages::Locale;
+use crate::simulation::Simulation;
+use crate::trias::TriasClient;
+use crate::tz;
+
+pub type ProviderFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, EfaError>> + 'a>>;
+
+/// A timetable information system stops and departure boards come from:
+/// EFA, or TRIAS where a Verbund has moved on from EFA. Stop ids are the
+/// provider's own; pass back what its `stopfinder` returned.
+pub trait TransitProvider {
+    /// Name for diagnostics, e.g. "EFA".
+    fn name(&self) -> &str;
+    /// Stops matching `query`, best first, at most `max`.
+    fn stopfinder<'a>(&'a self, query: &'a str, max: usize) -> ProviderFuture<'a, Vec<StopSuggestion>>;
+    /// The next `max` departures from `station_id` at or after `when`
+    /// (local time); `None` means now.
+    fn departures<'a>(&'a self, station_id: &'a str, max: usize, when: Option<NaiveDateTime>) -> ProviderFuture<'a, Vec<Departure>>;
+}
+
+impl TransitProvider for EfaClient {
+    fn name(&self) -> &str {
+        "EFA"
+    }
+
+    fn stopfinder<'a>(&'a self, query: &'a str, max: usize) -> ProviderFuture<'a, Vec<StopSuggestion>> {
+        Box::pin(EfaClient::stopfinder(self, query, max))
+    }
+
+    fn departures<'a>(&'a self, station_id: &'a str, max: usize, when: Option<NaiveDateTime>) -> ProviderFuture<'a, Vec<Departure>> {
+        Box::pin(EfaClient::departures(self, station_id, max, when))
+    }
+}
+
+/// Where the app gets stops and boards from, chosen in the settings.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum Backend {
+    #[default]
+    Efa,
+    /// A TRIAS endpoint, and the access key its operator handed out.
+    Trias { url: String, requestor_ref: String },
+    /// Made-up departures for demos and screenshots, see `Simulation::demo`.
+    Simulation,
+}
+
+impl Backend {
+    /// The provider to ask instead of EFA; `None` for EFA itself, which the
+    /// app talks to directly for everything the trait doesn't cover.
+    pub fn provider(&self, language: Locale) -> Option<Rc<dyn TransitProvider>> {
+        match self {
+            Backend::Efa => None,
+            Backend::Trias { url, requestor_ref } => {
+                Some(Rc::new(TriasClient::new(url.clone(), requestor_ref.clone(), efa::transport()).with_language(language.code())))
+            }
+            Backend::Simulation => Some(Rc::new(Simulation::demo(tz::now()))),
+        }
+    }
+}
+
+/// Stops matching `query` from `backend`, names in `language`. Only EFA
+/// says whether it cut the list short.
+pub async fn stopfinder_hits(backend: &Backend, language: Locale, query: &str, max: usize) -> Result<StopHits, EfaError> {
+    match backend.provider(language) {
+        None => efa::stopfinder_hits(query, max).await,
+        Some(provider) => Ok(StopHits { stops: provider.stopfinder(query, max).await?, truncated: false, max }),
+    }
+}
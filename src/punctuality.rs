@@ -0,0 +1,147 @@
+use chrono::{NaiveDate, Timelike};
+use serde::{Deserialize, Serialize};
+
+use crate::efa::Departure;
+use crate::messages::Locale;
+use crate::store::{Expiring, Slice};
+
+const DAY_MS: f64 = 24.0 * 60.0 * 60.0 * 1000.0;
+
+/// One observed departure delay, recorded locally.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Observation {
+    pub stop_id: String,
+    pub line: String,
+    /// Days since the Unix epoch (local calendar day).
+    pub day: u32,
+    /// Hour of the planned departure (0-23).
+    pub hour: u8,
+    pub delay_minutes: i32,
+}
+
+/// Delays observed on this device, persisted for the "usually late" hints.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PunctualityLog {
+    pub observations: Vec<Observation>,
+}
+
+impl Slice for PunctualityLog {
+    const KEY: &'static str = "kvv.punctuality";
+    const VERSION: u32 = 1;
+}
+
+impl Expiring for PunctualityLog {
+    fn prune(&mut self, cutoff_ms: f64) {
+        // Observations only know their day; keep the day the cutoff falls on.
+        let first_day = (cutoff_ms / DAY_MS).floor().max(0.0) as u32;
+        self.observations.retain(|o| o.day >= first_day);
+    }
+}
+
+impl PunctualityLog {
+    /// Note the delay of `dep` at `stop_id`. Boards are polled over and over,
+    /// so a later observation of the same line in the same hour replaces the
+    /// earlier one instead of counting that trip again.
+    pub fn record(&mut self, stop_id: &str, dep: &Departure) {
+        let Some(delay_minutes) = dep.delay_minutes.filter(|_| !dep.cancelled) else { return };
+        let (day, hour) = (day_number(dep.planned_time.date()), dep.planned_time.hour() as u8);
+        let same = |o: &&mut Observation| o.stop_id == stop_id && o.line == dep.line && o.day == day && o.hour == hour;
+        match self.observations.iter_mut().find(same) {
+            Some(observation) => observation.delay_minutes = delay_minutes,
+            None => self.observations.push(Observation { stop_id: stop_id.to_string(), line: dep.line.clone(), day, hour, delay_minutes }),
+        }
+    }
+}
+
+/// Days since the Unix epoch, as in `Observation::day`.
+pub fn day_number(date: NaiveDate) -> u32 {
+    // The default date is 1970-01-01.
+    (date - NaiveDate::default()).num_days().max(0) as u32
+}
+
+/// How far back observations are taken into account.
+pub const LOOKBACK_DAYS: u32 = 28;
+// Fewer samples than this are too noisy to be shown as "usual".
+const MIN_SAMPLES: usize = 5;
+
+/// Median delay of `line` at `stop_id` around `hour` over the last
+/// `LOOKBACK_DAYS` before `today`, or `None` without enough history.
+pub fn typical_delay(observations: &[Observation], stop_id: &str, line: &str, hour: u8, today: u32) -> Option<i32> {
+    let mut delays: Vec<i32> = observations
+        .iter()
+        .filter(|o| o.stop_id == stop_id && o.line == line && o.hour == hour)
+        .filter(|o| o.day <= today && today - o.day < LOOKBACK_DAYS)
+        .map(|o| o.delay_minutes)
+        .collect();
+    if delays.len() < MIN_SAMPLES {
+        return None;
+    }
+    delays.sort_unstable();
+    Some(delays[delays.len() / 2])
+}
+
+/// Short hint like "usually +2 min at this hour"; nothing for punctual lines.
+pub fn delay_hint(typical: i32, locale: Locale) -> Option<String> {
+    if typical <= 0 {
+        return None;
+    }
+    Some(match locale {
+        Locale::En => format!("usually +{typical} min at this hour"),
+        Locale::De => format!("meist +{typical} Min. um diese Uhrzeit"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{day_number, delay_hint, typical_delay, Observation, PunctualityLog, DAY_MS};
+    use crate::efa::Departure;
+    use crate::messages::Locale;
+    use crate::store::Expiring;
+    use chrono::NaiveDate;
+
+    fn obs(day: u32, hour: u8, delay_minutes: i32) -> Observation {
+        Observation { stop_id: "7001004".to_string(), line: "S2".to_string(), day, hour, delay_minutes }
+    }
+
+    #[test]
+    fn median_of_recent_observations_at_the_same_hour() {
+        let history = vec![
+            obs(100, 8, 1),
+            obs(101, 8, 2),
+            obs(102, 8, 2),
+            obs(103, 8, 5),
+            obs(104, 8, 3),
+            obs(104, 9, 20),
+            obs(50, 8, 30),
+        ];
+        assert_eq!(typical_delay(&history, "7001004", "S2", 8, 105), Some(2));
+        assert_eq!(typical_delay(&history, "7001004", "S2", 9, 105), None);
+        assert_eq!(delay_hint(2, Locale::En).as_deref(), Some("usually +2 min at this hour"));
+        assert_eq!(delay_hint(0, Locale::En), None);
+    }
+
+    #[test]
+    fn prune_drops_days_before_the_cutoff() {
+        let mut log = PunctualityLog { observations: vec![obs(99, 8, 1), obs(100, 8, 2), obs(101, 8, 3)] };
+        log.prune(100.5 * DAY_MS);
+        assert_eq!(log.observations, [obs(100, 8, 2), obs(101, 8, 3)]);
+    }
+
+    #[test]
+    fn polling_records_each_line_once_per_hour() {
+        let date = NaiveDate::from_ymd_opt(1970, 4, 11).unwrap();
+        assert_eq!(day_number(date), 100);
+        let s2 = |minute: u32, delay: Option<i32>| Departure {
+            line: "S2".to_string(),
+            planned_time: date.and_hms_opt(8, minute, 0).unwrap(),
+            delay_minutes: delay,
+            ..Default::default()
+        };
+        let mut log = PunctualityLog::default();
+        log.record("7001004", &s2(5, Some(1)));
+        log.record("7001004", &s2(5, Some(3)));
+        log.record("7001004", &s2(15, None));
+        assert_eq!(log.observations, [obs(100, 8, 3)]);
+    }
+}
@@ -0,0 +1,311 @@
+use std::future::Future;
+use std::pin::Pin;
+
+use leptos::ev::MouseEvent;
+use leptos::prelude::*;
+use leptos::task::spawn_local;
+use wasm_bindgen::JsValue;
+
+use crate::diagnostics;
+use crate::efa::{api_usage, stopfinder, EfaError};
+use crate::geo::{self, GeoError, Permission};
+use crate::priority::Usage;
+use crate::storage;
+use crate::tauri::invoke;
+
+// Slower EFA responses still work but make the app feel sluggish.
+const SLOW_MS: f64 = 3000.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    Pass,
+    /// Works, but not as well as it could.
+    Warn,
+    Fail,
+}
+
+/// Outcome of one check.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CheckResult {
+    pub status: Status,
+    pub detail: String,
+    /// What the user can do about a warning or failure.
+    pub remedy: Option<String>,
+}
+
+impl CheckResult {
+    pub fn pass(detail: impl Into<String>) -> Self {
+        CheckResult { status: Status::Pass, detail: detail.into(), remedy: None }
+    }
+
+    pub fn warn(detail: impl Into<String>, remedy: impl Into<String>) -> Self {
+        CheckResult { status: Status::Warn, detail: detail.into(), remedy: Some(remedy.into()) }
+    }
+
+    pub fn fail(detail: impl Into<String>, remedy: impl Into<String>) -> Self {
+        CheckResult { status: Status::Fail, detail: detail.into(), remedy: Some(remedy.into()) }
+    }
+}
+
+pub type CheckFuture<'a> = Pin<Box<dyn Future<Output = CheckResult> + 'a>>;
+
+/// One self-test, e.g. "is the EFA server reachable".
+pub trait Check {
+    /// Name shown on the self-test page.
+    fn name(&self) -> &str;
+    fn run<'a>(&'a self) -> CheckFuture<'a>;
+}
+
+/// The checks run by the self-test page, in display order.
+#[derive(Default)]
+pub struct Registry {
+    checks: Vec<Box<dyn Check>>,
+}
+
+impl Registry {
+    /// Registry with all built-in checks.
+    pub fn standard() -> Self {
+        let mut registry = Registry::default();
+        registry.register(EfaCheck);
+        registry.register(LocationCheck);
+        registry.register(StorageCheck);
+        registry.register(NotificationCheck);
+        registry.register(BudgetCheck);
+        registry
+    }
+
+    pub fn register(&mut self, check: impl Check + 'static) {
+        self.checks.push(Box::new(check));
+    }
+
+    /// Run all checks one after another, returning (name, result) pairs.
+    pub async fn run_all(&self) -> Vec<(String, CheckResult)> {
+        let mut results = Vec::with_capacity(self.checks.len());
+        for check in &self.checks {
+            results.push((check.name().to_string(), check.run().await));
+        }
+        results
+    }
+}
+
+/// EFA server reachability and latency.
+pub struct EfaCheck;
+
+impl Check for EfaCheck {
+    fn name(&self) -> &str {
+        "Timetable server"
+    }
+
+    fn run<'a>(&'a self) -> CheckFuture<'a> {
+        Box::pin(async move {
+            let start = diagnostics::now_ms();
+            let result = stopfinder("Karlsruhe Hbf", 1).await.map(|_| ());
+            efa_result(result, diagnostics::now_ms() - start)
+        })
+    }
+}
+
+fn efa_result(result: Result<(), EfaError>, elapsed_ms: f64) -> CheckResult {
+    let detail = format!("answered in {} ms", elapsed_ms.max(0.0) as u32);
+    match result {
+        Ok(()) if elapsed_ms > SLOW_MS => {
+            CheckResult::warn(detail, "The connection is slow. Departures may take a while to load.")
+        }
+        Ok(()) => CheckResult::pass(detail),
+        Err(EfaError::RateLimited { retry_after }) => CheckResult::warn(
+            format!("asked to slow down for {} s", retry_after.as_secs()),
+            "Refresh less often for a while. The app pauses its requests by itself.",
+        ),
+        Err(EfaError::Network(msg)) => {
+            CheckResult::fail(format!("not reachable: {msg}"), "Check your internet connection and try again.")
+        }
+        Err(e) => CheckResult::fail(e.to_string(), "The timetable service has problems. Try again later."),
+    }
+}
+
+/// State of the location permission.
+pub struct LocationCheck;
+
+impl Check for LocationCheck {
+    fn name(&self) -> &str {
+        "Location access"
+    }
+
+    fn run<'a>(&'a self) -> CheckFuture<'a> {
+        Box::pin(async move { location_result(geo::permission().await) })
+    }
+}
+
+fn location_result(permission: Result<Permission, GeoError>) -> CheckResult {
+    match permission {
+        Ok(Permission::Granted) => CheckResult::pass("granted"),
+        Ok(Permission::Prompt) => {
+            CheckResult::warn("not decided yet", "Use \"Stops near me\" to allow location access.")
+        }
+        Ok(Permission::Denied) => CheckResult::fail(
+            "denied",
+            "Allow location access in the system settings to see nearby stops.",
+        ),
+        Err(e) => CheckResult::fail(e.to_string(), "Restart the app. Nearby stops are unavailable until then."),
+    }
+}
+
+/// Whether settings and favorites can be saved.
+pub struct StorageCheck;
+
+impl Check for StorageCheck {
+    fn name(&self) -> &str {
+        "Storage"
+    }
+
+    fn run<'a>(&'a self) -> CheckFuture<'a> {
+        Box::pin(async move {
+            if storage::writable() {
+                CheckResult::pass("writable")
+            } else {
+                CheckResult::fail(
+                    "not writable",
+                    "Free up space on the device. Until then favorites and settings are lost when the app closes.",
+                )
+            }
+        })
+    }
+}
+
+/// State of the notification permission.
+pub struct NotificationCheck;
+
+impl Check for NotificationCheck {
+    fn name(&self) -> &str {
+        "Notifications"
+    }
+
+    fn run<'a>(&'a self) -> CheckFuture<'a> {
+        Box::pin(async move {
+            let granted = invoke("plugin:notification|is_permission_granted", JsValue::NULL).await;
+            notification_result(granted.ok().and_then(|v| v.as_bool()))
+        })
+    }
+}
+
+fn notification_result(granted: Option<bool>) -> CheckResult {
+    match granted {
+        Some(true) => CheckResult::pass("allowed"),
+        Some(false) => CheckResult::warn(
+            "not allowed",
+            "Allow notifications in the system settings to hear about delays and cancellations.",
+        ),
+        None => CheckResult::fail("could not be checked", "Restart the app and run the self-test again."),
+    }
+}
+
+/// EFA requests of the last hour against the budget.
+pub struct BudgetCheck;
+
+impl Check for BudgetCheck {
+    fn name(&self) -> &str {
+        "Timetable requests"
+    }
+
+    fn run<'a>(&'a self) -> CheckFuture<'a> {
+        Box::pin(async move { budget_result(api_usage()) })
+    }
+}
+
+fn budget_result(usage: Usage) -> CheckResult {
+    let detail = format!("{} of {} per hour used", usage.used, usage.per_hour);
+    if usage.used > usage.per_hour {
+        CheckResult::fail(detail, "Refresh less often, or the timetable service may block this device for a while.")
+    } else if usage.used * 5 >= usage.per_hour * 4 {
+        CheckResult::warn(detail, "Close views you don't need to stay within the budget.")
+    } else {
+        CheckResult::pass(detail)
+    }
+}
+
+/// Self-test page: runs the standard checks and lists the results.
+#[component]
+pub fn SelfTestPanel() -> impl IntoView {
+    let (results, set_results) = signal(Vec::<(String, CheckResult)>::new());
+    let (running, set_running) = signal(false);
+    let run = move |_: MouseEvent| {
+        set_running.set(true);
+        spawn_local(async move {
+            set_results.set(Registry::standard().run_all().await);
+            set_running.set(false);
+        });
+    };
+    view! {
+        <div class="selftest">
+            <button on:click=run disabled=move || running.get()>
+                { move || if running.get() { "Running…" } else { "Run self-test" } }
+            </button>
+            <ul>
+                { move || results.get().into_iter().map(|(name, result)| {
+                    let (class, mark) = match result.status {
+                        Status::Pass => ("pass", "✓"),
+                        Status::Warn => ("warn", "!"),
+                        Status::Fail => ("fail", "✗"),
+                    };
+                    view! {
+                        <li class=class>
+                            <strong>{ format!("{mark} {name}") }</strong>
+                            " " { result.detail }
+                            { result.remedy.map(|r| view! { <p class="remedy">{ r }</p> }) }
+                        </li>
+                    }
+                }).collect::<Vec<_>>() }
+            </ul>
+        </div>
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{budget_result, efa_result, location_result, notification_result, Check, CheckFuture, CheckResult, Registry, Status};
+    use crate::efa::EfaError;
+    use crate::geo::{GeoError, Permission};
+    use crate::priority::Usage;
+
+    struct Fixed(&'static str, Status);
+
+    impl Check for Fixed {
+        fn name(&self) -> &str {
+            self.0
+        }
+
+        fn run<'a>(&'a self) -> CheckFuture<'a> {
+            let result = CheckResult { status: self.1, detail: String::new(), remedy: None };
+            Box::pin(async move { result })
+        }
+    }
+
+    #[tokio::test]
+    async fn registry_runs_checks_in_order() {
+        let mut registry = Registry::default();
+        registry.register(Fixed("first", Status::Pass));
+        registry.register(Fixed("second", Status::Fail));
+        let results = registry.run_all().await;
+        let rows: Vec<_> = results.iter().map(|(name, r)| (name.as_str(), r.status)).collect();
+        assert_eq!(rows, [("first", Status::Pass), ("second", Status::Fail)]);
+    }
+
+    #[test]
+    fn results_come_with_remedies() {
+        assert_eq!(efa_result(Ok(()), 120.4), CheckResult::pass("answered in 120 ms"));
+        assert_eq!(efa_result(Ok(()), 4500.0).status, Status::Warn);
+        let offline = efa_result(Err(EfaError::Network("timeout".to_string())), 10.0);
+        assert_eq!(offline.status, Status::Fail);
+        assert!(offline.remedy.is_some_and(|r| r.contains("internet")));
+
+        assert_eq!(location_result(Ok(Permission::Granted)).status, Status::Pass);
+        assert_eq!(location_result(Ok(Permission::Prompt)).status, Status::Warn);
+        assert_eq!(location_result(Err(GeoError::Denied)).status, Status::Fail);
+        assert_eq!(notification_result(Some(false)).status, Status::Warn);
+        assert_eq!(notification_result(None).status, Status::Fail);
+
+        assert_eq!(budget_result(Usage { used: 10, per_hour: 600 }).detail, "10 of 600 per hour used");
+        assert_eq!(budget_result(Usage { used: 480, per_hour: 600 }).status, Status::Warn);
+        assert_eq!(budget_result(Usage { used: 601, per_hour: 600 }).status, Status::Fail);
+    }
+}
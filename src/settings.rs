@@ -0,0 +1,117 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::efa::{BoardFormat, RetryPolicy};
+use crate::hafas::HafasProfile;
+use crate::messages::Locale;
+use crate::priority::DEFAULT_BUDGET_PER_HOUR;
+use crate::provider::Backend;
+use crate::store::Slice;
+use crate::tz::TimeDisplay;
+
+/// User preferences.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Look up nearby stops on launch (only if location access is already granted).
+    pub auto_nearby_on_launch: bool,
+    /// EFA requests per hour before the app warns about its usage.
+    pub api_budget_per_hour: u32,
+    pub retention: Retention,
+    /// Look up addresses EFA doesn't know with OpenStreetMap's Nominatim.
+    pub nominatim_fallback: bool,
+    /// Language of directions and disruption messages from the KVV.
+    pub language: Locale,
+    /// Clock board times are shown in.
+    pub time_display: TimeDisplay,
+    /// Keep KVV's timetable on the device to show planned departures while
+    /// EFA can't be reached. Downloads tens of megabytes on every launch.
+    pub offline_timetable: bool,
+    /// GTFS-Realtime TripUpdates feed with the delays of that timetable;
+    /// empty for planned times only.
+    pub realtime_feed_url: String,
+    /// Mark delays with a shape as well as a color (color-blind-safe).
+    pub delay_shapes: bool,
+    /// Keep the delays seen on boards (see `PunctualityLog`) to hint at
+    /// lines that are usually late.
+    pub punctuality_hints: bool,
+    /// Template of a text ticker under the board, e.g. "{line} {dir} {cd}m"
+    /// (see `Template`); empty for none.
+    pub ticker: String,
+    /// Estimate how long planned trips take by bike, next to the journeys.
+    pub bike_comparison: bool,
+    /// Root of an OSRM-compatible routing service for those estimates, e.g.
+    /// "https://routing.example.org/"; empty estimates from the distance.
+    pub bike_routing_url: String,
+    /// Where stops and boards come from.
+    pub backend: Backend,
+    /// HAFAS endpoint regional trains come from while EFA can't be reached,
+    /// see `RegionalFallback`; `None` to show the last board instead.
+    pub hafas_fallback: Option<HafasProfile>,
+    /// Format departure boards are fetched in; JSON responses are smaller.
+    pub board_format: BoardFormat,
+    /// Seconds to wait for an answer from KVV's server.
+    pub request_timeout_secs: u32,
+    /// Tries per request in all while the network keeps dropping it.
+    pub request_attempts: u32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            auto_nearby_on_launch: false,
+            api_budget_per_hour: DEFAULT_BUDGET_PER_HOUR,
+            retention: Retention::default(),
+            nominatim_fallback: false,
+            language: Locale::default(),
+            time_display: TimeDisplay::default(),
+            offline_timetable: false,
+            realtime_feed_url: String::new(),
+            delay_shapes: false,
+            punctuality_hints: false,
+            ticker: String::new(),
+            bike_comparison: false,
+            bike_routing_url: String::new(),
+            backend: Backend::default(),
+            hafas_fallback: None,
+            board_format: BoardFormat::default(),
+            request_timeout_secs: RetryPolicy::default().timeout.as_secs() as u32,
+            request_attempts: RetryPolicy::default().max_attempts,
+        }
+    }
+}
+
+impl Settings {
+    /// How requests to KVV's server time out and are tried again.
+    pub fn retry_policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            timeout: Duration::from_secs(self.request_timeout_secs.max(1).into()),
+            max_attempts: self.request_attempts.max(1),
+            ..RetryPolicy::default()
+        }
+    }
+}
+
+/// Days to keep locally recorded data before it is deleted; `None` keeps it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Retention {
+    /// Which stops were used when (drives the popularity ranking).
+    pub history_days: Option<u32>,
+    /// Observed delays behind the "usually late" hints.
+    pub punctuality_days: Option<u32>,
+    /// Request and warning log for problem reports.
+    pub diagnostics_days: Option<u32>,
+}
+
+impl Default for Retention {
+    fn default() -> Self {
+        Retention { history_days: Some(30), punctuality_days: Some(30), diagnostics_days: Some(30) }
+    }
+}
+
+impl Slice for Settings {
+    const KEY: &'static str = "kvv.settings";
+    const VERSION: u32 = 1;
+}
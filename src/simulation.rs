@@ -0,0 +1,241 @@
+use chrono::{NaiveDateTime, TimeDelta, Timelike};
+
+use crate::efa::{Departure, StopSuggestion, TransportMode};
+use crate::provider::{ProviderFuture, TransitProvider};
+use crate::tz;
+
+// Seed of the demo mode, so its boards look the same on every launch.
+const DEMO_SEED: u64 = 1;
+
+/// Small deterministic PRNG (splitmix64) so simulated boards are reproducible.
+#[derive(Clone, Debug)]
+pub struct SimRng(u64);
+
+impl SimRng {
+    pub fn new(seed: u64) -> Self {
+        SimRng(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform value in `0..n` (`n` must be non-zero).
+    pub fn below(&mut self, n: u32) -> u32 {
+        (self.next_u64() % n as u64) as u32
+    }
+}
+
+/// What happens to a line once a scripted event fires.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SimAction {
+    /// All following departures of the line are late by this many minutes.
+    Delay(u32),
+    /// The next departure of the line is cancelled and shown as such.
+    Cancel,
+}
+
+/// A scenario step, e.g. "S1 gets cancelled at T+60s".
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScriptedEvent {
+    pub at_secs: u64,
+    pub line: String,
+    pub action: SimAction,
+}
+
+/// Lines served by simulated stops: (line, direction, headway in minutes).
+const LINES: &[(&str, &str, u32)] = &[
+    ("S1", "Hochstetten", 20),
+    ("S1", "Bad Herrenalb", 20),
+    ("S2", "Spöck", 20),
+    ("S2", "Rheinstetten", 20),
+    ("1", "Durlach", 10),
+    ("2", "Wolfartsweier", 10),
+    ("4", "Waldstadt", 10),
+    ("5", "Rheinhafen", 10),
+];
+
+/// Stops the stop search of the simulation finds, as (id, name).
+const STOPS: &[(&str, &str)] = &[
+    ("7001004", "ZKM"),
+    ("7001011", "Europaplatz/Postgalerie (U)"),
+    ("7001012", "Kolpingplatz"),
+];
+
+/// Generates plausible departures without touching the network.
+#[derive(Clone, Debug)]
+pub struct Simulation {
+    seed: u64,
+    start: NaiveDateTime,
+    script: Vec<ScriptedEvent>,
+}
+
+impl Simulation {
+    /// `start` is the simulated wall clock at T+0.
+    pub fn new(seed: u64, start: NaiveDateTime) -> Self {
+        Simulation { seed, start, script: Vec::new() }
+    }
+
+    /// The demo scenario: S1 is cancelled a minute after `start`, and S2
+    /// runs five minutes late from two minutes on.
+    pub fn demo(start: NaiveDateTime) -> Self {
+        Simulation::new(DEMO_SEED, start).with_event(60, "S1", SimAction::Cancel).with_event(120, "S2", SimAction::Delay(5))
+    }
+
+    pub fn with_event(mut self, at_secs: u64, line: &str, action: SimAction) -> Self {
+        self.script.push(ScriptedEvent { at_secs, line: line.to_string(), action });
+        self
+    }
+
+    /// Departure board for `station_id`, `elapsed_secs` after the simulation started.
+    pub fn departures(&self, station_id: &str, elapsed_secs: u64, max: usize) -> Vec<Departure> {
+        // Minutes since midnight of the start day.
+        let now = self.start.num_seconds_from_midnight() / 60 + (elapsed_secs / 60) as u32;
+        let midnight = self.start - TimeDelta::seconds(self.start.num_seconds_from_midnight() as i64);
+        let at = |minute: u32| midnight + TimeDelta::minutes(minute as i64);
+        let mut rng = SimRng::new(self.seed ^ fnv1a(station_id));
+        let mut rows: Vec<(u32, Departure)> = Vec::new();
+
+        for (line, direction, headway) in LINES {
+            // Not every stop is served by every line.
+            if rng.below(3) == 0 {
+                continue;
+            }
+            let offset = rng.below(*headway);
+            let first = now - now % headway + offset;
+            let fired: Vec<&ScriptedEvent> = self
+                .script
+                .iter()
+                .filter(|ev| ev.line == *line && ev.at_secs <= elapsed_secs)
+                .collect();
+            let scripted_delay: u32 = fired
+                .iter()
+                .map(|ev| match ev.action {
+                    SimAction::Delay(min) => min,
+                    SimAction::Cancel => 0,
+                })
+                .sum();
+            let mut cancelled = fired.iter().filter(|ev| ev.action == SimAction::Cancel).count();
+
+            for i in 0..4 {
+                let planned = first + i * headway;
+                let jitter = if rng.below(4) == 0 { 1 + rng.below(4) } else { 0 };
+                let delay = jitter + scripted_delay;
+                if planned + delay < now {
+                    continue;
+                }
+                let is_cancelled = cancelled > 0;
+                cancelled = cancelled.saturating_sub(1);
+                let realtime = planned + delay;
+                rows.push((
+                    realtime,
+                    Departure {
+                        line: line.to_string(),
+                        direction: Some(direction.to_string()),
+                        time: at(realtime),
+                        planned_time: at(planned),
+                        realtime_time: Some(at(realtime)),
+                        mode: if line.starts_with('S') { TransportMode::SBahn } else { TransportMode::Tram },
+                        platform: None,
+                        delay_minutes: Some(delay as i32),
+                        cancelled: is_cancelled,
+                        ..Default::default()
+                    },
+                ));
+            }
+        }
+
+        rows.sort_by_key(|(minute, _)| *minute);
+        rows.into_iter().take(max).map(|(_, dep)| dep).collect()
+    }
+}
+
+impl TransitProvider for Simulation {
+    fn name(&self) -> &str {
+        "Simulation"
+    }
+
+    fn stopfinder<'a>(&'a self, query: &'a str, max: usize) -> ProviderFuture<'a, Vec<StopSuggestion>> {
+        let query = query.to_lowercase();
+        let stops = STOPS
+            .iter()
+            .filter(|(_, name)| name.to_lowercase().contains(&query))
+            .take(max)
+            .map(|(id, name)| StopSuggestion { id: id.to_string(), name: name.to_string(), place: Some("Karlsruhe".to_string()), ..Default::default() })
+            .collect();
+        Box::pin(async move { Ok(stops) })
+    }
+
+    /// The board at `when` on the simulated clock, which runs from `start`.
+    fn departures<'a>(&'a self, station_id: &'a str, max: usize, when: Option<NaiveDateTime>) -> ProviderFuture<'a, Vec<Departure>> {
+        let elapsed = (when.unwrap_or_else(tz::now) - self.start).num_seconds().max(0) as u64;
+        let board = Simulation::departures(self, station_id, elapsed, max);
+        Box::pin(async move { Ok(board) })
+    }
+}
+
+fn fnv1a(s: &str) -> u64 {
+    s.bytes().fold(0xcbf2_9ce4_8422_2325, |h, b| (h ^ b as u64).wrapping_mul(0x0100_0000_01b3))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SimAction, Simulation};
+    use crate::provider::TransitProvider;
+    use chrono::{NaiveDate, NaiveDateTime, TimeDelta};
+
+    fn eight_am() -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 1, 1).unwrap().and_hms_opt(8, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn same_seed_yields_same_board() {
+        let a = Simulation::new(42, eight_am()).departures("7001004", 0, 10);
+        let b = Simulation::new(42, eight_am()).departures("7001004", 0, 10);
+        assert!(!a.is_empty());
+        assert_eq!(a, b);
+        assert!(a.windows(2).all(|w| w[0].time <= w[1].time));
+    }
+
+    #[test]
+    fn scripted_delay_applies_after_its_time() {
+        let sim = Simulation::new(3, eight_am()).with_event(60, "S2", SimAction::Delay(5));
+        let after = sim.departures("7001004", 90, 50);
+        let s2: Vec<_> = after.iter().filter(|d| d.line == "S2").collect();
+        assert!(!s2.is_empty());
+        assert!(s2.iter().all(|d| (d.time - d.planned_time).num_minutes() >= 5));
+        let before = sim.departures("7001004", 30, 50);
+        assert!(before.iter().any(|d| d.line == "S2" && d.time == d.planned_time));
+    }
+
+    #[test]
+    fn scripted_cancellation_marks_the_next_departure() {
+        let base = Simulation::new(3, eight_am());
+        let cancel = base.clone().with_event(60, "1", SimAction::Cancel);
+        let line_1 = |sim: &Simulation| {
+            sim.departures("7000001", 61, 100)
+                .into_iter()
+                .filter(|d| d.line == "1" && !d.cancelled)
+                .map(|d| d.planned_time)
+                .collect::<Vec<_>>()
+        };
+        let before = line_1(&base);
+        assert!(!before.is_empty());
+        assert_eq!(line_1(&cancel), before[1..].to_vec());
+    }
+
+    #[tokio::test]
+    async fn demo_finds_stops_and_cancels_s1_after_a_minute() {
+        let demo = Simulation::demo(eight_am());
+        let found = TransitProvider::stopfinder(&demo, "europa", 5).await.expect("simulated");
+        assert_eq!(found[0].id, "7001011");
+        let board = |secs| TransitProvider::departures(&demo, "7001011", 50, Some(eight_am() + TimeDelta::seconds(secs)));
+        let cancelled = |board: Vec<crate::efa::Departure>| board.iter().any(|d| d.line == "S1" && d.cancelled);
+        assert!(!cancelled(board(30).await.expect("simulated")));
+        assert!(cancelled(board(90).await.expect("simulated")));
+    }
+}
@@ -0,0 +1,141 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::efa::{parse_coords, parse_stop_point, server_message, Coord, EfaClient, EfaError, EfaRequest};
+
+/// What EFA knows about a stop's accessibility; `None` where it doesn't say.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Accessibility {
+    /// Platforms can be reached without steps (lift, ramp or level access).
+    pub step_free: Option<bool>,
+    /// Tactile paving guides to the platforms.
+    pub tactile_guidance: Option<bool>,
+}
+
+/// A single stop, for the stop detail page and map markers.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StopDetails {
+    pub id: String,
+    pub name: String,
+    pub place: Option<String>,
+    pub coord: Option<Coord>,
+    pub accessibility: Accessibility,
+}
+
+/// Details of a KVV stop.
+pub async fn stop_details(station_id: &str) -> Result<Option<StopDetails>, EfaError> {
+    EfaClient::kvv().stop_details(station_id).await
+}
+
+impl EfaClient {
+    /// Details of the stop with id `station_id`, or `None` if EFA doesn't know it.
+    pub async fn stop_details(&self, station_id: &str) -> Result<Option<StopDetails>, EfaError> {
+        let body = self.fetch_request(&StopRequest { station_id }).await?;
+        parse_stop_json(&body, station_id)
+    }
+}
+
+/// Lookup of one stop by id (XML_STOPFINDER_REQUEST with `type_sf=stopID`).
+pub(crate) struct StopRequest<'a> {
+    pub station_id: &'a str,
+}
+
+impl EfaRequest for StopRequest<'_> {
+    fn endpoint(&self) -> &'static str {
+        "XML_STOPFINDER_REQUEST"
+    }
+
+    fn to_params(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("outputFormat", "JSON".to_string()),
+            ("locationServerActive", "1".to_string()),
+            ("type_sf", "stopID".to_string()),
+            ("name_sf", self.station_id.to_string()),
+        ]
+    }
+}
+
+// "1"/"0" flags in the point's attribute list.
+fn flag(attrs: &[Value], name: &str) -> Option<bool> {
+    let value = attrs.iter().find(|a| a.get("name").and_then(|n| n.as_str()) == Some(name))?.get("value")?;
+    match value.as_str()? {
+        "1" | "true" => Some(true),
+        "0" | "false" => Some(false),
+        _ => None,
+    }
+}
+
+fn parse_details(point: &Value) -> Option<StopDetails> {
+    let stop = parse_stop_point(point)?;
+    let coord = point.get("ref").and_then(|r| r.get("coords")).and_then(|c| c.as_str()).and_then(parse_coords);
+    let attrs = point.get("attrs").and_then(|a| a.as_array()).map(Vec::as_slice).unwrap_or_default();
+    let accessibility = Accessibility {
+        step_free: flag(attrs, "STOP_STEP_FREE_ACCESS"),
+        tactile_guidance: flag(attrs, "STOP_TACTILE_GUIDANCE"),
+    };
+    Some(StopDetails { id: stop.id, name: stop.name, place: stop.place, coord, accessibility })
+}
+
+fn parse_stop_json(body: &str, station_id: &str) -> Result<Option<StopDetails>, EfaError> {
+    let json: Value = serde_json::from_str(body).map_err(|e| EfaError::Parse(e.to_string()))?;
+    // A unique hit comes as {"point": {..}}, several as an array.
+    let points = match json.get("stopFinder").and_then(|sf| sf.get("points")) {
+        Some(Value::Array(points)) => points.iter().collect(),
+        Some(Value::Object(map)) => map.get("point").into_iter().collect(),
+        _ => Vec::new(),
+    };
+    let stops: Vec<StopDetails> = points.into_iter().filter_map(parse_details).collect();
+    if stops.is_empty() {
+        return match server_message(&json) {
+            Some(msg) => Err(EfaError::ServerMessage(msg)),
+            None => Ok(None),
+        };
+    }
+    Ok(stops.into_iter().find(|s| s.id == station_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_stop_json, Accessibility, StopRequest};
+    use crate::efa::{Coord, EfaClient, EfaRequest, HttpTransport};
+    use wiremock::matchers::{method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    const STOP_JSON: &str = include_str!("../testdata/stop.json");
+
+    #[test]
+    fn parses_coordinates_and_accessibility() {
+        let stop = parse_stop_json(STOP_JSON, "7001011").expect("parse succeeds").expect("stop found");
+        assert_eq!(stop.name, "Karlsruhe, Europaplatz/Postgalerie (U)");
+        assert_eq!(stop.place.as_deref(), Some("Karlsruhe"));
+        assert_eq!(stop.coord, Some(Coord { lat: 49.00992, lon: 8.39414 }));
+        assert_eq!(stop.accessibility, Accessibility { step_free: Some(true), tactile_guidance: Some(false) });
+
+        assert_eq!(parse_stop_json(STOP_JSON, "7000090"), Ok(None));
+    }
+
+    #[test]
+    fn stop_request_parameters() {
+        let params = StopRequest { station_id: "7001011" }.to_params();
+        let params: Vec<_> = params.iter().map(|(k, v)| (*k, v.as_str())).collect();
+        assert_eq!(
+            params,
+            [("outputFormat", "JSON"), ("locationServerActive", "1"), ("type_sf", "stopID"), ("name_sf", "7001011")]
+        );
+    }
+
+    #[tokio::test]
+    async fn stop_details_looks_up_the_id() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/XML_STOPFINDER_REQUEST"))
+            .and(query_param("type_sf", "stopID"))
+            .and(query_param("name_sf", "7001011"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(STOP_JSON))
+            .mount(&server)
+            .await;
+
+        let stop = EfaClient::new(server.uri(), HttpTransport).stop_details("7001011").await.expect("stop_details succeeds");
+        assert_eq!(stop.map(|s| s.id).as_deref(), Some("7001011"));
+    }
+}
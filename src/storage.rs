@@ -0,0 +1,28 @@
+use leptos::web_sys;
+
+fn local_storage() -> Option<web_sys::Storage> {
+    web_sys::window()?.local_storage().ok()?
+}
+
+/// Raw value stored under `key`.
+pub fn get(key: &str) -> Option<String> {
+    local_storage()?.get_item(key).ok()?
+}
+
+/// Store `value` under `key`. Without local storage (e.g. private browsing)
+/// state simply lives for this session only.
+pub fn set(key: &str, value: &str) {
+    if let Some(storage) = local_storage() {
+        let _ = storage.set_item(key, value);
+    }
+}
+
+/// Whether values can actually be saved, by writing and removing a probe key.
+#[cfg(feature = "selftest")]
+pub fn writable() -> bool {
+    const PROBE: &str = "kvv.probe";
+    let Some(storage) = local_storage() else { return false };
+    let ok = storage.set_item(PROBE, "1").is_ok() && storage.get_item(PROBE).ok().flatten().as_deref() == Some("1");
+    let _ = storage.remove_item(PROBE);
+    ok
+}
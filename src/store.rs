@@ -0,0 +1,153 @@
+use std::time::Duration;
+
+use leptos::prelude::*;
+
+use crate::chime::Chimes;
+use crate::columns::ColumnLayouts;
+use crate::dashboards::Dashboards;
+use crate::diagnostics;
+use crate::favorites::Favorites;
+use crate::geo::Geofences;
+use crate::migrations::{self, encode, quarantine_key};
+pub use crate::migrations::Slice;
+use crate::onboarding::Onboarding;
+use crate::operators::OperatorFilter;
+use crate::pinning::PinStore;
+use crate::platforms::PlatformLayout;
+use crate::popularity::Popularity;
+use crate::punctuality::PunctualityLog;
+use crate::settings::Settings;
+use crate::storage;
+use crate::undo::{Command, UndoStack};
+
+/// Recorded data that must not be kept longer than the user allows.
+pub trait Expiring {
+    /// Drop everything recorded before `cutoff_ms` (ms since the epoch).
+    fn prune(&mut self, cutoff_ms: f64);
+}
+
+const CLEANUP_EVERY: Duration = Duration::from_secs(60 * 60);
+const DAY_MS: f64 = 24.0 * 60.0 * 60.0 * 1000.0;
+
+/// `now_ms` minus `days`, or `None` if data is kept forever.
+pub fn retention_cutoff(days: Option<u32>, now_ms: f64) -> Option<f64> {
+    days.map(|d| now_ms - d as f64 * DAY_MS)
+}
+
+/// All persisted app state. Features read and update the slices; the store
+/// writes every change back to local storage.
+#[derive(Clone, Copy)]
+pub struct Store {
+    pub settings: RwSignal<Settings>,
+    pub chimes: RwSignal<Chimes>,
+    pub onboarding: RwSignal<Onboarding>,
+    pub favorites: RwSignal<Favorites>,
+    pub geofences: RwSignal<Geofences>,
+    pub dashboards: RwSignal<Dashboards>,
+    pub pins: RwSignal<PinStore>,
+    pub platform_layout: RwSignal<PlatformLayout>,
+    pub columns: RwSignal<ColumnLayouts>,
+    pub operator_filter: RwSignal<OperatorFilter>,
+    pub popularity: RwSignal<Popularity>,
+    pub punctuality: RwSignal<PunctualityLog>,
+    /// Session-only history of destructive commands.
+    pub undo: RwSignal<UndoStack>,
+}
+
+impl Store {
+    /// Load all slices and provide the store as context to the component tree.
+    pub fn provide() -> Self {
+        let store = Store {
+            settings: persisted(),
+            chimes: persisted(),
+            onboarding: persisted(),
+            favorites: persisted(),
+            geofences: persisted(),
+            dashboards: persisted(),
+            pins: persisted(),
+            platform_layout: persisted(),
+            columns: persisted(),
+            operator_filter: persisted(),
+            popularity: persisted(),
+            punctuality: persisted(),
+            undo: RwSignal::new(UndoStack::default()),
+        };
+        provide_context(store);
+        // Cleanup job: on launch, whenever the limits change and hourly for
+        // apps left open.
+        Effect::new(move |_| {
+            store.settings.track();
+            store.enforce_retention(diagnostics::now_ms());
+        });
+        set_interval(move || store.enforce_retention(diagnostics::now_ms()), CLEANUP_EVERY);
+        store
+    }
+
+    /// Delete recorded data older than the retention settings allow.
+    pub fn enforce_retention(&self, now_ms: f64) {
+        let retention = self.settings.with_untracked(|s| s.retention);
+        if let Some(cutoff) = retention_cutoff(retention.history_days, now_ms) {
+            prune_slice(self.popularity, cutoff);
+        }
+        if let Some(cutoff) = retention_cutoff(retention.punctuality_days, now_ms) {
+            prune_slice(self.punctuality, cutoff);
+        }
+        if let Some(cutoff) = retention_cutoff(retention.diagnostics_days, now_ms) {
+            diagnostics::prune(cutoff);
+        }
+    }
+
+    /// Apply a destructive command so it can be undone.
+    pub fn execute(&self, command: Command) {
+        command.apply(self);
+        self.undo.update(|u| u.record(command));
+    }
+
+    /// Revert the last command; returns it for the snackbar.
+    pub fn undo(&self) -> Option<Command> {
+        let mut command = None;
+        self.undo.update(|u| command = u.take_undo());
+        command.inspect(|c| c.revert(self))
+    }
+
+    /// Apply the last undone command again.
+    pub fn redo(&self) -> Option<Command> {
+        let mut command = None;
+        self.undo.update(|u| command = u.take_redo());
+        command.inspect(|c| c.apply(self))
+    }
+}
+
+// Only touch (and so re-persist) the slice if something actually expired.
+fn prune_slice<T: Slice + Expiring + PartialEq>(slice: RwSignal<T>, cutoff_ms: f64) {
+    let mut pruned = slice.get_untracked();
+    pruned.prune(cutoff_ms);
+    if slice.with_untracked(|current| *current != pruned) {
+        slice.set(pruned);
+    }
+}
+
+/// The store provided by `Store::provide`.
+pub fn use_store() -> Store {
+    expect_context::<Store>()
+}
+
+fn persisted<T: Slice>() -> RwSignal<T> {
+    let initial = migrations::load(storage::get(T::KEY), |raw, error| {
+        diagnostics::record_warning(format!("{}: {error}; starting fresh", T::KEY));
+        // Keep the first unreadable value: later ones only hold what was
+        // set up after starting fresh.
+        let key = quarantine_key(T::KEY);
+        if storage::get(&key).is_none() {
+            storage::set(&key, &raw);
+        }
+    });
+    let slice = RwSignal::new(initial);
+    // Persistence middleware: runs once on load (storing migrated data) and on every change.
+    Effect::new(move |_| {
+        if let Some(raw) = slice.with(encode) {
+            storage::set(T::KEY, &raw);
+        }
+    });
+    slice
+}
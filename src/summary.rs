@@ -0,0 +1,110 @@
+use chrono::NaiveDateTime;
+
+use crate::efa::Departure;
+
+/// Traffic-light state of a stop at a glance.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Light {
+    Green,
+    Yellow,
+    Red,
+}
+
+impl Light {
+    /// CSS class carrying the light's color, see styles.css.
+    pub fn class(self) -> &'static str {
+        match self {
+            Light::Green => "light-green",
+            Light::Yellow => "light-yellow",
+            Light::Red => "light-red",
+        }
+    }
+}
+
+/// Compact status of a stop for overview lists.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StopSummary {
+    /// Minutes until the next departure, if any is on the board.
+    pub next_in_minutes: Option<u32>,
+    /// Largest delay on the board in minutes.
+    pub worst_delay: u32,
+    pub disrupted: bool,
+    pub light: Light,
+}
+
+impl StopSummary {
+    /// One line for the overview, e.g. "in 7 min · +4".
+    pub fn label(&self) -> String {
+        let mut parts = vec![match self.next_in_minutes {
+            Some(0) => "now".to_string(),
+            Some(minutes) => format!("in {minutes} min"),
+            None => "no departures".to_string(),
+        }];
+        if self.worst_delay > 0 {
+            parts.push(format!("+{}", self.worst_delay));
+        }
+        if self.disrupted {
+            parts.push("disrupted".to_string());
+        }
+        parts.join(" · ")
+    }
+}
+
+// Delays at or above these thresholds turn the light yellow / red.
+const YELLOW_DELAY: u32 = 3;
+const RED_DELAY: u32 = 10;
+
+/// Summarize a departure board at `now`; `disrupted` tells whether a
+/// disruption affects the stop.
+pub fn summarize(departures: &[Departure], now: NaiveDateTime, disrupted: bool) -> StopSummary {
+    let next_in_minutes = departures
+        .iter()
+        .filter(|d| !d.cancelled)
+        .filter_map(|d| u32::try_from((d.time - now).num_minutes()).ok())
+        .min();
+
+    let worst_delay = departures
+        .iter()
+        .filter(|d| !d.cancelled)
+        .filter_map(|d| d.delay_minutes)
+        .map(|d| d.max(0) as u32)
+        .max()
+        .unwrap_or(0);
+
+    let light = if disrupted || worst_delay >= RED_DELAY {
+        Light::Red
+    } else if worst_delay >= YELLOW_DELAY {
+        Light::Yellow
+    } else {
+        Light::Green
+    };
+
+    StopSummary { next_in_minutes, worst_delay, disrupted, light }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{summarize, Light};
+    use crate::fixtures::{at, delayed};
+    use chrono::TimeDelta;
+
+    #[test]
+    fn summarizes_countdown_and_worst_delay() {
+        let deps = [delayed("S2", at("08:05"), Some(at("08:09"))), delayed("S2", at("08:10"), None)];
+        let s = summarize(&deps, at("08:02"), false);
+        assert_eq!(s.next_in_minutes, Some(7));
+        assert_eq!(s.worst_delay, 4);
+        assert_eq!(s.light, Light::Yellow);
+        assert_eq!(s.label(), "in 7 min · +4");
+    }
+
+    #[test]
+    fn handles_midnight_and_disruptions() {
+        let deps = [delayed("S2", at("23:58"), Some(at("00:03") + TimeDelta::days(1)))];
+        let s = summarize(&deps, at("23:55"), true);
+        assert_eq!(s.next_in_minutes, Some(8));
+        assert_eq!(s.worst_delay, 5);
+        assert_eq!(s.light, Light::Red);
+        assert_eq!(summarize(&[], at("00:00"), false).next_in_minutes, None);
+    }
+}
@@ -0,0 +1,120 @@
+use std::fmt;
+
+use chrono::NaiveDateTime;
+
+use crate::columns::Column;
+use crate::efa::Departure;
+
+/// A line of text with placeholders for departure data, e.g.
+/// `"{line} {dir} {cd}m"`, for tickers and text exports. Placeholders are the
+/// column names of `Column::placeholder`; `{{` and `}}` are literal braces.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Template(Vec<Piece>);
+
+#[derive(Clone, Debug, PartialEq)]
+enum Piece {
+    Text(String),
+    Value(Column),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TemplateError {
+    /// `{name}` that is no known placeholder.
+    Unknown(String),
+    /// A `{` without its `}`.
+    Unclosed,
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TemplateError::Unknown(name) => write!(f, "unknown placeholder {{{name}}}"),
+            TemplateError::Unclosed => write!(f, "missing }}"),
+        }
+    }
+}
+
+impl Template {
+    pub fn parse(source: &str) -> Result<Template, TemplateError> {
+        let mut pieces = Vec::new();
+        let mut text = String::new();
+        let mut chars = source.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => return Err(TemplateError::Unclosed),
+                        }
+                    }
+                    let column = Column::from_placeholder(name.trim()).ok_or(TemplateError::Unknown(name))?;
+                    if !text.is_empty() {
+                        pieces.push(Piece::Text(std::mem::take(&mut text)));
+                    }
+                    pieces.push(Piece::Value(column));
+                }
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            pieces.push(Piece::Text(text));
+        }
+        Ok(Template(pieces))
+    }
+
+    pub fn render(&self, dep: &Departure, now: NaiveDateTime) -> String {
+        self.0
+            .iter()
+            .map(|piece| match piece {
+                Piece::Text(text) => text.clone(),
+                Piece::Value(column) => column.value(dep, now),
+            })
+            .collect()
+    }
+
+    /// One line per departure, e.g. for a text export of a board.
+    pub fn render_board(&self, deps: &[Departure], now: NaiveDateTime) -> String {
+        deps.iter().map(|d| self.render(d, now)).collect::<Vec<_>>().join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Template, TemplateError};
+    use crate::efa::Departure;
+    use chrono::{NaiveDate, NaiveDateTime};
+
+    fn at(hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 1, 1).unwrap().and_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn templates_fill_in_departure_data() {
+        let dep = |line: &str, dir: &str, minute| Departure {
+            line: line.to_string(),
+            direction: Some(dir.to_string()),
+            time: at(8, minute),
+            planned_time: at(8, minute),
+            ..Default::default()
+        };
+        let deps = [dep("S2", "Spöck", 5), dep("2", "Wolfartsweier", 12)];
+        let ticker = Template::parse("{line} {dir} {cd}m").expect("valid template");
+        assert_eq!(ticker.render_board(&deps, at(8, 1)), "S2 Spöck 4m\n2 Wolfartsweier 11m");
+        let braces = Template::parse("{{{ time }}} {platform}|").expect("valid template");
+        assert_eq!(braces.render(&deps[0], at(8, 1)), "{08:05} |");
+
+        assert_eq!(Template::parse("{line} {via}"), Err(TemplateError::Unknown("via".to_string())));
+        assert_eq!(Template::parse("{line"), Err(TemplateError::Unclosed));
+    }
+}
@@ -0,0 +1,370 @@
+use std::rc::Rc;
+
+use chrono::{DateTime, NaiveDateTime};
+use efa_core::{berlin, berlin_offset, decode_text, CallingPoint};
+use quick_xml::escape::escape;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+
+use crate::efa::{post_text_with, Departure, EfaError, RetryPolicy, StopSuggestion, Transport, TransportMode};
+use crate::provider::{ProviderFuture, TransitProvider};
+use crate::tz;
+
+const CONTENT_TYPE: &str = "text/xml; charset=utf-8";
+
+/// Client for a TRIAS (VDV 431) interface, which several Verbünde offer
+/// instead of or next to EFA. Answers the same questions as `EfaClient`
+/// through `TransitProvider`; stop ids are the DHIDs TRIAS uses, e.g.
+/// "de:08212:1004".
+#[derive(Clone)]
+pub struct TriasClient {
+    url: String,
+    requestor_ref: String,
+    language: String,
+    retry: RetryPolicy,
+    transport: Rc<dyn Transport>,
+}
+
+impl TriasClient {
+    /// Client for the TRIAS endpoint `url`, identifying as `requestor_ref`,
+    /// the access key the operator hands out, over `transport`.
+    pub fn new(url: impl Into<String>, requestor_ref: impl Into<String>, transport: impl Transport + 'static) -> Self {
+        TriasClient {
+            url: url.into(),
+            requestor_ref: requestor_ref.into(),
+            language: "de".to_string(),
+            retry: RetryPolicy::default(),
+            transport: Rc::new(transport),
+        }
+    }
+
+    /// Language of names in responses, e.g. "en".
+    pub fn with_language(mut self, language: impl Into<String>) -> Self {
+        self.language = language.into();
+        self
+    }
+
+    /// Stops matching `query`, at most `max` (LocationInformationRequest).
+    pub async fn stopfinder(&self, query: &str, max: usize) -> Result<Vec<StopSuggestion>, EfaError> {
+        let payload = format!(
+            "<LocationInformationRequest><InitialInput><LocationName>{}</LocationName></InitialInput>\
+             <Restrictions><Type>stop</Type><Language>{}</Language><NumberOfResults>{max}</NumberOfResults></Restrictions>\
+             </LocationInformationRequest>",
+            escape(query),
+            escape(&self.language),
+        );
+        parse_locations(&self.request(&payload).await?)
+    }
+
+    /// The next `max` departures from `stop_id` at or after `when` (local
+    /// time), `None` meaning now (StopEventRequest).
+    pub async fn departures(&self, stop_id: &str, max: usize, when: Option<NaiveDateTime>) -> Result<Vec<Departure>, EfaError> {
+        let payload = format!(
+            "<StopEventRequest><Location><LocationRef><StopPointRef>{}</StopPointRef></LocationRef><DepArrTime>{}</DepArrTime></Location>\
+             <Params><Language>{}</Language><NumberOfResults>{max}</NumberOfResults><StopEventType>departure</StopEventType>\
+             <IncludePreviousCalls>true</IncludePreviousCalls><IncludeOnwardCalls>true</IncludeOnwardCalls>\
+             <IncludeRealtimeData>true</IncludeRealtimeData></Params></StopEventRequest>",
+            escape(stop_id),
+            timestamp(when.unwrap_or_else(tz::now)),
+            escape(&self.language),
+        );
+        parse_stop_events(&self.request(&payload).await?)
+    }
+
+    async fn request(&self, payload: &str) -> Result<String, EfaError> {
+        let body = format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+             <Trias version=\"1.2\" xmlns=\"http://www.vdv.de/trias\" xmlns:siri=\"http://www.siri.org.uk/siri\">\
+             <ServiceRequest><siri:RequestTimestamp>{}</siri:RequestTimestamp><siri:RequestorRef>{}</siri:RequestorRef>\
+             <RequestPayload>{payload}</RequestPayload></ServiceRequest></Trias>",
+            timestamp(tz::now()),
+            escape(&self.requestor_ref),
+        );
+        post_text_with(self.transport.as_ref(), &self.url, CONTENT_TYPE, &body, &self.retry).await
+    }
+}
+
+impl TransitProvider for TriasClient {
+    fn name(&self) -> &str {
+        "TRIAS"
+    }
+
+    fn stopfinder<'a>(&'a self, query: &'a str, max: usize) -> ProviderFuture<'a, Vec<StopSuggestion>> {
+        Box::pin(TriasClient::stopfinder(self, query, max))
+    }
+
+    fn departures<'a>(&'a self, station_id: &'a str, max: usize, when: Option<NaiveDateTime>) -> ProviderFuture<'a, Vec<Departure>> {
+        Box::pin(TriasClient::departures(self, station_id, max, when))
+    }
+}
+
+/// A local (Berlin) time as xs:dateTime with its offset.
+fn timestamp(local: NaiveDateTime) -> String {
+    berlin(local).format("%Y-%m-%dT%H:%M:%S%:z").to_string()
+}
+
+/// xs:dateTime as local (Berlin) time. TRIAS servers mostly answer in UTC;
+/// times without an offset are taken as local already.
+fn parse_time(text: &str) -> Option<NaiveDateTime> {
+    match DateTime::parse_from_rfc3339(text) {
+        Ok(time) => {
+            let utc = time.naive_utc();
+            Some(utc + berlin_offset(utc))
+        }
+        Err(_) => NaiveDateTime::parse_from_str(text, "%Y-%m-%dT%H:%M:%S").ok(),
+    }
+}
+
+/// `PtMode` and its submode, e.g. "rail" and "suburbanRailway".
+fn mode_of(pt_mode: &str, submode: &str) -> TransportMode {
+    match (pt_mode, submode) {
+        (_, "railReplacementBus" | "replacementRailService") => TransportMode::ReplacementBus,
+        (_, "suburbanRailway") => TransportMode::SBahn,
+        (_, "highSpeedRail" | "longDistance" | "interregionalRail") => TransportMode::LongDistanceTrain,
+        (_, "regionalRail" | "local") => TransportMode::RegionalTrain,
+        (_, "demandAndResponseBus") => TransportMode::OnDemand,
+        ("rail", _) => TransportMode::Train,
+        ("urbanRail", _) => TransportMode::SBahn,
+        ("metro" | "underground", _) => TransportMode::Subway,
+        ("tram", _) => TransportMode::Tram,
+        ("bus" | "trolleyBus" | "coach", _) => TransportMode::Bus,
+        ("water" | "ferry", _) => TransportMode::Ferry,
+        ("telecabin" | "cableway" | "funicular", _) => TransportMode::CableCar,
+        ("taxi", _) => TransportMode::OnDemand,
+        _ => TransportMode::Other,
+    }
+}
+
+/// Hits of a LocationInformationResponse that are stops or stations.
+fn parse_locations(xml: &str) -> Result<Vec<StopSuggestion>, EfaError> {
+    let mut stops = Vec::new();
+    let mut hit = StopSuggestion::default();
+    read(xml, |path, step| match step {
+        Step::Open if ends(path, &["LocationResult"]) => hit = StopSuggestion::default(),
+        Step::Close(text) if ends(path, &["StopPointRef"]) || ends(path, &["StopPlaceRef"]) => hit.id = text.to_string(),
+        Step::Close(text) if ends(path, &["StopPointName", "Text"]) || ends(path, &["StopPlaceName", "Text"]) => hit.name = text.to_string(),
+        Step::Close(text) if ends(path, &["Location", "LocationName", "Text"]) => hit.place = Some(text.to_string()),
+        Step::Close(text) if ends(path, &["LocationResult", "Probability"]) => {
+            hit.quality = text.parse::<f64>().ok().map(|p| (p.clamp(0.0, 1.0) * 1000.0).round() as u32);
+        }
+        // Addresses and POIs have no stop reference.
+        Step::Close(_) if ends(path, &["LocationResult"]) && !hit.id.is_empty() => stops.push(std::mem::take(&mut hit)),
+        _ => {}
+    })?;
+    if let Some(first) = stops.first_mut() {
+        first.best = true;
+    }
+    Ok(stops)
+}
+
+/// Departures of a StopEventResponse, in the server's order.
+fn parse_stop_events(xml: &str) -> Result<Vec<Departure>, EfaError> {
+    #[derive(Default)]
+    struct Row {
+        departure: Departure,
+        planned: Option<NaiveDateTime>,
+        estimated: Option<NaiveDateTime>,
+        pt_mode: String,
+        submode: String,
+        call: Option<CallingPoint>,
+    }
+    let mut rows = Vec::new();
+    let mut row = Row::default();
+    read(xml, |path, step| match step {
+        Step::Open if ends(path, &["StopEvent"]) => row = Row::default(),
+        Step::Open if ends(path, &["CallAtStop"]) => row.call = Some(CallingPoint { id: String::new(), name: String::new() }),
+        Step::Close(text) => {
+            let field = |tail: &[&str]| ends(path, tail);
+            if field(&["ThisCall", "CallAtStop", "ServiceDeparture", "TimetabledTime"]) {
+                row.planned = parse_time(text);
+            } else if field(&["ThisCall", "CallAtStop", "ServiceDeparture", "EstimatedTime"]) {
+                row.estimated = parse_time(text);
+            } else if field(&["ThisCall", "CallAtStop", "PlannedBay", "Text"]) {
+                row.departure.platform = row.departure.platform.take().or(Some(text.to_string()));
+            } else if field(&["ThisCall", "CallAtStop", "EstimatedBay", "Text"]) {
+                // A changed platform wins over the planned one.
+                row.departure.platform = Some(text.to_string());
+            } else if field(&["ThisCall", "CallAtStop", "NotServicedStop"]) {
+                row.departure.cancelled |= text == "true";
+            } else if field(&["CallAtStop", "StopPointRef"]) {
+                if let Some(call) = &mut row.call {
+                    call.id = text.to_string();
+                }
+            } else if field(&["CallAtStop", "StopPointName", "Text"]) {
+                if let Some(call) = &mut row.call {
+                    call.name = text.to_string();
+                }
+            } else if field(&["PreviousCall", "CallAtStop"]) {
+                row.departure.previous_stops.extend(row.call.take());
+            } else if field(&["OnwardCall", "CallAtStop"]) {
+                row.departure.onward_stops.extend(row.call.take());
+            } else if field(&["Service", "PublishedLineName", "Text"]) {
+                row.departure.line = text.to_string();
+            } else if field(&["Service", "DestinationText", "Text"]) {
+                row.departure.direction = Some(text.to_string());
+            } else if field(&["Service", "Mode", "PtMode"]) {
+                row.pt_mode = text.to_string();
+            } else if path.len() >= 2 && path[path.len() - 2] == "Mode" && path[path.len() - 1].ends_with("Submode") {
+                row.submode = text.to_string();
+            } else if field(&["Service", "Cancelled"]) {
+                row.departure.cancelled |= text == "true";
+            } else if field(&["StopEvent"]) {
+                let mut row = std::mem::take(&mut row);
+                // Rows without a departure time, e.g. a trip ending here, are no departures.
+                let Some(planned) = row.planned else { return };
+                row.departure.planned_time = planned;
+                row.departure.realtime_time = row.estimated;
+                row.departure.time = row.estimated.unwrap_or(planned);
+                row.departure.delay_minutes = row.estimated.map(|at| (at - planned).num_minutes() as i32);
+                row.departure.mode = mode_of(&row.pt_mode, &row.submode);
+                rows.push(row.departure);
+            }
+        }
+        _ => {}
+    })?;
+    Ok(rows)
+}
+
+enum Step<'a> {
+    Open,
+    /// End of an element, with its text.
+    Close(&'a str),
+}
+
+fn ends(path: &[String], tail: &[&str]) -> bool {
+    path.len() >= tail.len() && path[path.len() - tail.len()..].iter().zip(tail).all(|(a, b)| a == b)
+}
+
+// Walks a TRIAS response, calling `visit` with the local names of the
+// elements down to the current one. Error messages of the server fail the
+// whole response.
+fn read(xml: &str, mut visit: impl FnMut(&[String], Step)) -> Result<(), EfaError> {
+    let mut reader = Reader::from_str(xml.trim_start_matches('\u{feff}'));
+    let mut path: Vec<String> = Vec::new();
+    let mut text = String::new();
+    let mut error = None;
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) => {
+                path.push(String::from_utf8_lossy(e.local_name().as_ref()).into_owned());
+                text.clear();
+                visit(&path, Step::Open);
+            }
+            Ok(Event::Empty(e)) => {
+                path.push(String::from_utf8_lossy(e.local_name().as_ref()).into_owned());
+                visit(&path, Step::Open);
+                visit(&path, Step::Close(""));
+                path.pop();
+            }
+            Ok(Event::Text(t)) => text.push_str(&String::from_utf8_lossy(&t)),
+            Ok(Event::CData(t)) => text.push_str(&String::from_utf8_lossy(&t)),
+            Ok(Event::GeneralRef(r)) => text.push_str(&decode_text(&format!("&{};", String::from_utf8_lossy(&r)))),
+            Ok(Event::End(_)) => {
+                let value = text.trim();
+                if ends(&path, &["ErrorMessage", "Text"]) && !value.is_empty() {
+                    error.get_or_insert_with(|| value.to_string());
+                }
+                visit(&path, Step::Close(value));
+                text.clear();
+                path.pop();
+            }
+            Ok(Event::Eof) => break,
+            Err(e) => return Err(EfaError::Parse(e.to_string())),
+            _ => {}
+        }
+    }
+    match error {
+        Some(message) => Err(EfaError::ServerMessage(message)),
+        None if path.is_empty() => Ok(()),
+        None => Err(EfaError::Parse("TRIAS response cut short".to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_locations, parse_stop_events, TriasClient};
+    use crate::efa::{EfaClient, EfaError, HttpTransport, Transport, TransportFuture, TransportMode};
+    use crate::provider::TransitProvider;
+    use chrono::NaiveDate;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    #[test]
+    fn stops_are_read_from_location_results() {
+        let stops = parse_locations(include_str!("../testdata/trias/location_information.xml")).expect("valid response");
+        assert_eq!(stops.len(), 2, "the address is no stop");
+        assert_eq!(stops[0].id, "de:08212:1004");
+        assert_eq!(stops[0].name, "ZKM");
+        assert_eq!(stops[0].place.as_deref(), Some("Karlsruhe"));
+        assert_eq!(stops[0].quality, Some(970));
+        assert!(stops[0].best && !stops[1].best);
+        assert_eq!(stops[1].name, "Marktplatz (Pyramide U) & Kaiserstraße");
+    }
+
+    #[test]
+    fn departures_are_read_from_stop_events() {
+        let at = |hour, minute| NaiveDate::from_ymd_opt(2024, 1, 15).unwrap().and_hms_opt(hour, minute, 0).unwrap();
+        let deps = parse_stop_events(include_str!("../testdata/trias/stop_event.xml")).expect("valid response");
+        assert_eq!(deps.len(), 2);
+        let s2 = &deps[0];
+        assert_eq!((s2.line.as_str(), s2.direction.as_deref()), ("S2", Some("Spöck")));
+        // UTC on the wire, local time on the board.
+        assert_eq!(s2.planned_time, at(8, 5));
+        assert_eq!(s2.time, at(8, 7));
+        assert_eq!(s2.delay_minutes, Some(2));
+        assert_eq!(s2.mode, TransportMode::SBahn);
+        assert_eq!(s2.platform.as_deref(), Some("Gleis 1"));
+        assert_eq!(s2.previous_stops.len(), 1);
+        assert_eq!(s2.continues_via(2).as_deref(), Some("Karlsruhe Marktplatz (Pyramide U) → Spöck Richard-Hecht-Schule"));
+
+        let sev = &deps[1];
+        assert!(sev.cancelled && sev.is_replacement());
+        assert_eq!(sev.realtime_time, None);
+        assert_eq!(sev.delay_minutes, None);
+    }
+
+    #[test]
+    fn server_errors_fail_the_response() {
+        let xml = r#"<Trias xmlns="http://www.vdv.de/trias"><ServiceDelivery><DeliveryPayload><StopEventResponse>
+            <ErrorMessage><Code>-4030</Code><Text>STOPEVENT_LOCATIONUNSERVED</Text></ErrorMessage>
+            </StopEventResponse></DeliveryPayload></ServiceDelivery></Trias>"#;
+        assert_eq!(parse_stop_events(xml), Err(EfaError::ServerMessage("STOPEVENT_LOCATIONUNSERVED".to_string())));
+        assert!(parse_stop_events("<Trias><ServiceDelivery>").is_err());
+    }
+
+    /// Answers every POST with `response`, keeping the request bodies.
+    struct Posted {
+        response: String,
+        bodies: Rc<RefCell<Vec<String>>>,
+    }
+
+    impl Transport for Posted {
+        fn get<'a>(&'a self, _url: &'a str, _timeout: Duration) -> TransportFuture<'a> {
+            Box::pin(async { Err(EfaError::Network("TRIAS only posts".to_string())) })
+        }
+
+        fn post<'a>(&'a self, _url: &'a str, content_type: &'a str, body: &'a str, _timeout: Duration) -> TransportFuture<'a> {
+            assert!(content_type.starts_with("text/xml"));
+            self.bodies.borrow_mut().push(body.to_string());
+            let response = self.response.clone();
+            Box::pin(async move { Ok(response) })
+        }
+    }
+
+    #[tokio::test]
+    async fn trias_and_efa_serve_the_same_trait() {
+        let bodies = Rc::new(RefCell::new(Vec::new()));
+        let response = include_str!("../testdata/trias/location_information.xml").to_string();
+        let trias = TriasClient::new("http://canned.test/trias", "KEY&1", Posted { response, bodies: bodies.clone() });
+        let providers: [&dyn TransitProvider; 2] = [&trias, &EfaClient::new("http://canned.test/unused", HttpTransport)];
+        assert_eq!(providers.map(|p| p.name()), ["TRIAS", "EFA"]);
+
+        let stops = providers[0].stopfinder("ZKM <Karlsruhe>", 5).await.expect("canned stops");
+        assert_eq!(stops[0].id, "de:08212:1004");
+        let body = &bodies.borrow()[0];
+        assert!(body.contains("<siri:RequestorRef>KEY&amp;1</siri:RequestorRef>"));
+        assert!(body.contains("<LocationName>ZKM &lt;Karlsruhe&gt;</LocationName>"));
+        assert!(body.contains("<NumberOfResults>5</NumberOfResults>"));
+    }
+}
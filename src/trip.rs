@@ -0,0 +1,835 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::time::Duration;
+
+use futures::{Stream, StreamExt};
+use quick_xml::events::{BytesStart, BytesText, Event};
+use quick_xml::Reader;
+use serde::{Deserialize, Serialize};
+
+use crate::diagnostics;
+use crate::efa::{decode_text, full_url, hhmm, is_replacement, parse_coords, parse_time_from_attrs, Coord, Departure, EfaClient, EfaError, EfaRequest, TransportMode};
+
+/// Options for `trip()`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TripOptions {
+    /// Date as "YYYYMMDD"; `None` means today.
+    pub date: Option<String>,
+    /// Time as "HH:MM"; `None` means now.
+    pub time: Option<String>,
+    /// Treat `time` as the latest arrival instead of the earliest departure.
+    pub arrive_by: bool,
+    /// Maximum number of changes, `None` for the server default.
+    pub max_changes: Option<u8>,
+    /// Number of journeys to ask for.
+    pub max_journeys: usize,
+    /// Stop id every journey has to pass through. EFA takes only one.
+    #[serde(default)]
+    pub via: Option<String>,
+    /// Stop id no journey may pass through, e.g. an interchange under
+    /// construction. EFA takes only one.
+    #[serde(default)]
+    pub avoid: Option<String>,
+    /// Only journeys with low-floor, wheelchair-accessible vehicles.
+    #[serde(default)]
+    pub accessible: bool,
+    /// Only journeys where bicycles may be taken along on every ride.
+    #[serde(default)]
+    pub with_bike: bool,
+    /// How fast the user walks between stops when changing.
+    #[serde(default)]
+    pub walk_speed: WalkSpeed,
+    /// Longest walk to the first and from the last stop, in minutes;
+    /// `None` for the server default.
+    #[serde(default)]
+    pub max_walk_minutes: Option<u32>,
+    /// Ask for the path of every leg (`Leg::path`). Makes the response much larger.
+    #[serde(default)]
+    pub with_path: bool,
+}
+
+impl Default for TripOptions {
+    fn default() -> Self {
+        TripOptions {
+            date: None,
+            time: None,
+            arrive_by: false,
+            max_changes: None,
+            max_journeys: 4,
+            via: None,
+            avoid: None,
+            accessible: false,
+            with_bike: false,
+            walk_speed: WalkSpeed::Normal,
+            max_walk_minutes: None,
+            with_path: false,
+        }
+    }
+}
+
+/// Walking speed EFA plans interchanges with.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum WalkSpeed {
+    Slow,
+    #[default]
+    Normal,
+    Fast,
+}
+
+/// A stop on a journey together with its planned and realtime time.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TripStop {
+    pub id: String,
+    pub name: String,
+    pub platform: Option<String>,
+    pub planned_time: String,
+    pub realtime_time: Option<String>,
+}
+
+impl TripStop {
+    /// Realtime if known, planned otherwise.
+    pub fn time(&self) -> &str {
+        self.realtime_time.as_deref().unwrap_or(&self.planned_time)
+    }
+}
+
+/// One leg of a journey: a ride with a line, or a walk (`line == None`).
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Leg {
+    pub line: Option<String>,
+    pub direction: Option<String>,
+    pub origin: TripStop,
+    pub destination: TripStop,
+    #[serde(default)]
+    pub mode: TransportMode,
+    /// Company running the ride, e.g. "AVG", if EFA names one.
+    #[serde(default)]
+    pub operator: Option<String>,
+    /// The route as driven or walked, from origin to destination. Empty
+    /// unless requested with `TripOptions::with_path`.
+    #[serde(default)]
+    pub path: Vec<Coord>,
+    #[serde(default)]
+    pub vehicle: VehicleAccess,
+}
+
+/// Accessibility of the vehicle of a leg, `None` where EFA doesn't say.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VehicleAccess {
+    pub low_floor: Option<bool>,
+    pub wheelchair: Option<bool>,
+    /// Whether bicycles may be taken along.
+    pub bikes: Option<bool>,
+}
+
+impl Leg {
+    pub fn is_walk(&self) -> bool {
+        self.line.is_none()
+    }
+
+    /// Whether this ride is on a bus replacing a rail line.
+    pub fn is_replacement(&self) -> bool {
+        self.line.as_deref().is_some_and(|line| is_replacement(self.mode, line))
+    }
+}
+
+/// A connection between two stops.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Journey {
+    pub legs: Vec<Leg>,
+    /// Number of changes as reported by EFA.
+    pub changes: u32,
+    /// Total duration as "HH:MM", if reported.
+    pub duration: Option<String>,
+    /// What the journey costs in the KVV tariff, if EFA knows.
+    #[serde(default)]
+    pub fare: Option<Fare>,
+}
+
+/// Tariff of a journey.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Fare {
+    /// Number of fare zones ("Waben") travelled through.
+    pub zones: Option<u32>,
+    /// Tickets valid for the journey, in EFA's order.
+    pub tickets: Vec<Ticket>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Ticket {
+    /// E.g. "Einzelfahrkarte".
+    pub name: String,
+    pub price_cents: u32,
+    pub currency: String,
+}
+
+impl Ticket {
+    /// Price as printed on KVV tickets, e.g. "3,00 €".
+    pub fn price_label(&self) -> String {
+        let amount = format!("{},{:02}", self.price_cents / 100, self.price_cents % 100);
+        match self.currency.as_str() {
+            "EUR" => format!("{amount} €"),
+            other => format!("{amount} {other}"),
+        }
+    }
+}
+
+impl Journey {
+    pub fn departure(&self) -> Option<&TripStop> {
+        self.legs.first().map(|l| &l.origin)
+    }
+
+    pub fn arrival(&self) -> Option<&TripStop> {
+        self.legs.last().map(|l| &l.destination)
+    }
+
+    /// Stops where the rider changes from one ride to the next.
+    pub fn interchanges(&self) -> Vec<&TripStop> {
+        let rides: Vec<&Leg> = self.legs.iter().filter(|l| !l.is_walk()).collect();
+        rides.windows(2).map(|pair| &pair[0].destination).collect()
+    }
+}
+
+/// Plan journeys from `origin_id` to `destination_id` (EFA stop ids).
+pub async fn trip(origin_id: &str, destination_id: &str, options: &TripOptions) -> Result<Vec<Journey>, EfaError> {
+    EfaClient::kvv().trip(origin_id, destination_id, options).await
+}
+
+// Planned journeys are reused as they are for this long…
+const TRIP_FRESH: Duration = Duration::from_secs(2 * 60);
+// …and up to this age with their boarding times brought up to date.
+const TRIP_STALE: Duration = Duration::from_secs(10 * 60);
+// Queries for "now" within one such slot share their results.
+const NOW_BUCKET_MS: f64 = 5.0 * 60.0 * 1000.0;
+const MAX_CACHED_TRIPS: usize = 16;
+// Departures of the origin searched for the first ride of each journey.
+const ORIGIN_BOARD_SIZE: usize = 20;
+
+/// Age of a cached trip result.
+#[derive(Clone, Debug, PartialEq)]
+enum CachedTrip {
+    Fresh(Vec<Journey>),
+    /// Still usable once its realtime data is refreshed.
+    Stale(Vec<Journey>),
+}
+
+/// Parsed trip results by request URL and time bucket, so going back and
+/// forth between board and trip page doesn't plan the same journeys again.
+#[derive(Debug, Default)]
+struct TripCache {
+    // (URL, bucket) -> (fetched at ms, journeys)
+    entries: HashMap<(String, i64), (f64, Vec<Journey>)>,
+}
+
+impl TripCache {
+    /// Cache key of a request: its URL, plus the current time slot when
+    /// `options` ask for journeys from now on.
+    fn key(url: String, options: &TripOptions, now_ms: f64) -> (String, i64) {
+        let bucket = match (&options.date, &options.time) {
+            (None, None) => (now_ms / NOW_BUCKET_MS) as i64,
+            _ => 0,
+        };
+        (url, bucket)
+    }
+
+    fn get(&mut self, key: &(String, i64), now_ms: f64) -> Option<CachedTrip> {
+        let (fetched, journeys) = self.entries.get(key)?;
+        let age = now_ms - fetched;
+        if age < TRIP_FRESH.as_secs_f64() * 1000.0 {
+            Some(CachedTrip::Fresh(journeys.clone()))
+        } else if age < TRIP_STALE.as_secs_f64() * 1000.0 {
+            Some(CachedTrip::Stale(journeys.clone()))
+        } else {
+            self.entries.remove(key);
+            None
+        }
+    }
+
+    /// Store `journeys` fetched at `fetched_ms`; revalidated results keep
+    /// the time of their original fetch.
+    fn put(&mut self, key: (String, i64), journeys: Vec<Journey>, fetched_ms: f64) {
+        if self.entries.len() >= MAX_CACHED_TRIPS && !self.entries.contains_key(&key) {
+            let oldest = self.entries.iter().min_by(|a, b| a.1 .0.total_cmp(&b.1 .0)).map(|(k, _)| k.clone());
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.entries.insert(key, (fetched_ms, journeys));
+    }
+}
+
+thread_local! {
+    static TRIP_CACHE: RefCell<TripCache> = RefCell::new(TripCache::default());
+}
+
+impl EfaClient {
+    /// Plan journeys from `origin_id` to `destination_id` (EFA stop ids).
+    /// Results of the last few minutes are reused; after two minutes only
+    /// the departure board of the origin is fetched to refresh their
+    /// boarding times, which is much cheaper than planning again.
+    pub async fn trip(&self, origin_id: &str, destination_id: &str, options: &TripOptions) -> Result<Vec<Journey>, EfaError> {
+        let request = TripRequest { origin_id, destination_id, options };
+        let params = self.request_params(&request);
+        let now = diagnostics::now_ms();
+        let key = TripCache::key(full_url(&self.url(request.endpoint()), &params)?, options, now);
+        match TRIP_CACHE.with(|c| c.borrow_mut().get(&key, now)) {
+            Some(CachedTrip::Fresh(journeys)) => return Ok(journeys),
+            Some(CachedTrip::Stale(mut journeys)) => {
+                if let Ok(board) = self.departures(origin_id, ORIGIN_BOARD_SIZE, None).await {
+                    refresh_boarding(&mut journeys, &board);
+                    let fetched = TRIP_CACHE.with(|c| c.borrow().entries.get(&key).map(|e| e.0)).unwrap_or(now);
+                    TRIP_CACHE.with(|c| c.borrow_mut().put(key, journeys.clone(), fetched));
+                    return Ok(journeys);
+                }
+            }
+            None => {}
+        }
+        let journeys = parse_trip_xml(&self.fetch(request.endpoint(), &params).await?)?;
+        TRIP_CACHE.with(|c| c.borrow_mut().put(key, journeys.clone(), now));
+        Ok(journeys)
+    }
+}
+
+/// `EfaClient::live_boarding` against the KVV.
+pub fn live_boarding(origin_id: &str, journeys: Vec<Journey>, interval: Duration) -> impl Stream<Item = Vec<Journey>> + use<> {
+    EfaClient::kvv().live_boarding(origin_id, journeys, interval)
+}
+
+impl EfaClient {
+    /// `journeys` planned from `origin_id` with the realtime boarding time
+    /// of their first ride, again whenever the board of the origin changes.
+    /// Keeps the trip results page live without planning the trip again.
+    pub fn live_boarding(
+        &self,
+        origin_id: &str,
+        journeys: Vec<Journey>,
+        interval: Duration,
+    ) -> impl Stream<Item = Vec<Journey>> + use<> {
+        self.departures_stream(origin_id, ORIGIN_BOARD_SIZE, interval).map(move |board| {
+            let mut journeys = journeys.clone();
+            refresh_boarding(&mut journeys, &board.departures);
+            journeys
+        })
+    }
+}
+
+/// Update the realtime boarding time of every journey whose first ride
+/// appears on `board`, the departure board of the origin.
+fn refresh_boarding(journeys: &mut [Journey], board: &[Departure]) {
+    for journey in journeys {
+        let Some(leg) = journey.legs.iter_mut().find(|l| !l.is_walk()) else { continue };
+        let departure = board
+            .iter()
+            .find(|d| leg.line.as_ref() == Some(&d.line) && hhmm(&d.planned_time) == leg.origin.planned_time);
+        if let Some(departure) = departure {
+            leg.origin.realtime_time = departure.realtime_time.as_ref().map(hhmm);
+        }
+    }
+}
+
+/// Journey planner request (XSLT_TRIP_REQUEST).
+pub(crate) struct TripRequest<'a> {
+    pub origin_id: &'a str,
+    pub destination_id: &'a str,
+    pub options: &'a TripOptions,
+}
+
+impl EfaRequest for TripRequest<'_> {
+    fn endpoint(&self) -> &'static str {
+        "XSLT_TRIP_REQUEST"
+    }
+
+    fn to_params(&self) -> Vec<(&'static str, String)> {
+        let options = self.options;
+        let mut params = vec![
+            ("outputFormat", "XML".to_string()),
+            ("locationServerActive", "1".to_string()),
+            ("type_origin", "stop".to_string()),
+            ("name_origin", self.origin_id.to_string()),
+            ("type_destination", "stop".to_string()),
+            ("name_destination", self.destination_id.to_string()),
+            ("useRealtime", "1".to_string()),
+            ("ptOptionsActive", "1".to_string()),
+            ("routeType", "LEASTTIME".to_string()),
+            ("calcNumberOfTrips", options.max_journeys.to_string()),
+            ("itdTripDateTimeDepArr", if options.arrive_by { "arr" } else { "dep" }.to_string()),
+        ];
+        if let Some(date) = &options.date {
+            params.push(("itdDate", date.clone()));
+        }
+        if let Some(time) = &options.time {
+            params.push(("itdTime", time.replace(':', "")));
+        }
+        if let Some(max) = options.max_changes {
+            params.push(("maxChanges", max.to_string()));
+        }
+        if let Some(via) = &options.via {
+            params.push(("type_via", "stop".to_string()));
+            params.push(("name_via", via.clone()));
+        }
+        if let Some(avoid) = &options.avoid {
+            params.push(("type_notVia", "stop".to_string()));
+            params.push(("name_notVia", avoid.clone()));
+        }
+        if options.accessible {
+            params.push(("imparedOptionsActive", "1".to_string()));
+            params.push(("lowPlatformVhcl", "1".to_string()));
+            params.push(("wheelchair", "1".to_string()));
+        }
+        if options.with_bike {
+            params.push(("bikeTakeAlong", "1".to_string()));
+        }
+        match options.walk_speed {
+            WalkSpeed::Slow => params.push(("changeSpeed", "slow".to_string())),
+            WalkSpeed::Normal => {}
+            WalkSpeed::Fast => params.push(("changeSpeed", "fast".to_string())),
+        }
+        if let Some(minutes) = options.max_walk_minutes {
+            // Footpaths (means of transport 100) to and from the stops.
+            params.push(("useProxFootSearch", "1".to_string()));
+            params.push(("trITMOT", "100".to_string()));
+            params.push(("trITMOTvalue100", minutes.to_string()));
+        }
+        if options.with_path {
+            params.push(("coordListOutputFormat", "STRING".to_string()));
+        }
+        params
+    }
+}
+
+// Which time of an `itdPoint` is being read.
+#[derive(Clone, Copy, PartialEq)]
+enum PointTime {
+    Actual,
+    Planned,
+}
+
+#[derive(Default)]
+struct PointBuilder {
+    id: String,
+    name: String,
+    platform: Option<String>,
+    departure: bool,
+    actual: Option<String>,
+    planned: Option<String>,
+}
+
+impl PointBuilder {
+    fn build(self) -> Option<TripStop> {
+        // Without a target time EFA only sends the (planned) itdDateTime.
+        let (planned_time, realtime_time) = match (self.planned, self.actual) {
+            (Some(planned), actual) => (planned, actual),
+            (None, Some(actual)) => (actual, None),
+            (None, None) => return None,
+        };
+        Some(TripStop { id: self.id, name: self.name, platform: self.platform, planned_time, realtime_time })
+    }
+}
+
+#[derive(Default)]
+struct LegBuilder {
+    line: Option<String>,
+    direction: Option<String>,
+    origin: Option<TripStop>,
+    destination: Option<TripStop>,
+    mode: TransportMode,
+    operator: Option<String>,
+    path: Vec<Coord>,
+    vehicle: VehicleAccess,
+}
+
+// Element whose text is being read inside a leg.
+#[derive(Clone, Copy, PartialEq)]
+enum LegText {
+    Path,
+    AttrName,
+    AttrValue,
+    Operator,
+}
+
+#[derive(Default)]
+struct TripParser {
+    journeys: Vec<Journey>,
+    journey: Option<Journey>,
+    leg: Option<LegBuilder>,
+    point: Option<PointBuilder>,
+    point_time: Option<PointTime>,
+    // Points inside itdStopSeq are intermediate stops, not leg ends.
+    in_stop_seq: bool,
+    in_operator: bool,
+    leg_text: Option<LegText>,
+    // Name and value of the genAttrElem being read.
+    leg_attr: (String, String),
+}
+
+impl TripParser {
+    fn open(&mut self, e: &BytesStart<'_>, empty: bool) {
+        match e.name().as_ref() {
+            b"itdRoute" if !empty => {
+                self.journey = Some(Journey {
+                    legs: Vec::new(),
+                    changes: attr(e, b"changes").and_then(|c| c.parse().ok()).unwrap_or(0),
+                    duration: attr(e, b"publicDuration"),
+                    fare: None,
+                });
+            }
+            b"itdPartialRoute" if !empty && self.journey.is_some() => {
+                self.leg = Some(LegBuilder::default());
+            }
+            b"itdStopSeq" if !empty => self.in_stop_seq = true,
+            b"itdCoordinateString" if !empty && self.leg.is_some() => self.leg_text = Some(LegText::Path),
+            b"itdOperator" if !empty && self.leg.is_some() => self.in_operator = true,
+            b"name" if !empty && self.in_operator => self.leg_text = Some(LegText::Operator),
+            b"genAttrElem" if !empty && self.in_leg_attrs() => self.leg_attr = Default::default(),
+            b"name" if !empty && self.in_leg_attrs() => self.leg_text = Some(LegText::AttrName),
+            b"value" if !empty && self.in_leg_attrs() => self.leg_text = Some(LegText::AttrValue),
+            b"itdPoint" if !empty && self.leg.is_some() && !self.in_stop_seq => {
+                self.point = Some(PointBuilder {
+                    id: attr(e, b"stopID").unwrap_or_default(),
+                    name: attr(e, b"name").map(|n| decode_text(&n)).unwrap_or_default(),
+                    platform: attr(e, b"platformName").filter(|p| !p.is_empty()),
+                    departure: attr(e, b"usage").as_deref() == Some("departure"),
+                    ..PointBuilder::default()
+                });
+            }
+            b"itdDateTime" if !empty && self.point.is_some() => self.point_time = Some(PointTime::Actual),
+            b"itdDateTimeTarget" if !empty && self.point.is_some() => self.point_time = Some(PointTime::Planned),
+            b"itdTime" => {
+                if let (Some(point), Some(which)) = (self.point.as_mut(), self.point_time) {
+                    let time = parse_time_from_attrs(e);
+                    match which {
+                        PointTime::Actual => point.actual = time,
+                        PointTime::Planned => point.planned = time,
+                    }
+                }
+            }
+            b"itdSingleTicket" => {
+                if let Some(journey) = self.journey.as_mut() {
+                    let fare = journey.fare.get_or_insert_with(Fare::default);
+                    fare.zones = attr(e, b"unitsAdult").and_then(|u| u.parse().ok());
+                }
+            }
+            b"itdUnifiedTicket" => {
+                if let Some(journey) = self.journey.as_mut() {
+                    let name = attr(e, b"name").map(|n| decode_text(&n)).filter(|n| !n.is_empty());
+                    let price = attr(e, b"priceBrutto").as_deref().and_then(parse_cents);
+                    if let (Some(name), Some(price_cents)) = (name, price) {
+                        let currency = attr(e, b"currency").unwrap_or_else(|| "EUR".to_string());
+                        let fare = journey.fare.get_or_insert_with(Fare::default);
+                        fare.tickets.push(Ticket { name, price_cents, currency });
+                    }
+                }
+            }
+            b"itdMeansOfTransport" => {
+                if let Some(leg) = self.leg.as_mut() {
+                    let walk = matches!(attr(e, b"type").as_deref(), Some("99" | "100"))
+                        || attr(e, b"productName").as_deref() == Some("Fussweg");
+                    if !walk {
+                        leg.line = attr(e, b"symbol")
+                            .or_else(|| attr(e, b"shortname"))
+                            .or_else(|| attr(e, b"name"))
+                            .map(|l| decode_text(&l));
+                        leg.direction = attr(e, b"destination").map(|d| decode_text(&d)).filter(|d| !d.is_empty());
+                        leg.mode = attr(e, b"motType").map_or(TransportMode::Other, |m| TransportMode::from_mot_type(&m));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Inside a leg, but not in one of its stops: genAttrElem describes the leg.
+    fn in_leg_attrs(&self) -> bool {
+        self.leg.is_some() && self.point.is_none() && !self.in_stop_seq
+    }
+
+    fn text(&mut self, text: &BytesText<'_>) {
+        let (Some(which), Some(leg)) = (self.leg_text, self.leg.as_mut()) else { return };
+        let text = String::from_utf8_lossy(text);
+        match which {
+            // "lon,lat lon,lat …" of itdPathCoordinates.
+            LegText::Path => leg.path.extend(text.split_whitespace().filter_map(parse_coords)),
+            LegText::AttrName => self.leg_attr.0.push_str(text.trim()),
+            LegText::AttrValue => self.leg_attr.1.push_str(text.trim()),
+            LegText::Operator => {
+                let name = decode_text(text.trim());
+                if !name.is_empty() {
+                    leg.operator = Some(name);
+                }
+            }
+        }
+    }
+
+    fn close(&mut self, name: &[u8]) {
+        match name {
+            b"itdStopSeq" => self.in_stop_seq = false,
+            b"itdOperator" => self.in_operator = false,
+            b"itdCoordinateString" | b"name" | b"value" => self.leg_text = None,
+            b"genAttrElem" if self.in_leg_attrs() => {
+                if let (Some(leg), (name, value)) = (self.leg.as_mut(), std::mem::take(&mut self.leg_attr)) {
+                    let flag = match value.as_str() {
+                        "1" | "true" => Some(true),
+                        "0" | "false" => Some(false),
+                        _ => None,
+                    };
+                    match name.as_str() {
+                        "PlanLowFloorVehicle" => leg.vehicle.low_floor = flag,
+                        "PlanWheelChairAccess" => leg.vehicle.wheelchair = flag,
+                        "PlanBikeTransport" => leg.vehicle.bikes = flag,
+                        _ => {}
+                    }
+                }
+            }
+            b"itdDateTime" | b"itdDateTimeTarget" => self.point_time = None,
+            b"itdPoint" => {
+                if let (Some(point), Some(leg)) = (self.point.take(), self.leg.as_mut()) {
+                    let departure = point.departure;
+                    if let Some(stop) = point.build() {
+                        if departure {
+                            leg.origin = Some(stop);
+                        } else {
+                            leg.destination = Some(stop);
+                        }
+                    }
+                }
+            }
+            b"itdPartialRoute" => {
+                if let (Some(leg), Some(journey)) = (self.leg.take(), self.journey.as_mut())
+                    && let (Some(origin), Some(destination)) = (leg.origin, leg.destination)
+                {
+                    journey.legs.push(Leg {
+                        line: leg.line,
+                        direction: leg.direction,
+                        origin,
+                        destination,
+                        mode: leg.mode,
+                        operator: leg.operator,
+                        path: leg.path,
+                        vehicle: leg.vehicle,
+                    });
+                }
+            }
+            b"itdRoute" => {
+                if let Some(journey) = self.journey.take()
+                    && !journey.legs.is_empty()
+                {
+                    self.journeys.push(journey);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn attr(e: &BytesStart<'_>, key: &[u8]) -> Option<String> {
+    e.attributes()
+        .flatten()
+        .find(|a| a.key.as_ref() == key)
+        .map(|a| String::from_utf8_lossy(&a.value).to_string())
+}
+
+// "3.00", "7.4" or "3" (euros) to cents.
+fn parse_cents(price: &str) -> Option<u32> {
+    let (units, fraction) = price.trim().split_once(['.', ',']).unwrap_or((price.trim(), "0"));
+    let fraction = format!("{fraction:0<2}");
+    if fraction.len() > 2 {
+        return None;
+    }
+    Some(units.parse::<u32>().ok()? * 100 + fraction.parse::<u32>().ok()?)
+}
+
+fn parse_trip_xml(xml: &str) -> Result<Vec<Journey>, EfaError> {
+    let mut reader = Reader::from_str(xml);
+    let mut buf = Vec::new();
+    let mut parser = TripParser::default();
+
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(e)) => parser.open(&e, false),
+            Ok(Event::Empty(e)) => parser.open(&e, true),
+            Ok(Event::Text(t)) => parser.text(&t),
+            Ok(Event::End(e)) => parser.close(e.name().as_ref()),
+            Ok(Event::Eof) => break,
+            Err(e) => return Err(EfaError::Parse(e.to_string())),
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    Ok(parser.journeys)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_trip_xml, refresh_boarding, CachedTrip, TripCache, TripOptions, TripRequest, VehicleAccess, WalkSpeed};
+    use crate::efa::{Coord, Departure, EfaClient, EfaRequest, HttpTransport, TransportMode};
+    use chrono::NaiveDate;
+    use wiremock::matchers::{method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    const TRIP_XML: &str = include_str!("../testdata/trip.xml");
+
+    #[test]
+    fn trip_request_parameters() {
+        let options = TripOptions {
+            date: Some("20240116".to_string()),
+            time: Some("07:30".to_string()),
+            arrive_by: true,
+            max_changes: Some(2),
+            max_journeys: 3,
+            via: Some("7000002".to_string()),
+            avoid: Some("7000001".to_string()),
+            accessible: true,
+            with_bike: true,
+            walk_speed: WalkSpeed::Slow,
+            max_walk_minutes: Some(8),
+            with_path: true,
+        };
+        let params = TripRequest { origin_id: "7001004", destination_id: "7000090", options: &options }.to_params();
+        let params: Vec<_> = params.iter().map(|(k, v)| (*k, v.as_str())).collect();
+        assert_eq!(
+            params,
+            [
+                ("outputFormat", "XML"),
+                ("locationServerActive", "1"),
+                ("type_origin", "stop"),
+                ("name_origin", "7001004"),
+                ("type_destination", "stop"),
+                ("name_destination", "7000090"),
+                ("useRealtime", "1"),
+                ("ptOptionsActive", "1"),
+                ("routeType", "LEASTTIME"),
+                ("calcNumberOfTrips", "3"),
+                ("itdTripDateTimeDepArr", "arr"),
+                ("itdDate", "20240116"),
+                ("itdTime", "0730"),
+                ("maxChanges", "2"),
+                ("type_via", "stop"),
+                ("name_via", "7000002"),
+                ("type_notVia", "stop"),
+                ("name_notVia", "7000001"),
+                ("imparedOptionsActive", "1"),
+                ("lowPlatformVhcl", "1"),
+                ("wheelchair", "1"),
+                ("bikeTakeAlong", "1"),
+                ("changeSpeed", "slow"),
+                ("useProxFootSearch", "1"),
+                ("trITMOT", "100"),
+                ("trITMOTvalue100", "8"),
+                ("coordListOutputFormat", "STRING"),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_trip_xml_extracts_journeys_and_legs() {
+        let journeys = parse_trip_xml(TRIP_XML).expect("parse succeeds");
+        assert_eq!(journeys.len(), 2);
+
+        let first = &journeys[0];
+        assert_eq!(first.changes, 1);
+        assert_eq!(first.duration.as_deref(), Some("00:31"));
+        assert_eq!(first.legs.len(), 3);
+
+        let s2 = &first.legs[0];
+        assert_eq!(s2.line.as_deref(), Some("S2"));
+        assert_eq!(s2.direction.as_deref(), Some("Spöck"));
+        assert_eq!(s2.origin.id, "7001004");
+        assert_eq!(s2.origin.platform.as_deref(), Some("Gleis 1"));
+        assert_eq!(s2.origin.planned_time, "08:05");
+        assert_eq!(s2.origin.realtime_time.as_deref(), Some("08:07"));
+        assert_eq!(s2.destination.name, "Karlsruhe, Marktplatz (Kaiserstraße U)");
+        assert_eq!(s2.destination.time(), "08:13");
+        assert_eq!(s2.path.len(), 3);
+        assert_eq!(s2.path[2], Coord { lat: 49.00937, lon: 8.40378 });
+        assert_eq!(s2.vehicle, VehicleAccess { low_floor: Some(true), wheelchair: Some(false), bikes: Some(true) });
+
+        assert!(first.legs[1].is_walk());
+        assert!(first.legs[1].path.is_empty());
+        assert_eq!(first.legs[2].vehicle, VehicleAccess::default());
+        assert_eq!(first.legs[2].line.as_deref(), Some("1"));
+        assert_eq!(first.legs[2].mode, TransportMode::Tram);
+        assert_eq!(first.legs[2].operator.as_deref(), Some("VBK"));
+        assert_eq!(s2.operator, None);
+        assert!(!s2.is_replacement());
+        assert_eq!(first.arrival().map(|s| s.time()), Some("08:36"));
+
+        let interchanges: Vec<_> = first.interchanges().iter().map(|s| s.id.as_str()).collect();
+        assert_eq!(interchanges, ["7000002"]);
+
+        let fare = first.fare.as_ref().expect("fare");
+        assert_eq!(fare.zones, Some(2));
+        let tickets: Vec<_> = fare.tickets.iter().map(|t| (t.name.as_str(), t.price_label())).collect();
+        assert_eq!(
+            tickets,
+            [
+                ("Einzelfahrkarte", "3,00 €".to_string()),
+                ("Einzelfahrkarte Kind", "1,80 €".to_string()),
+                ("Tageskarte", "7,40 €".to_string()),
+            ]
+        );
+
+        let direct = &journeys[1];
+        assert_eq!(direct.changes, 0);
+        assert_eq!(direct.fare, None);
+        assert_eq!(direct.departure().map(|s| s.planned_time.as_str()), Some("08:20"));
+        assert_eq!(direct.departure().and_then(|s| s.realtime_time.as_deref()), None);
+        assert!(direct.interchanges().is_empty());
+    }
+
+    #[test]
+    fn trip_results_go_stale_before_they_expire() {
+        const MIN: f64 = 60.0 * 1000.0;
+        let now = TripOptions::default();
+        let key = TripCache::key("trip?a".to_string(), &now, 1.0 * MIN);
+        // Same slot for "now" a minute later, a new one after five minutes.
+        assert_eq!(TripCache::key("trip?a".to_string(), &now, 2.0 * MIN), key);
+        assert_ne!(TripCache::key("trip?a".to_string(), &now, 5.0 * MIN), key);
+        let fixed = TripOptions { time: Some("08:00".to_string()), ..TripOptions::default() };
+        assert_eq!(TripCache::key("trip?b".to_string(), &fixed, 1.0 * MIN).1, 0);
+
+        let journeys = parse_trip_xml(TRIP_XML).expect("parse succeeds");
+        let mut cache = TripCache::default();
+        cache.put(key.clone(), journeys.clone(), 1.0 * MIN);
+        assert_eq!(cache.get(&key, 2.0 * MIN), Some(CachedTrip::Fresh(journeys.clone())));
+        assert_eq!(cache.get(&key, 4.0 * MIN), Some(CachedTrip::Stale(journeys)));
+        assert_eq!(cache.get(&key, 12.0 * MIN), None);
+        assert!(cache.entries.is_empty());
+    }
+
+    #[test]
+    fn boarding_times_are_refreshed_from_the_origin_board() {
+        let mut journeys = parse_trip_xml(TRIP_XML).expect("parse succeeds");
+        let at = |h, m| NaiveDate::from_ymd_opt(2024, 1, 15).unwrap().and_hms_opt(h, m, 0).unwrap();
+        let board = vec![
+            Departure { line: "S2".to_string(), planned_time: at(8, 5), realtime_time: Some(at(8, 9)), ..Default::default() },
+            Departure { line: "2".to_string(), planned_time: at(8, 21), realtime_time: Some(at(8, 22)), ..Default::default() },
+        ];
+        refresh_boarding(&mut journeys, &board);
+        assert_eq!(journeys[0].legs[0].origin.realtime_time.as_deref(), Some("08:09"));
+        // No departure of line 2 at 08:20 on the board.
+        assert_eq!(journeys[1].legs[0].origin.realtime_time, None);
+    }
+
+    #[tokio::test]
+    async fn trip_sends_origin_destination_and_time() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/XSLT_TRIP_REQUEST"))
+            .and(query_param("name_origin", "7001004"))
+            .and(query_param("name_destination", "7000090"))
+            .and(query_param("itdTime", "0800"))
+            .and(query_param("itdTripDateTimeDepArr", "arr"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(TRIP_XML))
+            .mount(&server)
+            .await;
+
+        let options = TripOptions { time: Some("08:00".to_string()), arrive_by: true, ..TripOptions::default() };
+        let journeys = EfaClient::new(server.uri(), HttpTransport)
+            .trip("7001004", "7000090", &options)
+            .await
+            .expect("trip succeeds");
+        assert_eq!(journeys.len(), 2);
+    }
+}
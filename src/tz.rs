@@ -0,0 +1,69 @@
+use std::cell::Cell;
+
+use chrono::{FixedOffset, Local, NaiveDateTime, Offset, Utc};
+use efa_core::{berlin, berlin_offset, hhmm};
+use serde::{Deserialize, Serialize};
+
+/// Clock the times on boards are shown in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TimeDisplay {
+    /// Local time of the KVV area, as printed on timetables.
+    #[default]
+    Berlin,
+    /// The device's time zone, e.g. for planning from abroad.
+    Device,
+}
+
+thread_local! {
+    static DISPLAY: Cell<TimeDisplay> = const { Cell::new(TimeDisplay::Berlin) };
+}
+
+pub fn set_display(display: TimeDisplay) {
+    DISPLAY.with(|d| d.set(display));
+}
+
+/// Berlin wall-clock time now, whatever time zone the device is in. EFA
+/// times are Berlin time, so countdowns must be measured against this.
+pub fn now() -> NaiveDateTime {
+    let utc = Utc::now().naive_utc();
+    utc + berlin_offset(utc)
+}
+
+/// Offset of the device's time zone right now.
+pub fn device_offset() -> FixedOffset {
+    Local::now().offset().fix()
+}
+
+/// Whether the device clock currently differs from Berlin time, i.e. board
+/// times don't match the time the device shows.
+pub fn device_differs() -> bool {
+    device_offset() != berlin_offset(Utc::now().naive_utc())
+}
+
+/// An EFA time as "HH:MM" in the clock chosen with `set_display`.
+pub fn show(time: &NaiveDateTime) -> String {
+    clock(time, DISPLAY.with(Cell::get), device_offset())
+}
+
+/// An EFA time as "HH:MM" in `display`, the device being at `device`.
+pub fn clock(time: &NaiveDateTime, display: TimeDisplay, device: FixedOffset) -> String {
+    match display {
+        TimeDisplay::Berlin => hhmm(time),
+        TimeDisplay::Device => hhmm(&berlin(*time).with_timezone(&device).naive_local()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{clock, TimeDisplay};
+    use chrono::{FixedOffset, NaiveDate};
+
+    #[test]
+    fn board_times_stay_in_berlin_unless_asked() {
+        let dep = NaiveDate::from_ymd_opt(2024, 7, 1).unwrap().and_hms_opt(8, 5, 0).unwrap();
+        let new_york = FixedOffset::west_opt(4 * 3600).unwrap();
+        assert_eq!(clock(&dep, TimeDisplay::Berlin, new_york), "08:05");
+        assert_eq!(clock(&dep, TimeDisplay::Device, new_york), "02:05");
+        assert_eq!(clock(&dep, TimeDisplay::Device, FixedOffset::east_opt(2 * 3600).unwrap()), "08:05");
+    }
+}
@@ -0,0 +1,132 @@
+use leptos::prelude::Update;
+
+use crate::efa::StopSuggestion;
+use crate::favorites::Favorites;
+use crate::store::Store;
+
+// Older commands are forgotten; nobody undoes twenty steps on a phone.
+const MAX_UNDO: usize = 20;
+
+/// A destructive change to the store that can be reverted.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    RemoveFavorite { index: usize, stop: StopSuggestion },
+}
+
+impl Command {
+    /// Removal of the favorite `stop_id`, or `None` if it isn't one.
+    pub fn remove_favorite(favorites: &Favorites, stop_id: &str) -> Option<Self> {
+        let index = favorites.stops.iter().position(|s| s.id == stop_id)?;
+        Some(Command::RemoveFavorite { index, stop: favorites.stops[index].clone() })
+    }
+
+    /// Snackbar text describing what the command did.
+    pub fn label(&self) -> String {
+        match self {
+            Command::RemoveFavorite { stop, .. } => format!("Removed {} from favorites", stop.name),
+        }
+    }
+
+    /// Snackbar text after the command was undone.
+    pub fn undone_label(&self) -> String {
+        match self {
+            Command::RemoveFavorite { stop, .. } => format!("Restored {}", stop.name),
+        }
+    }
+
+    pub(crate) fn apply(&self, store: &Store) {
+        match self {
+            Command::RemoveFavorite { stop, .. } => store.favorites.update(|f| {
+                f.remove(&stop.id);
+            }),
+        }
+    }
+
+    pub(crate) fn revert(&self, store: &Store) {
+        match self {
+            Command::RemoveFavorite { index, stop } => store.favorites.update(|f| f.insert(*index, stop.clone())),
+        }
+    }
+}
+
+/// Applied and undone commands, newest last.
+#[derive(Clone, Debug, Default)]
+pub struct UndoStack {
+    done: Vec<Command>,
+    undone: Vec<Command>,
+}
+
+impl UndoStack {
+    /// Remember an applied command. A new command makes redo impossible.
+    pub fn record(&mut self, command: Command) {
+        self.done.push(command);
+        if self.done.len() > MAX_UNDO {
+            self.done.remove(0);
+        }
+        self.undone.clear();
+    }
+
+    /// The command to revert, moved to the redo list.
+    pub fn take_undo(&mut self) -> Option<Command> {
+        let command = self.done.pop()?;
+        self.undone.push(command.clone());
+        Some(command)
+    }
+
+    /// The command to apply again, moved back to the undo list.
+    pub fn take_redo(&mut self) -> Option<Command> {
+        let command = self.undone.pop()?;
+        self.done.push(command.clone());
+        Some(command)
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.undone.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Command, UndoStack, MAX_UNDO};
+    use crate::efa::StopSuggestion;
+    use crate::favorites::Favorites;
+
+    fn stop(id: &str) -> StopSuggestion {
+        StopSuggestion { id: id.to_string(), name: format!("Stop {id}"), place: None, ..Default::default() }
+    }
+
+    #[test]
+    fn undo_and_redo_move_commands_between_lists() {
+        let mut favorites = Favorites::default();
+        favorites.add(stop("1"));
+        favorites.add(stop("2"));
+        let remove = Command::remove_favorite(&favorites, "2").expect("is a favorite");
+        assert_eq!(remove, Command::RemoveFavorite { index: 1, stop: stop("2") });
+        assert_eq!(Command::remove_favorite(&favorites, "3"), None);
+
+        let mut stack = UndoStack::default();
+        stack.record(remove.clone());
+        assert_eq!(stack.take_undo(), Some(remove.clone()));
+        assert_eq!(stack.take_undo(), None);
+        assert!(stack.can_redo());
+        assert_eq!(stack.take_redo(), Some(remove));
+        assert!(!stack.can_redo());
+    }
+
+    #[test]
+    fn new_commands_clear_redo_and_history_is_bounded() {
+        let mut stack = UndoStack::default();
+        for i in 0..=MAX_UNDO {
+            stack.record(Command::RemoveFavorite { index: 0, stop: stop(&i.to_string()) });
+        }
+        stack.take_undo();
+        stack.record(Command::RemoveFavorite { index: 0, stop: stop("x") });
+        assert!(!stack.can_redo());
+
+        let mut count = 0;
+        while stack.take_undo().is_some() {
+            count += 1;
+        }
+        assert_eq!(count, MAX_UNDO);
+    }
+}
@@ -0,0 +1,128 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use chrono::{NaiveDateTime, TimeDelta};
+
+use crate::diagnostics;
+use crate::diff::DepartureKey;
+use crate::efa::{hhmm, Departure, EfaError};
+use crate::messages::Locale;
+use crate::provider::TransitProvider;
+
+// Vehicles don't leave this much ahead of schedule; the realtime data is off.
+const MAX_EARLY_MINUTES: i64 = 30;
+// Boards reach a few hours ahead at most.
+const MAX_AHEAD_HOURS: i64 = 24;
+
+/// Something implausible in a departure board.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Anomaly {
+    /// Realtime departure more than 30 minutes before the planned one.
+    EarlyRealtime { key: DepartureKey, minutes: i64 },
+    /// The same trip listed more than once.
+    DuplicateTrip { key: DepartureKey },
+    /// Departure more than a day after the board was loaded.
+    FarAhead { key: DepartureKey },
+}
+
+impl fmt::Display for Anomaly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let trip = |key: &DepartureKey| format!("{} at {}", key.line, hhmm(&key.planned_time));
+        match self {
+            Anomaly::EarlyRealtime { key, minutes } => write!(f, "{} reported {minutes} min early", trip(key)),
+            Anomaly::DuplicateTrip { key } => write!(f, "{} listed twice", trip(key)),
+            Anomaly::FarAhead { key } => write!(f, "{} more than {MAX_AHEAD_HOURS} h ahead", trip(key)),
+        }
+    }
+}
+
+/// A departure board together with what looked wrong in it.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CheckedBoard {
+    pub departures: Vec<Departure>,
+    pub anomalies: Vec<Anomaly>,
+}
+
+impl CheckedBoard {
+    /// Subtle hint for boards with anomalies, `None` for clean ones.
+    pub fn hint(&self, locale: Locale) -> Option<&'static str> {
+        if self.anomalies.is_empty() {
+            return None;
+        }
+        Some(match locale {
+            Locale::En => "Some of this data may be unreliable.",
+            Locale::De => "Einige dieser Daten sind möglicherweise unzuverlässig.",
+        })
+    }
+}
+
+/// Anomalies of a board loaded at `now`, in board order.
+pub fn validate(departures: &[Departure], now: NaiveDateTime) -> Vec<Anomaly> {
+    let mut anomalies = Vec::new();
+    let mut seen = HashSet::new();
+    for dep in departures {
+        let key = DepartureKey::of(dep);
+        if let Some(realtime) = dep.realtime_time {
+            let early = (dep.planned_time - realtime).num_minutes();
+            if early > MAX_EARLY_MINUTES {
+                anomalies.push(Anomaly::EarlyRealtime { key: key.clone(), minutes: early });
+            }
+        }
+        if dep.time - now > TimeDelta::hours(MAX_AHEAD_HOURS) {
+            anomalies.push(Anomaly::FarAhead { key: key.clone() });
+        }
+        if !seen.insert(key.clone()) {
+            anomalies.push(Anomaly::DuplicateTrip { key });
+        }
+    }
+    anomalies
+}
+
+impl dyn TransitProvider + '_ {
+    /// `departures` from `now`, validated. Anomalies are also recorded as
+    /// warnings for problem reports.
+    pub async fn departures_checked(&self, station_id: &str, max: usize, now: NaiveDateTime) -> Result<CheckedBoard, EfaError> {
+        let departures = self.departures(station_id, max, Some(now)).await?;
+        let anomalies = validate(&departures, now);
+        for anomaly in &anomalies {
+            diagnostics::record_warning(format!("board {station_id}: {anomaly}"));
+        }
+        Ok(CheckedBoard { departures, anomalies })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{validate, Anomaly, CheckedBoard};
+    use crate::fixtures::{delayed, on};
+    use crate::messages::Locale;
+    use crate::provider::TransitProvider;
+    use crate::simulation::Simulation;
+
+    #[test]
+    fn implausible_rows_are_flagged() {
+        let now = on(15, 8, 0);
+        let board = vec![
+            delayed("S1", on(15, 8, 5), Some(on(15, 8, 7))),
+            delayed("S2", on(15, 9, 0), Some(on(15, 8, 10))),
+            delayed("S1", on(15, 8, 5), None),
+            delayed("4", on(16, 9, 0), None),
+        ];
+        let anomalies = validate(&board, now);
+        let kinds: Vec<_> = anomalies.iter().map(|a| a.to_string()).collect();
+        assert_eq!(kinds, ["S2 at 09:00 reported 50 min early", "S1 at 08:05 listed twice", "4 at 09:00 more than 24 h ahead"]);
+        assert!(matches!(anomalies[0], Anomaly::EarlyRealtime { minutes: 50, .. }));
+
+        let checked = CheckedBoard { departures: board, anomalies };
+        assert!(checked.hint(Locale::En).is_some());
+        assert_eq!(CheckedBoard::default().hint(Locale::De), None);
+    }
+
+    #[tokio::test]
+    async fn picked_boards_come_from_the_chosen_provider() {
+        let simulation: &dyn TransitProvider = &Simulation::demo(on(15, 8, 0));
+        let board = simulation.departures_checked("7001004", 5, on(15, 9, 0)).await.expect("simulated");
+        assert_eq!(board.departures.len(), 5);
+        assert!(board.departures.iter().all(|d| d.planned_time >= on(15, 9, 0)));
+    }
+}